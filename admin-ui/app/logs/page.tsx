@@ -322,6 +322,13 @@ export default function LogsPage() {
                     ? `${selectedLog.prompt_tokens ?? 0} / ${selectedLog.completion_tokens ?? 0}`
                     : "—"}
                 </div>
+                {(selectedLog.reasoning_tokens != null ||
+                  selectedLog.cached_prompt_tokens != null) && (
+                  <div>
+                    <span className="text-muted-foreground">Reasoning / Cached prompt:</span>{" "}
+                    {`${selectedLog.reasoning_tokens ?? 0} / ${selectedLog.cached_prompt_tokens ?? 0}`}
+                  </div>
+                )}
                 <div>
                   <span className="text-muted-foreground">Key ID:</span>{" "}
                   <span className="font-mono">
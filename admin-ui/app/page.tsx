@@ -466,6 +466,29 @@ export default function DashboardPage() {
           </CardContent>
         </Card>
       )}
+
+      {/* Top error reasons */}
+      {stats && stats.top_errors.length > 0 && (
+        <Card>
+          <CardHeader className="pb-2">
+            <CardTitle className="text-sm">Top Errors (24h)</CardTitle>
+          </CardHeader>
+          <CardContent>
+            <ul className="space-y-1.5 text-xs">
+              {stats.top_errors.map((e) => (
+                <li key={e.reason} className="flex items-start justify-between gap-4">
+                  <span className="font-mono text-muted-foreground break-all">
+                    {e.reason}
+                  </span>
+                  <span className="tabular-nums font-medium">
+                    {formatNumber(e.count)}
+                  </span>
+                </li>
+              ))}
+            </ul>
+          </CardContent>
+        </Card>
+      )}
     </div>
   );
 }
\ No newline at end of file
@@ -151,6 +151,8 @@ export async function listLogs(
   if (params.per_page) searchParams.set("per_page", String(params.per_page));
   if (params.key_id) searchParams.set("key_id", params.key_id);
   if (params.model) searchParams.set("model", params.model);
+  if (params.retriable !== undefined)
+    searchParams.set("retriable", String(params.retriable));
   const qs = searchParams.toString();
   return request<LogListResponse>(`/admin/logs${qs ? `?${qs}` : ""}`);
 }
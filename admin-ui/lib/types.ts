@@ -7,6 +7,9 @@ export interface ProviderInfo {
   base_url: string;
   api_key_preview: string;
   is_active: boolean;
+  force_buffered_upstream: boolean;
+  supports_stream_usage: boolean;
+  retry_on_status: number[] | null;
   created_at: string;
   updated_at: string;
 }
@@ -16,6 +19,9 @@ export interface CreateProviderRequest {
   kind: string;
   base_url?: string;
   api_key: string;
+  force_buffered_upstream?: boolean;
+  supports_stream_usage?: boolean;
+  retry_on_status?: number[];
 }
 
 export interface UpdateProviderRequest {
@@ -24,6 +30,9 @@ export interface UpdateProviderRequest {
   base_url?: string;
   api_key?: string;
   is_active?: boolean;
+  force_buffered_upstream?: boolean;
+  supports_stream_usage?: boolean;
+  retry_on_status?: number[] | null;
 }
 
 export interface ModelInfo {
@@ -35,16 +44,42 @@ export interface ModelInfo {
   is_active: boolean;
   input_token_coefficient: number;
   output_token_coefficient: number;
+  reasoning_token_coefficient: number | null;
+  input_price_per_mtok: number | null;
+  output_price_per_mtok: number | null;
+  weight: number;
+  fallback_response: Record<string, unknown> | null;
   created_at: string;
   updated_at: string;
 }
 
+export interface AlternateRouteInfo {
+  id: string;
+  model_id: string;
+  provider_id: string;
+  provider_name: string;
+  provider_model_name: string | null;
+  weight: number;
+  created_at: string;
+}
+
+export interface CreateAlternateRouteRequest {
+  provider_id: string;
+  provider_model_name?: string;
+  weight?: number;
+}
+
 export interface CreateModelRequest {
   name: string;
   provider_id: string;
   provider_model_name?: string;
   input_token_coefficient?: number;
   output_token_coefficient?: number;
+  reasoning_token_coefficient?: number | null;
+  input_price_per_mtok?: number;
+  output_price_per_mtok?: number;
+  weight?: number;
+  fallback_response?: Record<string, unknown> | null;
 }
 
 export interface UpdateModelRequest {
@@ -54,6 +89,11 @@ export interface UpdateModelRequest {
   is_active?: boolean;
   input_token_coefficient?: number;
   output_token_coefficient?: number;
+  reasoning_token_coefficient?: number | null;
+  input_price_per_mtok?: number | null;
+  output_price_per_mtok?: number | null;
+  weight?: number;
+  fallback_response?: Record<string, unknown> | null;
 }
 
 export interface UserKeyInfo {
@@ -63,6 +103,12 @@ export interface UserKeyInfo {
   is_active: boolean;
   token_budget: number | null;
   tokens_used: number;
+  allowed_provider_kinds: string[] | null;
+  tag: string | null;
+  tpm_limit: number | null;
+  metadata: Record<string, unknown> | null;
+  group_id: string | null;
+  spend_usd: number | null;
   created_at: string;
   updated_at: string;
 }
@@ -78,16 +124,49 @@ export interface UserKeyCreated {
 export interface CreateKeyRequest {
   name: string;
   token_budget?: number | null;
+  allowed_provider_kinds?: string[] | null;
+  tag?: string | null;
+  tpm_limit?: number | null;
+  metadata?: Record<string, unknown> | null;
+  group_id?: string | null;
 }
 
 export interface UpdateKeyRequest {
   token_budget?: number | null;
   reset_usage?: boolean;
+  allowed_provider_kinds?: string[] | null;
+  tag?: string | null;
+  tpm_limit?: number | null;
+  metadata?: Record<string, unknown> | null;
+  group_id?: string | null;
+}
+
+export interface KeyGroupInfo {
+  id: string;
+  name: string;
+  token_budget: number | null;
+  tokens_used: number;
+  key_count: number;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface CreateKeyGroupRequest {
+  name: string;
+  token_budget?: number | null;
+}
+
+export interface UpdateKeyGroupRequest {
+  name?: string;
+  token_budget?: number | null;
+  reset_usage?: boolean;
 }
 
 export interface ApiError {
   error: {
     message: string;
+    type: string;
+    code: string | null;
   };
 }
 
@@ -106,6 +185,8 @@ export interface RequestLogInfo {
   prompt_tokens: number | null;
   completion_tokens: number | null;
   total_tokens: number | null;
+  reasoning_tokens: number | null;
+  cached_prompt_tokens: number | null;
   weighted_total_tokens: number | null;
   latency_ms: number;
   is_stream: boolean;
@@ -113,6 +194,10 @@ export interface RequestLogInfo {
   response_body: unknown | null;
   error_message: string | null;
   created_at: string;
+  stored: boolean;
+  retriable: boolean;
+  is_fallback: boolean;
+  key_metadata: Record<string, unknown> | null;
 }
 
 export interface LogListResponse {
@@ -127,6 +212,30 @@ export interface ListLogsParams {
   per_page?: number;
   key_id?: string;
   model?: string;
+  retriable?: boolean;
+}
+
+/** Payload of a `log` event on GET /admin/logs/stream. */
+export interface LogEvent {
+  request_id: string | null;
+  user_key_id: string | null;
+  model_requested: string;
+  model_sent: string;
+  provider_id: string | null;
+  provider_kind: string | null;
+  status_code: number;
+  is_error: boolean;
+  retriable: boolean;
+  prompt_tokens: number | null;
+  completion_tokens: number | null;
+  total_tokens: number | null;
+  reasoning_tokens: number | null;
+  cached_prompt_tokens: number | null;
+  latency_ms: number;
+  is_stream: boolean;
+  error_message: string | null;
+  is_fallback: boolean;
+  created_at: string;
 }
 
 // ── Dashboard Stats ───────────────────────────────────────────
@@ -151,6 +260,11 @@ export interface ProviderUsage {
   errors: number;
 }
 
+export interface ErrorReason {
+  reason: string;
+  count: number;
+}
+
 export interface DashboardStats {
   total_requests: number;
   total_requests_24h: number;
@@ -160,4 +274,5 @@ export interface DashboardStats {
   requests_per_hour: HourlyBucket[];
   model_usage: ModelUsage[];
   provider_usage: ProviderUsage[];
+  top_errors: ErrorReason[];
 }
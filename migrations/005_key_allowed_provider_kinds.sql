@@ -0,0 +1,2 @@
+-- Restrict which provider kinds a user key may be routed to
+ALTER TABLE user_keys ADD COLUMN allowed_provider_kinds TEXT[] NULL;  -- NULL = all kinds
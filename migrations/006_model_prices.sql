@@ -0,0 +1,4 @@
+-- Add optional USD pricing to models, per million tokens
+-- NULL means unpriced (counted as zero spend)
+ALTER TABLE models ADD COLUMN input_price_per_mtok  DOUBLE PRECISION NULL;
+ALTER TABLE models ADD COLUMN output_price_per_mtok DOUBLE PRECISION NULL;
@@ -0,0 +1,2 @@
+-- FALSE when the client opted out of body storage via the x-gateway-no-store header
+ALTER TABLE request_logs ADD COLUMN stored BOOLEAN NOT NULL DEFAULT TRUE;
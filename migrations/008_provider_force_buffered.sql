@@ -0,0 +1,2 @@
+-- Send stream:false upstream and re-emit the buffered completion as SSE to the client
+ALTER TABLE providers ADD COLUMN force_buffered_upstream BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Optional tag grouping user keys (e.g. per team); scoped admin tokens manage one tag
+ALTER TABLE user_keys ADD COLUMN tag TEXT NULL;
+CREATE INDEX idx_user_keys_tag ON user_keys (tag);
@@ -0,0 +1,4 @@
+-- Whether a failed request was worth retrying (429 / 5xx) as opposed to a client error
+ALTER TABLE request_logs ADD COLUMN retriable BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE request_logs SET retriable = TRUE WHERE is_error AND (status_code = 429 OR status_code >= 500);
+CREATE INDEX idx_request_logs_retriable ON request_logs (retriable) WHERE is_error;
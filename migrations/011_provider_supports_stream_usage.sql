@@ -0,0 +1,2 @@
+-- Providers that reject `stream_options` (HTTP 400) can opt out of usage injection
+ALTER TABLE providers ADD COLUMN supports_stream_usage BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,14 @@
+-- Weighted provider routes for a model in addition to its primary provider
+ALTER TABLE models ADD COLUMN weight INTEGER NOT NULL DEFAULT 1;  -- routing weight of the primary provider
+
+CREATE TABLE model_alternate_routes (
+    id                   UUID PRIMARY KEY,
+    model_id             UUID         NOT NULL REFERENCES models(id) ON DELETE CASCADE,
+    provider_id          UUID         NOT NULL REFERENCES providers(id),
+    provider_model_name  VARCHAR(255),                    -- NULL = same as the model's provider-side name
+    weight               INTEGER      NOT NULL DEFAULT 1,
+    created_at           TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    UNIQUE (model_id, provider_id)
+);
+
+CREATE INDEX idx_model_alternate_routes_provider_id ON model_alternate_routes (provider_id);
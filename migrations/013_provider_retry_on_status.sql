@@ -0,0 +1,2 @@
+-- Upstream status codes that make the proxy fail over to another route of the model
+ALTER TABLE providers ADD COLUMN retry_on_status INTEGER[] NULL;  -- NULL = 429 and 5xx
@@ -0,0 +1,2 @@
+-- Per-key tokens-per-minute limit, enforced with a Redis counter per minute
+ALTER TABLE user_keys ADD COLUMN tpm_limit BIGINT NULL;  -- NULL = unlimited
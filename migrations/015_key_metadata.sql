@@ -0,0 +1,2 @@
+-- Free-form bookkeeping metadata on user keys (team, cost center, contact, ...)
+ALTER TABLE user_keys ADD COLUMN metadata JSONB NULL;
@@ -0,0 +1,14 @@
+-- Shared token budget for a group of user keys
+CREATE TABLE key_groups (
+    id            UUID PRIMARY KEY,
+    name          VARCHAR(255) NOT NULL UNIQUE,
+    token_budget  BIGINT       NULL,                -- NULL = unlimited
+    tokens_used   BIGINT       NOT NULL DEFAULT 0,
+    created_at    TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    updated_at    TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+-- Grouped keys are checked against the group's budget instead of their own
+ALTER TABLE user_keys ADD COLUMN group_id UUID NULL REFERENCES key_groups(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_user_keys_group_id ON user_keys (group_id);
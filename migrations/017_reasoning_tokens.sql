@@ -0,0 +1,6 @@
+-- Token usage breakdown reported in usage.*_tokens_details
+ALTER TABLE request_logs ADD COLUMN reasoning_tokens INTEGER NULL;      -- subset of completion_tokens
+ALTER TABLE request_logs ADD COLUMN cached_prompt_tokens INTEGER NULL;  -- subset of prompt_tokens
+
+-- Budget coefficient for reasoning tokens; NULL = same as output_token_coefficient
+ALTER TABLE models ADD COLUMN reasoning_token_coefficient DOUBLE PRECISION NULL;
@@ -0,0 +1,5 @@
+-- Canned completion returned when every route of a model fails (NULL = 502 as before)
+ALTER TABLE models ADD COLUMN fallback_response JSONB NULL;
+
+-- Log entries answered with the fallback instead of an upstream response
+ALTER TABLE request_logs ADD COLUMN is_fallback BOOLEAN NOT NULL DEFAULT FALSE;
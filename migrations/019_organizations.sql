@@ -0,0 +1,31 @@
+-- Tenants: keys, key groups, providers, models and logs belong to exactly one organization
+CREATE TABLE organizations (
+    id          UUID PRIMARY KEY,
+    name        VARCHAR(255) NOT NULL UNIQUE,
+    created_at  TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+-- Existing data (and ADMIN_KEY) belongs to the default organization
+INSERT INTO organizations (id, name) VALUES ('00000000-0000-0000-0000-000000000000', 'default');
+
+ALTER TABLE user_keys ADD COLUMN org_id UUID NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES organizations(id);
+ALTER TABLE key_groups ADD COLUMN org_id UUID NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES organizations(id);
+ALTER TABLE providers ADD COLUMN org_id UUID NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES organizations(id);
+ALTER TABLE models ADD COLUMN org_id UUID NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES organizations(id);
+ALTER TABLE request_logs ADD COLUMN org_id UUID NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000';
+
+-- Names only need to be unique within an organization
+ALTER TABLE key_groups DROP CONSTRAINT key_groups_name_key;
+ALTER TABLE key_groups ADD CONSTRAINT key_groups_org_name_key UNIQUE (org_id, name);
+ALTER TABLE providers DROP CONSTRAINT providers_name_key;
+ALTER TABLE providers ADD CONSTRAINT providers_org_name_key UNIQUE (org_id, name);
+ALTER TABLE models DROP CONSTRAINT models_name_key;
+ALTER TABLE models ADD CONSTRAINT models_org_name_key UNIQUE (org_id, name);
+
+CREATE INDEX idx_user_keys_org_id ON user_keys (org_id);
+CREATE INDEX idx_request_logs_org_created_at ON request_logs (org_id, created_at DESC);
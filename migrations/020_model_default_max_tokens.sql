@@ -0,0 +1,2 @@
+-- max_tokens injected into requests that set no completion cap; NULL = DEFAULT_MAX_TOKENS
+ALTER TABLE models ADD COLUMN default_max_tokens INTEGER NULL;
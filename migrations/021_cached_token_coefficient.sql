@@ -0,0 +1,2 @@
+-- Budget coefficient for cached prompt tokens; NULL = same as input_token_coefficient
+ALTER TABLE models ADD COLUMN cached_token_coefficient DOUBLE PRECISION NULL;
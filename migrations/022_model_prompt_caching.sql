@@ -0,0 +1,2 @@
+-- Mark the system prompt with cache_control for providers with explicit prompt caching
+ALTER TABLE models ADD COLUMN prompt_caching BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- request_id now holds the gateway-generated id; the provider's x-request-id moves here
+ALTER TABLE request_logs ADD COLUMN upstream_request_id VARCHAR(255) NULL;
+UPDATE request_logs SET upstream_request_id = request_id WHERE request_id IS NOT NULL;
@@ -0,0 +1,3 @@
+-- Case applied to provider-side model names before sending upstream: none | lower | upper
+ALTER TABLE providers ADD COLUMN normalize_model_case VARCHAR(16) NOT NULL DEFAULT 'none'
+    CHECK (normalize_model_case IN ('none', 'lower', 'upper'));
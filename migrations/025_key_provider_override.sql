@@ -0,0 +1,4 @@
+-- Pin a user key to one provider: its requests only use that provider's routes
+ALTER TABLE user_keys ADD COLUMN provider_override_id UUID NULL REFERENCES providers(id);  -- NULL = normal routing
+
+CREATE INDEX idx_user_keys_provider_override_id ON user_keys (provider_override_id);
@@ -0,0 +1,2 @@
+-- Region tag for data-residency routing via the x-gateway-region request header
+ALTER TABLE providers ADD COLUMN region VARCHAR(64) NULL;  -- NULL = no region
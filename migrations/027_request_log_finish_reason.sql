@@ -0,0 +1,2 @@
+-- finish_reason of the first choice (e.g. stop, length, content_filter)
+ALTER TABLE request_logs ADD COLUMN finish_reason VARCHAR(64) NULL;
@@ -0,0 +1,2 @@
+-- Path appended to base_url for chat completions (NULL = /chat/completions)
+ALTER TABLE providers ADD COLUMN chat_path VARCHAR(255);
@@ -0,0 +1,2 @@
+-- Object key of request/response bodies kept in external storage (NULL = stored inline)
+ALTER TABLE request_logs ADD COLUMN body_key VARCHAR(512);
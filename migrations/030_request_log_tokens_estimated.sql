@@ -0,0 +1,2 @@
+-- Token counts estimated by the gateway (missing usage, client-aborted streams)
+ALTER TABLE request_logs ADD COLUMN tokens_estimated BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Side that produced a failed request's error: gateway or upstream (NULL for successes)
+ALTER TABLE request_logs ADD COLUMN error_source VARCHAR(16);
@@ -0,0 +1,10 @@
+-- Per-model token budgets of a key, enforced in addition to the key's own budget
+CREATE TABLE key_model_budgets (
+    key_id        UUID         NOT NULL REFERENCES user_keys(id) ON DELETE CASCADE,
+    model         VARCHAR(255) NOT NULL,            -- gateway model name as requested
+    token_budget  BIGINT       NOT NULL,
+    tokens_used   BIGINT       NOT NULL DEFAULT 0,
+    created_at    TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    updated_at    TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (key_id, model)
+);
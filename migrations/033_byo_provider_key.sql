@@ -0,0 +1,5 @@
+-- Keys allowed to send their own provider API key (x-provider-api-key) per request
+ALTER TABLE user_keys ADD COLUMN allow_byo_provider_key BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Request sent with the client's provider key (the key itself is never stored)
+ALTER TABLE request_logs ADD COLUMN byo_provider_key BOOLEAN NOT NULL DEFAULT FALSE;
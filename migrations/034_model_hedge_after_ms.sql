@@ -0,0 +1,3 @@
+-- Delay after which a non-streaming request is also sent to another route of the
+-- model (request hedging); NULL = no hedging
+ALTER TABLE models ADD COLUMN hedge_after_ms INTEGER NULL;
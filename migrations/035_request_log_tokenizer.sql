@@ -0,0 +1,2 @@
+-- Estimator that produced the token counts of entries with tokens_estimated
+ALTER TABLE request_logs ADD COLUMN tokenizer TEXT NULL;
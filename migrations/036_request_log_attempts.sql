@@ -0,0 +1,2 @@
+-- Routes the request was sent to (failovers and hedges included); 0 = never sent
+ALTER TABLE request_logs ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,8 @@
+-- Forward unregistered model names to the provider as-is. With a prefix, only names
+-- starting with it match (the prefix is stripped); without one, the provider takes
+-- any remaining name. At most one passthrough provider per prefix in an organization.
+ALTER TABLE providers ADD COLUMN passthrough BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE providers ADD COLUMN passthrough_prefix VARCHAR(64);
+CREATE UNIQUE INDEX providers_passthrough_prefix_key
+    ON providers (org_id, COALESCE(passthrough_prefix, ''))
+    WHERE passthrough;
@@ -0,0 +1,2 @@
+-- Incoming request headers with credentials removed (LOG_REQUEST_HEADERS)
+ALTER TABLE request_logs ADD COLUMN request_headers JSONB;
@@ -1,10 +1,112 @@
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use chrono::{DateTime, Utc};
 use serde_json::json;
 
+/// Stable, OpenAI-style `error.type` values returned in every error body.
+///
+/// | Type | Used for |
+/// | ---- | -------- |
+/// | `invalid_request_error` | Malformed or disallowed request (400) |
+/// | `authentication_error` | Missing or invalid credentials (401) |
+/// | `permission_error` | Authenticated but not permitted (403) |
+/// | `not_found_error` | Resource does not exist (404) |
+/// | `rate_limit_error` | Budget or rate limit exhausted (429) |
+/// | `upstream_error` | Provider unreachable or returned an unusable response (502) |
+/// | `api_error` | Internal gateway failure (500) |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorType {
+    InvalidRequest,
+    Authentication,
+    Permission,
+    NotFound,
+    RateLimit,
+    Upstream,
+    Api,
+}
+
+impl ErrorType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorType::InvalidRequest => "invalid_request_error",
+            ErrorType::Authentication => "authentication_error",
+            ErrorType::Permission => "permission_error",
+            ErrorType::NotFound => "not_found_error",
+            ErrorType::RateLimit => "rate_limit_error",
+            ErrorType::Upstream => "upstream_error",
+            ErrorType::Api => "api_error",
+        }
+    }
+}
+
+/// Build a JSON error response: `{ "error": { "message", "type", "code" } }`.
+/// `code` is a machine-readable reason within the type, or null.
+pub fn json_error(
+    status: StatusCode,
+    error_type: ErrorType,
+    message: impl Into<String>,
+    code: Option<&str>,
+) -> Response {
+    let body = Json(json!({
+        "error": {
+            "message": message.into(),
+            "type": error_type.as_str(),
+            "code": code,
+        }
+    }));
+    (status, body).into_response()
+}
+
+/// Which limit a 429 hit, reported as `error.limit_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitType {
+    /// Tokens-per-minute limit of the key.
+    Tpm,
+    /// Token budget of the key, its group, or one of its models.
+    Budget,
+}
+
+impl LimitType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitType::Tpm => "tpm",
+            LimitType::Budget => "budget",
+        }
+    }
+}
+
+/// Build a 429 `rate_limit_error`: the `json_error` body plus `limit_type` and
+/// `reset_at` (when the limit frees up, or null if it never does on its own).
+/// A known reset also sets `Retry-After` in whole seconds.
+pub fn rate_limit_error(
+    limit_type: LimitType,
+    message: impl Into<String>,
+    code: &str,
+    reset_at: Option<DateTime<Utc>>,
+) -> Response {
+    let body = Json(json!({
+        "error": {
+            "message": message.into(),
+            "type": ErrorType::RateLimit.as_str(),
+            "code": code,
+            "limit_type": limit_type.as_str(),
+            "reset_at": reset_at,
+        }
+    }));
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, body).into_response();
+    if let Some(reset_at) = reset_at {
+        let millis = (reset_at - Utc::now()).num_milliseconds().max(0);
+        let retry_after = ((millis + 999) / 1000).max(1);
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    response
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum AppError {
     #[error("Unauthorized")]
@@ -14,6 +116,12 @@ pub enum AppError {
     #[error("Not found")]
     NotFound,
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     #[error("Bad request: {0}")]
     BadRequest(String),
 
@@ -35,14 +143,37 @@ pub enum AppError {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, message) = match &self {
-            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
-            AppError::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
+        let (status, error_type, message) = match &self {
+            AppError::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                ErrorType::Authentication,
+                "Unauthorized".to_string(),
+            ),
+            AppError::NotFound => (
+                StatusCode::NOT_FOUND,
+                ErrorType::NotFound,
+                "Not found".to_string(),
+            ),
+            AppError::Forbidden(msg) => (
+                StatusCode::FORBIDDEN,
+                ErrorType::Permission,
+                msg.clone(),
+            ),
+            AppError::Conflict(msg) => (
+                StatusCode::CONFLICT,
+                ErrorType::InvalidRequest,
+                msg.clone(),
+            ),
+            AppError::BadRequest(msg) => (
+                StatusCode::BAD_REQUEST,
+                ErrorType::InvalidRequest,
+                msg.clone(),
+            ),
             AppError::Internal(msg) => {
                 tracing::error!("Internal error: {}", msg);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorType::Api,
                     "Internal server error".to_string(),
                 )
             }
@@ -50,6 +181,7 @@ impl IntoResponse for AppError {
                 tracing::error!("Database error: {}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorType::Api,
                     "Internal server error".to_string(),
                 )
             }
@@ -57,6 +189,7 @@ impl IntoResponse for AppError {
                 tracing::error!("Redis error: {}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorType::Api,
                     "Internal server error".to_string(),
                 )
             }
@@ -64,6 +197,7 @@ impl IntoResponse for AppError {
                 tracing::error!("HTTP client error: {}", e);
                 (
                     StatusCode::BAD_GATEWAY,
+                    ErrorType::Upstream,
                     "Upstream service error".to_string(),
                 )
             }
@@ -71,12 +205,12 @@ impl IntoResponse for AppError {
                 tracing::error!("Error: {}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorType::Api,
                     "Internal server error".to_string(),
                 )
             }
         };
 
-        let body = Json(json!({ "error": { "message": message } }));
-        (status, body).into_response()
+        json_error(status, error_type, message, None)
     }
 }
@@ -1,7 +1,10 @@
 mod config;
 mod error;
+mod metrics;
 mod middleware;
 mod models;
+mod redis_keys;
+mod request_id;
 mod routes;
 mod services;
 mod state;
@@ -10,7 +13,7 @@ use std::sync::Arc;
 
 use axum::{http::HeaderValue, middleware as axum_mw, Router};
 use sqlx::postgres::PgPoolOptions;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, UnixListener};
 use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::EnvFilter;
@@ -34,6 +37,24 @@ async fn main() -> anyhow::Result<()> {
     let config = Config::from_env()?;
     tracing::info!("Starting LLM Gateway on {}", config.listen_addr);
 
+    // Warn about admin tokens that fall short of the key policy
+    let admin_tokens = std::iter::once(("ADMIN_KEY".to_string(), config.admin_key.as_str()))
+        .chain(config.admin_scoped_keys.iter().map(|k| {
+            (format!("ADMIN_SCOPED_KEYS entry for tag '{}'", k.tag), k.key.as_str())
+        }))
+        .chain(config.org_admin_keys.iter().map(|k| {
+            (format!("ORG_ADMIN_KEYS entry for org '{}'", k.org), k.key.as_str())
+        }));
+    for (name, token) in admin_tokens {
+        if let Some(violation) = services::key_service::key_policy_violation(
+            token,
+            config.min_key_length,
+            &config.key_required_prefix,
+        ) {
+            tracing::warn!("{} is weak: it {}", name, violation);
+        }
+    }
+
     // Create Postgres connection pool
     let db = PgPoolOptions::new()
         .max_connections(10)
@@ -44,31 +65,109 @@ async fn main() -> anyhow::Result<()> {
     sqlx::migrate!("./migrations").run(&db).await?;
     tracing::info!("Database migrations applied");
 
+    // Create organizations named in ORG_ADMIN_KEYS
+    let org_names: Vec<&str> = config.org_admin_keys.iter().map(|k| k.org.as_str()).collect();
+    let org_ids = services::org_service::ensure_orgs(&org_names, &db).await?;
+
     // Create Redis connection manager
+    redis_keys::init(&config.redis_namespace);
     let redis_client = redis::Client::open(config.redis_url.as_str())?;
     let mut redis = redis_client.get_connection_manager().await?;
     tracing::info!("Connected to Redis");
 
     // Warm up Redis caches
     services::key_service::warm_up_redis(&db, &mut redis).await?;
-    services::model_service::warm_up_model_routes(&db, &mut redis).await?;
+    services::model_service::warm_up_model_routes(config.model_route_cache_ttl_secs, &db, &mut redis)
+        .await?;
+    if !config.allow_insecure_provider_urls {
+        services::provider_service::warn_insecure_providers(&db).await?;
+    }
+
+    // Shared upstream HTTP client
+    let mut http_client_builder = reqwest::Client::builder();
+    if let Some(min_tls) = config.upstream_min_tls {
+        http_client_builder = http_client_builder.min_tls_version(min_tls.as_reqwest());
+    }
+    if config.provider_keepalive_secs > 0 {
+        // Idle connections must outlive the keep-alive interval to stay pooled
+        http_client_builder = http_client_builder.pool_idle_timeout(std::time::Duration::from_secs(
+            config.provider_keepalive_secs + 30,
+        ));
+    }
+    let http_client = http_client_builder.build()?;
+
+    // Optional end-to-end self-test before accepting traffic
+    if let Some(model) = &config.startup_selftest_model {
+        match services::selftest_service::run_selftest(
+            model,
+            config.model_route_cache_ttl_secs,
+            &http_client,
+            &db,
+            &mut redis,
+        )
+        .await
+        {
+            Ok(()) => tracing::info!("Startup self-test via model {} passed", model),
+            Err(e) if config.startup_selftest_required => {
+                anyhow::bail!("Startup self-test via model {} failed: {}", model, e);
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Startup self-test via model {} failed, serving anyway: {}",
+                    model,
+                    e
+                );
+            }
+        }
+    }
 
     // Build shared state
     let state = Arc::new(AppState {
         db,
         redis,
         config: config.clone(),
-        http_client: reqwest::Client::new(),
+        http_client: http_client.clone(),
+        provider_inflight: Arc::new(metrics::ProviderInflight::default()),
+        provider_health: Arc::new(metrics::ProviderHealth::new(std::time::Duration::from_secs(
+            config.routing_health_window_secs,
+        ))),
+        provider_quotas: Arc::new(metrics::ProviderQuotas::default()),
+        log_events: tokio::sync::broadcast::channel(services::log_service::LOG_EVENT_CAPACITY).0,
+        slow_requests: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+        org_ids,
+        usage: Arc::new(services::usage_service::UsageBuffer::new(
+            config.usage_flush_interval_ms > 0,
+        )),
+        stream_slots: Arc::new(metrics::StreamSlots::new(config.max_streams)),
+        budget_alerts: Arc::new(services::budget_alert_service::BudgetAlerts::new(
+            config.budget_alert_thresholds.clone(),
+            config.budget_alert_webhook_url.clone(),
+            http_client.clone(),
+        )),
+        body_store: match &config.log_body_s3 {
+            Some(s3) => Arc::new(services::body_store_service::S3BodyStore::new(
+                s3.clone(),
+                http_client.clone(),
+            )),
+            None => Arc::new(services::body_store_service::PostgresBodyStore),
+        },
     });
 
     // Spawn background log retention task
     if config.log_retention_days > 0 {
         let retention_db = state.db.clone();
+        let retention_body_store = state.body_store.clone();
         let retention_days = config.log_retention_days;
         tokio::spawn(async move {
             // Run cleanup once on startup, then every hour
             loop {
-                match services::log_service::cleanup_old_logs(&retention_db, retention_days).await {
+                match services::log_service::cleanup_old_logs(
+                    &retention_db,
+                    retention_body_store.as_ref(),
+                    retention_days,
+                )
+                .await
+                {
                     Ok(n) if n > 0 => {
                         tracing::info!(
                             "Cleaned up {} request logs older than {} days",
@@ -86,6 +185,148 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
+    // Spawn background body compaction task
+    if config.body_retention_days > 0 {
+        let compaction_db = state.db.clone();
+        let compaction_body_store = state.body_store.clone();
+        let body_retention_days = config.body_retention_days;
+        tokio::spawn(async move {
+            // Run compaction once on startup, then every hour
+            loop {
+                match services::log_service::compact_bodies(
+                    &compaction_db,
+                    compaction_body_store.as_ref(),
+                    body_retention_days,
+                )
+                .await
+                {
+                    Ok(n) if n > 0 => {
+                        tracing::info!(
+                            "Cleared bodies of {} request logs older than {} days",
+                            n,
+                            body_retention_days
+                        );
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::error!("Log body compaction error: {}", e);
+                    }
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+            }
+        });
+    }
+
+    // Spawn background dashboard stats refresh for every organization
+    if config.dashboard_cache_refresh_secs > 0 {
+        let stats_db = state.db.clone();
+        let mut stats_redis = state.redis.clone();
+        let refresh_secs = config.dashboard_cache_refresh_secs;
+        let org_ids: Vec<uuid::Uuid> = std::iter::once(services::org_service::DEFAULT_ORG_ID)
+            .chain(state.org_ids.values().copied())
+            .collect();
+        tokio::spawn(async move {
+            loop {
+                for &org_id in &org_ids {
+                    if let Err(e) = services::log_service::refresh_dashboard_stats(
+                        org_id,
+                        refresh_secs,
+                        &stats_db,
+                        &mut stats_redis,
+                    )
+                    .await
+                    {
+                        tracing::error!("Dashboard stats refresh error: {}", e);
+                    }
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(refresh_secs)).await;
+            }
+        });
+    }
+
+    // Spawn background model route cache refresh so entries never outlive their TTL
+    if config.model_route_cache_ttl_secs > 0 {
+        let refresh_db = state.db.clone();
+        let mut refresh_redis = state.redis.clone();
+        let ttl_secs = config.model_route_cache_ttl_secs;
+        tokio::spawn(async move {
+            let interval = std::time::Duration::from_secs((ttl_secs / 2).max(1));
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = services::model_service::warm_up_model_routes(
+                    ttl_secs,
+                    &refresh_db,
+                    &mut refresh_redis,
+                )
+                .await
+                {
+                    tracing::error!("Model route cache refresh error: {}", e);
+                }
+            }
+        });
+    }
+
+    // Spawn background provider keep-alive so bursts after idle periods skip the TLS handshake
+    if config.provider_keepalive_secs > 0 {
+        let keepalive_db = state.db.clone();
+        let keepalive_client = state.http_client.clone();
+        let interval = std::time::Duration::from_secs(config.provider_keepalive_secs);
+        tokio::spawn(async move {
+            loop {
+                match services::provider_service::warm_provider_connections(
+                    &keepalive_client,
+                    &keepalive_db,
+                )
+                .await
+                {
+                    Ok(n) => tracing::debug!("Provider keep-alive reached {} base URLs", n),
+                    Err(e) => tracing::error!("Provider keep-alive error: {}", e),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    // Spawn background active-key reconciliation for keys changed directly in Postgres
+    if config.active_key_refresh_secs > 0 {
+        let refresh_db = state.db.clone();
+        let mut refresh_redis = state.redis.clone();
+        let interval = std::time::Duration::from_secs(config.active_key_refresh_secs);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                match services::key_service::reconcile_active_keys(&refresh_db, &mut refresh_redis)
+                    .await
+                {
+                    Ok((0, 0)) => {}
+                    Ok((added, removed)) => tracing::info!(
+                        "Active key set reconciled: {} hashes added, {} removed",
+                        added,
+                        removed
+                    ),
+                    Err(e) => tracing::error!("Active key set reconciliation error: {}", e),
+                }
+            }
+        });
+    }
+
+    // Spawn background usage flush; the final flush runs after graceful shutdown
+    if config.usage_flush_interval_ms > 0 {
+        let flush_db = state.db.clone();
+        let usage = state.usage.clone();
+        let interval = std::time::Duration::from_millis(config.usage_flush_interval_ms);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = usage.flush(&flush_db).await {
+                    tracing::error!("Token usage flush error, retrying next interval: {}", e);
+                }
+            }
+        });
+    }
+    let shutdown_db = state.db.clone();
+    let shutdown_usage = state.usage.clone();
+
     // Build routes
     let admin_routes = routes::admin::router()
         .route_layer(axum_mw::from_fn_with_state(
@@ -119,22 +360,107 @@ async fn main() -> anyhow::Result<()> {
             axum::http::Method::DELETE,
             axum::http::Method::OPTIONS,
         ])
-        .allow_headers([
-            axum::http::header::CONTENT_TYPE,
-            axum::http::header::AUTHORIZATION,
-        ]);
+        .allow_headers(
+            [
+                axum::http::header::CONTENT_TYPE,
+                axum::http::header::AUTHORIZATION,
+            ]
+            .into_iter()
+            // Alternate user key headers (validated at config load)
+            .chain(
+                config
+                    .user_key_headers
+                    .iter()
+                    .filter_map(|name| name.parse().ok()),
+            )
+            .collect::<Vec<axum::http::HeaderName>>(),
+        );
 
     let app = Router::new()
+        .merge(routes::health::router())
         .nest("/admin", admin_routes)
         .nest("/v1", proxy_routes)
+        .fallback(routes::not_found)
+        .method_not_allowed_fallback(routes::method_not_allowed)
         .layer(cors)
         .layer(TraceLayer::new_for_http())
         .with_state(state);
 
-    // Start server
-    let listener = TcpListener::bind(&config.listen_addr).await?;
-    tracing::info!("Listening on {}", config.listen_addr);
-    axum::serve(listener, app).await?;
+    // Start server: `unix:/path` listens on a Unix domain socket, anything else is TCP
+    match config.listen_addr.strip_prefix("unix:") {
+        Some(path) => {
+            remove_stale_socket(path)?;
+            let listener = UnixListener::bind(path)?;
+            tracing::info!("Listening on Unix socket {}", path);
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await?;
+            if let Err(e) = std::fs::remove_file(path) {
+                tracing::warn!("Failed to remove socket {}: {}", path, e);
+            }
+        }
+        None => {
+            let listener = TcpListener::bind(&config.listen_addr).await?;
+            tracing::info!("Listening on {}", config.listen_addr);
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await?;
+        }
+    }
+
+    // Persist buffered token usage before exiting
+    match shutdown_usage.flush(&shutdown_db).await {
+        Ok(n) if n > 0 => tracing::info!("Flushed buffered token usage for {} keys", n),
+        Ok(_) => {}
+        Err(e) => tracing::error!("Final token usage flush failed: {}", e),
+    }
 
     Ok(())
 }
+
+/// Remove a socket file left behind by an unclean shutdown, which would make binding
+/// fail. Any other kind of file at the path is an error rather than deleted.
+fn remove_stale_socket(path: &str) -> anyhow::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            tracing::info!("Removing stale socket {}", path);
+            std::fs::remove_file(path)?;
+            Ok(())
+        }
+        Ok(_) => anyhow::bail!("LISTEN_ADDR {path} exists and is not a socket"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Resolves on Ctrl+C or SIGTERM so in-flight requests can finish.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutting down");
+}
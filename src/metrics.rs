@@ -0,0 +1,307 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+/// In-flight upstream request counters, one per provider.
+///
+/// A request counts as in flight from the moment it is sent upstream until the
+/// upstream response body has been fully forwarded to the client (or the client
+/// disconnects). Post-stream work in the shadow logging task is not counted, since
+/// it no longer holds a provider connection.
+#[derive(Debug, Default)]
+pub struct ProviderInflight {
+    counters: Mutex<HashMap<Uuid, Arc<AtomicI64>>>,
+}
+
+impl ProviderInflight {
+    /// Increment the counter for `provider_id` and return a guard that
+    /// decrements it again when dropped.
+    pub fn acquire(&self, provider_id: Uuid) -> InflightGuard {
+        let counter = self
+            .counters
+            .lock()
+            .unwrap()
+            .entry(provider_id)
+            .or_default()
+            .clone();
+        let current = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        InflightGuard { counter, current }
+    }
+
+    /// Snapshot of the current in-flight count per provider.
+    pub fn snapshot(&self) -> HashMap<Uuid, i64> {
+        self.counters
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, c)| (*id, c.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// RAII guard for one in-flight upstream request.
+#[derive(Debug)]
+pub struct InflightGuard {
+    counter: Arc<AtomicI64>,
+    current: i64,
+}
+
+impl InflightGuard {
+    /// In-flight count for the provider right after this request was added.
+    pub fn current(&self) -> i64 {
+        self.current
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Upper bound on outcomes kept per provider, regardless of the window length.
+const MAX_HEALTH_SAMPLES: usize = 1000;
+
+/// Rolling upstream success rate per provider over a fixed time window.
+///
+/// A request counts as failed when the provider could not be reached or answered
+/// with 429 / 5xx; client errors (other 4xx) are not the provider's fault.
+#[derive(Debug)]
+pub struct ProviderHealth {
+    window: Duration,
+    samples: Mutex<HashMap<Uuid, VecDeque<(Instant, bool)>>>,
+}
+
+impl ProviderHealth {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record the outcome of one upstream request.
+    pub fn record(&self, provider_id: Uuid, success: bool) {
+        let now = Instant::now();
+        let mut samples = self.samples.lock().unwrap();
+        let entry = samples.entry(provider_id).or_default();
+        entry.push_back((now, success));
+        if entry.len() > MAX_HEALTH_SAMPLES {
+            entry.pop_front();
+        }
+        Self::prune(entry, now, self.window);
+    }
+
+    /// Success rate within the window, 1.0 when there are no recent samples.
+    pub fn success_rate(&self, provider_id: Uuid) -> f64 {
+        let mut samples = self.samples.lock().unwrap();
+        match samples.get_mut(&provider_id) {
+            Some(entry) => {
+                Self::prune(entry, Instant::now(), self.window);
+                Self::rate(entry)
+            }
+            None => 1.0,
+        }
+    }
+
+    /// Snapshot of the current success rate per provider with recent samples.
+    pub fn snapshot(&self) -> HashMap<Uuid, f64> {
+        let now = Instant::now();
+        let mut samples = self.samples.lock().unwrap();
+        samples
+            .iter_mut()
+            .filter_map(|(id, entry)| {
+                Self::prune(entry, now, self.window);
+                (!entry.is_empty()).then(|| (*id, Self::rate(entry)))
+            })
+            .collect()
+    }
+
+    fn prune(entry: &mut VecDeque<(Instant, bool)>, now: Instant, window: Duration) {
+        while entry
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > window)
+        {
+            entry.pop_front();
+        }
+    }
+
+    fn rate(entry: &VecDeque<(Instant, bool)>) -> f64 {
+        if entry.is_empty() {
+            return 1.0;
+        }
+        let ok = entry.iter().filter(|(_, success)| *success).count();
+        ok as f64 / entry.len() as f64
+    }
+}
+
+/// Concurrent streaming responses, optionally capped by `MAX_STREAMS`.
+///
+/// A stream holds its slot from before it is sent upstream until the shadow logging
+/// task has finished with it, so the count covers every open client stream.
+#[derive(Debug)]
+pub struct StreamSlots {
+    /// None = unlimited; streams are still counted.
+    semaphore: Option<Arc<Semaphore>>,
+    active: Arc<AtomicI64>,
+}
+
+impl StreamSlots {
+    pub fn new(max_streams: Option<usize>) -> Self {
+        Self {
+            semaphore: max_streams.map(|max| Arc::new(Semaphore::new(max))),
+            active: Arc::new(AtomicI64::new(0)),
+        }
+    }
+
+    /// Take a slot, waiting up to `wait` for one to free up. None when the limit is
+    /// still reached afterwards.
+    pub async fn acquire(&self, wait: Duration) -> Option<StreamGuard> {
+        let permit = match &self.semaphore {
+            None => None,
+            Some(semaphore) if wait.is_zero() => Some(semaphore.clone().try_acquire_owned().ok()?),
+            Some(semaphore) => Some(
+                tokio::time::timeout(wait, semaphore.clone().acquire_owned())
+                    .await
+                    .ok()?
+                    .ok()?,
+            ),
+        };
+        self.active.fetch_add(1, Ordering::Relaxed);
+        Some(StreamGuard {
+            _permit: permit,
+            active: self.active.clone(),
+        })
+    }
+
+    /// Streams currently holding a slot.
+    pub fn active(&self) -> i64 {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+/// RAII guard for one streaming slot.
+#[derive(Debug)]
+pub struct StreamGuard {
+    _permit: Option<OwnedSemaphorePermit>,
+    active: Arc<AtomicI64>,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Rate-limit quota last reported by a provider in its `x-ratelimit-*` headers.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ProviderQuota {
+    pub limit_requests: Option<i64>,
+    pub remaining_requests: Option<i64>,
+    /// Provider-formatted time until the request quota resets, e.g. `"1s"`.
+    pub reset_requests: Option<String>,
+    pub limit_tokens: Option<i64>,
+    pub remaining_tokens: Option<i64>,
+    /// Provider-formatted time until the token quota resets, e.g. `"6m0s"`.
+    pub reset_tokens: Option<String>,
+    /// When these headers were received.
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ProviderQuota {
+    /// Parse the rate-limit headers of an upstream response; None when it has none.
+    pub fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        let text = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string())
+        };
+        let count = |name: &str| text(name).and_then(|v| v.parse::<i64>().ok());
+        let quota = Self {
+            limit_requests: count("x-ratelimit-limit-requests"),
+            remaining_requests: count("x-ratelimit-remaining-requests"),
+            reset_requests: text("x-ratelimit-reset-requests"),
+            limit_tokens: count("x-ratelimit-limit-tokens"),
+            remaining_tokens: count("x-ratelimit-remaining-tokens"),
+            reset_tokens: text("x-ratelimit-reset-tokens"),
+            updated_at: chrono::Utc::now(),
+        };
+        let reported = quota.limit_requests.is_some()
+            || quota.remaining_requests.is_some()
+            || quota.limit_tokens.is_some()
+            || quota.remaining_tokens.is_some();
+        reported.then_some(quota)
+    }
+}
+
+/// Latest rate-limit quota reported by each provider.
+#[derive(Debug, Default)]
+pub struct ProviderQuotas {
+    quotas: Mutex<HashMap<Uuid, ProviderQuota>>,
+}
+
+impl ProviderQuotas {
+    /// Record the quota headers of an upstream response, if it has any.
+    pub fn record(&self, provider_id: Uuid, headers: &reqwest::header::HeaderMap) {
+        if let Some(quota) = ProviderQuota::from_headers(headers) {
+            self.quotas.lock().unwrap().insert(provider_id, quota);
+        }
+    }
+
+    /// Snapshot of the latest quota per provider that reported one.
+    pub fn snapshot(&self) -> HashMap<Uuid, ProviderQuota> {
+        self.quotas.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn streams_past_the_limit_are_refused_until_a_slot_frees() {
+        let slots = StreamSlots::new(Some(2));
+        let first = slots.acquire(Duration::ZERO).await.unwrap();
+        let _second = slots.acquire(Duration::ZERO).await.unwrap();
+        assert_eq!(slots.active(), 2);
+
+        assert!(slots.acquire(Duration::ZERO).await.is_none());
+        assert!(slots.acquire(Duration::from_millis(20)).await.is_none());
+        assert_eq!(slots.active(), 2);
+
+        drop(first);
+        assert_eq!(slots.active(), 1);
+        assert!(slots.acquire(Duration::ZERO).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn a_waiting_stream_gets_the_next_free_slot() {
+        let slots = Arc::new(StreamSlots::new(Some(1)));
+        let held = slots.acquire(Duration::ZERO).await.unwrap();
+        let waiter = {
+            let slots = slots.clone();
+            tokio::spawn(async move { slots.acquire(Duration::from_secs(5)).await.is_some() })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(held);
+        assert!(waiter.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn unlimited_streams_are_still_counted() {
+        let slots = StreamSlots::new(None);
+        let guards: Vec<_> = futures::future::join_all(
+            (0..10).map(|_| slots.acquire(Duration::ZERO)),
+        )
+        .await;
+        assert!(guards.iter().all(Option::is_some));
+        assert_eq!(slots.active(), 10);
+        drop(guards);
+        assert_eq!(slots.active(), 0);
+    }
+}
@@ -2,14 +2,15 @@ use axum::{
     extract::{Request, State},
     http::{header, StatusCode},
     middleware::Next,
-    response::{IntoResponse, Response},
-    Json,
+    response::Response,
 };
-use serde_json::json;
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::config::AuthFailMode;
+use crate::error::{json_error, ErrorType};
 use crate::services::key_service;
+use crate::services::org_service::DEFAULT_ORG_ID;
 use crate::state::AppState;
 
 /// Identity of the authenticated user key, injected into request extensions.
@@ -17,8 +18,61 @@ use crate::state::AppState;
 pub struct KeyIdentity {
     pub key_id: Uuid,
     pub key_hash: String,
+    /// Organization the key belongs to; models resolve within it.
+    pub org_id: Uuid,
+    /// Key group; when set, the budget fields are the group's.
+    pub group_id: Option<Uuid>,
     pub token_budget: Option<i64>,
     pub tokens_used: i64,
+    /// Provider kinds this key may be routed to. None = all.
+    pub allowed_provider_kinds: Option<Vec<String>>,
+    /// Tokens per minute. None = unlimited.
+    pub tpm_limit: Option<i64>,
+    /// Provider every request of this key is routed to. None = normal routing.
+    pub provider_override_id: Option<Uuid>,
+    /// May send its own provider key per request (`x-provider-api-key`).
+    pub allow_byo_provider_key: bool,
+    /// Whether the key has per-model budgets to check.
+    pub has_model_budgets: bool,
+}
+
+impl KeyIdentity {
+    /// Stand-in identity for a request let through by `AUTH_FAIL_MODE=open`: the nil
+    /// key id in the default organization, with no budget, limits or restrictions.
+    fn unvalidated(token: &str) -> Self {
+        Self {
+            key_id: Uuid::nil(),
+            key_hash: key_service::hash_key(token),
+            org_id: DEFAULT_ORG_ID,
+            group_id: None,
+            token_budget: None,
+            tokens_used: 0,
+            allowed_provider_kinds: None,
+            tpm_limit: None,
+            provider_override_id: None,
+            allow_byo_provider_key: false,
+            has_model_budgets: false,
+        }
+    }
+}
+
+/// Identity of the authenticated admin, injected into request extensions.
+#[derive(Debug, Clone)]
+pub struct AdminIdentity {
+    /// Organization this admin manages.
+    pub org_id: Uuid,
+    /// Tag this admin is limited to. None = full admin.
+    pub scope: Option<String>,
+}
+
+impl AdminIdentity {
+    /// Whether this admin may manage a key with the given tag.
+    pub fn can_manage(&self, tag: Option<&str>) -> bool {
+        match &self.scope {
+            None => true,
+            Some(scope) => tag == Some(scope.as_str()),
+        }
+    }
 }
 
 /// Extract a Bearer token from the Authorization header.
@@ -30,29 +84,111 @@ fn extract_bearer(req: &Request) -> Option<&str> {
         .strip_prefix("Bearer ")
 }
 
-/// Middleware that validates the Admin Key from env config.
+/// Extract a user key from the first configured header present on the request.
+/// `authorization` must carry a Bearer token; other headers carry the bare key.
+fn extract_user_key<'a>(req: &'a Request, header_names: &[String]) -> Option<&'a str> {
+    let (name, value) = header_names
+        .iter()
+        .find_map(|name| req.headers().get(name.as_str()).map(|v| (name, v)))?;
+    let value = value.to_str().ok()?;
+    if name == header::AUTHORIZATION.as_str() {
+        value.strip_prefix("Bearer ")
+    } else {
+        Some(value.trim()).filter(|v| !v.is_empty())
+    }
+}
+
+/// Middleware that validates the Admin Key (or a scoped admin key) from env config.
 pub async fn admin_auth(
     State(state): State<Arc<AppState>>,
-    req: Request,
+    mut req: Request,
     next: Next,
 ) -> Response {
     let token = match extract_bearer(&req) {
         Some(t) => t,
         None => {
-            return (
+            return json_error(
                 StatusCode::UNAUTHORIZED,
-                Json(json!({ "error": { "message": "Missing Authorization header" } })),
+                ErrorType::Authentication,
+                "Missing Authorization header",
+                Some("missing_api_key"),
             )
-                .into_response()
         }
     };
 
-    if token != state.config.admin_key {
-        return (
+    let org_admin = state
+        .config
+        .org_admin_keys
+        .iter()
+        .find(|k| k.key == token)
+        .and_then(|k| state.org_ids.get(&k.org));
+
+    let identity = if token == state.config.admin_key {
+        AdminIdentity {
+            org_id: DEFAULT_ORG_ID,
+            scope: None,
+        }
+    } else if let Some(&org_id) = org_admin {
+        AdminIdentity {
+            org_id,
+            scope: None,
+        }
+    } else if let Some(scoped) = state
+        .config
+        .admin_scoped_keys
+        .iter()
+        .find(|s| s.key == token)
+    {
+        AdminIdentity {
+            org_id: DEFAULT_ORG_ID,
+            scope: Some(scoped.tag.clone()),
+        }
+    } else {
+        return json_error(
             StatusCode::UNAUTHORIZED,
-            Json(json!({ "error": { "message": "Invalid admin key" } })),
-        )
-            .into_response();
+            ErrorType::Authentication,
+            "Invalid admin key",
+            Some("invalid_api_key"),
+        );
+    };
+
+    req.extensions_mut().insert(identity);
+    next.run(req).await
+}
+
+/// Middleware for admin routes outside key management: rejects scoped admins.
+/// Must run inside `admin_auth`.
+pub async fn require_full_admin(req: Request, next: Next) -> Response {
+    let scoped = req
+        .extensions()
+        .get::<AdminIdentity>()
+        .is_none_or(|identity| identity.scope.is_some());
+    if scoped {
+        return json_error(
+            StatusCode::FORBIDDEN,
+            ErrorType::Permission,
+            "This admin key is limited to managing its own keys",
+            Some("admin_scope"),
+        );
+    }
+
+    next.run(req).await
+}
+
+/// Middleware for instance-wide admin routes (process metrics, config, key cache):
+/// only the full admin of the default organization passes. Must run inside `admin_auth`.
+pub async fn require_instance_admin(req: Request, next: Next) -> Response {
+    let allowed = req
+        .extensions()
+        .get::<AdminIdentity>()
+        .is_some_and(|identity| identity.scope.is_none() && identity.org_id == DEFAULT_ORG_ID);
+    if !allowed {
+        return json_error(
+            StatusCode::FORBIDDEN,
+            ErrorType::Permission,
+            "This endpoint covers every organization and needs the ADMIN_KEY token",
+            Some("admin_scope"),
+        );
     }
 
     next.run(req).await
@@ -64,14 +200,22 @@ pub async fn user_key_auth(
     req: Request,
     next: Next,
 ) -> Response {
-    let token = match extract_bearer(&req) {
+    let header_names = &state.config.user_key_headers;
+    let token = match extract_user_key(&req, header_names) {
         Some(t) => t.to_string(),
         None => {
-            return (
+            let message = match header_names.as_slice() {
+                [only] if only == header::AUTHORIZATION.as_str() => {
+                    "Missing Authorization header".to_string()
+                }
+                names => format!("Missing API key; send one of: {}", names.join(", ")),
+            };
+            return json_error(
                 StatusCode::UNAUTHORIZED,
-                Json(json!({ "error": { "message": "Missing Authorization header" } })),
-            )
-                .into_response()
+                ErrorType::Authentication,
+                message,
+                Some("missing_api_key"),
+            );
         }
     };
 
@@ -82,23 +226,94 @@ pub async fn user_key_auth(
             req.extensions_mut().insert(KeyIdentity {
                 key_id: v.key_id,
                 key_hash: v.key_hash,
+                org_id: v.org_id,
+                group_id: v.group_id,
                 token_budget: v.token_budget,
-                tokens_used: v.tokens_used,
+                tokens_used: v.tokens_used + state.usage.pending(v.key_id, v.group_id),
+                allowed_provider_kinds: v.allowed_provider_kinds,
+                tpm_limit: v.tpm_limit,
+                provider_override_id: v.provider_override_id,
+                allow_byo_provider_key: v.allow_byo_provider_key,
+                has_model_budgets: v.has_model_budgets,
             });
             next.run(req).await
         }
-        Ok(None) => (
+        Ok(None) => json_error(
             StatusCode::UNAUTHORIZED,
-            Json(json!({ "error": { "message": "Invalid API key" } })),
-        )
-            .into_response(),
+            ErrorType::Authentication,
+            "Invalid API key",
+            Some("invalid_api_key"),
+        ),
+        Err(e) if state.config.auth_fail_mode == AuthFailMode::Open => {
+            tracing::error!(
+                "Key validation error, letting the request through unvalidated \
+                 (AUTH_FAIL_MODE=open): {}",
+                e
+            );
+            let mut req = req;
+            req.extensions_mut().insert(KeyIdentity::unvalidated(&token));
+            next.run(req).await
+        }
         Err(e) => {
             tracing::error!("Key validation error: {}", e);
-            (
+            json_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": { "message": "Internal server error" } })),
+                ErrorType::Api,
+                "Internal server error",
+                None,
             )
-                .into_response()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        let mut builder = Request::builder().uri("/v1/chat/completions");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(axum::body::Body::empty()).unwrap()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn authorization_needs_a_bearer_token() {
+        let headers = names(&["authorization"]);
+        let req = request(&[("authorization", "Bearer sk-abc")]);
+        assert_eq!(extract_user_key(&req, &headers), Some("sk-abc"));
+        let req = request(&[("authorization", "sk-abc")]);
+        assert_eq!(extract_user_key(&req, &headers), None);
+    }
+
+    #[test]
+    fn alternate_headers_carry_the_bare_key() {
+        let headers = names(&["authorization", "x-api-key", "api-key"]);
+        let req = request(&[("x-api-key", "sk-from-x-api-key")]);
+        assert_eq!(extract_user_key(&req, &headers), Some("sk-from-x-api-key"));
+        let req = request(&[("api-key", " sk-from-api-key ")]);
+        assert_eq!(extract_user_key(&req, &headers), Some("sk-from-api-key"));
+        let req = request(&[("api-key", "")]);
+        assert_eq!(extract_user_key(&req, &headers), None);
+    }
+
+    #[test]
+    fn first_configured_header_present_wins() {
+        let req = request(&[("authorization", "Bearer sk-bearer"), ("x-api-key", "sk-x")]);
+        let key = extract_user_key(&req, &names(&["x-api-key", "authorization"]));
+        assert_eq!(key, Some("sk-x"));
+        let key = extract_user_key(&req, &names(&["authorization", "x-api-key"]));
+        assert_eq!(key, Some("sk-bearer"));
+    }
+
+    #[test]
+    fn unconfigured_headers_are_ignored() {
+        let req = request(&[("x-api-key", "sk-x")]);
+        assert_eq!(extract_user_key(&req, &names(&["authorization"])), None);
+    }
+}
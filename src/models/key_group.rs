@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A pool of tokens shared by its member keys.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct KeyGroupInfo {
+    pub id: Uuid,
+    pub name: String,
+    /// Shared token budget. None = unlimited.
+    pub token_budget: Option<i64>,
+    /// Weighted tokens used by all member keys since the last reset.
+    pub tokens_used: i64,
+    /// Number of active keys in the group.
+    pub key_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -1,4 +1,6 @@
+pub mod key_group;
 pub mod model;
 pub mod provider;
 pub mod request_log;
+pub mod usage;
 pub mod user_key;
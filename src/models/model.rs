@@ -13,8 +13,17 @@ pub struct Model {
     pub is_active: bool,
     pub input_token_coefficient: f64,
     pub output_token_coefficient: f64,
+    pub input_price_per_mtok: Option<f64>,
+    pub output_price_per_mtok: Option<f64>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub weight: i32,
+    pub reasoning_token_coefficient: Option<f64>,
+    pub fallback_response: Option<serde_json::Value>,
+    pub default_max_tokens: Option<i32>,
+    pub cached_token_coefficient: Option<f64>,
+    pub prompt_caching: bool,
+    pub hedge_after_ms: Option<i32>,
 }
 
 /// Public info returned by list/get.
@@ -28,10 +37,42 @@ pub struct ModelInfo {
     pub is_active: bool,
     pub input_token_coefficient: f64,
     pub output_token_coefficient: f64,
+    /// Coefficient for reasoning tokens (None = output coefficient)
+    pub reasoning_token_coefficient: Option<f64>,
+    /// Coefficient for cached prompt tokens (None = input coefficient)
+    pub cached_token_coefficient: Option<f64>,
+    /// USD per million prompt tokens (None = unpriced)
+    pub input_price_per_mtok: Option<f64>,
+    /// USD per million completion tokens (None = unpriced)
+    pub output_price_per_mtok: Option<f64>,
+    /// Routing weight of the primary provider relative to alternate routes
+    pub weight: i32,
+    /// Completion returned when every route fails (None = 502)
+    pub fallback_response: Option<serde_json::Value>,
+    /// `max_tokens` injected when the client sets no cap (None = `DEFAULT_MAX_TOKENS`)
+    pub default_max_tokens: Option<i32>,
+    /// Add `cache_control` markers to the system prompt on supporting providers
+    pub prompt_caching: bool,
+    /// Milliseconds without a response before a non-streaming request is also sent to
+    /// another route (None = no hedging)
+    pub hedge_after_ms: Option<i32>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// An additional provider a model can be routed to.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct AlternateRouteInfo {
+    pub id: Uuid,
+    pub model_id: Uuid,
+    pub provider_id: Uuid,
+    pub provider_name: String,
+    /// Provider-side model name (None = same as the model's)
+    pub provider_model_name: Option<String>,
+    pub weight: i32,
+    pub created_at: DateTime<Utc>,
+}
+
 /// The resolved routing information for a model — used by the proxy.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelRoute {
@@ -49,4 +90,147 @@ pub struct ModelRoute {
     pub input_token_coefficient: f64,
     /// Output (completion) token cost coefficient (default 1.0)
     pub output_token_coefficient: f64,
+    /// Reasoning token cost coefficient (None = output coefficient)
+    #[serde(default)]
+    pub reasoning_token_coefficient: Option<f64>,
+    /// Cached prompt token cost coefficient (None = input coefficient)
+    #[serde(default)]
+    pub cached_token_coefficient: Option<f64>,
+    /// USD per million prompt tokens (None = unpriced)
+    #[serde(default)]
+    pub input_price_per_mtok: Option<f64>,
+    /// USD per million completion tokens (None = unpriced)
+    #[serde(default)]
+    pub output_price_per_mtok: Option<f64>,
+    /// Send streaming requests upstream buffered and re-emit them as SSE
+    #[serde(default)]
+    pub force_buffered_upstream: bool,
+    /// Inject `stream_options.include_usage` into streaming requests
+    #[serde(default = "default_true")]
+    pub supports_stream_usage: bool,
+    /// Routing weight among this model's candidate routes
+    #[serde(default = "default_weight")]
+    pub weight: i32,
+    /// Upstream statuses that trigger failover to another candidate. None = 429 and 5xx
+    #[serde(default)]
+    pub retry_on_status: Option<Vec<i32>>,
+    /// Canned completion returned when every candidate route fails (None = 502)
+    #[serde(default)]
+    pub fallback_response: Option<serde_json::Value>,
+    /// `max_tokens` injected when the client sets no cap (None = `DEFAULT_MAX_TOKENS`)
+    #[serde(default)]
+    pub default_max_tokens: Option<i32>,
+    /// Add `cache_control` markers to the system prompt (openrouter / dashscope only)
+    #[serde(default)]
+    pub prompt_caching: bool,
+    /// Delay before a non-streaming request is hedged to another route (None = never)
+    #[serde(default)]
+    pub hedge_after_ms: Option<i32>,
+    /// Region tag of the provider, matched against `x-gateway-region`
+    #[serde(default)]
+    pub provider_region: Option<String>,
+    /// Chat completions path appended to `base_url` (None = `/chat/completions`)
+    #[serde(default)]
+    pub chat_path: Option<String>,
+    /// Other providers this model can be routed to (primary route only)
+    #[serde(default)]
+    pub alternates: Vec<ModelRoute>,
+}
+
+impl ModelRoute {
+    /// Upstream chat completions URL.
+    pub fn chat_url(&self) -> String {
+        let path = self
+            .chat_path
+            .as_deref()
+            .unwrap_or(crate::models::provider::DEFAULT_CHAT_PATH);
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Budget cost of a response's usage. Reasoning tokens are part of the
+    /// completion tokens and are re-weighted with the reasoning coefficient;
+    /// cached tokens are part of the prompt tokens and use the cached coefficient.
+    pub fn weighted_tokens(
+        &self,
+        prompt: i32,
+        completion: i32,
+        reasoning: i32,
+        cached: i32,
+    ) -> i64 {
+        let reasoning = reasoning.clamp(0, completion.max(0)) as f64;
+        let cached = cached.clamp(0, prompt.max(0)) as f64;
+        let reasoning_coeff = self
+            .reasoning_token_coefficient
+            .unwrap_or(self.output_token_coefficient);
+        let cached_coeff = self
+            .cached_token_coefficient
+            .unwrap_or(self.input_token_coefficient);
+        ((prompt as f64 - cached) * self.input_token_coefficient
+            + cached * cached_coeff
+            + (completion as f64 - reasoning) * self.output_token_coefficient
+            + reasoning * reasoning_coeff)
+            .round() as i64
+    }
+
+    /// The primary route followed by its alternates, as standalone candidates.
+    pub fn into_candidates(mut self) -> Vec<ModelRoute> {
+        let alternates = std::mem::take(&mut self.alternates);
+        std::iter::once(self).chain(alternates).collect()
+    }
+}
+
+fn default_weight() -> i32 {
+    1
+}
+
+pub(crate) fn default_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(reasoning: Option<f64>, cached: Option<f64>) -> ModelRoute {
+        serde_json::from_value(serde_json::json!({
+            "provider_id": Uuid::nil(),
+            "provider_model_name": "o3",
+            "base_url": "https://api.openai.com/v1",
+            "api_key": "sk-test",
+            "provider_kind": "openai",
+            "input_token_coefficient": 1.0,
+            "output_token_coefficient": 2.0,
+            "reasoning_token_coefficient": reasoning,
+            "cached_token_coefficient": cached,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reasoning_tokens_are_weighted_with_their_own_coefficient() {
+        // 10 visible completion tokens at 2.0, 40 reasoning tokens at 4.0
+        assert_eq!(route(Some(4.0), None).weighted_tokens(100, 50, 40, 0), 100 + 20 + 160);
+        // Without a reasoning coefficient they cost as much as other completion tokens
+        assert_eq!(route(None, None).weighted_tokens(100, 50, 40, 0), 100 + 100);
+    }
+
+    #[test]
+    fn cached_prompt_tokens_are_weighted_with_their_own_coefficient() {
+        assert_eq!(route(None, Some(0.25)).weighted_tokens(100, 0, 0, 80), 20 + 20);
+        // Counts above their total are clamped
+        assert_eq!(route(Some(4.0), None).weighted_tokens(0, 10, 99, 0), 40);
+    }
+
+    #[test]
+    fn chat_url_uses_the_provider_chat_path() {
+        let mut route = route(None, None);
+        assert_eq!(route.chat_url(), "https://api.openai.com/v1/chat/completions");
+
+        route.base_url = "https://llm.internal.example".into();
+        route.chat_path = Some("/openai/deployments/gpt-4o/chat?api-version=2024-06-01".into());
+        assert_eq!(
+            route.chat_url(),
+            "https://llm.internal.example/openai/deployments/gpt-4o/chat?api-version=2024-06-01"
+        );
+    }
 }
@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+/// Chat completions path appended to a provider's base URL unless it sets `chat_path`.
+pub const DEFAULT_CHAT_PATH: &str = "/chat/completions";
+
 /// Supported provider kinds.
 /// All use OpenAI-compatible chat completions format, but differ in base URL and headers.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -12,6 +15,9 @@ pub enum ProviderKind {
     OpenRouter,
     DashScope,
     Ark,
+    /// Any other OpenAI-compatible API (Groq, Together, vLLM, ...): Bearer auth and no
+    /// vendor-specific headers. Has no default base URL.
+    OpenAICompatible,
 }
 
 impl ProviderKind {
@@ -21,6 +27,7 @@ impl ProviderKind {
             ProviderKind::OpenRouter => "openrouter",
             ProviderKind::DashScope => "dashscope",
             ProviderKind::Ark => "ark",
+            ProviderKind::OpenAICompatible => "openai_compatible",
         }
     }
 
@@ -30,17 +37,54 @@ impl ProviderKind {
             "openrouter" => Some(ProviderKind::OpenRouter),
             "dashscope" => Some(ProviderKind::DashScope),
             "ark" => Some(ProviderKind::Ark),
+            "openai_compatible" | "custom" => Some(ProviderKind::OpenAICompatible),
             _ => None,
         }
     }
 
-    /// Default base URL for each provider kind.
-    pub fn default_base_url(&self) -> &'static str {
+    /// Default base URL for each provider kind (None = must be given explicitly).
+    pub fn default_base_url(&self) -> Option<&'static str> {
         match self {
-            ProviderKind::OpenAI => "https://api.openai.com/v1",
-            ProviderKind::OpenRouter => "https://openrouter.ai/api/v1",
-            ProviderKind::DashScope => "https://dashscope.aliyuncs.com/compatible-mode/v1",
-            ProviderKind::Ark => "https://ark.cn-beijing.volces.com/api/v3",
+            ProviderKind::OpenAI => Some("https://api.openai.com/v1"),
+            ProviderKind::OpenRouter => Some("https://openrouter.ai/api/v1"),
+            ProviderKind::DashScope => Some("https://dashscope.aliyuncs.com/compatible-mode/v1"),
+            ProviderKind::Ark => Some("https://ark.cn-beijing.volces.com/api/v3"),
+            ProviderKind::OpenAICompatible => None,
+        }
+    }
+}
+
+/// Case applied to provider-side model names before they are sent upstream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelNameCase {
+    None,
+    Lower,
+    Upper,
+}
+
+impl ModelNameCase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModelNameCase::None => "none",
+            ModelNameCase::Lower => "lower",
+            ModelNameCase::Upper => "upper",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Some(ModelNameCase::None),
+            "lower" => Some(ModelNameCase::Lower),
+            "upper" => Some(ModelNameCase::Upper),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, model_name: &str) -> String {
+        match self {
+            ModelNameCase::None => model_name.to_string(),
+            ModelNameCase::Lower => model_name.to_lowercase(),
+            ModelNameCase::Upper => model_name.to_uppercase(),
         }
     }
 }
@@ -55,6 +99,14 @@ pub struct Provider {
     pub is_active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub force_buffered_upstream: bool,
+    pub supports_stream_usage: bool,
+    pub retry_on_status: Option<Vec<i32>>,
+    pub normalize_model_case: String,
+    pub region: Option<String>,
+    pub chat_path: Option<String>,
+    pub passthrough: bool,
+    pub passthrough_prefix: Option<String>,
 }
 
 /// Public info returned by list/get — never exposes the full api_key.
@@ -66,6 +118,23 @@ pub struct ProviderInfo {
     pub base_url: String,
     pub api_key_preview: String,
     pub is_active: bool,
+    /// Stream requests are sent upstream buffered and re-emitted as SSE.
+    pub force_buffered_upstream: bool,
+    /// Streaming requests get `stream_options.include_usage` injected.
+    pub supports_stream_usage: bool,
+    /// Statuses that trigger failover to another route. None = 429 and 5xx.
+    pub retry_on_status: Option<Vec<i32>>,
+    /// Case applied to model names sent upstream: "none" | "lower" | "upper".
+    pub normalize_model_case: String,
+    /// Region tag matched against the `x-gateway-region` request header.
+    pub region: Option<String>,
+    /// Path appended to `base_url` for chat completions (None = `/chat/completions`).
+    pub chat_path: Option<String>,
+    /// Unregistered model names are forwarded to this provider as-is.
+    pub passthrough: bool,
+    /// Only names starting with this prefix pass through (stripped before sending).
+    /// None = any name not taken by a prefixed passthrough provider.
+    pub passthrough_prefix: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -84,8 +153,30 @@ impl From<Provider> for ProviderInfo {
             base_url: p.base_url,
             api_key_preview: preview,
             is_active: p.is_active,
+            force_buffered_upstream: p.force_buffered_upstream,
+            supports_stream_usage: p.supports_stream_usage,
+            retry_on_status: p.retry_on_status,
+            normalize_model_case: p.normalize_model_case,
+            region: p.region,
+            chat_path: p.chat_path,
+            passthrough: p.passthrough,
+            passthrough_prefix: p.passthrough_prefix,
             created_at: p.created_at,
             updated_at: p.updated_at,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_name_case_is_applied_as_configured() {
+        assert_eq!(ModelNameCase::None.apply("GPT-4o"), "GPT-4o");
+        assert_eq!(ModelNameCase::Lower.apply("GPT-4o"), "gpt-4o");
+        assert_eq!(ModelNameCase::Upper.apply("gpt-4o"), "GPT-4O");
+        assert_eq!(ModelNameCase::from_str("LOWER"), Some(ModelNameCase::Lower));
+        assert_eq!(ModelNameCase::from_str("title"), None);
+    }
+}
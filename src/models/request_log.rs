@@ -9,6 +9,7 @@ use uuid::Uuid;
 pub struct RequestLog {
     pub id: Uuid,
     pub request_id: Option<String>,
+    pub upstream_request_id: Option<String>,
     pub user_key_id: Option<Uuid>,
     pub user_key_hash: String,
     pub model_requested: String,
@@ -26,13 +27,39 @@ pub struct RequestLog {
     pub response_body: Option<serde_json::Value>,
     pub error_message: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// False when the client opted out of body storage (`x-gateway-no-store`).
+    pub stored: bool,
+    /// Error worth retrying (429 / 5xx); false for client errors and successes.
+    pub retriable: bool,
+    pub reasoning_tokens: Option<i32>,
+    pub cached_prompt_tokens: Option<i32>,
+    /// Answered with the model's fallback response after every route failed.
+    pub is_fallback: bool,
+    pub finish_reason: Option<String>,
+    /// Token counts were estimated by the gateway rather than reported by the provider.
+    pub tokens_estimated: bool,
+    /// "gateway" or "upstream" for failed requests.
+    pub error_source: Option<String>,
+    /// Sent with the client's own provider key (`x-provider-api-key`).
+    pub byo_provider_key: bool,
+    /// Encoding estimated token counts stand in for, e.g. `o200k_base`.
+    pub tokenizer: Option<String>,
+    /// Routes the request was sent to, failovers and hedges included.
+    pub attempts: i32,
+    /// Redacted incoming headers (`LOG_REQUEST_HEADERS`).
+    pub request_headers: Option<serde_json::Value>,
+    /// Object key of bodies kept in external storage (`LOG_BODY_STORE=s3`).
+    pub body_key: Option<String>,
 }
 
 /// Public info returned by the admin logs listing API.
 #[derive(Debug, Serialize)]
 pub struct RequestLogInfo {
     pub id: Uuid,
+    /// Gateway-generated id (`x-gateway-request-id`).
     pub request_id: Option<String>,
+    /// The provider's `x-request-id`, when it sent one.
+    pub upstream_request_id: Option<String>,
     pub user_key_id: Option<Uuid>,
     pub model_requested: String,
     pub model_sent: String,
@@ -43,14 +70,49 @@ pub struct RequestLogInfo {
     pub prompt_tokens: Option<i32>,
     pub completion_tokens: Option<i32>,
     pub total_tokens: Option<i32>,
+    /// Reasoning tokens, already included in `completion_tokens`.
+    pub reasoning_tokens: Option<i32>,
+    /// Prompt tokens served from the provider's cache, included in `prompt_tokens`.
+    pub cached_prompt_tokens: Option<i32>,
     /// Token usage weighted by model input/output coefficients.
     pub weighted_total_tokens: Option<i64>,
     pub latency_ms: i32,
     pub is_stream: bool,
     pub request_body: Option<serde_json::Value>,
     pub response_body: Option<serde_json::Value>,
+    /// Object key of bodies kept in external storage. Listings leave the bodies
+    /// null for these; `GET /admin/logs/{id}` loads them.
+    pub body_key: Option<String>,
     pub error_message: Option<String>,
+    /// Side that produced the error: "gateway" (request rejected before or instead of
+    /// reaching a provider) or "upstream" (provider failure). None for successes.
+    pub error_source: Option<String>,
+    /// `finish_reason` of the first choice (e.g. "stop", "length", "content_filter").
+    pub finish_reason: Option<String>,
+    /// Token counts were estimated by the gateway (no usage reported, or the client
+    /// disconnected mid-stream) rather than reported by the provider.
+    pub tokens_estimated: bool,
+    /// Sent with the client's own provider key (`x-provider-api-key`) instead of the
+    /// provider's configured one.
+    pub byo_provider_key: bool,
+    /// Encoding of the model estimated token counts stand in for (`chars/4` when
+    /// unmapped); null when the provider reported usage.
+    pub tokenizer: Option<String>,
+    /// Routes the request was sent to, counting failovers and hedges
+    /// (`MAX_FAILOVER_ATTEMPTS`); 0 when the gateway rejected it before sending.
+    pub attempts: i32,
+    /// Incoming headers without credentials (`LOG_REQUEST_HEADERS`); null when not
+    /// logged.
+    pub request_headers: Option<serde_json::Value>,
     pub created_at: DateTime<Utc>,
+    /// False when the client opted out of body storage (`x-gateway-no-store`).
+    pub stored: bool,
+    /// Error worth retrying (429 / 5xx); false for client errors and successes.
+    pub retriable: bool,
+    /// Answered with the model's fallback response after every route failed.
+    pub is_fallback: bool,
+    /// Current metadata of the user key (joined at query time, not stored per log).
+    pub key_metadata: Option<serde_json::Value>,
 }
 
 impl From<RequestLog> for RequestLogInfo {
@@ -58,6 +120,7 @@ impl From<RequestLog> for RequestLogInfo {
         Self {
             id: r.id,
             request_id: r.request_id,
+            upstream_request_id: r.upstream_request_id,
             user_key_id: r.user_key_id,
             model_requested: r.model_requested,
             model_sent: r.model_sent,
@@ -68,13 +131,27 @@ impl From<RequestLog> for RequestLogInfo {
             prompt_tokens: r.prompt_tokens,
             completion_tokens: r.completion_tokens,
             total_tokens: r.total_tokens,
+            reasoning_tokens: r.reasoning_tokens,
+            cached_prompt_tokens: r.cached_prompt_tokens,
             weighted_total_tokens: None,
             latency_ms: r.latency_ms,
             is_stream: r.is_stream,
             request_body: r.request_body,
             response_body: r.response_body,
+            body_key: r.body_key,
             error_message: r.error_message,
+            finish_reason: r.finish_reason,
+            tokens_estimated: r.tokens_estimated,
+            error_source: r.error_source,
+            byo_provider_key: r.byo_provider_key,
+            tokenizer: r.tokenizer,
+            attempts: r.attempts,
+            request_headers: r.request_headers,
             created_at: r.created_at,
+            stored: r.stored,
+            retriable: r.retriable,
+            is_fallback: r.is_fallback,
+            key_metadata: None,
         }
     }
 }
@@ -0,0 +1,241 @@
+/// Token counts reported by a provider, normalized across response shapes:
+/// OpenAI-style chat usage (`prompt_tokens` / `completion_tokens`, also used by
+/// DashScope and DeepSeek), and `input_tokens` / `output_tokens` usage as sent by
+/// the Responses API and Anthropic-compatible endpoints.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub prompt: Option<i32>,
+    pub completion: Option<i32>,
+    pub total: Option<i32>,
+    /// Reasoning tokens (part of `completion`)
+    pub reasoning: Option<i32>,
+    /// Prompt tokens served from the provider's cache (part of `prompt`)
+    pub cached: Option<i32>,
+}
+
+impl Usage {
+    /// Parse a `usage` object.
+    pub fn from_json(usage: &serde_json::Value) -> Self {
+        let count = |v: Option<&serde_json::Value>| v.and_then(|v| v.as_i64()).map(|v| v as i32);
+
+        // Anthropic counts cache reads and writes separately from `input_tokens`;
+        // the Responses API includes cached tokens in `input_tokens`
+        let cache_read = count(usage.get("cache_read_input_tokens"));
+        let cache_write = count(usage.get("cache_creation_input_tokens"));
+        let prompt = count(usage.get("prompt_tokens")).or_else(|| {
+            count(usage.get("input_tokens"))
+                .map(|input| input + cache_read.unwrap_or(0) + cache_write.unwrap_or(0))
+        });
+        let completion =
+            count(usage.get("completion_tokens")).or(count(usage.get("output_tokens")));
+        // Anthropic reports no total; OpenAI-style usage without one keeps None
+        let total = match count(usage.get("total_tokens")) {
+            Some(total) => Some(total),
+            None if usage.get("input_tokens").is_some() => {
+                prompt.zip(completion).map(|(p, c)| p + c)
+            }
+            None => None,
+        };
+
+        Self {
+            prompt,
+            completion,
+            total,
+            reasoning: count(usage.pointer("/completion_tokens_details/reasoning_tokens"))
+                .or(count(usage.pointer("/output_tokens_details/reasoning_tokens"))),
+            cached: count(usage.pointer("/prompt_tokens_details/cached_tokens"))
+                .or(count(usage.get("prompt_cache_hit_tokens")))
+                .or(count(usage.pointer("/input_tokens_details/cached_tokens")))
+                .or(cache_read),
+        }
+    }
+
+    /// Usage of a complete (non-streaming) response body; all counts None when it
+    /// has no `usage`.
+    pub fn from_response_json(body: &serde_json::Value) -> Self {
+        body.get("usage")
+            .filter(|u| !u.is_null())
+            .map(Self::from_json)
+            .unwrap_or_default()
+    }
+
+    /// Parse concatenated SSE bytes to extract `usage` from any `data:` event.
+    /// Scans all chunks and keeps the last value of each count (providers may place
+    /// usage on the final content chunk, a separate chunk, or both).
+    /// Returns the usage and the parsed events as a JSON array (None if there were none).
+    pub fn from_sse_buffer(buffer: &[u8]) -> (Self, Option<serde_json::Value>) {
+        let text = String::from_utf8_lossy(buffer);
+
+        let mut all_chunks: Vec<serde_json::Value> = Vec::new();
+        let mut usage = Self::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if let Some(data) = line.strip_prefix("data:") {
+                let data = data.trim();
+                if data == "[DONE]" {
+                    continue;
+                }
+                if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
+                    // Check for usage in this chunk (keep latest found)
+                    usage.merge(Self::from_response_json(&json));
+                    all_chunks.push(json);
+                }
+            }
+        }
+
+        // Build a response body from the chunks for storage
+        let response_body = if all_chunks.is_empty() {
+            None
+        } else {
+            Some(serde_json::Value::Array(all_chunks))
+        };
+
+        (usage, response_body)
+    }
+
+    /// Take every count `other` reports, keeping ours where it has none.
+    pub fn merge(&mut self, other: Usage) {
+        self.prompt = other.prompt.or(self.prompt);
+        self.completion = other.completion.or(self.completion);
+        self.total = other.total.or(self.total);
+        self.reasoning = other.reasoning.or(self.reasoning);
+        self.cached = other.cached.or(self.cached);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasoning_and_cached_tokens_come_from_usage_details() {
+        let usage = Usage::from_json(&serde_json::json!({
+            "prompt_tokens": 120,
+            "completion_tokens": 300,
+            "total_tokens": 420,
+            "prompt_tokens_details": { "cached_tokens": 100 },
+            "completion_tokens_details": { "reasoning_tokens": 256 },
+        }));
+        assert_eq!(usage.reasoning, Some(256));
+        assert_eq!(usage.cached, Some(100));
+        assert_eq!(usage.completion, Some(300));
+    }
+
+    #[test]
+    fn streamed_reasoning_usage_is_read_from_the_usage_chunk() {
+        let sse = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"42\"}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":10,\"completion_tokens\":50,",
+            "\"total_tokens\":60,\"completion_tokens_details\":{\"reasoning_tokens\":40}}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let (usage, _) = Usage::from_sse_buffer(sse.as_bytes());
+        assert_eq!(usage.completion, Some(50));
+        assert_eq!(usage.reasoning, Some(40));
+    }
+
+    #[test]
+    fn cached_prompt_tokens_from_a_response_body() {
+        let body = serde_json::json!({
+            "choices": [],
+            "usage": {
+                "prompt_tokens": 2006,
+                "completion_tokens": 300,
+                "total_tokens": 2306,
+                "prompt_tokens_details": { "cached_tokens": 1920 },
+            },
+        });
+        let usage = Usage::from_response_json(&body);
+        assert_eq!(usage.prompt, Some(2006));
+        assert_eq!(usage.cached, Some(1920));
+    }
+
+    #[test]
+    fn cached_prompt_tokens_from_a_stream() {
+        let sse = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"ok\"}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":2006,\"completion_tokens\":1,",
+            "\"total_tokens\":2007,\"prompt_tokens_details\":{\"cached_tokens\":1920}}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let (usage, _) = Usage::from_sse_buffer(sse.as_bytes());
+        assert_eq!(usage.cached, Some(1920));
+    }
+
+    #[test]
+    fn deepseek_cache_hit_tokens_count_as_cached() {
+        let usage = Usage::from_json(&serde_json::json!({
+            "prompt_tokens": 100,
+            "completion_tokens": 10,
+            "total_tokens": 110,
+            "prompt_cache_hit_tokens": 64,
+            "prompt_cache_miss_tokens": 36,
+        }));
+        assert_eq!(usage.cached, Some(64));
+    }
+
+    #[test]
+    fn openai_chat_usage() {
+        let body = serde_json::json!({
+            "object": "chat.completion",
+            "choices": [],
+            "usage": { "prompt_tokens": 19, "completion_tokens": 10, "total_tokens": 29 },
+        });
+        let usage = Usage::from_response_json(&body);
+        assert_eq!(usage.prompt, Some(19));
+        assert_eq!(usage.completion, Some(10));
+        assert_eq!(usage.total, Some(29));
+        assert_eq!(usage.reasoning, None);
+        assert_eq!(usage.cached, None);
+    }
+
+    #[test]
+    fn dashscope_stream_usage_on_the_last_chunk() {
+        // DashScope's compatible mode sends usage on a final chunk with no choices,
+        // and `usage: null` on the ones before it
+        let sse = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"你好\"}}],\"usage\":null}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}],",
+            "\"usage\":null}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":22,\"completion_tokens\":3,",
+            "\"total_tokens\":25,\"prompt_tokens_details\":{\"cached_tokens\":0}}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let (usage, chunks) = Usage::from_sse_buffer(sse.as_bytes());
+        assert_eq!(usage.prompt, Some(22));
+        assert_eq!(usage.completion, Some(3));
+        assert_eq!(usage.total, Some(25));
+        assert_eq!(usage.cached, Some(0));
+        assert_eq!(chunks.unwrap().as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn anthropic_usage_adds_cache_reads_and_writes_to_the_prompt() {
+        let usage = Usage::from_json(&serde_json::json!({
+            "input_tokens": 50,
+            "cache_creation_input_tokens": 200,
+            "cache_read_input_tokens": 1000,
+            "output_tokens": 80,
+        }));
+        assert_eq!(usage.prompt, Some(1250));
+        assert_eq!(usage.completion, Some(80));
+        assert_eq!(usage.total, Some(1330));
+        assert_eq!(usage.cached, Some(1000));
+    }
+
+    #[test]
+    fn responses_api_usage_keeps_cached_tokens_inside_the_input() {
+        let usage = Usage::from_json(&serde_json::json!({
+            "input_tokens": 300,
+            "input_tokens_details": { "cached_tokens": 256 },
+            "output_tokens": 40,
+            "output_tokens_details": { "reasoning_tokens": 32 },
+            "total_tokens": 340,
+        }));
+        assert_eq!(usage.prompt, Some(300));
+        assert_eq!(usage.cached, Some(256));
+        assert_eq!(usage.reasoning, Some(32));
+        assert_eq!(usage.total, Some(340));
+    }
+}
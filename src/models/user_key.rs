@@ -14,6 +14,13 @@ pub struct UserKey {
     pub tokens_used: i64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub allowed_provider_kinds: Option<Vec<String>>,
+    pub tag: Option<String>,
+    pub tpm_limit: Option<i64>,
+    pub metadata: Option<serde_json::Value>,
+    pub group_id: Option<Uuid>,
+    pub provider_override_id: Option<Uuid>,
+    pub allow_byo_provider_key: bool,
 }
 
 /// Response when listing keys — never exposes hash or full key
@@ -25,6 +32,22 @@ pub struct UserKeyInfo {
     pub is_active: bool,
     pub token_budget: Option<i64>,
     pub tokens_used: i64,
+    /// Provider kinds this key may be routed to. None = all.
+    pub allowed_provider_kinds: Option<Vec<String>>,
+    /// Grouping tag; scoped admin tokens only see keys with their tag.
+    pub tag: Option<String>,
+    /// Tokens per minute. None = unlimited.
+    pub tpm_limit: Option<i64>,
+    /// Arbitrary JSON object for bookkeeping (team, cost center, contact).
+    pub metadata: Option<serde_json::Value>,
+    /// Key group whose shared budget applies. None = the key's own budget.
+    pub group_id: Option<Uuid>,
+    /// Provider all of this key's requests are routed to. None = normal routing.
+    pub provider_override_id: Option<Uuid>,
+    /// May send its own provider key per request (`x-provider-api-key`).
+    pub allow_byo_provider_key: bool,
+    /// USD spend from retained request logs at current model prices (list endpoint only).
+    pub spend_usd: Option<f64>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -38,6 +61,14 @@ impl From<UserKey> for UserKeyInfo {
             is_active: k.is_active,
             token_budget: k.token_budget,
             tokens_used: k.tokens_used,
+            allowed_provider_kinds: k.allowed_provider_kinds,
+            tag: k.tag,
+            tpm_limit: k.tpm_limit,
+            metadata: k.metadata,
+            group_id: k.group_id,
+            provider_override_id: k.provider_override_id,
+            allow_byo_provider_key: k.allow_byo_provider_key,
+            spend_usd: None,
             created_at: k.created_at,
             updated_at: k.updated_at,
         }
@@ -53,3 +84,16 @@ pub struct UserKeyCreated {
     pub key_prefix: String,
     pub created_at: DateTime<Utc>,
 }
+
+/// A key's token budget for one model, on top of its overall budget.
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct KeyModelBudget {
+    pub key_id: Uuid,
+    /// Gateway model name the budget applies to.
+    pub model: String,
+    pub token_budget: i64,
+    /// Weighted tokens used on this model since the last reset.
+    pub tokens_used: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,77 @@
+use std::sync::OnceLock;
+
+use uuid::Uuid;
+
+/// Namespace used until `init` runs.
+const DEFAULT_NAMESPACE: &str = "gateway";
+
+static NAMESPACE: OnceLock<String> = OnceLock::new();
+
+/// Set the prefix of every gateway Redis key (`REDIS_NAMESPACE`). Call once at
+/// startup, before anything touches Redis; later calls are ignored.
+pub fn init(namespace: &str) {
+    let _ = NAMESPACE.set(namespace.to_string());
+}
+
+fn key(name: &str) -> String {
+    let namespace = NAMESPACE.get().map(String::as_str).unwrap_or(DEFAULT_NAMESPACE);
+    format!("{namespace}:{name}")
+}
+
+/// Set of the hashes of all active user keys.
+pub fn active_key_hashes() -> String {
+    key("active_key_hashes")
+}
+
+/// A key's token counter for the minute starting at `minute` (Unix minutes).
+pub fn tpm_counter(key_id: Uuid, minute: i64) -> String {
+    key(&format!("tpm:{key_id}:{minute}"))
+}
+
+/// Cached route of an organization's model. An empty `model_name` gives the
+/// prefix shared by all of the organization's routes.
+pub fn route(org_id: Uuid, model_name: &str) -> String {
+    key(&format!("route:{org_id}:{model_name}"))
+}
+
+/// SCAN pattern matching every cached route.
+pub fn all_routes_pattern() -> String {
+    key("route:*")
+}
+
+/// Cached passthrough providers of an organization; an empty list records that it
+/// has none.
+pub fn passthrough_providers(org_id: Uuid) -> String {
+    key(&format!("passthrough_providers:{org_id}"))
+}
+
+/// SCAN pattern matching every cached passthrough provider list.
+pub fn all_passthrough_providers_pattern() -> String {
+    key("passthrough_providers:*")
+}
+
+/// Ids of deactivated providers; cached routes through them are treated as misses
+/// so deactivation applies even before the route cache has been rebuilt.
+pub fn inactive_providers() -> String {
+    key("inactive_providers")
+}
+
+/// Legacy single-hash route cache, removed on warm-up.
+pub fn legacy_model_routes() -> String {
+    key("model_routes")
+}
+
+/// Notified-threshold marker of a budget (`scope` is `key:{id}` or `group:{id}`).
+pub fn budget_alert(scope: &str, percent: u32) -> String {
+    key(&format!("budget_alert:{scope}:{percent}"))
+}
+
+/// SCAN pattern matching every notified-threshold marker of a budget.
+pub fn budget_alerts_pattern(scope: &str) -> String {
+    key(&format!("budget_alert:{scope}:*"))
+}
+
+/// Cached dashboard stats of an organization.
+pub fn dashboard_stats(org_id: Uuid) -> String {
+    key(&format!("dashboard_stats:{org_id}"))
+}
@@ -0,0 +1,92 @@
+use rand::RngCore;
+
+use crate::config::RequestIdFormat;
+
+/// KSUID epoch (2014-05-13T16:53:20Z) in Unix seconds.
+const KSUID_EPOCH: i64 = 1_400_000_000;
+/// Length of a base62-encoded KSUID.
+const KSUID_LEN: usize = 27;
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// A new request id in the configured format, with `prefix` prepended.
+pub fn generate(format: RequestIdFormat, prefix: &str) -> String {
+    let id = match format {
+        RequestIdFormat::Uuid => uuid::Uuid::new_v4().to_string(),
+        RequestIdFormat::Ksuid => ksuid(),
+    };
+    format!("{prefix}{id}")
+}
+
+/// A KSUID: 4-byte big-endian timestamp since `KSUID_EPOCH` followed by 16 random
+/// bytes, base62-encoded and left-padded with '0' to 27 characters.
+fn ksuid() -> String {
+    let timestamp = (chrono::Utc::now().timestamp() - KSUID_EPOCH).clamp(0, u32::MAX as i64) as u32;
+    let mut payload = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut payload);
+    encode_ksuid(timestamp, payload)
+}
+
+/// Base62 encoding of a KSUID's timestamp and payload.
+fn encode_ksuid(timestamp: u32, payload: [u8; 16]) -> String {
+    let mut bytes = [0u8; 20];
+    bytes[..4].copy_from_slice(&timestamp.to_be_bytes());
+    bytes[4..].copy_from_slice(&payload);
+
+    // Repeated division of the 160-bit big-endian number by 62
+    let mut digits = Vec::with_capacity(KSUID_LEN);
+    let mut number = bytes.to_vec();
+    while number.iter().any(|&b| b != 0) {
+        let mut remainder = 0u32;
+        for byte in number.iter_mut() {
+            let acc = (remainder << 8) | *byte as u32;
+            *byte = (acc / 62) as u8;
+            remainder = acc % 62;
+        }
+        digits.push(BASE62[remainder as usize]);
+    }
+    digits.resize(KSUID_LEN, b'0');
+    digits.reverse();
+    String::from_utf8(digits).expect("base62 digits are ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ksuids_have_a_fixed_length_and_the_base62_alphabet() {
+        for _ in 0..100 {
+            let id = ksuid();
+            assert_eq!(id.len(), KSUID_LEN);
+            assert!(id.bytes().all(|b| b.is_ascii_alphanumeric()), "{id}");
+        }
+    }
+
+    #[test]
+    fn ksuid_encoding_matches_known_vectors() {
+        assert_eq!(encode_ksuid(0, [0; 16]), "0".repeat(KSUID_LEN));
+        assert_eq!(encode_ksuid(u32::MAX, [0xff; 16]), "aWgEPTl1tmebfsQzFP4bxwgy80V");
+        let payload = 0xB5A1CD34B5F99D1154FB6853345C9735u128.to_be_bytes();
+        assert_eq!(encode_ksuid(0x0669F7EF, payload), "0ujtsYcgvSTl8PAuAdqWYSMnLOv");
+    }
+
+    #[test]
+    fn ksuids_sort_by_timestamp() {
+        let now = (chrono::Utc::now().timestamp() - KSUID_EPOCH) as u32;
+        let earlier = encode_ksuid(now - 1, [0xff; 16]);
+        let later = encode_ksuid(now, [0; 16]);
+        assert!(earlier < later, "{earlier} >= {later}");
+        assert!(ksuid() >= encode_ksuid(now, [0; 16]));
+    }
+
+    #[test]
+    fn generate_prepends_the_prefix() {
+        let id = generate(RequestIdFormat::Ksuid, "req_");
+        assert_eq!(id.len(), 4 + KSUID_LEN);
+        assert!(id.starts_with("req_"));
+
+        let id = generate(RequestIdFormat::Uuid, "gw-");
+        let uuid = id.strip_prefix("gw-").unwrap();
+        assert!(uuid::Uuid::parse_str(uuid).is_ok());
+    }
+}
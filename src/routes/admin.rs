@@ -1,63 +1,284 @@
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    middleware as axum_mw,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
     routing::{delete, get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
+use futures::stream::Stream;
 use serde::Deserialize;
+use std::convert::Infallible;
 use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
 use uuid::Uuid;
 
+use crate::config::BudgetEnforcement;
 use crate::error::AppError;
-use crate::services::{key_service, log_service, model_service, provider_service};
+use crate::middleware::auth::{require_full_admin, require_instance_admin, AdminIdentity};
+use crate::models::provider::{ModelNameCase, ProviderKind};
+use crate::services::{
+    budget_alert_service, export_service, key_group_service, key_service, log_service,
+    model_service, provider_service,
+};
 use crate::state::AppState;
 
+/// Deserialize a field so that an explicit `null` becomes `Some(None)`
+/// while an omitted field (via `#[serde(default)]`) stays `None`.
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
 // ── User Key endpoints ────────────────────────────────────────────────
 
 #[derive(Debug, Deserialize)]
 pub struct CreateKeyRequest {
     pub name: String,
     pub token_budget: Option<i64>,
+    /// Provider kinds this key may use. Omit or null = all.
+    pub allowed_provider_kinds: Option<Vec<String>>,
+    /// Grouping tag. Scoped admins may omit it; their own tag is applied.
+    pub tag: Option<String>,
+    /// Tokens per minute. Omit or null = unlimited.
+    pub tpm_limit: Option<i64>,
+    /// Arbitrary JSON object for bookkeeping.
+    pub metadata: Option<serde_json::Value>,
+    /// Key group sharing its token budget. Full admins only.
+    pub group_id: Option<Uuid>,
+    /// Provider all of the key's requests are routed to. Full admins only.
+    pub provider_override_id: Option<Uuid>,
+    /// Let the key send its own provider key per request (`x-provider-api-key`).
+    /// Default false. Full admins only.
+    #[serde(default)]
+    pub allow_byo_provider_key: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateKeyRequest {
-    /// Token budget. null = unlimited.
-    pub token_budget: Option<i64>,
+    /// Use `null` for unlimited. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub token_budget: Option<Option<i64>>,
     /// If true, reset tokens_used to 0.
     #[serde(default)]
     pub reset_usage: bool,
+    /// Use `null` to allow all kinds. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub allowed_provider_kinds: Option<Option<Vec<String>>>,
+    /// Use `null` to clear the tag. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub tag: Option<Option<String>>,
+    /// Use `null` to remove the limit. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub tpm_limit: Option<Option<i64>>,
+    /// Replaces the whole object; `null` clears it. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub metadata: Option<Option<serde_json::Value>>,
+    /// Use `null` to leave the group. Omit the field to keep current value. Full admins only.
+    #[serde(default, deserialize_with = "double_option")]
+    pub group_id: Option<Option<Uuid>>,
+    /// Use `null` to restore normal routing. Omit the field to keep current value.
+    /// Full admins only.
+    #[serde(default, deserialize_with = "double_option")]
+    pub provider_override_id: Option<Option<Uuid>>,
+    /// Omit the field to keep current value. Full admins only.
+    pub allow_byo_provider_key: Option<bool>,
+}
+
+/// Maximum serialized size of key metadata.
+const MAX_KEY_METADATA_BYTES: usize = 4096;
+
+/// Key metadata must be a JSON object of bounded size.
+fn validate_key_metadata(metadata: Option<&serde_json::Value>) -> Result<(), AppError> {
+    let Some(metadata) = metadata else {
+        return Ok(());
+    };
+    if !metadata.is_object() {
+        return Err(AppError::BadRequest("metadata must be a JSON object".into()));
+    }
+    let size = metadata.to_string().len();
+    if size > MAX_KEY_METADATA_BYTES {
+        return Err(AppError::BadRequest(format!(
+            "metadata is {size} bytes; the limit is {MAX_KEY_METADATA_BYTES}"
+        )));
+    }
+    Ok(())
+}
+
+/// Reject negative token budgets.
+fn validate_token_budget(token_budget: Option<i64>) -> Result<(), AppError> {
+    match token_budget {
+        Some(b) if b < 0 => Err(AppError::BadRequest("token_budget must be >= 0".into())),
+        _ => Ok(()),
+    }
+}
+
+/// Reject non-positive tokens-per-minute limits.
+fn validate_tpm_limit(tpm_limit: Option<i64>) -> Result<(), AppError> {
+    match tpm_limit {
+        Some(limit) if limit <= 0 => Err(AppError::BadRequest("tpm_limit must be > 0".into())),
+        _ => Ok(()),
+    }
+}
+
+/// Validate and lowercase a list of provider kinds.
+fn normalize_provider_kinds(kinds: Vec<String>) -> Result<Vec<String>, AppError> {
+    kinds
+        .into_iter()
+        .map(|k| {
+            ProviderKind::from_str(&k)
+                .map(|pk| pk.as_str().to_string())
+                .ok_or_else(|| AppError::BadRequest(format!("Unknown provider kind: {k}")))
+        })
+        .collect()
+}
+
+/// Key groups pool budgets across tags, so only full admins may assign them, and
+/// only groups of their own organization.
+async fn authorize_group_change(
+    identity: &AdminIdentity,
+    group_id: Option<Uuid>,
+    db: &sqlx::PgPool,
+) -> Result<(), AppError> {
+    if identity.scope.is_some() {
+        return Err(AppError::Forbidden(
+            "Only the full admin token can assign key groups".into(),
+        ));
+    }
+    if let Some(group_id) = group_id {
+        match key_group_service::get_group(group_id, identity.org_id, db).await {
+            Err(AppError::NotFound) => return Err(AppError::BadRequest("Unknown key group".into())),
+            other => other?,
+        };
+    }
+    Ok(())
+}
+
+/// Providers are managed by full admins, so only they may pin a key to one, and
+/// only to a provider of their own organization.
+async fn authorize_provider_override(
+    identity: &AdminIdentity,
+    provider_id: Option<Uuid>,
+    db: &sqlx::PgPool,
+) -> Result<(), AppError> {
+    if identity.scope.is_some() {
+        return Err(AppError::Forbidden(
+            "Only the full admin token can set a provider override".into(),
+        ));
+    }
+    if let Some(provider_id) = provider_id {
+        if provider_service::get_provider(provider_id, identity.org_id, db)
+            .await?
+            .is_none()
+        {
+            return Err(AppError::BadRequest("Unknown provider_override_id".into()));
+        }
+    }
+    Ok(())
+}
+
+/// Sending a client's provider key bypasses the configured provider credentials, so
+/// only full admins may allow it.
+fn authorize_byo_provider_key(identity: &AdminIdentity) -> Result<(), AppError> {
+    if identity.scope.is_some() {
+        return Err(AppError::Forbidden(
+            "Only the full admin token can allow x-provider-api-key".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reject the request unless the admin may manage key `id`; keys of other
+/// organizations are reported as missing.
+async fn authorize_key(
+    identity: &AdminIdentity,
+    id: Uuid,
+    db: &sqlx::PgPool,
+) -> Result<(), AppError> {
+    let tag = key_service::get_key_tag(id, identity.org_id, db).await?;
+    if !identity.can_manage(tag.as_deref()) {
+        return Err(AppError::Forbidden("Key is outside this admin's scope".into()));
+    }
+    Ok(())
 }
 
 /// POST /admin/keys — create a new user key
 async fn create_key(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Json(body): Json<CreateKeyRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     if body.name.trim().is_empty() {
         return Err(AppError::BadRequest("name is required".into()));
     }
 
+    validate_token_budget(body.token_budget)?;
+    validate_tpm_limit(body.tpm_limit)?;
+    validate_key_metadata(body.metadata.as_ref())?;
+
+    let tag = body.tag.or_else(|| identity.scope.clone());
+    if !identity.can_manage(tag.as_deref()) {
+        return Err(AppError::Forbidden("Key is outside this admin's scope".into()));
+    }
+    if body.group_id.is_some() {
+        authorize_group_change(&identity, body.group_id, &state.db).await?;
+    }
+    if body.provider_override_id.is_some() {
+        authorize_provider_override(&identity, body.provider_override_id, &state.db).await?;
+    }
+    if body.allow_byo_provider_key {
+        authorize_byo_provider_key(&identity)?;
+    }
+
+    let allowed_provider_kinds = body
+        .allowed_provider_kinds
+        .map(normalize_provider_kinds)
+        .transpose()?;
+
     let mut redis = state.redis.clone();
-    let result = key_service::create_key(&body.name, body.token_budget, &state.db, &mut redis).await?;
+    let result = key_service::create_key(
+        key_service::NewKey {
+            org_id: identity.org_id,
+            name: body.name,
+            token_budget: body.token_budget,
+            allowed_provider_kinds,
+            tag,
+            tpm_limit: body.tpm_limit,
+            metadata: body.metadata,
+            group_id: body.group_id,
+            provider_override_id: body.provider_override_id,
+            allow_byo_provider_key: body.allow_byo_provider_key,
+        },
+        &state.db,
+        &mut redis,
+    )
+    .await?;
 
     Ok((StatusCode::CREATED, Json(result)))
 }
 
-/// GET /admin/keys — list all keys (without plaintext)
+/// GET /admin/keys — list all keys (without plaintext); scoped admins see only their tag
 async fn list_keys(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
 ) -> Result<Json<Vec<crate::models::user_key::UserKeyInfo>>, AppError> {
-    let keys = key_service::list_keys(&state.db).await?;
+    let keys = key_service::list_keys(identity.org_id, identity.scope.as_deref(), &state.db).await?;
     Ok(Json(keys))
 }
 
 /// POST /admin/keys/:id/rotate — rotate a key, return new plaintext
 async fn rotate_key(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Path(id): Path<Uuid>,
 ) -> Result<Json<crate::models::user_key::UserKeyCreated>, AppError> {
+    authorize_key(&identity, id, &state.db).await?;
     let mut redis = state.redis.clone();
     let result = key_service::rotate_key(id, &state.db, &mut redis).await?;
     Ok(Json(result))
@@ -66,39 +287,259 @@ async fn rotate_key(
 /// DELETE /admin/keys/:id — soft-delete a key
 async fn delete_key_handler(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Path(id): Path<Uuid>,
 ) -> Result<StatusCode, AppError> {
+    authorize_key(&identity, id, &state.db).await?;
     let mut redis = state.redis.clone();
     key_service::delete_key(id, &state.db, &mut redis).await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
-/// PUT /admin/keys/:id — update key budget / reset usage
+/// PUT /admin/keys/:id — update budget / usage reset / provider kinds / tag / TPM / metadata /
+/// group / provider override
 async fn update_key_handler(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Path(id): Path<Uuid>,
     Json(body): Json<UpdateKeyRequest>,
 ) -> Result<Json<crate::models::user_key::UserKeyInfo>, AppError> {
-    let result = key_service::update_key_budget(
-        id,
+    authorize_key(&identity, id, &state.db).await?;
+    validate_token_budget(body.token_budget.flatten())?;
+    validate_tpm_limit(body.tpm_limit.flatten())?;
+    validate_key_metadata(body.metadata.as_ref().and_then(|m| m.as_ref()))?;
+    if let Some(provider_id) = body.provider_override_id {
+        authorize_provider_override(&identity, provider_id, &state.db).await?;
+    }
+    if body.allow_byo_provider_key.is_some() {
+        authorize_byo_provider_key(&identity)?;
+    }
+
+    if let Some(tag) = &body.tag {
+        if !identity.can_manage(tag.as_deref()) {
+            return Err(AppError::Forbidden("Key is outside this admin's scope".into()));
+        }
+    }
+    if let Some(group_id) = body.group_id {
+        authorize_group_change(&identity, group_id, &state.db).await?;
+    }
+    let allowed_provider_kinds = body
+        .allowed_provider_kinds
+        .map(|kinds| kinds.map(normalize_provider_kinds).transpose())
+        .transpose()?;
+
+    let key_update = key_service::KeyUpdate {
+        token_budget: body.token_budget,
+        reset_usage: body.reset_usage,
+        allowed_provider_kinds,
+        tag: body.tag,
+        tpm_limit: body.tpm_limit,
+        metadata: body.metadata,
+        group_id: body.group_id,
+        provider_override_id: body.provider_override_id,
+        allow_byo_provider_key: body.allow_byo_provider_key,
+    };
+    let update = || key_service::update_key(id, key_update, &state.db);
+    let result = if body.reset_usage || body.group_id.is_some() {
+        // Write buffered charges first so they neither land on the reset counter nor
+        // follow the key into its new group
+        state.usage.flush_and_recompute(&state.db, update).await?
+    } else {
+        update().await?
+    };
+    if body.reset_usage {
+        let mut redis = state.redis.clone();
+        budget_alert_service::clear_key_alerts(id, &mut redis).await?;
+    }
+    Ok(Json(result))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetKeyModelBudgetRequest {
+    pub token_budget: i64,
+    /// Zero the model's usage. Default false.
+    #[serde(default)]
+    pub reset_usage: bool,
+}
+
+/// GET /admin/keys/:id/model-budgets — a key's per-model budgets
+async fn list_key_model_budgets(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<crate::models::user_key::KeyModelBudget>>, AppError> {
+    authorize_key(&identity, id, &state.db).await?;
+    let budgets = key_service::list_key_model_budgets(id, &state.db).await?;
+    Ok(Json(budgets))
+}
+
+/// PUT /admin/keys/:id/model-budgets/:model — set a key's budget for a model
+async fn set_key_model_budget(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path((id, model)): Path<(Uuid, String)>,
+    Json(body): Json<SetKeyModelBudgetRequest>,
+) -> Result<Json<crate::models::user_key::KeyModelBudget>, AppError> {
+    authorize_key(&identity, id, &state.db).await?;
+    validate_token_budget(Some(body.token_budget))?;
+    let set_budget = || {
+        key_service::set_key_model_budget(id, &model, body.token_budget, body.reset_usage, &state.db)
+    };
+    let budget = if body.reset_usage {
+        // Write buffered charges first so they do not land on the reset counter
+        state.usage.flush_and_recompute(&state.db, set_budget).await?
+    } else {
+        set_budget().await?
+    };
+    Ok(Json(budget))
+}
+
+/// DELETE /admin/keys/:id/model-budgets/:model — remove a key's budget for a model
+async fn delete_key_model_budget(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path((id, model)): Path<(Uuid, String)>,
+) -> Result<StatusCode, AppError> {
+    authorize_key(&identity, id, &state.db).await?;
+    key_service::delete_key_model_budget(id, &model, &state.db).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ── Key Group endpoints ───────────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct CreateKeyGroupRequest {
+    pub name: String,
+    /// Shared token budget. Omit or null = unlimited.
+    pub token_budget: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateKeyGroupRequest {
+    pub name: Option<String>,
+    /// Use `null` to remove the budget. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub token_budget: Option<Option<i64>>,
+    /// If true, reset the group's tokens_used to 0.
+    #[serde(default)]
+    pub reset_usage: bool,
+}
+
+/// POST /admin/key-groups
+async fn create_key_group(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Json(body): Json<CreateKeyGroupRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if body.name.trim().is_empty() {
+        return Err(AppError::BadRequest("name is required".into()));
+    }
+    validate_token_budget(body.token_budget)?;
+    let result = key_group_service::create_group(
+        identity.org_id,
+        &body.name,
         body.token_budget,
-        body.reset_usage,
         &state.db,
     )
     .await?;
+    Ok((StatusCode::CREATED, Json(result)))
+}
+
+/// GET /admin/key-groups — groups with shared usage
+async fn list_key_groups(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<Vec<crate::models::key_group::KeyGroupInfo>>, AppError> {
+    let groups = key_group_service::list_groups(identity.org_id, &state.db).await?;
+    Ok(Json(groups))
+}
+
+/// GET /admin/key-groups/:id — shared budget and usage of one group
+async fn get_key_group(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<crate::models::key_group::KeyGroupInfo>, AppError> {
+    let group = key_group_service::get_group(id, identity.org_id, &state.db).await?;
+    Ok(Json(group))
+}
+
+/// PUT /admin/key-groups/:id — rename / change budget / reset usage
+async fn update_key_group(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<UpdateKeyGroupRequest>,
+) -> Result<Json<crate::models::key_group::KeyGroupInfo>, AppError> {
+    if body.name.as_deref().is_some_and(|n| n.trim().is_empty()) {
+        return Err(AppError::BadRequest("name must not be empty".into()));
+    }
+    validate_token_budget(body.token_budget.flatten())?;
+    let reset_usage = body.reset_usage;
+    let update_group = || {
+        key_group_service::update_group(
+            id,
+            identity.org_id,
+            key_group_service::KeyGroupUpdate {
+                name: body.name,
+                token_budget: body.token_budget,
+                reset_usage,
+            },
+            &state.db,
+        )
+    };
+    let result = if reset_usage {
+        // Write buffered charges first so they do not land on the reset counter
+        state.usage.flush_and_recompute(&state.db, update_group).await?
+    } else {
+        update_group().await?
+    };
+    if reset_usage {
+        let mut redis = state.redis.clone();
+        budget_alert_service::clear_group_alerts(id, &mut redis).await?;
+    }
     Ok(Json(result))
 }
 
+/// DELETE /admin/key-groups/:id — member keys fall back to their own budgets
+async fn delete_key_group(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    key_group_service::delete_group(id, identity.org_id, &state.db).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
 // ── Provider endpoints ────────────────────────────────────────────────
 
 #[derive(Debug, Deserialize)]
 pub struct CreateProviderRequest {
     pub name: String,
-    /// "openai" | "openrouter" | "dashscope" | "ark"
+    /// "openai" | "openrouter" | "dashscope" | "ark" | "openai_compatible" (alias "custom")
     pub kind: String,
     /// Optional; defaults based on kind
     pub base_url: Option<String>,
     pub api_key: String,
+    /// Send streaming requests upstream as non-streaming and re-emit as SSE (default false)
+    #[serde(default)]
+    pub force_buffered_upstream: bool,
+    /// Inject `stream_options.include_usage` into streaming requests (default true)
+    #[serde(default = "crate::models::model::default_true")]
+    pub supports_stream_usage: bool,
+    /// Upstream statuses that fail over to another route of the model (default 429 and 5xx)
+    pub retry_on_status: Option<Vec<i32>>,
+    /// "none" | "lower" | "upper": case of model names sent upstream (default "none")
+    pub normalize_model_case: Option<String>,
+    /// Region tag for `x-gateway-region` routing, e.g. "eu" (default none)
+    pub region: Option<String>,
+    /// Path appended to `base_url` for chat completions (default "/chat/completions")
+    pub chat_path: Option<String>,
+    /// Forward model names not registered in `models` to this provider (default false)
+    #[serde(default)]
+    pub passthrough: bool,
+    /// Only names starting with this prefix pass through; it is stripped (default none)
+    pub passthrough_prefix: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -108,57 +549,268 @@ pub struct UpdateProviderRequest {
     pub base_url: Option<String>,
     pub api_key: Option<String>,
     pub is_active: Option<bool>,
+    pub force_buffered_upstream: Option<bool>,
+    pub supports_stream_usage: Option<bool>,
+    /// Use `null` to restore the default (429 and 5xx). Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub retry_on_status: Option<Option<Vec<i32>>>,
+    pub normalize_model_case: Option<String>,
+    /// Use `null` to clear the region. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub region: Option<Option<String>>,
+    /// Use `null` to restore the default path. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub chat_path: Option<Option<String>>,
+    pub passthrough: Option<bool>,
+    /// Use `null` to make it the catch-all. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub passthrough_prefix: Option<Option<String>>,
+}
+
+/// Parse a `normalize_model_case` value.
+fn parse_model_case(value: &str) -> Result<ModelNameCase, AppError> {
+    ModelNameCase::from_str(value).ok_or_else(|| {
+        AppError::BadRequest(format!(
+            "Unknown normalize_model_case: {value}. Supported: none, lower, upper"
+        ))
+    })
+}
+
+/// Maximum length of a provider region tag.
+const MAX_REGION_LEN: usize = 64;
+
+/// Validate and lowercase a provider region tag: letters, digits, `-` and `_`.
+fn normalize_region(region: String) -> Result<String, AppError> {
+    let region = region.trim().to_ascii_lowercase();
+    let valid = !region.is_empty()
+        && region.len() <= MAX_REGION_LEN
+        && region
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(AppError::BadRequest(format!(
+            "region must be 1-{MAX_REGION_LEN} letters, digits, '-' or '_'"
+        )));
+    }
+    Ok(region)
+}
+
+/// Maximum length of a provider chat path.
+const MAX_CHAT_PATH_LEN: usize = 255;
+
+/// Validate a provider chat path: starts with `/`, no whitespace. A query string
+/// (e.g. `?api-version=...`) is allowed.
+fn validate_chat_path(path: &str) -> Result<(), AppError> {
+    if !path.starts_with('/')
+        || path.len() > MAX_CHAT_PATH_LEN
+        || path.chars().any(|c| c.is_whitespace() || c.is_control())
+    {
+        return Err(AppError::BadRequest(format!(
+            "chat_path must start with '/', contain no whitespace and be at most \
+             {MAX_CHAT_PATH_LEN} characters"
+        )));
+    }
+    Ok(())
+}
+
+/// Maximum length of a passthrough model name prefix.
+const MAX_PASSTHROUGH_PREFIX_LEN: usize = 64;
+
+/// Validate a passthrough prefix: non-empty, no whitespace (`null` is the catch-all).
+fn validate_passthrough_prefix(prefix: &str) -> Result<(), AppError> {
+    if prefix.is_empty()
+        || prefix.len() > MAX_PASSTHROUGH_PREFIX_LEN
+        || prefix.chars().any(|c| c.is_whitespace() || c.is_control())
+    {
+        return Err(AppError::BadRequest(format!(
+            "passthrough_prefix must be 1 to {MAX_PASSTHROUGH_PREFIX_LEN} characters without \
+             whitespace; use null to match any model name"
+        )));
+    }
+    Ok(())
+}
+
+/// Reject status codes outside the HTTP range.
+fn validate_retry_on_status(statuses: Option<&[i32]>) -> Result<(), AppError> {
+    match statuses.and_then(|s| s.iter().find(|code| !(100..=599).contains(*code))) {
+        Some(code) => Err(AppError::BadRequest(format!(
+            "retry_on_status contains invalid HTTP status {code}"
+        ))),
+        None => Ok(()),
+    }
 }
 
 /// POST /admin/providers
 async fn create_provider(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Json(body): Json<CreateProviderRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     if body.name.trim().is_empty() {
         return Err(AppError::BadRequest("name is required".into()));
     }
+    validate_retry_on_status(body.retry_on_status.as_deref())?;
+    let normalize_model_case = match &body.normalize_model_case {
+        Some(case) => parse_model_case(case)?,
+        None => ModelNameCase::None,
+    };
+    let region = body.region.map(normalize_region).transpose()?;
+    if let Some(path) = &body.chat_path {
+        validate_chat_path(path)?;
+    }
+    if let Some(prefix) = &body.passthrough_prefix {
+        validate_passthrough_prefix(prefix)?;
+    }
 
     let result = provider_service::create_provider(
-        &body.name,
-        &body.kind,
-        body.base_url.as_deref(),
-        &body.api_key,
+        provider_service::NewProvider {
+            org_id: identity.org_id,
+            name: body.name,
+            kind: body.kind,
+            base_url: body.base_url,
+            api_key: body.api_key,
+            force_buffered_upstream: body.force_buffered_upstream,
+            supports_stream_usage: body.supports_stream_usage,
+            retry_on_status: body.retry_on_status,
+            normalize_model_case,
+            region,
+            chat_path: body.chat_path,
+            passthrough: body.passthrough,
+            passthrough_prefix: body.passthrough_prefix,
+        },
+        state.config.allow_insecure_provider_urls,
+        state
+            .config
+            .validate_provider_on_create
+            .then_some(&state.http_client),
         &state.db,
     )
     .await?;
 
+    if result.passthrough {
+        let mut redis = state.redis.clone();
+        model_service::invalidate_passthrough_providers(identity.org_id, &mut redis).await?;
+    }
+
     Ok((StatusCode::CREATED, Json(result)))
 }
 
 /// GET /admin/providers
 async fn list_providers(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
 ) -> Result<Json<Vec<crate::models::provider::ProviderInfo>>, AppError> {
-    let providers = provider_service::list_providers(&state.db).await?;
+    let providers = provider_service::list_providers(identity.org_id, &state.db).await?;
     Ok(Json(providers))
 }
 
+/// GET /admin/providers/:id
+async fn get_provider_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<provider_service::ProviderDetail>, AppError> {
+    let detail = provider_service::get_provider_detail(id, identity.org_id, &state.db).await?;
+    Ok(Json(detail))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DiscoverModelsRequest {
+    /// Prepended to every discovered model name (e.g. "openrouter/").
+    pub prefix: Option<String>,
+    /// "as_is" (default) | "strip_namespace"
+    pub naming: Option<String>,
+}
+
+/// POST /admin/providers/:id/discover — create models from the provider's model list
+async fn discover_provider_models(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(id): Path<Uuid>,
+    body: Option<Json<DiscoverModelsRequest>>,
+) -> Result<Json<Vec<crate::models::model::ModelInfo>>, AppError> {
+    let body = body.map(|Json(body)| body).unwrap_or_default();
+    let naming = match body.naming.as_deref() {
+        None => model_service::DiscoveryNaming::AsIs,
+        Some(s) => model_service::DiscoveryNaming::from_str(s).ok_or_else(|| {
+            AppError::BadRequest("naming must be one of: as_is, strip_namespace".into())
+        })?,
+    };
+    let mut redis = state.redis.clone();
+    let added = model_service::discover_models(
+        model_service::ModelDiscovery {
+            org_id: identity.org_id,
+            provider_id: id,
+            prefix: body.prefix.unwrap_or_default(),
+            naming,
+        },
+        state.config.model_route_cache_ttl_secs,
+        &state.http_client,
+        &state.db,
+        &mut redis,
+    )
+    .await?;
+    Ok(Json(added))
+}
+
 /// PUT /admin/providers/:id
 async fn update_provider(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Path(id): Path<Uuid>,
     Json(body): Json<UpdateProviderRequest>,
 ) -> Result<Json<crate::models::provider::ProviderInfo>, AppError> {
+    validate_retry_on_status(body.retry_on_status.as_ref().and_then(|s| s.as_deref()))?;
+    let normalize_model_case = body
+        .normalize_model_case
+        .as_deref()
+        .map(parse_model_case)
+        .transpose()?;
+    let region = body
+        .region
+        .map(|region| region.map(normalize_region).transpose())
+        .transpose()?;
+    if let Some(Some(path)) = &body.chat_path {
+        validate_chat_path(path)?;
+    }
+    if let Some(Some(prefix)) = &body.passthrough_prefix {
+        validate_passthrough_prefix(prefix)?;
+    }
+
     let result = provider_service::update_provider(
         id,
-        body.name.as_deref(),
-        body.kind.as_deref(),
-        body.base_url.as_deref(),
-        body.api_key.as_deref(),
-        body.is_active,
+        identity.org_id,
+        provider_service::ProviderUpdate {
+            name: body.name,
+            kind: body.kind,
+            base_url: body.base_url,
+            api_key: body.api_key,
+            is_active: body.is_active,
+            force_buffered_upstream: body.force_buffered_upstream,
+            supports_stream_usage: body.supports_stream_usage,
+            retry_on_status: body.retry_on_status,
+            normalize_model_case,
+            region,
+            chat_path: body.chat_path,
+            passthrough: body.passthrough,
+            passthrough_prefix: body.passthrough_prefix,
+        },
+        state.config.allow_insecure_provider_urls,
         &state.db,
     )
     .await?;
 
     // Rebuild model route cache since provider details may have changed
     let mut redis = state.redis.clone();
-    model_service::warm_up_model_routes(&state.db, &mut redis).await?;
+    if !result.is_active {
+        model_service::mark_provider_inactive(id, &mut redis).await?;
+    }
+    model_service::warm_up_model_routes(
+        state.config.model_route_cache_ttl_secs,
+        &state.db,
+        &mut redis,
+    )
+    .await?;
 
     Ok(Json(result))
 }
@@ -166,13 +818,20 @@ async fn update_provider(
 /// DELETE /admin/providers/:id
 async fn delete_provider_handler(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Path(id): Path<Uuid>,
 ) -> Result<StatusCode, AppError> {
-    provider_service::delete_provider(id, &state.db).await?;
+    provider_service::delete_provider(id, identity.org_id, &state.db).await?;
 
     // Rebuild model route cache
     let mut redis = state.redis.clone();
-    model_service::warm_up_model_routes(&state.db, &mut redis).await?;
+    model_service::mark_provider_inactive(id, &mut redis).await?;
+    model_service::warm_up_model_routes(
+        state.config.model_route_cache_ttl_secs,
+        &state.db,
+        &mut redis,
+    )
+    .await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -191,24 +850,131 @@ pub struct CreateModelRequest {
     pub input_token_coefficient: Option<f64>,
     /// Token budget coefficient for completion tokens (default 1.0)
     pub output_token_coefficient: Option<f64>,
+    /// Token budget coefficient for reasoning tokens (omit = output coefficient)
+    pub reasoning_token_coefficient: Option<f64>,
+    /// Token budget coefficient for cached prompt tokens (omit = input coefficient)
+    pub cached_token_coefficient: Option<f64>,
+    /// USD per million prompt tokens (omit for unpriced)
+    pub input_price_per_mtok: Option<f64>,
+    /// USD per million completion tokens (omit for unpriced)
+    pub output_price_per_mtok: Option<f64>,
+    /// Routing weight of the primary provider against alternate routes (default 1)
+    pub weight: Option<i32>,
+    /// Completion object returned when every route fails (omit = 502)
+    pub fallback_response: Option<serde_json::Value>,
+    /// `max_tokens` injected when the client sets none (omit = `DEFAULT_MAX_TOKENS`)
+    pub default_max_tokens: Option<i32>,
+    /// Mark the system prompt for upstream prompt caching (default false)
+    #[serde(default)]
+    pub prompt_caching: bool,
+    /// Milliseconds without a response before a non-streaming request is also sent to
+    /// another route (omit = no hedging)
+    pub hedge_after_ms: Option<i32>,
+}
+
+/// Coefficients and prices feed budget and spend math, so they must be finite and >= 0.
+fn validate_rate(field: &str, value: Option<f64>) -> Result<(), AppError> {
+    match value {
+        Some(v) if !v.is_finite() || v < 0.0 => Err(AppError::BadRequest(format!(
+            "{field} must be a finite number >= 0 (got {v})"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Validate every coefficient and price field of a model request.
+fn validate_model_rates(
+    input_token_coefficient: Option<f64>,
+    output_token_coefficient: Option<f64>,
+    reasoning_token_coefficient: Option<f64>,
+    cached_token_coefficient: Option<f64>,
+    input_price_per_mtok: Option<f64>,
+    output_price_per_mtok: Option<f64>,
+) -> Result<(), AppError> {
+    validate_rate("input_token_coefficient", input_token_coefficient)?;
+    validate_rate("output_token_coefficient", output_token_coefficient)?;
+    validate_rate("reasoning_token_coefficient", reasoning_token_coefficient)?;
+    validate_rate("cached_token_coefficient", cached_token_coefficient)?;
+    validate_rate("input_price_per_mtok", input_price_per_mtok)?;
+    validate_rate("output_price_per_mtok", output_price_per_mtok)
+}
+
+/// Reject negative routing weights.
+fn validate_weight(weight: Option<i32>) -> Result<(), AppError> {
+    match weight {
+        Some(w) if w < 0 => Err(AppError::BadRequest("weight must be >= 0".into())),
+        _ => Ok(()),
+    }
+}
+
+/// A fallback response is returned to clients as a completion, so it must be an object.
+fn validate_fallback_response(fallback: Option<&serde_json::Value>) -> Result<(), AppError> {
+    match fallback {
+        Some(v) if !v.is_object() => Err(AppError::BadRequest(
+            "fallback_response must be a JSON object shaped like a chat completion".into(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// A default completion cap must allow at least one token.
+fn validate_default_max_tokens(value: Option<i32>) -> Result<(), AppError> {
+    match value {
+        Some(v) if v < 1 => Err(AppError::BadRequest("default_max_tokens must be >= 1".into())),
+        _ => Ok(()),
+    }
+}
+
+/// A hedge delay must be positive.
+fn validate_hedge_after_ms(value: Option<i32>) -> Result<(), AppError> {
+    match value {
+        Some(v) if v < 1 => Err(AppError::BadRequest("hedge_after_ms must be >= 1".into())),
+        _ => Ok(()),
+    }
 }
 
 /// POST /admin/models
 async fn create_model(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Json(body): Json<CreateModelRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     if body.name.trim().is_empty() {
         return Err(AppError::BadRequest("name is required".into()));
     }
+    validate_weight(body.weight)?;
+    validate_model_rates(
+        body.input_token_coefficient,
+        body.output_token_coefficient,
+        body.reasoning_token_coefficient,
+        body.cached_token_coefficient,
+        body.input_price_per_mtok,
+        body.output_price_per_mtok,
+    )?;
+    validate_fallback_response(body.fallback_response.as_ref())?;
+    validate_default_max_tokens(body.default_max_tokens)?;
+    validate_hedge_after_ms(body.hedge_after_ms)?;
 
     let mut redis = state.redis.clone();
     let result = model_service::create_model(
-        &body.name,
-        body.provider_id,
-        body.provider_model_name.as_deref(),
-        body.input_token_coefficient.unwrap_or(1.0),
-        body.output_token_coefficient.unwrap_or(1.0),
+        model_service::NewModel {
+            org_id: identity.org_id,
+            name: body.name,
+            provider_id: body.provider_id,
+            provider_model_name: body.provider_model_name,
+            input_token_coefficient: body.input_token_coefficient.unwrap_or(1.0),
+            output_token_coefficient: body.output_token_coefficient.unwrap_or(1.0),
+            reasoning_token_coefficient: body.reasoning_token_coefficient,
+            cached_token_coefficient: body.cached_token_coefficient,
+            input_price_per_mtok: body.input_price_per_mtok,
+            output_price_per_mtok: body.output_price_per_mtok,
+            weight: body.weight.unwrap_or(1),
+            fallback_response: body.fallback_response,
+            default_max_tokens: body.default_max_tokens,
+            prompt_caching: body.prompt_caching,
+            hedge_after_ms: body.hedge_after_ms,
+        },
+        state.config.model_route_cache_ttl_secs,
         &state.db,
         &mut redis,
     )
@@ -220,18 +986,20 @@ async fn create_model(
 /// GET /admin/models
 async fn list_models(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
 ) -> Result<Json<Vec<crate::models::model::ModelInfo>>, AppError> {
-    let models = model_service::list_models(&state.db).await?;
+    let models = model_service::list_models(identity.org_id, &state.db).await?;
     Ok(Json(models))
 }
 
 /// DELETE /admin/models/:id
 async fn delete_model_handler(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Path(id): Path<Uuid>,
 ) -> Result<StatusCode, AppError> {
     let mut redis = state.redis.clone();
-    model_service::delete_model(id, &state.db, &mut redis).await?;
+    model_service::delete_model(id, identity.org_id, &state.db, &mut redis).await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -240,27 +1008,78 @@ pub struct UpdateModelRequest {
     pub name: Option<String>,
     pub provider_id: Option<Uuid>,
     /// Use `null` to reset to default (= model name). Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
     pub provider_model_name: Option<Option<String>>,
     pub is_active: Option<bool>,
     pub input_token_coefficient: Option<f64>,
     pub output_token_coefficient: Option<f64>,
+    /// Use `null` to fall back to the output coefficient. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub reasoning_token_coefficient: Option<Option<f64>>,
+    /// Use `null` to fall back to the input coefficient. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub cached_token_coefficient: Option<Option<f64>>,
+    /// Use `null` to clear the price. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub input_price_per_mtok: Option<Option<f64>>,
+    /// Use `null` to clear the price. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub output_price_per_mtok: Option<Option<f64>>,
+    pub weight: Option<i32>,
+    /// Use `null` to restore the 502. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub fallback_response: Option<Option<serde_json::Value>>,
+    /// Use `null` to fall back to `DEFAULT_MAX_TOKENS`. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub default_max_tokens: Option<Option<i32>>,
+    pub prompt_caching: Option<bool>,
+    /// Use `null` to turn hedging off. Omit the field to keep current value.
+    #[serde(default, deserialize_with = "double_option")]
+    pub hedge_after_ms: Option<Option<i32>>,
 }
 
 /// PUT /admin/models/:id
 async fn update_model_handler(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Path(id): Path<Uuid>,
     Json(body): Json<UpdateModelRequest>,
 ) -> Result<Json<crate::models::model::ModelInfo>, AppError> {
+    validate_weight(body.weight)?;
+    validate_model_rates(
+        body.input_token_coefficient,
+        body.output_token_coefficient,
+        body.reasoning_token_coefficient.flatten(),
+        body.cached_token_coefficient.flatten(),
+        body.input_price_per_mtok.flatten(),
+        body.output_price_per_mtok.flatten(),
+    )?;
+    validate_fallback_response(body.fallback_response.as_ref().and_then(|f| f.as_ref()))?;
+    validate_default_max_tokens(body.default_max_tokens.flatten())?;
+    validate_hedge_after_ms(body.hedge_after_ms.flatten())?;
+
     let mut redis = state.redis.clone();
     let result = model_service::update_model(
         id,
-        body.name.as_deref(),
-        body.provider_id,
-        body.provider_model_name.as_ref().map(|o| o.as_deref()),
-        body.is_active,
-        body.input_token_coefficient,
-        body.output_token_coefficient,
+        identity.org_id,
+        model_service::ModelUpdate {
+            name: body.name,
+            provider_id: body.provider_id,
+            provider_model_name: body.provider_model_name,
+            is_active: body.is_active,
+            input_token_coefficient: body.input_token_coefficient,
+            output_token_coefficient: body.output_token_coefficient,
+            reasoning_token_coefficient: body.reasoning_token_coefficient,
+            cached_token_coefficient: body.cached_token_coefficient,
+            input_price_per_mtok: body.input_price_per_mtok,
+            output_price_per_mtok: body.output_price_per_mtok,
+            weight: body.weight,
+            fallback_response: body.fallback_response,
+            default_max_tokens: body.default_max_tokens,
+            prompt_caching: body.prompt_caching,
+            hedge_after_ms: body.hedge_after_ms,
+        },
+        state.config.model_route_cache_ttl_secs,
         &state.db,
         &mut redis,
     )
@@ -269,7 +1088,244 @@ async fn update_model_handler(
     Ok(Json(result))
 }
 
-// ── Router ────────────────────────────────────────────────────────────
+#[derive(Debug, Deserialize)]
+pub struct CreateAlternateRouteRequest {
+    pub provider_id: Uuid,
+    /// Optional: model name on this provider (defaults to the model's provider-side name)
+    pub provider_model_name: Option<String>,
+    /// Routing weight (default 1)
+    pub weight: Option<i32>,
+}
+
+/// GET /admin/models/:id/alternates
+async fn list_alternates(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<crate::models::model::AlternateRouteInfo>>, AppError> {
+    let routes = model_service::list_alternate_routes(id, identity.org_id, &state.db).await?;
+    Ok(Json(routes))
+}
+
+/// POST /admin/models/:id/alternates — route the model to an additional provider
+async fn create_alternate(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<CreateAlternateRouteRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    validate_weight(body.weight)?;
+
+    let mut redis = state.redis.clone();
+    let result = model_service::add_alternate_route(
+        id,
+        identity.org_id,
+        model_service::NewAlternateRoute {
+            provider_id: body.provider_id,
+            provider_model_name: body.provider_model_name,
+            weight: body.weight.unwrap_or(1),
+        },
+        state.config.model_route_cache_ttl_secs,
+        &state.db,
+        &mut redis,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(result)))
+}
+
+/// DELETE /admin/models/:id/alternates/:route_id
+async fn delete_alternate(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path((id, route_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    let mut redis = state.redis.clone();
+    model_service::delete_alternate_route(
+        id,
+        route_id,
+        identity.org_id,
+        state.config.model_route_cache_ttl_secs,
+        &state.db,
+        &mut redis,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ── Cache endpoints ───────────────────────────────────────────────────
+
+/// Maximum entries returned by the cache inspection endpoints.
+const MAX_CACHE_LISTING: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct CacheListingQuery {
+    /// Maximum entries to return (default and cap: 1000).
+    pub limit: Option<usize>,
+}
+
+/// GET /admin/cache/keys — hashes in the Redis active-key set
+async fn cached_keys_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CacheListingQuery>,
+) -> Result<Json<key_service::CachedKeyHashes>, AppError> {
+    let limit = query.limit.unwrap_or(MAX_CACHE_LISTING).clamp(1, MAX_CACHE_LISTING);
+    let mut redis = state.redis.clone();
+    let result = key_service::cached_key_hashes(limit, &mut redis).await?;
+    Ok(Json(result))
+}
+
+/// GET /admin/cache/routes — the organization's cached model routes with API keys masked
+async fn cached_routes_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Query(query): Query<CacheListingQuery>,
+) -> Result<Json<model_service::CachedRoutes>, AppError> {
+    let limit = query.limit.unwrap_or(MAX_CACHE_LISTING).clamp(1, MAX_CACHE_LISTING);
+    let mut redis = state.redis.clone();
+    let result = model_service::cached_routes(identity.org_id, limit, &mut redis).await?;
+    Ok(Json(result))
+}
+
+/// DELETE /admin/cache/route/:model_name — drop one model's cached route
+async fn invalidate_route_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(model_name): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let mut redis = state.redis.clone();
+    let removed =
+        model_service::invalidate_model_route(identity.org_id, &model_name, &mut redis).await?;
+    Ok(Json(serde_json::json!({ "model": model_name, "removed": removed })))
+}
+
+// ── Export / import endpoints ─────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    /// Also upsert user keys by hash (default false).
+    #[serde(default)]
+    pub keys: bool,
+}
+
+/// Prefix a validation error with the bundle entry it came from.
+fn bundle_entry<T>(entry: &str, result: Result<T, AppError>) -> Result<T, AppError> {
+    result.map_err(|e| match e {
+        AppError::BadRequest(msg) => AppError::BadRequest(format!("{entry}: {msg}")),
+        other => other,
+    })
+}
+
+/// Apply the same field checks as the create endpoints to every bundle entry,
+/// normalizing key provider kinds and provider regions in place.
+fn validate_bundle(bundle: &mut export_service::ConfigBundle) -> Result<(), AppError> {
+    let require_name = |name: &str| {
+        if name.trim().is_empty() {
+            return Err(AppError::BadRequest("name is required".into()));
+        }
+        Ok(())
+    };
+    for p in &mut bundle.providers {
+        let entry = format!("provider '{}'", p.name);
+        bundle_entry(&entry, require_name(&p.name))?;
+        bundle_entry(&entry, validate_retry_on_status(p.retry_on_status.as_deref()))?;
+        bundle_entry(&entry, parse_model_case(&p.normalize_model_case))?;
+        p.region = bundle_entry(&entry, p.region.take().map(normalize_region).transpose())?;
+        if let Some(path) = &p.chat_path {
+            bundle_entry(&entry, validate_chat_path(path))?;
+        }
+        if let Some(prefix) = &p.passthrough_prefix {
+            bundle_entry(&entry, validate_passthrough_prefix(prefix))?;
+        }
+    }
+    for m in &bundle.models {
+        let entry = format!("model '{}'", m.name);
+        bundle_entry(&entry, require_name(&m.name))?;
+        bundle_entry(&entry, validate_weight(Some(m.weight)))?;
+        bundle_entry(
+            &entry,
+            validate_model_rates(
+                Some(m.input_token_coefficient),
+                Some(m.output_token_coefficient),
+                m.reasoning_token_coefficient,
+                m.cached_token_coefficient,
+                m.input_price_per_mtok,
+                m.output_price_per_mtok,
+            ),
+        )?;
+        bundle_entry(&entry, validate_fallback_response(m.fallback_response.as_ref()))?;
+        bundle_entry(&entry, validate_default_max_tokens(m.default_max_tokens))?;
+        bundle_entry(&entry, validate_hedge_after_ms(m.hedge_after_ms))?;
+        for a in &m.alternates {
+            bundle_entry(&entry, validate_weight(Some(a.weight)))?;
+        }
+    }
+    for g in &bundle.key_groups {
+        let entry = format!("key group '{}'", g.name);
+        bundle_entry(&entry, require_name(&g.name))?;
+        bundle_entry(&entry, validate_token_budget(g.token_budget))?;
+    }
+    for k in &mut bundle.keys {
+        let entry = format!("key '{}'", k.key_prefix);
+        if k.key_hash.len() != 64 || !k.key_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AppError::BadRequest(format!(
+                "{entry}: key_hash must be a hex SHA-256 digest"
+            )));
+        }
+        bundle_entry(&entry, require_name(&k.name))?;
+        bundle_entry(&entry, validate_token_budget(k.token_budget))?;
+        bundle_entry(&entry, validate_tpm_limit(k.tpm_limit))?;
+        bundle_entry(&entry, validate_key_metadata(k.metadata.as_ref()))?;
+        k.allowed_provider_kinds = bundle_entry(
+            &entry,
+            k.allowed_provider_kinds
+                .take()
+                .map(normalize_provider_kinds)
+                .transpose(),
+        )?;
+    }
+    Ok(())
+}
+
+/// GET /admin/export — providers, models, key groups and keys as a JSON bundle
+async fn export_config(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<export_service::ConfigBundle>, AppError> {
+    let bundle = export_service::export_bundle(identity.org_id, &state.db).await?;
+    Ok(Json(bundle))
+}
+
+/// POST /admin/import — upsert an exported bundle in one transaction, then re-warm caches
+async fn import_config(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Query(query): Query<ImportQuery>,
+    Json(mut bundle): Json<export_service::ConfigBundle>,
+) -> Result<Json<export_service::ImportSummary>, AppError> {
+    validate_bundle(&mut bundle)?;
+    let summary = export_service::import_bundle(
+        identity.org_id,
+        &bundle,
+        query.keys,
+        state.config.allow_insecure_provider_urls,
+        &state.db,
+    )
+    .await?;
+
+    let mut redis = state.redis.clone();
+    model_service::warm_up_model_routes(
+        state.config.model_route_cache_ttl_secs,
+        &state.db,
+        &mut redis,
+    )
+    .await?;
+    if summary.keys > 0 {
+        key_service::warm_up_redis(&state.db, &mut redis).await?;
+    }
+
+    Ok(Json(summary))
+}
 
 // ── Request Log endpoints ─────────────────────────────────────────────
 
@@ -279,46 +1335,557 @@ pub struct ListLogsQuery {
     pub per_page: Option<i64>,
     pub key_id: Option<Uuid>,
     pub model: Option<String>,
+    pub retriable: Option<bool>,
 }
 
 /// GET /admin/logs — list request logs with pagination + optional filters
 async fn list_logs(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
     Query(query): Query<ListLogsQuery>,
 ) -> Result<Json<crate::models::request_log::LogListResponse>, AppError> {
+    let max_per_page = state.config.logs_max_per_page;
+    let per_page = query.per_page.unwrap_or(state.config.logs_default_per_page);
+    if !(1..=max_per_page).contains(&per_page) {
+        return Err(AppError::BadRequest(format!(
+            "per_page must be between 1 and {max_per_page}"
+        )));
+    }
     let params = log_service::ListLogsParams {
+        org_id: identity.org_id,
         page: query.page.unwrap_or(1).max(1),
-        per_page: query.per_page.unwrap_or(50).min(200).max(1),
+        per_page,
         key_id: query.key_id,
         model: query.model,
+        retriable: query.retriable,
     };
     let result = log_service::list_logs(&state.db, params).await?;
     Ok(Json(result))
 }
 
-/// GET /admin/stats — dashboard statistics
+/// GET /admin/logs/:id — one request log, with bodies loaded from the body store
+async fn get_log(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<crate::models::request_log::RequestLogInfo>, AppError> {
+    let log =
+        log_service::get_log(id, identity.org_id, state.body_store.as_ref(), &state.db).await?;
+    Ok(Json(log))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogStreamQuery {
+    pub key_id: Option<Uuid>,
+    pub model: Option<String>,
+}
+
+/// GET /admin/logs/stream — live tail of new request logs as server-sent events
+async fn stream_logs(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Query(query): Query<LogStreamQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let rx = state.log_events.subscribe();
+    let org_id = identity.org_id;
+    let stream = futures::stream::unfold((rx, query), move |(mut rx, query)| async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(log) => {
+                    if log.org_id != org_id {
+                        continue;
+                    }
+                    if query.key_id.is_some() && log.user_key_id != query.key_id {
+                        continue;
+                    }
+                    if query
+                        .model
+                        .as_deref()
+                        .is_some_and(|m| m != log.model_requested)
+                    {
+                        continue;
+                    }
+                    let data = serde_json::to_string(&log).unwrap_or_default();
+                    Event::default().event("log").data(data)
+                }
+                // Slow consumer: report how many events were dropped and keep going
+                Err(RecvError::Lagged(n)) => Event::default().event("lagged").data(n.to_string()),
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((Ok(event), (rx, query)));
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// GET /admin/stats — dashboard statistics, from the dashboard cache when enabled
 async fn get_stats(
     State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<log_service::DashboardStats>, AppError> {
+    let stats = if state.config.dashboard_cache_refresh_secs > 0 {
+        let mut redis = state.redis.clone();
+        log_service::get_cached_dashboard_stats(identity.org_id, &state.db, &mut redis).await?
+    } else {
+        log_service::get_dashboard_stats(identity.org_id, &state.db).await?
+    };
+    Ok(Json(stats))
+}
+
+/// POST /admin/stats/refresh — recompute the dashboard cache now
+async fn refresh_stats(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
 ) -> Result<Json<log_service::DashboardStats>, AppError> {
-    let stats = log_service::get_dashboard_stats(&state.db).await?;
+    let refresh_secs = state.config.dashboard_cache_refresh_secs;
+    if refresh_secs == 0 {
+        return Err(AppError::BadRequest(
+            "The dashboard cache is disabled (DASHBOARD_CACHE_REFRESH_SECS=0)".into(),
+        ));
+    }
+    let mut redis = state.redis.clone();
+    let stats =
+        log_service::refresh_dashboard_stats(identity.org_id, refresh_secs, &state.db, &mut redis)
+            .await?;
     Ok(Json(stats))
 }
 
+/// Most buckets a usage series may span.
+const MAX_STATS_BUCKETS: i64 = 366;
+
+#[derive(Debug, Deserialize)]
+pub struct ModelUsageQuery {
+    /// RFC 3339 timestamp or `YYYY-MM-DD` (UTC midnight).
+    pub from: Option<String>,
+    /// RFC 3339 timestamp or `YYYY-MM-DD` (UTC midnight), exclusive; defaults to now.
+    pub to: Option<String>,
+    /// `day` (default) or `hour`.
+    pub interval: Option<String>,
+    /// Number of models, by total tokens (default 10, max 50).
+    pub limit: Option<i64>,
+}
+
+fn parse_stats_time(field: &str, value: &str) -> Result<chrono::DateTime<chrono::Utc>, AppError> {
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(t.with_timezone(&chrono::Utc));
+    }
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|d| d.and_time(chrono::NaiveTime::MIN).and_utc())
+        .map_err(|_| {
+            AppError::BadRequest(format!("{field} must be an RFC 3339 timestamp or YYYY-MM-DD"))
+        })
+}
+
+/// GET /admin/stats/models/usage — per-model request and token totals per time bucket
+async fn get_model_usage_stats(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Query(query): Query<ModelUsageQuery>,
+) -> Result<Json<log_service::ModelUsageSeries>, AppError> {
+    let interval = match query.interval.as_deref() {
+        None => log_service::StatsInterval::Day,
+        Some(s) => log_service::StatsInterval::from_str(s)
+            .ok_or_else(|| AppError::BadRequest("interval must be 'day' or 'hour'".into()))?,
+    };
+    let to = match query.to.as_deref() {
+        Some(to) => parse_stats_time("to", to)?,
+        None => chrono::Utc::now(),
+    };
+    let from = match query.from.as_deref() {
+        Some(from) => parse_stats_time("from", from)?,
+        None => match interval {
+            log_service::StatsInterval::Day => to - chrono::TimeDelta::days(30),
+            log_service::StatsInterval::Hour => to - chrono::TimeDelta::hours(24),
+        },
+    };
+    if from >= to {
+        return Err(AppError::BadRequest("from must be before to".into()));
+    }
+    if (to - from).num_seconds() / interval.width().num_seconds() >= MAX_STATS_BUCKETS {
+        return Err(AppError::BadRequest(format!(
+            "Range spans more than {MAX_STATS_BUCKETS} {}s",
+            interval.as_str()
+        )));
+    }
+
+    let params = log_service::ModelUsageSeriesParams {
+        org_id: identity.org_id,
+        from,
+        to,
+        interval,
+        limit: query.limit.unwrap_or(10).clamp(1, 50),
+    };
+    let series = log_service::get_model_usage_series(&state.db, params).await?;
+    Ok(Json(series))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DriftQuery {
+    /// Number of keys listed (default 50, max 500).
+    pub limit: Option<i64>,
+}
+
+/// GET /admin/stats/drift — keys whose `tokens_used` differs from their logged usage
+async fn get_drift_stats(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Query(query): Query<DriftQuery>,
+) -> Result<Json<log_service::TokenDrift>, AppError> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let drift = log_service::get_token_drift(identity.org_id, limit, &state.db).await?;
+    Ok(Json(drift))
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RecomputeUsageResponse {
+    pub keys_updated: u64,
+}
+
+/// POST /admin/keys/recompute-usage — reset keys' `tokens_used` to the weighted usage
+/// of their request logs at current coefficients; scoped admins only touch their keys
+async fn recompute_key_usage(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<RecomputeUsageResponse>, AppError> {
+    // Reservations of in-flight requests live only in `tokens_used`; recomputing
+    // would drop them and let their settlement push usage below the real total
+    if state.config.budget_enforcement == BudgetEnforcement::Reserve {
+        return Err(AppError::Conflict(
+            "Usage cannot be recomputed while BUDGET_ENFORCEMENT=reserve".into(),
+        ));
+    }
+    // Write buffered charges first so they are not added on top of the new totals
+    let keys_updated = state
+        .usage
+        .flush_and_recompute(&state.db, || {
+            log_service::recompute_key_usage(identity.org_id, identity.scope.as_deref(), &state.db)
+        })
+        .await?;
+    Ok(Json(RecomputeUsageResponse { keys_updated }))
+}
+
+/// Runtime metrics snapshot.
+#[derive(Debug, serde::Serialize)]
+pub struct MetricsResponse {
+    /// In-flight upstream requests keyed by provider id.
+    pub provider_inflight: std::collections::HashMap<Uuid, i64>,
+    /// Success rate over `ROUTING_HEALTH_WINDOW_SECS`, for providers with recent traffic.
+    pub provider_success_rate: std::collections::HashMap<Uuid, f64>,
+    /// Rate-limit quota from the latest `x-ratelimit-*` headers, for providers that send them.
+    pub provider_quota: std::collections::HashMap<Uuid, crate::metrics::ProviderQuota>,
+    /// Requests slower than `SLOW_REQUEST_MS` since startup (0 when unset).
+    pub slow_requests: u64,
+    /// Streaming responses currently open.
+    pub active_streams: i64,
+    /// `MAX_STREAMS` (null = unlimited).
+    pub max_streams: Option<usize>,
+}
+
+/// GET /admin/metrics — live in-process counters
+async fn get_metrics(State(state): State<Arc<AppState>>) -> Json<MetricsResponse> {
+    Json(MetricsResponse {
+        provider_inflight: state.provider_inflight.snapshot(),
+        provider_success_rate: state.provider_health.snapshot(),
+        provider_quota: state.provider_quotas.snapshot(),
+        slow_requests: state.slow_requests.load(std::sync::atomic::Ordering::Relaxed),
+        active_streams: state.stream_slots.active(),
+        max_streams: state.config.max_streams,
+    })
+}
+
+/// GET /admin/config — effective runtime configuration with secrets redacted
+async fn get_config(State(state): State<Arc<AppState>>) -> Json<crate::config::Config> {
+    Json(state.config.redacted())
+}
+
+// ── Router ────────────────────────────────────────────────────────────
+
 /// Build the admin router (to be nested under /admin)
 pub fn router() -> Router<Arc<AppState>> {
-    Router::new()
-        // User keys
-        .route("/keys", post(create_key).get(list_keys))
-        .route("/keys/{id}", delete(delete_key_handler).put(update_key_handler))
-        .route("/keys/{id}/rotate", post(rotate_key))
+    // Process-wide state spans every organization: ADMIN_KEY only
+    let instance_admin_routes = Router::new()
+        .route("/cache/keys", get(cached_keys_handler))
+        // Metrics
+        .route("/metrics", get(get_metrics))
+        // Effective config
+        .route("/config", get(get_config))
+        .route_layer(axum_mw::from_fn(require_instance_admin));
+
+    // Everything except user keys is off-limits to scoped admins
+    let full_admin_routes = Router::new()
         // Providers
+        .route("/key-groups", post(create_key_group).get(list_key_groups))
+        .route(
+            "/key-groups/{id}",
+            get(get_key_group).put(update_key_group).delete(delete_key_group),
+        )
         .route("/providers", post(create_provider).get(list_providers))
-        .route("/providers/{id}", delete(delete_provider_handler).put(update_provider))
+        .route(
+            "/providers/{id}",
+            get(get_provider_handler)
+                .put(update_provider)
+                .delete(delete_provider_handler),
+        )
+        .route("/providers/{id}/discover", post(discover_provider_models))
         // Models
         .route("/models", post(create_model).get(list_models))
         .route("/models/{id}", delete(delete_model_handler).put(update_model_handler))
+        .route("/models/{id}/alternates", post(create_alternate).get(list_alternates))
+        .route("/models/{id}/alternates/{route_id}", delete(delete_alternate))
+        // Cache
+        .route("/cache/routes", get(cached_routes_handler))
+        .route("/cache/route/{model_name}", delete(invalidate_route_handler))
+        // Export / import
+        .route("/export", get(export_config))
+        .route("/import", post(import_config))
         // Logs
         .route("/logs", get(list_logs))
+        .route("/logs/stream", get(stream_logs))
+        .route("/logs/{id}", get(get_log))
         // Dashboard stats
         .route("/stats", get(get_stats))
+        .route("/stats/refresh", post(refresh_stats))
+        .route("/stats/models/usage", get(get_model_usage_stats))
+        .route("/stats/drift", get(get_drift_stats))
+        .merge(instance_admin_routes)
+        .route_layer(axum_mw::from_fn(require_full_admin));
+
+    Router::new()
+        // User keys (scoped admins limited to their tag)
+        .route("/keys", post(create_key).get(list_keys))
+        .route("/keys/{id}", delete(delete_key_handler).put(update_key_handler))
+        .route("/keys/recompute-usage", post(recompute_key_usage))
+        .route("/keys/{id}/rotate", post(rotate_key))
+        .route("/keys/{id}/model-budgets", get(list_key_model_budgets))
+        .route(
+            "/keys/{id}/model-budgets/{model}",
+            delete(delete_key_model_budget).put(set_key_model_budget),
+        )
+        .merge(full_admin_routes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sqlx::PgPool;
+
+    use crate::services::org_service::DEFAULT_ORG_ID;
+
+    const FULL_ADMIN: AdminIdentity = AdminIdentity { org_id: DEFAULT_ORG_ID, scope: None };
+
+    /// Insert a key of the default organization with `token_budget`; returns its id.
+    async fn insert_key(token_budget: Option<i64>, db: &PgPool) -> Uuid {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO user_keys
+                 (id, name, key_hash, key_prefix, token_budget, tokens_used, org_id)
+             VALUES ($1, 'test', $2, 'sk-test', $3, 40, $4)",
+        )
+        .bind(id)
+        .bind(id.to_string())
+        .bind(token_budget)
+        .bind(DEFAULT_ORG_ID)
+        .execute(db)
+        .await
+        .unwrap();
+        id
+    }
+
+    /// PUT /admin/keys/:id with `body` as `identity`.
+    async fn update_key(
+        state: &Arc<AppState>,
+        identity: AdminIdentity,
+        id: Uuid,
+        body: serde_json::Value,
+    ) -> Result<crate::models::user_key::UserKeyInfo, AppError> {
+        let body = serde_json::from_value(body).unwrap();
+        let result =
+            update_key_handler(State(state.clone()), Extension(identity), Path(id), Json(body))
+                .await;
+        result.map(|Json(key)| key)
+    }
+
+    fn bad_request(result: Result<(), AppError>) -> String {
+        match result {
+            Err(AppError::BadRequest(msg)) => msg,
+            other => panic!("expected a bad request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn nan_and_negative_rates_are_rejected_by_field() {
+        let msg = bad_request(validate_model_rates(Some(f64::NAN), None, None, None, None, None));
+        assert!(msg.starts_with("input_token_coefficient "), "{msg}");
+        let msg = bad_request(validate_model_rates(None, Some(-1.0), None, None, None, None));
+        assert!(msg.starts_with("output_token_coefficient "), "{msg}");
+        let infinite_price = Some(f64::INFINITY);
+        let msg = bad_request(validate_model_rates(None, None, None, None, None, infinite_price));
+        assert!(msg.starts_with("output_price_per_mtok "), "{msg}");
+    }
+
+    #[test]
+    fn finite_non_negative_rates_are_accepted() {
+        let rates = validate_model_rates(Some(0.0), Some(1.5), Some(4.0), Some(0.1), None, Some(10.0));
+        assert!(rates.is_ok());
+    }
+
+    #[test]
+    fn negative_token_budget_is_rejected() {
+        assert_eq!(bad_request(validate_token_budget(Some(-1))), "token_budget must be >= 0");
+        assert!(validate_token_budget(Some(0)).is_ok());
+        assert!(validate_token_budget(None).is_ok());
+    }
+
+    #[test]
+    fn chat_path_must_be_an_absolute_path_without_whitespace() {
+        assert!(validate_chat_path("/v1/chat/completions").is_ok());
+        assert!(validate_chat_path("/chat?api-version=2024-06-01").is_ok());
+        assert!(validate_chat_path("chat/completions").is_err());
+        assert!(validate_chat_path("/chat completions").is_err());
+        assert!(validate_chat_path(&format!("/{}", "a".repeat(MAX_CHAT_PATH_LEN))).is_err());
+    }
+
+    #[sqlx::test]
+    async fn partial_key_update_keeps_the_budget(db: PgPool) {
+        let state = AppState::for_tests(db, &[]).await;
+        let id = insert_key(Some(500), &state.db).await;
+
+        let key = update_key(&state, FULL_ADMIN, id, serde_json::json!({ "tag": "team-a" }))
+            .await
+            .unwrap();
+        assert_eq!(key.tag.as_deref(), Some("team-a"));
+        assert_eq!(key.token_budget, Some(500));
+        assert_eq!(key.tokens_used, 40);
+
+        let body = serde_json::json!({ "token_budget": null, "reset_usage": true });
+        let key = update_key(&state, FULL_ADMIN, id, body).await.unwrap();
+        assert_eq!(key.token_budget, None);
+        assert_eq!(key.tokens_used, 0);
+    }
+
+    /// Stored `tokens_used` of `key_id` and of `group_id` (0 without a group).
+    async fn usage_of(state: &AppState, key_id: Uuid, group_id: Option<Uuid>) -> (i64, i64) {
+        let key: i64 = sqlx::query_scalar("SELECT tokens_used FROM user_keys WHERE id = $1")
+            .bind(key_id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        let group = match group_id {
+            Some(id) => key_group_service::get_group(id, DEFAULT_ORG_ID, &state.db)
+                .await
+                .unwrap()
+                .tokens_used,
+            None => 0,
+        };
+        (key, group)
+    }
+
+    #[sqlx::test]
+    async fn resets_and_group_changes_settle_buffered_charges(db: PgPool) {
+        let state = AppState::for_tests(db, &[("USAGE_FLUSH_INTERVAL_MS", "60000")]).await;
+        let id = insert_key(None, &state.db).await;
+        let group = key_group_service::create_group(DEFAULT_ORG_ID, "team", None, &state.db)
+            .await
+            .unwrap()
+            .id;
+
+        // A reset key starts from zero once its buffered charges are flushed
+        state.usage.charge(id, None, 100, &state.db).await.unwrap();
+        let body = serde_json::json!({ "reset_usage": true });
+        let key = update_key(&state, FULL_ADMIN, id, body).await.unwrap();
+        assert_eq!(key.tokens_used, 0);
+        assert_eq!(state.usage.pending(id, None), 0);
+        state.usage.flush(&state.db).await.unwrap();
+        assert_eq!(usage_of(&state, id, None).await, (0, 0));
+
+        // Charges made before a group change stay out of the new group
+        state.usage.charge(id, None, 30, &state.db).await.unwrap();
+        let body = serde_json::json!({ "group_id": group });
+        update_key(&state, FULL_ADMIN, id, body).await.unwrap();
+        state.usage.flush(&state.db).await.unwrap();
+        assert_eq!(usage_of(&state, id, Some(group)).await, (30, 0));
+
+        // A reset group starts from zero; its keys keep counting for reporting
+        state.usage.charge(id, Some(group), 50, &state.db).await.unwrap();
+        let body = serde_json::from_value(serde_json::json!({ "reset_usage": true })).unwrap();
+        let Json(reset) =
+            update_key_group(State(state.clone()), Extension(FULL_ADMIN), Path(group), Json(body))
+                .await
+                .unwrap();
+        assert_eq!(reset.tokens_used, 0);
+        assert_eq!(state.usage.pending(id, Some(group)), 0);
+        state.usage.flush(&state.db).await.unwrap();
+        assert_eq!(usage_of(&state, id, Some(group)).await, (80, 0));
+    }
+
+    /// A key tagged `team-a` and an admin scoped to that tag.
+    async fn scoped_key(state: &AppState) -> (Uuid, AdminIdentity) {
+        let id = insert_key(Some(500), &state.db).await;
+        sqlx::query("UPDATE user_keys SET tag = 'team-a' WHERE id = $1")
+            .bind(id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        (id, AdminIdentity { org_id: DEFAULT_ORG_ID, scope: Some("team-a".into()) })
+    }
+
+    /// PUT `body` as `identity` and expect it to fail with the key left as it was.
+    async fn update_is_rejected(
+        state: &Arc<AppState>,
+        identity: AdminIdentity,
+        id: Uuid,
+        body: serde_json::Value,
+    ) -> AppError {
+        let row = || {
+            sqlx::query_scalar::<_, serde_json::Value>(
+                "SELECT to_jsonb(k) FROM user_keys k WHERE id = $1",
+            )
+            .bind(id)
+            .fetch_one(&state.db)
+        };
+        let before = row().await.unwrap();
+        let error = update_key(state, identity, id, body).await.unwrap_err();
+        assert_eq!(row().await.unwrap(), before);
+        error
+    }
+
+    #[sqlx::test]
+    async fn forbidden_provider_override_changes_nothing(db: PgPool) {
+        let state = AppState::for_tests(db, &[]).await;
+        let (id, scoped) = scoped_key(&state).await;
+
+        let body = serde_json::json!({ "tpm_limit": 100, "provider_override_id": Uuid::new_v4() });
+        let error = update_is_rejected(&state, scoped, id, body).await;
+        assert!(matches!(error, AppError::Forbidden(_)), "{error:?}");
+    }
+
+    #[sqlx::test]
+    async fn forbidden_byo_provider_key_changes_nothing(db: PgPool) {
+        let state = AppState::for_tests(db, &[]).await;
+        let (id, scoped) = scoped_key(&state).await;
+
+        let body = serde_json::json!({ "metadata": { "team": "a" }, "allow_byo_provider_key": true });
+        let error = update_is_rejected(&state, scoped, id, body).await;
+        assert!(matches!(error, AppError::Forbidden(_)), "{error:?}");
+    }
+
+    #[sqlx::test]
+    async fn invalid_field_rejects_the_whole_key_update(db: PgPool) {
+        let state = AppState::for_tests(db, &[]).await;
+        let id = insert_key(Some(500), &state.db).await;
+
+        let body = serde_json::json!({
+            "tag": "team-b",
+            "tpm_limit": 100,
+            "token_budget": 900,
+            "allowed_provider_kinds": ["bogus"],
+        });
+        let error = update_is_rejected(&state, FULL_ADMIN, id, body).await;
+        assert!(matches!(error, AppError::BadRequest(_)), "{error:?}");
+
+        let body = serde_json::json!({ "tpm_limit": 100, "group_id": Uuid::new_v4() });
+        let error = update_is_rejected(&state, FULL_ADMIN, id, body).await;
+        assert!(!matches!(error, AppError::Internal(_)), "{error:?}");
+    }
 }
@@ -0,0 +1,157 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::ReadinessRequire;
+use crate::state::AppState;
+
+/// Status of a single dependency check.
+#[derive(Debug, Serialize)]
+pub struct DependencyStatus {
+    /// "up", "down" or "timeout"
+    pub status: &'static str,
+    /// Whether this dependency is required for readiness (per `READINESS_REQUIRE`).
+    pub critical: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessChecks {
+    pub redis: DependencyStatus,
+    pub db: DependencyStatus,
+}
+
+/// Readiness response body.
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    /// "ready" | "degraded" | "unavailable"
+    pub status: &'static str,
+    pub checks: ReadinessChecks,
+}
+
+fn dependency_status(result: Result<(), String>, critical: bool) -> DependencyStatus {
+    match result {
+        Ok(()) => DependencyStatus {
+            status: "up",
+            critical,
+            error: None,
+        },
+        Err(e) => DependencyStatus {
+            status: "down",
+            critical,
+            error: Some(e),
+        },
+    }
+}
+
+/// Run a dependency check, reporting a timeout once `timeout` passes without a result.
+async fn check(
+    probe: impl Future<Output = Result<(), String>>,
+    timeout: Duration,
+    critical: bool,
+) -> DependencyStatus {
+    match tokio::time::timeout(timeout, probe).await {
+        Ok(result) => dependency_status(result, critical),
+        Err(_) => DependencyStatus {
+            status: "timeout",
+            critical,
+            error: Some(format!("No response within {}ms", timeout.as_millis())),
+        },
+    }
+}
+
+/// GET /health — liveness probe, always 200 while the process is serving
+async fn health() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// GET /ready — readiness probe; checks Redis and Postgres concurrently, each within
+/// `READINESS_TIMEOUT_MS`, according to `READINESS_REQUIRE`
+async fn ready(State(state): State<Arc<AppState>>) -> Response {
+    let mut redis = state.redis.clone();
+    let redis_probe = async {
+        redis::cmd("PING")
+            .query_async::<String>(&mut redis)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    };
+
+    let db_probe = async {
+        sqlx::query("SELECT 1")
+            .execute(&state.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    };
+
+    let (redis_critical, db_critical) = match state.config.readiness_require {
+        ReadinessRequire::Both => (true, true),
+        ReadinessRequire::Redis => (true, false),
+        ReadinessRequire::Db => (false, true),
+    };
+
+    let timeout = Duration::from_millis(state.config.readiness_timeout_ms);
+    let (redis, db) = tokio::join!(
+        check(redis_probe, timeout, redis_critical),
+        check(db_probe, timeout, db_critical),
+    );
+    let checks = ReadinessChecks { redis, db };
+
+    let critical_down = [&checks.redis, &checks.db]
+        .iter()
+        .any(|c| c.critical && c.error.is_some());
+    let any_down = checks.redis.error.is_some() || checks.db.error.is_some();
+
+    let (status, label) = if critical_down {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    } else if any_down {
+        (StatusCode::OK, "degraded")
+    } else {
+        (StatusCode::OK, "ready")
+    };
+
+    (
+        status,
+        Json(ReadinessResponse {
+            status: label,
+            checks,
+        }),
+    )
+        .into_response()
+}
+
+/// Build the health router (mounted at the root, no auth)
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn hung_dependency_is_reported_as_timed_out() {
+        let timeout = Duration::from_millis(50);
+        let (hung, up) = tokio::join!(
+            check(std::future::pending(), timeout, true),
+            check(async { Ok(()) }, timeout, false),
+        );
+        assert_eq!(hung.status, "timeout");
+        assert_eq!(hung.error.as_deref(), Some("No response within 50ms"));
+        assert!(hung.critical);
+        assert_eq!(up.status, "up");
+        assert!(up.error.is_none());
+    }
+}
@@ -1,2 +1,28 @@
 pub mod admin;
+pub mod health;
 pub mod proxy;
+
+use axum::http::StatusCode;
+use axum::response::Response;
+
+use crate::error::{json_error, ErrorType};
+
+/// Fallback for paths no route matches, in the usual JSON error shape.
+pub async fn not_found() -> Response {
+    json_error(
+        StatusCode::NOT_FOUND,
+        ErrorType::NotFound,
+        "Not found",
+        Some("route_not_found"),
+    )
+}
+
+/// Fallback for known paths requested with an unsupported method.
+pub async fn method_not_allowed() -> Response {
+    json_error(
+        StatusCode::METHOD_NOT_ALLOWED,
+        ErrorType::InvalidRequest,
+        "Method not allowed",
+        Some("method_not_allowed"),
+    )
+}
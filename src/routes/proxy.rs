@@ -2,48 +2,386 @@ use axum::{
     body::Body,
     extract::State,
     http::{header, HeaderMap, HeaderValue, StatusCode},
-    response::{IntoResponse, Response},
+    response::Response,
     routing::post,
     Extension, Router,
 };
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+use crate::config::{
+    is_credential_header, AuthFailMode, BudgetEnforcement, EmptyCompletionPolicy,
+    MissingUsagePolicy,
+};
+use crate::error::{json_error, rate_limit_error, ErrorType, LimitType};
+use crate::metrics::{InflightGuard, ProviderHealth};
 use crate::middleware::auth::KeyIdentity;
+use crate::models::model::ModelRoute;
+use crate::models::usage::Usage;
+use crate::request_id;
+use crate::services::log_service::ErrorSource;
 use crate::services::{key_service, log_service, model_service};
 use crate::state::AppState;
 
 type ByteChunk = Vec<u8>;
 
-/// POST /v1/chat/completions — proxy to the provider resolved from the model name
+/// Request header that opts a single call out of request/response body storage.
+const NO_STORE_HEADER: &str = "x-gateway-no-store";
+
+/// Request header naming the region whose providers should serve the call.
+const REGION_HEADER: &str = "x-gateway-region";
+
+/// Request header identifying a conversation whose requests should stick to one provider.
+const SESSION_HEADER: &str = "x-gateway-session-id";
+
+/// Request header setting an end-to-end deadline for the call, in milliseconds.
+const TIMEOUT_HEADER: &str = "x-gateway-timeout-ms";
+
+/// Request header carrying the client's own provider API key, used instead of the
+/// provider's configured key by keys allowed to (`allow_byo_provider_key`).
+const PROVIDER_API_KEY_HEADER: &str = "x-provider-api-key";
+
+/// Response header marking a model's fallback response (every route failed).
+const FALLBACK_HEADER: &str = "x-gateway-fallback";
+
+/// Response header marking token counts the gateway estimated instead of the provider
+/// reporting them (buffered responses only; streams send headers before usage).
+const USAGE_ESTIMATED_HEADER: &str = "x-gateway-usage-estimated";
+
+/// Response header carrying the gateway-generated request id.
+const REQUEST_ID_HEADER: &str = "x-gateway-request-id";
+
+/// POST /v1/chat/completions — proxy to the provider resolved from the model name.
+/// Every response, errors included, carries the request id logged for it.
 async fn chat_completions(
     State(state): State<Arc<AppState>>,
     Extension(key_identity): Extension<KeyIdentity>,
     headers: HeaderMap,
     body: axum::body::Bytes,
+) -> Response {
+    let request_id =
+        request_id::generate(state.config.request_id_format, &state.config.request_id_prefix);
+    let start = Instant::now();
+    let no_store = headers.contains_key(NO_STORE_HEADER);
+    let byo_provider_key =
+        key_identity.allow_byo_provider_key && headers.contains_key(PROVIDER_API_KEY_HEADER);
+    let request_headers = (state.config.log_request_headers && !no_store)
+        .then(|| log_service::redacted_headers(&headers, &state.config));
+    let result = proxy_chat_completion(
+        state.clone(),
+        key_identity.clone(),
+        headers,
+        body.clone(),
+        request_id.clone(),
+    )
+    .await;
+    // Responses are logged by the proxy; errors it returns early are logged here
+    let mut response = match result {
+        Ok(response) => response,
+        Err(response) => {
+            let rejected = RejectedRequest {
+                key_identity,
+                body,
+                request_id: request_id.clone(),
+                latency_ms: start.elapsed().as_millis() as i32,
+                no_store,
+                byo_provider_key,
+                request_headers,
+            };
+            log_rejected_request(&state, rejected, response).await
+        }
+    };
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// A request `proxy_chat_completion` returned an error for.
+struct RejectedRequest {
+    key_identity: KeyIdentity,
+    body: axum::body::Bytes,
+    request_id: String,
+    latency_ms: i32,
+    no_store: bool,
+    byo_provider_key: bool,
+    request_headers: Option<serde_json::Value>,
+}
+
+/// Log a request that ended in an error response without reaching the point where
+/// the proxy logs it (invalid body, unknown model, budget, all routes unreachable,
+/// ...), and return the response unchanged. 502 and 504 are the gateway reporting
+/// on the provider, so their `error_source` is `upstream`; everything else is `gateway`.
+async fn log_rejected_request(
+    state: &AppState,
+    rejected: RejectedRequest,
+    response: Response,
+) -> Response {
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, 64 * 1024).await.unwrap_or_default();
+    let error_message = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .and_then(|e| e.pointer("/error/message")?.as_str().map(str::to_string));
+    let error_source = match parts.status {
+        StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT => ErrorSource::Upstream,
+        _ => ErrorSource::Gateway,
+    };
+
+    let body_json: Option<serde_json::Value> = serde_json::from_slice(&rejected.body).ok();
+    let model_requested = body_json
+        .as_ref()
+        .and_then(|b| b.get("model")?.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let is_stream = body_json
+        .as_ref()
+        .and_then(|b| b.get("stream")?.as_bool())
+        .unwrap_or(false);
+    let request_body = if state.config.log_request_body && !rejected.no_store {
+        log_service::truncate_body(body_json, state.config.log_body_max_bytes)
+    } else {
+        None
+    };
+    let status_code = parts.status.as_u16() as i16;
+
+    let log_context = LogContext {
+        key_identity: rejected.key_identity,
+        request_id: rejected.request_id,
+        model_requested,
+        is_stream,
+        request_body,
+        request_headers: rejected.request_headers,
+        byo_provider_key: rejected.byo_provider_key,
+        no_store: rejected.no_store,
+    };
+    let mut new_log = log_service::NewRequestLog {
+        is_error: true,
+        retriable: is_retriable_status(status_code),
+        error_message,
+        error_source: Some(error_source),
+        ..log_context.entry(status_code, rejected.latency_ms)
+    };
+    // Sent upstream before giving up: the deadline passed or no route answered
+    if let Some(upstream) = parts.extensions.get::<UpstreamAttempts>() {
+        new_log.model_sent = upstream.model_sent.clone();
+        new_log.provider_id = Some(upstream.provider_id);
+        new_log.provider_kind = Some(upstream.provider_kind.clone());
+        new_log.attempts = upstream.attempts;
+    }
+    spawn_log(state, new_log);
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Request-level fields shared by every log entry written for one request.
+struct LogContext {
+    key_identity: KeyIdentity,
+    request_id: String,
+    model_requested: String,
+    is_stream: bool,
+    request_body: Option<serde_json::Value>,
+    request_headers: Option<serde_json::Value>,
+    byo_provider_key: bool,
+    no_store: bool,
+}
+
+impl LogContext {
+    /// A log entry for this request answered with `status_code`: no provider, usage
+    /// or error recorded yet.
+    fn entry(&self, status_code: i16, latency_ms: i32) -> log_service::NewRequestLog {
+        log_service::NewRequestLog {
+            org_id: self.key_identity.org_id,
+            request_id: Some(self.request_id.clone()),
+            upstream_request_id: None,
+            user_key_id: Some(self.key_identity.key_id),
+            user_key_hash: self.key_identity.key_hash.clone(),
+            model_requested: self.model_requested.clone(),
+            model_sent: self.model_requested.clone(),
+            provider_id: None,
+            provider_kind: None,
+            status_code,
+            is_error: false,
+            retriable: false,
+            prompt_tokens: None,
+            completion_tokens: None,
+            total_tokens: None,
+            reasoning_tokens: None,
+            cached_prompt_tokens: None,
+            latency_ms,
+            is_stream: self.is_stream,
+            request_body: self.request_body.clone(),
+            response_body: None,
+            error_message: None,
+            error_source: None,
+            finish_reason: None,
+            tokens_estimated: false,
+            tokenizer: None,
+            attempts: 0,
+            request_headers: self.request_headers.clone(),
+            byo_provider_key: self.byo_provider_key,
+            stored: !self.no_store,
+            is_fallback: false,
+        }
+    }
+}
+
+/// Insert a request log in the background and announce it to live-tail subscribers.
+fn spawn_log(state: &AppState, new_log: log_service::NewRequestLog) {
+    let db = state.db.clone();
+    let log_events = state.log_events.clone();
+    let body_store = state.body_store.clone();
+    tokio::spawn(async move {
+        let event = log_service::LogEvent::from(&new_log);
+        match log_service::insert_log(&db, body_store.as_ref(), new_log).await {
+            Ok(()) => {
+                // No subscribers is the common case; ignore the send error
+                let _ = log_events.send(event);
+            }
+            Err(e) => tracing::error!("Failed to insert request log: {}", e),
+        }
+    });
+}
+
+/// Charge a finished request: its weighted tokens go against the key's (or group's)
+/// budget and against the model budget, settling their reservations; then budget
+/// alerts are checked and raw tokens counted against the per-minute limit.
+async fn charge_usage(
+    state: &AppState,
+    key_identity: &KeyIdentity,
+    route: &ModelRoute,
+    usage: Usage,
+    reservation: Option<BudgetReservation>,
+    budget_model: Option<&str>,
+    model_reservation: Option<BudgetReservation>,
+) {
+    let mut redis = state.redis.clone();
+    let weighted = route.weighted_tokens(
+        usage.prompt.unwrap_or(0),
+        usage.completion.unwrap_or(0),
+        usage.reasoning.unwrap_or(0),
+        usage.cached.unwrap_or(0),
+    );
+    let charge = match reservation {
+        Some(reservation) => reservation.settle(weighted),
+        None => weighted,
+    };
+    if charge != 0 {
+        if let Err(e) = state
+            .usage
+            .charge(key_identity.key_id, key_identity.group_id, charge, &state.db)
+            .await
+        {
+            tracing::error!("Failed to increment token usage: {}", e);
+        }
+    }
+    if let Some(model) = budget_model {
+        let charge = match model_reservation {
+            Some(reservation) => reservation.settle(weighted),
+            None => weighted,
+        };
+        if charge != 0 {
+            if let Err(e) =
+                state.usage.charge_model(key_identity.key_id, model, charge, &state.db).await
+            {
+                tracing::error!("Failed to increment model token usage: {}", e);
+            }
+        }
+    }
+    if let Err(e) = state
+        .budget_alerts
+        .check(
+            key_identity.key_id,
+            key_identity.group_id,
+            key_identity.token_budget,
+            key_identity.tokens_used + weighted,
+            &mut redis,
+        )
+        .await
+    {
+        tracing::error!("Budget alert check failed: {}", e);
+    }
+
+    // Count raw tokens against the key's per-minute limit
+    if key_identity.tpm_limit.is_some() {
+        let tokens = (usage.prompt.unwrap_or(0) + usage.completion.unwrap_or(0)) as i64;
+        if tokens > 0 {
+            let key_id = key_identity.key_id;
+            if let Err(e) = key_service::add_minute_tokens(key_id, tokens, &mut redis).await {
+                tracing::error!("Failed to update TPM counter: {}", e);
+            }
+        }
+    }
+}
+
+/// The upstream sends made for a request that ended in an error response anyway
+/// (deadline passed, no route answered), for its log entry.
+#[derive(Clone)]
+struct UpstreamAttempts {
+    attempts: i32,
+    provider_id: uuid::Uuid,
+    provider_kind: String,
+    model_sent: String,
+}
+
+impl UpstreamAttempts {
+    fn new(attempts: u32, route: &ModelRoute) -> Self {
+        Self {
+            attempts: attempts as i32,
+            provider_id: route.provider_id,
+            provider_kind: route.provider_kind.clone(),
+            model_sent: route.provider_model_name.clone(),
+        }
+    }
+}
+
+async fn proxy_chat_completion(
+    state: Arc<AppState>,
+    key_identity: KeyIdentity,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+    request_id: String,
 ) -> Result<Response, Response> {
     let start = Instant::now();
 
+    // The body is parsed as JSON whatever its Content-Type, so say so up front
+    // rather than reporting a JSON syntax error for a form or text body
+    if let Some(content_type) = headers.get(header::CONTENT_TYPE) {
+        if !is_json_content_type(content_type) {
+            return Err(json_error(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ErrorType::InvalidRequest,
+                format!(
+                    "Content-Type must be application/json, got {}",
+                    String::from_utf8_lossy(content_type.as_bytes())
+                ),
+                Some("unsupported_media_type"),
+            ));
+        }
+    }
+
     // Parse body to extract model name and stream flag
     let mut body_json: serde_json::Value =
         serde_json::from_slice(&body).map_err(|e| {
-            (
+            json_error(
                 StatusCode::BAD_REQUEST,
-                axum::Json(serde_json::json!({ "error": { "message": format!("Invalid JSON: {e}") } })),
+                ErrorType::InvalidRequest,
+                format!("Invalid JSON: {e}"),
+                Some("invalid_json"),
             )
-                .into_response()
         })?;
 
     let model_name = body_json
         .get("model")
         .and_then(|v| v.as_str())
         .ok_or_else(|| {
-            (
+            json_error(
                 StatusCode::BAD_REQUEST,
-                axum::Json(serde_json::json!({ "error": { "message": "\"model\" field is required" } })),
+                ErrorType::InvalidRequest,
+                "\"model\" field is required",
+                Some("model_required"),
             )
-                .into_response()
         })?
         .to_string();
 
@@ -52,316 +390,1233 @@ async fn chat_completions(
         .and_then(|v| v.as_bool())
         .unwrap_or(false);
 
+    // `x-gateway-timeout-ms`: every upstream attempt must finish (body included) by
+    // the deadline, counted from the start of the request
+    let timeout_ms = match headers.get(TIMEOUT_HEADER) {
+        None => None,
+        Some(value) => {
+            let max = state.config.max_request_timeout_ms;
+            let timeout_ms = parse_timeout_ms(value, max).ok_or_else(|| {
+                json_error(
+                    StatusCode::BAD_REQUEST,
+                    ErrorType::InvalidRequest,
+                    format!("{TIMEOUT_HEADER} must be a number of milliseconds from 1 to {max}"),
+                    Some("invalid_timeout"),
+                )
+            })?;
+            Some(timeout_ms)
+        }
+    };
+    let deadline = timeout_ms.map(|ms| start + Duration::from_millis(ms));
+
+    // `x-provider-api-key`: trusted keys may have the request sent with their own
+    // provider credentials; the key is used for this request only and never stored
+    let byo_api_key = match headers.get(PROVIDER_API_KEY_HEADER) {
+        None => None,
+        Some(_) if !key_identity.allow_byo_provider_key => {
+            return Err(json_error(
+                StatusCode::FORBIDDEN,
+                ErrorType::Permission,
+                format!("This key may not send {PROVIDER_API_KEY_HEADER}"),
+                Some("byo_provider_key_not_allowed"),
+            ));
+        }
+        Some(value) => {
+            let api_key = value
+                .to_str()
+                .ok()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| {
+                    json_error(
+                        StatusCode::BAD_REQUEST,
+                        ErrorType::InvalidRequest,
+                        format!("{PROVIDER_API_KEY_HEADER} must be a non-empty API key"),
+                        Some("invalid_parameter"),
+                    )
+                })?;
+            Some(api_key.to_string())
+        }
+    };
+    let byo_provider_key = byo_api_key.is_some();
+
+    // Reject pathological prompts before contacting the provider
+    if let Err((code, msg)) = check_prompt_limits(
+        &body_json,
+        state.config.max_messages,
+        state.config.max_prompt_chars,
+    ) {
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            ErrorType::InvalidRequest,
+            msg,
+            Some(code),
+        ));
+    }
+    if state.config.validate_request_fields {
+        if let Err(msg) = check_request_fields(&body_json) {
+            return Err(json_error(
+                StatusCode::BAD_REQUEST,
+                ErrorType::InvalidRequest,
+                msg,
+                Some("invalid_parameter"),
+            ));
+        }
+    }
+
     // Check token budget before proxying
     if let Some(budget) = key_identity.token_budget {
         if key_identity.tokens_used >= budget {
-            return Err((
-                StatusCode::TOO_MANY_REQUESTS,
-                axum::Json(serde_json::json!({
-                    "error": {
-                        "message": format!(
-                            "Token budget exhausted: {}/{} tokens used",
-                            key_identity.tokens_used, budget
-                        )
-                    }
-                })),
-            )
-                .into_response());
+            let scope = if key_identity.group_id.is_some() {
+                "Key group token budget"
+            } else {
+                "Token budget"
+            };
+            return Err(rate_limit_error(
+                LimitType::Budget,
+                format!(
+                    "{} exhausted: {}/{} tokens used",
+                    scope, key_identity.tokens_used, budget
+                ),
+                "budget_exhausted",
+                None,
+            ));
+        }
+    }
+
+    // Per-model budget of the key, checked like the overall budget. A failed lookup
+    // rejects the request unless AUTH_FAIL_MODE=open
+    let mut budget_model = None;
+    if key_identity.has_model_budgets {
+        let key_id = key_identity.key_id;
+        match key_service::get_key_model_budget(key_id, &model_name, &state.db).await {
+            Ok(Some(budget)) => {
+                let used = budget.tokens_used + state.usage.pending_model(key_id, &model_name);
+                if used >= budget.token_budget {
+                    return Err(rate_limit_error(
+                        LimitType::Budget,
+                        format!(
+                            "Token budget for model \"{}\" exhausted: {}/{} tokens used",
+                            model_name, used, budget.token_budget
+                        ),
+                        "model_budget_exhausted",
+                        None,
+                    ));
+                }
+                budget_model = Some(model_name.clone());
+            }
+            Ok(None) => {}
+            Err(e) if state.config.auth_fail_mode == AuthFailMode::Open => {
+                tracing::error!(
+                    "Model budget lookup failed, skipping the check (AUTH_FAIL_MODE=open): {}",
+                    e
+                );
+            }
+            Err(e) => {
+                tracing::error!("Model budget lookup failed: {}", e);
+                return Err(json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorType::Api,
+                    "Internal server error",
+                    None,
+                ));
+            }
+        }
+    }
+
+    // Per-key tokens-per-minute limit: usage is only known after the response, so
+    // block only once the current minute's total has already reached the limit
+    if let Some(tpm_limit) = key_identity.tpm_limit {
+        let mut redis = state.redis.clone();
+        let used = key_service::current_minute_tokens(key_identity.key_id, &mut redis)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("TPM counter lookup failed: {}", e);
+                0
+            });
+        if used >= tpm_limit {
+            // The counter rolls over at the start of the next minute
+            let now = chrono::Utc::now().timestamp();
+            let reset_at = chrono::DateTime::from_timestamp(now - now % 60 + 60, 0);
+            return Err(rate_limit_error(
+                LimitType::Tpm,
+                format!("Tokens-per-minute limit exceeded: {used}/{tpm_limit} tokens this minute"),
+                "tpm_exceeded",
+                reset_at,
+            ));
         }
     }
 
     // Resolve model → provider routing
     let mut redis = state.redis.clone();
-    let route = model_service::resolve_model_route(&model_name, &mut redis, &state.db)
+    let route = model_service::resolve_model_route(
+        key_identity.org_id,
+        &model_name,
+        state.config.model_route_cache_ttl_secs,
+        &mut redis,
+        &state.db,
+    )
         .await
         .map_err(|e| {
             tracing::error!("Model route resolution error: {}", e);
-            (
+            json_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                axum::Json(serde_json::json!({ "error": { "message": "Internal server error" } })),
+                ErrorType::Api,
+                "Internal server error",
+                None,
             )
-                .into_response()
         })?
         .ok_or_else(|| {
-            (
+            json_error(
                 StatusCode::BAD_REQUEST,
-                axum::Json(serde_json::json!({
-                    "error": { "message": format!("Model \"{model_name}\" is not configured in the gateway") }
-                })),
+                ErrorType::InvalidRequest,
+                format!("Model \"{model_name}\" is not configured in the gateway"),
+                Some("model_not_found"),
             )
-                .into_response()
         })?;
 
-    // Capture log context
-    let log_request_body = state.config.log_request_body;
-    let log_response_body = state.config.log_response_body;
-    let saved_request_body = if log_request_body {
-        Some(body_json.clone())
-    } else {
-        None
-    };
+    // Bound completions of requests without a cap: the model's default_max_tokens,
+    // else DEFAULT_MAX_TOKENS. A client-provided cap is always forwarded as-is.
+    let default_max_tokens = route
+        .default_max_tokens
+        .map(|n| n.max(1) as u32)
+        .or(state.config.default_max_tokens);
+    if let Some(max_tokens) = default_max_tokens {
+        let client_cap = ["max_tokens", "max_completion_tokens"]
+            .iter()
+            .any(|field| body_json.get(field).is_some_and(|v| !v.is_null()));
+        if !client_cap {
+            body_json["max_tokens"] = serde_json::Value::from(max_tokens);
+        }
+    }
 
-    // Rewrite model name if the provider uses a different name
-    let model_sent = route.provider_model_name.clone();
-    if route.provider_model_name != model_name {
-        body_json["model"] = serde_json::Value::String(route.provider_model_name.clone());
+    // Reject requests whose worst-case cost at the model's prices exceeds the ceiling
+    if let Some(max_cost) = state.config.max_request_cost_usd {
+        if let Some(cost) = projected_cost_usd(&body_json, &route) {
+            if cost > max_cost {
+                return Err(json_error(
+                    StatusCode::BAD_REQUEST,
+                    ErrorType::InvalidRequest,
+                    format!(
+                        "Projected request cost ${cost:.4} exceeds the ${max_cost:.4} limit; \
+                         lower max_tokens or shorten the prompt"
+                    ),
+                    Some("request_cost_exceeded"),
+                ));
+            }
+        }
     }
 
-    // For streaming requests, inject stream_options to request usage data
-    // Many OpenAI-compatible providers only include usage when this is set
-    if is_stream {
-        if body_json.get("stream_options").is_none() {
-            body_json["stream_options"] = serde_json::json!({ "include_usage": true });
+    // BUDGET_ENFORCEMENT=reserve: hold the request's worst-case cost against the budget
+    // while it is in flight; the final charge reconciles it with actual usage
+    let mut reservation = None;
+    if state.config.budget_enforcement == BudgetEnforcement::Reserve
+        && key_identity.token_budget.is_some()
+    {
+        let tokens = reservation_estimate(&body_json, &route);
+        // Settled charges may still sit in the usage buffer; count them too
+        let pending = state.usage.pending(key_identity.key_id, key_identity.group_id);
+        match key_service::reserve_tokens(key_identity.key_id, tokens, pending, &state.db).await {
+            Ok(true) => {
+                reservation = Some(BudgetReservation {
+                    key_id: key_identity.key_id,
+                    model: None,
+                    tokens,
+                    db: state.db.clone(),
+                    settled: false,
+                });
+            }
+            Ok(false) => {
+                return Err(rate_limit_error(
+                    LimitType::Budget,
+                    format!(
+                        "Token budget exhausted: this request may use up to {tokens} tokens, \
+                         more than the budget has left"
+                    ),
+                    "budget_exhausted",
+                    None,
+                ));
+            }
+            Err(e) => tracing::error!("Budget reservation failed, charging after the response: {}", e),
+        }
+    }
+    let mut model_reservation = None;
+    if let (BudgetEnforcement::Reserve, Some(model)) =
+        (state.config.budget_enforcement, &budget_model)
+    {
+        let tokens = reservation_estimate(&body_json, &route);
+        let key_id = key_identity.key_id;
+        let pending = state.usage.pending_model(key_id, model);
+        match key_service::reserve_model_tokens(key_id, model, tokens, pending, &state.db).await {
+            Ok(true) => {
+                model_reservation = Some(BudgetReservation {
+                    key_id,
+                    model: Some(model.clone()),
+                    tokens,
+                    db: state.db.clone(),
+                    settled: false,
+                });
+            }
+            Ok(false) => {
+                return Err(rate_limit_error(
+                    LimitType::Budget,
+                    format!(
+                        "Token budget for model \"{model}\" exhausted: this request may use up \
+                         to {tokens} tokens, more than the budget has left"
+                    ),
+                    "model_budget_exhausted",
+                    None,
+                ));
+            }
+            Err(e) => tracing::error!(
+                "Model budget reservation failed, charging after the response: {}",
+                e
+            ),
+        }
+    }
+
+    // A key pinned to a provider only uses the model's routes on that provider; then
+    // enforce per-key provider kind restrictions and pick one of the remaining routes
+    let primary_kind = route.provider_kind.clone();
+    let fallback_response = route.fallback_response.clone();
+    let mut candidates = route.into_candidates();
+    if let Some(provider_id) = key_identity.provider_override_id {
+        candidates.retain(|r| r.provider_id == provider_id);
+        if candidates.is_empty() {
+            return Err(json_error(
+                StatusCode::BAD_REQUEST,
+                ErrorType::InvalidRequest,
+                format!("Model \"{model_name}\" is not configured on this key's provider"),
+                Some("model_not_on_key_provider"),
+            ));
         }
     }
+    let mut candidates: Vec<ModelRoute> = candidates
+        .into_iter()
+        .filter(|r| {
+            key_identity
+                .allowed_provider_kinds
+                .as_ref()
+                .is_none_or(|allowed| allowed.iter().any(|k| k == &r.provider_kind))
+        })
+        .collect();
 
-    let upstream_body = serde_json::to_vec(&body_json).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            axum::Json(serde_json::json!({ "error": { "message": format!("JSON serialization error: {e}") } })),
+    // `x-gateway-region`: when the model has routes on providers tagged with that
+    // region, only those are used (failover included); otherwise any route is
+    let region = headers
+        .get(REGION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty());
+    if let Some(region) = region {
+        let in_region = |r: &ModelRoute| r.provider_region.as_deref() == Some(region.as_str());
+        if candidates.iter().any(in_region) {
+            candidates.retain(in_region);
+        }
+    }
+    let blend = state.config.routing_health_blend;
+    // `x-gateway-session-id`: the first attempt goes to the session's provider; failover
+    // and unhealthy session providers fall through to weighted selection
+    let session_id = headers
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && state.config.session_affinity);
+    let sticky = session_id.and_then(|session_id| {
+        select_sticky_route(
+            &mut candidates,
+            session_id,
+            &state.provider_health,
+            state.config.session_affinity_min_success_rate,
         )
-            .into_response()
-    })?;
+    });
+    let Some(mut route) =
+        sticky.or_else(|| select_route(&mut candidates, &state.provider_health, blend))
+    else {
+        return Err(json_error(
+            StatusCode::FORBIDDEN,
+            ErrorType::Permission,
+            format!("Provider kind \"{primary_kind}\" is not allowed for this key"),
+            Some("provider_kind_not_allowed"),
+        ));
+    };
 
-    // Build upstream URL
-    let url = format!("{}/chat/completions", route.base_url);
+    // MAX_STREAMS: a streaming request holds a slot until its shadow task is done
+    let stream_slot = if is_stream {
+        let wait = Duration::from_millis(state.config.stream_slot_wait_ms);
+        match state.stream_slots.acquire(wait).await {
+            Some(slot) => Some(slot),
+            None => {
+                let mut resp = json_error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    ErrorType::Api,
+                    "Too many concurrent streams; retry shortly",
+                    Some("too_many_streams"),
+                );
+                resp.headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(1));
+                return Err(resp);
+            }
+        }
+    } else {
+        None
+    };
 
-    // Build the upstream request with provider-specific auth
-    let mut upstream_req = state
-        .http_client
-        .post(&url)
-        .header(header::AUTHORIZATION, format!("Bearer {}", route.api_key))
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(upstream_body);
+    // The client's provider key replaces the configured one on every attempt
+    if let Some(api_key) = &byo_api_key {
+        route.api_key = api_key.clone();
+        for candidate in &mut candidates {
+            candidate.api_key = api_key.clone();
+        }
+    }
 
-    // Provider-specific headers
-    match route.provider_kind.as_str() {
-        "openrouter" => {
-            if let Some(referer) = headers.get("http-referer") {
-                upstream_req = upstream_req.header("HTTP-Referer", referer);
+    // Capture log context. `x-gateway-no-store` suppresses body storage for this
+    // request regardless of any logging configuration; metadata is still logged.
+    let no_store = headers.contains_key(NO_STORE_HEADER);
+    let log_request_body = state.config.log_request_body && !no_store;
+    let log_response_body = state.config.log_response_body && !no_store;
+    let log_stream_assembled = state.config.log_stream_assembled;
+    let log_stream_max_chunks = state.config.log_stream_max_chunks;
+    let log_body_max_bytes = state.config.log_body_max_bytes;
+    let saved_request_body = if log_request_body {
+        log_service::truncate_body(Some(body_json.clone()), log_body_max_bytes)
+    } else {
+        None
+    };
+    let saved_request_headers = (state.config.log_request_headers && !no_store)
+        .then(|| log_service::redacted_headers(&headers, &state.config));
+    let log_context = LogContext {
+        key_identity: key_identity.clone(),
+        request_id: request_id.clone(),
+        model_requested: model_name.clone(),
+        is_stream,
+        request_body: saved_request_body,
+        request_headers: saved_request_headers,
+        byo_provider_key,
+        no_store,
+    };
+
+    // Send upstream. When the attempted route fails to connect or answers with one of
+    // its `retry_on_status` codes, fail over to another candidate while any remain and
+    // fewer than MAX_FAILOVER_ATTEMPTS routes were tried (hedges count too).
+    let max_attempts = state.config.max_failover_attempts;
+    let mut routes_tried: u32 = 1;
+    let mut hedges_left = state.config.max_hedges;
+    let hedge_delay = route
+        .hedge_after_ms
+        .filter(|_| !is_stream)
+        .map(|ms| Duration::from_millis(ms.max(1) as u64));
+    // Upstream request for a route, limited to what is left of the client deadline
+    let upstream_request = |route: &ModelRoute| {
+        let timeout = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        build_upstream_request(&state, &headers, &body_json, route, is_stream, timeout)
+    };
+    let deadline_passed = || deadline.is_some_and(|d| Instant::now() >= d);
+    // The latest send, for the log entry should the request still fail
+    let mut last_sent: Option<UpstreamAttempts> = None;
+    let (upstream_resp, inflight) = loop {
+        if deadline_passed() {
+            return Err(deadline_exceeded(last_sent.as_ref()));
+        }
+        let upstream_req = upstream_request(&route).map_err(serialization_error)?;
+        last_sent = Some(UpstreamAttempts::new(routes_tried, &route));
+
+        // Hedging: while no response has arrived after the model's `hedge_after_ms`, send
+        // the request to another candidate too (up to MAX_HEDGES per request). The first
+        // response that would not fail over wins; dropping the others cancels them, so
+        // only it is logged and billed. A failed attempt only counts against its route's
+        // health while others are still running, and is used once none are left.
+        let mut attempts = futures::stream::FuturesUnordered::new();
+        attempts.push(send_attempt(&state, route.clone(), upstream_req));
+        let (sent, attempt_route, inflight) = loop {
+            let hedge_timer = async {
+                match hedge_delay {
+                    Some(delay)
+                        if hedges_left > 0 && routes_tried < max_attempts && !candidates.is_empty() =>
+                    {
+                        tokio::time::sleep(delay).await
+                    }
+                    _ => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                Some(done) = attempts.next() => {
+                    let (sent, attempt_route, _) = &done;
+                    let failed = match sent {
+                        Ok(resp) => {
+                            let code = resp.status().as_u16() as i16;
+                            !resp.status().is_success() && fails_over_on(attempt_route, code)
+                        }
+                        Err(_) => true,
+                    };
+                    if !failed || attempts.is_empty() {
+                        break done;
+                    }
+                    let healthy = match sent {
+                        Ok(resp) => {
+                            let code = resp.status().as_u16() as i16;
+                            state.provider_quotas.record(attempt_route.provider_id, resp.headers());
+                            !is_retriable_status(code)
+                        }
+                        Err(_) => false,
+                    };
+                    state.provider_health.record(attempt_route.provider_id, healthy);
+                    tracing::warn!(
+                        "Hedged attempt of {} on provider {} failed; waiting for the others",
+                        model_name,
+                        attempt_route.provider_kind
+                    );
+                }
+                _ = hedge_timer => {
+                    let Some(hedge) = select_route(&mut candidates, &state.provider_health, blend)
+                    else {
+                        continue;
+                    };
+                    hedges_left -= 1;
+                    routes_tried += 1;
+                    tracing::info!(
+                        "Hedging {} on provider {} with {}",
+                        model_name,
+                        route.provider_kind,
+                        hedge.provider_kind
+                    );
+                    if deadline_passed() {
+                        return Err(deadline_exceeded(last_sent.as_ref()));
+                    }
+                    let hedge_req = upstream_request(&hedge).map_err(serialization_error)?;
+                    last_sent = Some(UpstreamAttempts::new(routes_tried, &hedge));
+                    attempts.push(send_attempt(&state, hedge, hedge_req));
+                }
             }
-            if let Some(title) = headers.get("x-title") {
-                upstream_req = upstream_req.header("X-Title", title);
+        };
+        drop(attempts);
+        route = attempt_route;
+
+        let (failure, failed_resp) = match sent {
+            Ok(resp) => {
+                let code = resp.status().as_u16() as i16;
+                let is_error = !resp.status().is_success();
+                state
+                    .provider_health
+                    .record(route.provider_id, !(is_error && is_retriable_status(code)));
+                state.provider_quotas.record(route.provider_id, resp.headers());
+                // A 400 to a stream whose `stream_options` we injected is most likely the
+                // provider rejecting that field: retry this route once without it (its
+                // streamed usage is then estimated)
+                if code == 400 && injects_stream_options(&body_json, &route, is_stream) {
+                    tracing::warn!(
+                        "Provider {} returned 400 for {} with injected stream_options; \
+                         retrying without it",
+                        route.provider_kind,
+                        model_name
+                    );
+                    route.supports_stream_usage = false;
+                    continue;
+                }
+                if !is_error || !fails_over_on(&route, code) {
+                    break (resp, inflight);
+                }
+                (format!("status {code}"), Some(resp))
             }
-        }
-        _ => {
-            if let Some(org) = headers.get("openai-organization") {
-                upstream_req = upstream_req.header("OpenAI-Organization", org);
+            // The deadline is spent, so there is no time left to fail over
+            Err(e) if e.is_timeout() && deadline.is_some() => {
+                tracing::warn!("Upstream request to {} hit the client deadline", route.provider_kind);
+                let attempts = UpstreamAttempts::new(routes_tried, &route);
+                return Err(deadline_exceeded(Some(&attempts)));
+            }
+            Err(e) => {
+                tracing::error!("Upstream request to {} failed: {}", route.provider_kind, e);
+                state.provider_health.record(route.provider_id, false);
+                (e.to_string(), None)
+            }
+        };
+
+        let next = if routes_tried < max_attempts {
+            select_route(&mut candidates, &state.provider_health, blend)
+        } else {
+            if !candidates.is_empty() {
+                tracing::warn!(
+                    "Not failing over {} after {} routes (MAX_FAILOVER_ATTEMPTS)",
+                    model_name,
+                    routes_tried
+                );
+            }
+            None
+        };
+        match next {
+            Some(next) => {
+                tracing::warn!(
+                    "Failing over {} from provider {} ({}) to {}",
+                    model_name,
+                    route.provider_kind,
+                    failure,
+                    next.provider_kind
+                );
+                route = next;
+                routes_tried += 1;
+            }
+            None => {
+                let Some(fallback) = fallback_response else {
+                    // Out of routes: pass the last provider error through when there is one
+                    if let Some(resp) = failed_resp {
+                        break (resp, inflight);
+                    }
+                    let mut resp = json_error(
+                        StatusCode::BAD_GATEWAY,
+                        ErrorType::Upstream,
+                        "Upstream service error",
+                        Some("upstream_unavailable"),
+                    );
+                    resp.extensions_mut()
+                        .insert(UpstreamAttempts::new(routes_tried, &route));
+                    return Err(resp);
+                };
+                tracing::warn!(
+                    "All routes for {} failed (last: {} {}); serving fallback response",
+                    model_name,
+                    route.provider_kind,
+                    failure
+                );
+
+                // Logged without token counts, so nothing is billed
+                let latency_ms = start.elapsed().as_millis() as i32;
+                let new_log = log_service::NewRequestLog {
+                    model_sent: route.provider_model_name.clone(),
+                    provider_id: Some(route.provider_id),
+                    provider_kind: Some(route.provider_kind.clone()),
+                    response_body: if log_response_body {
+                        log_service::truncate_body(Some(fallback.clone()), log_body_max_bytes)
+                    } else {
+                        None
+                    },
+                    error_message: Some(format!("All routes failed (last: {failure})")),
+                    error_source: Some(ErrorSource::Upstream),
+                    attempts: routes_tried as i32,
+                    is_fallback: true,
+                    ..log_context.entry(StatusCode::OK.as_u16() as i16, latency_ms)
+                };
+                spawn_log(&state, new_log);
+
+                return Ok(fallback_to_response(&fallback, is_stream));
             }
         }
-    }
+    };
+    let inflight_header = state
+        .config
+        .expose_inflight_header
+        .then(|| inflight.current());
 
-    let upstream_resp = upstream_req.send().await.map_err(|e| {
-        tracing::error!("Upstream request to {} failed: {}", route.provider_kind, e);
-        (
-            StatusCode::BAD_GATEWAY,
-            axum::Json(serde_json::json!({ "error": { "message": "Upstream service error" } })),
-        )
-            .into_response()
-    })?;
+    // Pre-compute the prompt estimate in case the provider omits usage. Streams from
+    // providers without `stream_options` support never report usage, so always estimate;
+    // streams also need it when the client disconnects before usage arrives.
+    let on_missing_usage = if is_stream && !route.supports_stream_usage {
+        MissingUsagePolicy::Estimate
+    } else {
+        state.config.on_missing_usage
+    };
+    let estimated_prompt_tokens = (is_stream || on_missing_usage == MissingUsagePolicy::Estimate)
+        .then(|| estimate_tokens(prompt_text_chars(&body_json)));
+    let on_empty_completion = state.config.on_empty_completion;
+
+    let model_sent = route.provider_model_name.clone();
+    let tokenizer = resolve_tokenizer(&model_sent, &state.config.tokenizer_encodings).to_string();
+    let buffer_upstream = is_stream && route.force_buffered_upstream;
 
     let status =
         StatusCode::from_u16(upstream_resp.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
     let is_error = !status.is_success();
 
     // Extract upstream request-id if present
-    let request_id = upstream_resp
+    let upstream_request_id = upstream_resp
         .headers()
         .get("x-request-id")
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
-    if is_stream {
-        let upstream_headers = upstream_resp.headers().clone();
+    // Non-streaming bodies are read up to PASSTHROUGH_RESPONSE_BYTES; larger ones are
+    // passed through to the client and teed for usage parsing like SSE instead of being
+    // held in memory. Replaying a buffered upstream as SSE needs the whole completion,
+    // and error bodies (even for streaming requests) are read in full to be annotated.
+    // So do the response post-processors (field stripping, model rewriting and the
+    // missing-usage policy), which turn passthrough off.
+    let upstream_headers = upstream_resp.headers().clone();
+    let mut upstream_body: UpstreamBody = Box::pin(upstream_resp.bytes_stream());
+    let mut read_ahead = Vec::new();
+    let mut passthrough = false;
+    if !is_stream || buffer_upstream || is_error {
+        let config = &state.config;
+        let post_processed = !config.response_strip_fields.is_empty()
+            || config.rewrite_response_model
+            || config.on_missing_usage != MissingUsagePolicy::Ignore;
+        let threshold = config
+            .passthrough_response_bytes
+            .filter(|_| !buffer_upstream && !is_error && !post_processed);
+        while let Some(chunk) = upstream_body.next().await {
+            let chunk = chunk.map_err(|e| {
+                if e.is_timeout() && deadline.is_some() {
+                    return deadline_exceeded(last_sent.as_ref());
+                }
+                tracing::error!("Failed to read upstream response: {}", e);
+                json_error(
+                    StatusCode::BAD_GATEWAY,
+                    ErrorType::Upstream,
+                    "Failed to read upstream response",
+                    Some("upstream_read_failed"),
+                )
+            })?;
+            read_ahead.extend_from_slice(&chunk);
+            if threshold.is_some_and(|max| read_ahead.len() > max) {
+                passthrough = true;
+                break;
+            }
+        }
+    }
 
-        // Always use shadow stream for SSE to capture usage/tokens regardless of log_response_body setting
+    if (is_stream && !buffer_upstream && !is_error) || passthrough {
+        // Always use shadow stream to capture usage/tokens regardless of log_response_body setting
         let (shadow_tx, shadow_rx) = mpsc::unbounded_channel::<ByteChunk>();
 
-        let raw_stream = upstream_resp.bytes_stream();
+        let inner: UpstreamBody = if passthrough {
+            let prefix = bytes::Bytes::from(std::mem::take(&mut read_ahead));
+            Box::pin(futures::stream::once(async move { Ok(prefix) }).chain(upstream_body))
+        } else {
+            upstream_body
+        };
+
+        // Clients get rewritten events (SSE_STRIP_FIELDS, REWRITE_RESPONSE_MODEL,
+        // provider request id on errors); the shadow copy stays original
+        let rewriter = if passthrough {
+            None
+        } else {
+            SseRewriter::new(
+                state.config.sse_strip_fields.clone(),
+                state.config.rewrite_response_model.then(|| model_name.clone()),
+                upstream_request_id.clone(),
+            )
+        };
 
+        let upstream_finished = Arc::new(AtomicBool::new(false));
+        let upstream_stalled = Arc::new(AtomicBool::new(false));
+        let stream_expired = Arc::new(AtomicBool::new(false));
+        let idle_timeout = Duration::from_secs(state.config.stream_idle_timeout_secs);
+        let max_duration = Duration::from_secs(state.config.max_stream_duration_secs);
         let shadow_stream = ShadowStream {
-            inner: Box::pin(raw_stream),
+            inner,
             tx: shadow_tx,
+            rewriter,
+            finished: upstream_finished.clone(),
+            idle_timeout,
+            idle: Box::pin(tokio::time::sleep(idle_timeout)),
+            waiting: false,
+            stalled: upstream_stalled.clone(),
+            deadline: Box::pin(tokio::time::sleep(max_duration)),
+            expired: stream_expired.clone(),
+            _inflight: inflight,
         };
 
         let body = Body::from_stream(shadow_stream);
 
-        let mut response = Response::builder()
-            .status(status)
-            .header(header::CONTENT_TYPE, "text/event-stream")
-            .header(header::CACHE_CONTROL, "no-cache")
-            .header(header::CONNECTION, "keep-alive")
-            .body(body)
-            .unwrap();
+        let builder = Response::builder().status(status);
+        let builder = if passthrough {
+            builder.header(header::CONTENT_TYPE, "application/json")
+        } else {
+            builder
+                .header(header::CONTENT_TYPE, "text/event-stream")
+                .header(header::CACHE_CONTROL, "no-cache")
+                .header(header::CONNECTION, "keep-alive")
+        };
+        let mut response = builder.body(body).unwrap();
 
         copy_upstream_headers(&upstream_headers, response.headers_mut());
+        if let Some(n) = inflight_header {
+            response
+                .headers_mut()
+                .insert("x-gateway-provider-inflight", HeaderValue::from(n));
+        }
+        // Echo the deadline the upstream attempts were held to
+        if let Some(ms) = timeout_ms {
+            response.headers_mut().insert(TIMEOUT_HEADER, HeaderValue::from(ms));
+        }
 
         // Spawn background task to accumulate shadow chunks, parse usage, and log
-        let db = state.db.clone();
+        let log_state = state.clone();
         let log_model_requested = model_name.clone();
         let log_model_sent = model_sent.clone();
-        let log_provider_id = route.provider_id;
         let log_provider_kind = route.provider_kind.clone();
-        let log_input_coeff = route.input_token_coefficient;
-        let log_output_coeff = route.output_token_coefficient;
-        let log_key_identity = key_identity.clone();
-        let log_request_id = request_id.clone();
+        let log_route = route.clone();
+        let log_upstream_request_id = upstream_request_id.clone();
         let log_status = status.as_u16() as i16;
         let log_is_error = is_error;
+        let slow_request_ms = state.config.slow_request_ms;
+        let slow_requests = state.slow_requests.clone();
+        let max_shadow_buffer_bytes = state.config.max_shadow_buffer_bytes;
 
         tokio::spawn(async move {
-            let mut buffer = Vec::new();
-            let mut shadow_rx = shadow_rx;
-            while let Some(chunk) = shadow_rx.recv().await {
-                buffer.extend_from_slice(&chunk);
+            let _stream_slot = stream_slot;
+            // A passthrough body is one JSON document, parsed whole for its usage
+            let mut buffer = ShadowBuffer::new(max_shadow_buffer_bytes.filter(|_| !passthrough));
+            // Watchdog: the stream ends itself at MAX_STREAM_DURATION_SECS, but only while
+            // the client reads it; stop waiting shortly after so the entry still gets logged
+            let recv_timed_out = buffer
+                .fill(shadow_rx, max_duration + Duration::from_secs(1))
+                .await;
+            let ShadowBuffer {
+                bytes: buffer,
+                mut overflow,
+                ..
+            } = buffer;
+            if let Some(summary) = overflow.as_mut() {
+                summary.finish();
+                tracing::warn!(
+                    "Stream of {} from {} exceeded MAX_SHADOW_BUFFER_BYTES ({} bytes); \
+                     logging parsed usage without the body",
+                    log_model_sent,
+                    log_provider_kind,
+                    summary.total_bytes
+                );
+            }
+            // The channel closes when the stream is dropped, finished or not
+            let stalled = upstream_stalled.load(Ordering::Relaxed);
+            let expired = recv_timed_out || stream_expired.load(Ordering::Relaxed);
+            let client_disconnected =
+                !stalled && !expired && !upstream_finished.load(Ordering::Relaxed);
+            if stalled {
+                tracing::warn!(
+                    "Stream of {} from {} stalled; ended after STREAM_IDLE_TIMEOUT_SECS",
+                    log_model_sent,
+                    log_provider_kind
+                );
+            } else if expired {
+                tracing::warn!(
+                    "Stream of {} from {} ended at MAX_STREAM_DURATION_SECS",
+                    log_model_sent,
+                    log_provider_kind
+                );
             }
 
+            // Total time until the stream finished, not just time to first byte
             let latency_ms = start.elapsed().as_millis() as i32;
+            report_slow_request(
+                slow_request_ms,
+                &slow_requests,
+                &log_model_requested,
+                &log_provider_kind,
+                latency_ms,
+                is_stream,
+            );
 
-            // Parse SSE buffer to extract usage
-            let (prompt_tokens, completion_tokens, total_tokens, response_body_json) =
-                parse_sse_usage_and_body(&buffer);
+            // Parse the teed body to extract usage
+            let (usage, response_body_json) = if let Some(summary) = &overflow {
+                let marker = serde_json::json!({
+                    "_truncated": true,
+                    "original_bytes": summary.total_bytes,
+                });
+                (summary.usage, Some(marker))
+            } else if passthrough {
+                let json: Option<serde_json::Value> = serde_json::from_slice(&buffer).ok();
+                let usage = json.as_ref().map(Usage::from_response_json).unwrap_or_default();
+                (usage, json)
+            } else {
+                Usage::from_sse_buffer(&buffer)
+            };
+            let Usage {
+                prompt: mut prompt_tokens,
+                completion: mut completion_tokens,
+                total: mut total_tokens,
+                reasoning: reasoning_tokens,
+                cached: cached_prompt_tokens,
+            } = usage;
 
-            // Only store response body if configured
-            let saved_response = if log_response_body { response_body_json } else { None };
-
-            if let Err(e) = log_service::insert_log(
-                &db,
-                log_service::NewRequestLog {
-                    request_id: log_request_id,
-                    user_key_id: Some(log_key_identity.key_id),
-                    user_key_hash: log_key_identity.key_hash,
-                    model_requested: log_model_requested,
-                    model_sent: log_model_sent,
-                    provider_id: Some(log_provider_id),
-                    provider_kind: Some(log_provider_kind),
-                    status_code: log_status,
-                    is_error: log_is_error,
+            // A streamed error event after a 2xx status still marks the request failed
+            let mut log_is_error = log_is_error;
+            let stream_error = match (&overflow, &response_body_json) {
+                (Some(summary), _) => summary.error.as_ref(),
+                (None, Some(serde_json::Value::Array(chunks))) => {
+                    chunks.iter().find_map(|chunk| chunk.get("error"))
+                }
+                _ => None,
+            };
+            let mut error_message = stream_error
+                .map(|error| {
+                    log_is_error = true;
+                    error
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("Upstream stream error")
+                        .to_string()
+                })
+                .map(|message| {
+                    with_provider_request_id(message, log_upstream_request_id.as_deref())
+                });
+            let reported_completion_tokens = completion_tokens;
+            let mut tokens_estimated = false;
+            let completion_chars = || match (&overflow, &response_body_json) {
+                (Some(summary), _) => summary.completion_chars,
+                (None, Some(serde_json::Value::Array(chunks))) => {
+                    completion_text_chars(&assemble_stream_chunks(chunks))
+                }
+                (None, Some(completion)) if passthrough => completion_text_chars(completion),
+                _ => 0,
+            };
+
+            // A stream cut short (client disconnected, upstream stalled or too long) still
+            // pays for what was generated: estimate whatever usage the provider did not report
+            if client_disconnected || stalled || expired {
+                if let Some((pt, ct)) = partial_usage(
                     prompt_tokens,
                     completion_tokens,
-                    total_tokens,
-                    latency_ms,
-                    is_stream: true,
-                    request_body: saved_request_body,
-                    response_body: saved_response,
-                    error_message: None,
-                },
-            )
-            .await
+                    estimated_prompt_tokens,
+                    completion_chars,
+                ) {
+                    tokens_estimated = true;
+                    prompt_tokens = Some(pt);
+                    completion_tokens = Some(ct);
+                    total_tokens = Some(pt + ct);
+                }
+                if stalled || expired {
+                    let message = if stalled {
+                        UPSTREAM_STALLED_MESSAGE
+                    } else {
+                        STREAM_EXPIRED_MESSAGE
+                    };
+                    log_is_error = true;
+                    error_message.get_or_insert_with(|| {
+                        with_provider_request_id(
+                            message.to_string(),
+                            log_upstream_request_id.as_deref(),
+                        )
+                    });
+                } else if error_message.is_none() {
+                    error_message = Some(CLIENT_DISCONNECTED_MESSAGE.to_string());
+                }
+            }
+
+            // Apply ON_MISSING_USAGE — the client response is already sent, so `reject`
+            // can only flag the log entry
+            if !log_is_error
+                && !client_disconnected
+                && prompt_tokens.is_none()
+                && completion_tokens.is_none()
             {
-                tracing::error!("Failed to insert request log: {}", e);
+                match on_missing_usage {
+                    MissingUsagePolicy::Ignore => {}
+                    MissingUsagePolicy::Estimate => {
+                        let pt = estimated_prompt_tokens.unwrap_or(0);
+                        let ct = estimate_tokens(completion_chars());
+                        tokens_estimated = true;
+                        prompt_tokens = Some(pt);
+                        completion_tokens = Some(ct);
+                        total_tokens = Some(pt + ct);
+                    }
+                    MissingUsagePolicy::Reject => {
+                        tracing::warn!(
+                            "Provider {} did not report usage for already-sent {}",
+                            log_provider_kind,
+                            log_model_sent
+                        );
+                        log_is_error = true;
+                        error_message = Some(with_provider_request_id(
+                            MISSING_USAGE_MESSAGE.to_string(),
+                            log_upstream_request_id.as_deref(),
+                        ));
+                    }
+                }
             }
 
-            // Increment token usage (weighted by model coefficients)
+            // Apply ON_EMPTY_COMPLETION — as above, `reject` can only flag the log entry
+            let finish_reason = match (&overflow, &response_body_json) {
+                (Some(summary), _) => summary.finish_reason.clone(),
+                (None, Some(serde_json::Value::Array(chunks))) => stream_finish_reason(chunks),
+                (None, Some(completion)) => first_finish_reason(completion),
+                (None, None) => None,
+            };
+            // A stream past the size cap is not empty
+            if !log_is_error
+                && !client_disconnected
+                && overflow.is_none()
+                && on_empty_completion != EmptyCompletionPolicy::Ignore
             {
-                let pt = prompt_tokens.unwrap_or(0) as f64;
-                let ct = completion_tokens.unwrap_or(0) as f64;
-                let weighted = (pt * log_input_coeff + ct * log_output_coeff).round() as i64;
-                if weighted > 0 {
-                    if let Err(e) = key_service::increment_tokens_used(
-                        log_key_identity.key_id, weighted, &db,
-                    ).await {
-                        tracing::error!("Failed to increment token usage: {}", e);
+                let empty = match &response_body_json {
+                    Some(serde_json::Value::Array(chunks)) => is_empty_completion(
+                        &assemble_stream_chunks(chunks),
+                        reported_completion_tokens,
+                    ),
+                    Some(completion) => {
+                        is_empty_completion(completion, reported_completion_tokens)
                     }
+                    None => true,
+                };
+                if empty {
+                    log_is_error = true;
+                    error_message = Some(with_provider_request_id(
+                        empty_completion_message(finish_reason.as_deref()),
+                        log_upstream_request_id.as_deref(),
+                    ));
                 }
             }
+
+            // Only store response body if configured
+            let saved_response = if log_response_body {
+                let body = match response_body_json {
+                    Some(serde_json::Value::Array(chunks)) if log_stream_assembled => {
+                        Some(assemble_stream_chunks(&chunks))
+                    }
+                    Some(serde_json::Value::Array(chunks)) => Some(serde_json::Value::Array(
+                        log_service::cap_stream_chunks(chunks, log_stream_max_chunks),
+                    )),
+                    other => other,
+                };
+                log_service::truncate_body(body, log_body_max_bytes)
+            } else {
+                None
+            };
+
+            let new_log = log_service::NewRequestLog {
+                upstream_request_id: log_upstream_request_id,
+                model_sent: log_model_sent,
+                provider_id: Some(log_route.provider_id),
+                provider_kind: Some(log_provider_kind),
+                is_error: log_is_error,
+                retriable: log_is_error && is_retriable_status(log_status),
+                prompt_tokens,
+                completion_tokens,
+                total_tokens,
+                reasoning_tokens,
+                cached_prompt_tokens,
+                response_body: saved_response,
+                error_message,
+                error_source: log_is_error.then_some(ErrorSource::Upstream),
+                finish_reason,
+                tokens_estimated,
+                tokenizer: tokens_estimated.then_some(tokenizer),
+                attempts: routes_tried as i32,
+                ..log_context.entry(log_status, latency_ms)
+            };
+            spawn_log(&log_state, new_log);
+
+            let usage = Usage {
+                prompt: prompt_tokens,
+                completion: completion_tokens,
+                total: total_tokens,
+                reasoning: reasoning_tokens,
+                cached: cached_prompt_tokens,
+            };
+            charge_usage(
+                &log_state,
+                &log_context.key_identity,
+                &log_route,
+                usage,
+                reservation,
+                budget_model.as_deref(),
+                model_reservation,
+            )
+            .await;
         });
 
         Ok(response)
     } else {
-        // Non-streaming response
-        let upstream_headers = upstream_resp.headers().clone();
-        let response_bytes = upstream_resp.bytes().await.map_err(|e| {
-            tracing::error!("Failed to read upstream response: {}", e);
-            (
-                StatusCode::BAD_GATEWAY,
-                axum::Json(serde_json::json!({ "error": { "message": "Failed to read upstream response" } })),
-            )
-                .into_response()
-        })?;
+        // Non-streaming response, read in full above
+        let response_bytes = bytes::Bytes::from(read_ahead);
+        drop(inflight);
 
         // Parse usage from response body (always, since it's cheap)
         let resp_json: Option<serde_json::Value> =
             serde_json::from_slice(&response_bytes).ok();
 
-        let (prompt_tokens, completion_tokens, total_tokens) = resp_json
-            .as_ref()
-            .and_then(|j| j.get("usage"))
-            .map(|usage| {
-                (
-                    usage.get("prompt_tokens").and_then(|v| v.as_i64()).map(|v| v as i32),
-                    usage.get("completion_tokens").and_then(|v| v.as_i64()).map(|v| v as i32),
-                    usage.get("total_tokens").and_then(|v| v.as_i64()).map(|v| v as i32),
-                )
-            })
-            .unwrap_or((None, None, None));
+        let Usage {
+            prompt: mut prompt_tokens,
+            completion: mut completion_tokens,
+            total: mut total_tokens,
+            reasoning: reasoning_tokens,
+            cached: cached_prompt_tokens,
+        } = resp_json.as_ref().map(Usage::from_response_json).unwrap_or_default();
 
-        let error_message = if is_error {
+        let mut error_message = if is_error {
             resp_json
                 .as_ref()
                 .and_then(|j| j.get("error"))
                 .and_then(|e| e.get("message"))
                 .and_then(|m| m.as_str())
-                .map(|s| s.to_string())
+                .map(|s| with_provider_request_id(s.to_string(), upstream_request_id.as_deref()))
         } else {
             None
         };
 
-        let saved_response_body = if log_response_body { resp_json } else { None };
+        // Tag upstream errors with the provider's request id for support escalations
+        let mut response_bytes = response_bytes;
+        if let (true, Some(id), Some(mut annotated)) =
+            (is_error, &upstream_request_id, resp_json.clone())
+        {
+            if let Some(error) = annotated.get_mut("error").and_then(|e| e.as_object_mut()) {
+                error.insert("provider_request_id".into(), id.clone().into());
+                if let Ok(bytes) = serde_json::to_vec(&annotated) {
+                    response_bytes = bytes.into();
+                }
+            }
+        }
 
-        let mut response = Response::builder()
-            .status(status)
-            .header(header::CONTENT_TYPE, "application/json")
-            .body(Body::from(response_bytes))
-            .unwrap();
+        // Apply ON_MISSING_USAGE
+        let mut status = status;
+        let mut is_error = is_error;
+        let reported_completion_tokens = completion_tokens;
+        let mut tokens_estimated = false;
+        if !is_error && prompt_tokens.is_none() && completion_tokens.is_none() {
+            match on_missing_usage {
+                MissingUsagePolicy::Ignore => {}
+                MissingUsagePolicy::Estimate => {
+                    let pt = estimated_prompt_tokens.unwrap_or(0);
+                    let ct = estimate_tokens(resp_json.as_ref().map(completion_text_chars).unwrap_or(0));
+                    tokens_estimated = true;
+                    prompt_tokens = Some(pt);
+                    completion_tokens = Some(ct);
+                    total_tokens = Some(pt + ct);
+                }
+                MissingUsagePolicy::Reject => {
+                    tracing::warn!(
+                        "Provider {} did not report usage for {}",
+                        route.provider_kind,
+                        model_sent
+                    );
+                    status = StatusCode::BAD_GATEWAY;
+                    is_error = true;
+                    error_message = Some(with_provider_request_id(
+                        MISSING_USAGE_MESSAGE.to_string(),
+                        upstream_request_id.as_deref(),
+                    ));
+                    response_bytes = upstream_error_body(
+                        MISSING_USAGE_MESSAGE,
+                        "missing_usage",
+                        upstream_request_id.as_deref(),
+                    );
+                }
+            }
+        }
 
-        copy_upstream_headers(&upstream_headers, response.headers_mut());
+        // Apply ON_EMPTY_COMPLETION
+        let finish_reason = resp_json.as_ref().and_then(first_finish_reason);
+        let empty = !is_error
+            && on_empty_completion != EmptyCompletionPolicy::Ignore
+            && resp_json
+                .as_ref()
+                .is_some_and(|j| is_empty_completion(j, reported_completion_tokens));
+        if empty {
+            let message = empty_completion_message(finish_reason.as_deref());
+            is_error = true;
+            if on_empty_completion == EmptyCompletionPolicy::Reject {
+                status = StatusCode::BAD_GATEWAY;
+                response_bytes = upstream_error_body(
+                    &message,
+                    "empty_completion",
+                    upstream_request_id.as_deref(),
+                );
+            }
+            error_message = Some(with_provider_request_id(message, upstream_request_id.as_deref()));
+        }
 
-        // Async log insert
-        let db = state.db.clone();
-        let latency_ms = start.elapsed().as_millis() as i32;
-        let log_key_id = key_identity.key_id;
-        tokio::spawn(async move {
-            if let Err(e) = log_service::insert_log(
-                &db,
-                log_service::NewRequestLog {
-                    request_id,
-                    user_key_id: Some(key_identity.key_id),
-                    user_key_hash: key_identity.key_hash,
-                    model_requested: model_name,
-                    model_sent,
-                    provider_id: Some(route.provider_id),
-                    provider_kind: Some(route.provider_kind),
-                    status_code: status.as_u16() as i16,
-                    is_error,
-                    prompt_tokens,
-                    completion_tokens,
-                    total_tokens,
-                    latency_ms,
-                    is_stream: false,
-                    request_body: saved_request_body,
-                    response_body: saved_response_body,
-                    error_message,
-                },
-            )
-            .await
-            {
-                tracing::error!("Failed to insert request log: {}", e);
-            }
+        // RESPONSE_STRIP_FIELDS and REWRITE_RESPONSE_MODEL: the client gets the body
+        // without those keys and with the requested model name; usage was parsed and
+        // the log keeps the original
+        let response_model = state.config.rewrite_response_model.then_some(model_name.as_str());
+        let rewritten_json = resp_json
+            .as_ref()
+            .filter(|_| status.is_success())
+            .and_then(|j| {
+                rewrite_response_json(j, &state.config.response_strip_fields, response_model)
+            });
+        if let Some(bytes) = rewritten_json.as_ref().and_then(|j| serde_json::to_vec(j).ok()) {
+            response_bytes = bytes.into();
+        }
+        let client_json = rewritten_json.as_ref().or(resp_json.as_ref());
 
-            // Increment token usage (weighted by model coefficients)
-            {
-                let pt = prompt_tokens.unwrap_or(0) as f64;
-                let ct = completion_tokens.unwrap_or(0) as f64;
-                let weighted = (pt * route.input_token_coefficient + ct * route.output_token_coefficient).round() as i64;
-                if weighted > 0 {
-                    if let Err(e) = key_service::increment_tokens_used(
-                        log_key_id, weighted, &db,
-                    ).await {
-                        tracing::error!("Failed to increment token usage: {}", e);
-                    }
-                }
-            }
+        // Buffered upstream for a streaming client: replay the completion as SSE
+        let synthesize_sse = buffer_upstream && status.is_success() && client_json.is_some();
+        let (content_type, response_bytes) = match client_json {
+            Some(j) if synthesize_sse => ("text/event-stream", completion_to_sse(j).into()),
+            _ => ("application/json", response_bytes),
+        };
+
+        let saved_response_body = if log_response_body {
+            log_service::truncate_body(resp_json, log_body_max_bytes)
+        } else {
+            None
+        };
+
+        let mut builder = Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, content_type);
+        if synthesize_sse {
+            builder = builder.header(header::CACHE_CONTROL, "no-cache");
+        }
+        let mut response = builder.body(Body::from(response_bytes)).unwrap();
+
+        copy_upstream_headers(&upstream_headers, response.headers_mut());
+        if let Some(n) = inflight_header {
+            response
+                .headers_mut()
+                .insert("x-gateway-provider-inflight", HeaderValue::from(n));
+        }
+        // Echo the deadline the upstream attempts were held to
+        if let Some(ms) = timeout_ms {
+            response.headers_mut().insert(TIMEOUT_HEADER, HeaderValue::from(ms));
+        }
+        if tokens_estimated {
+            response
+                .headers_mut()
+                .insert(USAGE_ESTIMATED_HEADER, HeaderValue::from_static("true"));
+        }
+
+        // Async log insert
+        let latency_ms = start.elapsed().as_millis() as i32;
+        report_slow_request(
+            state.config.slow_request_ms,
+            &state.slow_requests,
+            &model_name,
+            &route.provider_kind,
+            latency_ms,
+            false,
+        );
+        let status_code = status.as_u16() as i16;
+        let new_log = log_service::NewRequestLog {
+            upstream_request_id,
+            model_sent,
+            provider_id: Some(route.provider_id),
+            provider_kind: Some(route.provider_kind.clone()),
+            is_error,
+            retriable: is_error && is_retriable_status(status_code),
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
+            reasoning_tokens,
+            cached_prompt_tokens,
+            response_body: saved_response_body,
+            error_message,
+            error_source: is_error.then_some(ErrorSource::Upstream),
+            finish_reason,
+            tokens_estimated,
+            tokenizer: tokens_estimated.then_some(tokenizer),
+            attempts: routes_tried as i32,
+            ..log_context.entry(status_code, latency_ms)
+        };
+        spawn_log(&state, new_log);
+
+        let usage = Usage {
+            prompt: prompt_tokens,
+            completion: completion_tokens,
+            total: total_tokens,
+            reasoning: reasoning_tokens,
+            cached: cached_prompt_tokens,
+        };
+        tokio::spawn(async move {
+            charge_usage(
+                &state,
+                &key_identity,
+                &route,
+                usage,
+                reservation,
+                budget_model.as_deref(),
+                model_reservation,
+            )
+            .await;
         });
 
         Ok(response)
@@ -370,119 +1625,2647 @@ async fn chat_completions(
 
 // ── Shadow Stream ─────────────────────────────────────────────────────
 
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+/// Upstream response body as a byte stream.
+type UpstreamBody = Pin<Box<dyn Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>;
+
 /// A stream wrapper that yields chunks to the client while sending copies
 /// to a background channel for aggregation (shadow stream).
 struct ShadowStream {
-    inner: Pin<Box<dyn Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>,
+    inner: UpstreamBody,
     tx: mpsc::UnboundedSender<ByteChunk>,
+    /// Rewrites what the client receives; the shadow channel gets upstream bytes.
+    rewriter: Option<SseRewriter>,
+    /// Set once the upstream body ended or failed; still false when the stream is
+    /// dropped means the client disconnected first.
+    finished: Arc<AtomicBool>,
+    /// Longest wait for upstream data (STREAM_IDLE_TIMEOUT_SECS).
+    idle_timeout: Duration,
+    /// Fires once `idle_timeout` passes while waiting for upstream data; restarted
+    /// whenever a wait begins, so time the client spends not reading is not counted.
+    idle: Pin<Box<tokio::time::Sleep>>,
+    /// Whether the last poll found no upstream data (the idle timer is running).
+    waiting: bool,
+    /// Set when the idle timer ended the stream.
+    stalled: Arc<AtomicBool>,
+    /// Fires at MAX_STREAM_DURATION_SECS after the response started.
+    deadline: Pin<Box<tokio::time::Sleep>>,
+    /// Set when `deadline` ended the stream.
+    expired: Arc<AtomicBool>,
+    /// Released when the stream is dropped (finished or client disconnected).
+    _inflight: InflightGuard,
 }
 
 impl Stream for ShadowStream {
     type Item = Result<bytes::Bytes, std::io::Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match self.inner.as_mut().poll_next(cx) {
-            Poll::Ready(Some(Ok(chunk))) => {
-                // Send a copy to the shadow channel (ignore errors if receiver dropped)
-                let _ = self.tx.send(chunk.to_vec());
-                Poll::Ready(Some(Ok(chunk)))
+        if self.stalled.load(Ordering::Relaxed) || self.expired.load(Ordering::Relaxed) {
+            return Poll::Ready(None);
+        }
+        if std::future::Future::poll(self.deadline.as_mut(), cx).is_ready() {
+            self.expired.store(true, Ordering::Relaxed);
+            return Poll::Ready(Some(Err(std::io::Error::other(STREAM_EXPIRED_MESSAGE))));
+        }
+        loop {
+            return match self.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.waiting = false;
+                    // Send a copy to the shadow channel (ignore errors if receiver dropped)
+                    let _ = self.tx.send(chunk.to_vec());
+                    let chunk = match self.rewriter.as_mut() {
+                        Some(rewriter) => rewriter.feed(chunk),
+                        None => chunk,
+                    };
+                    // Only a partial line so far: keep reading instead of sending nothing
+                    if chunk.is_empty() {
+                        continue;
+                    }
+                    Poll::Ready(Some(Ok(chunk)))
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    self.finished.store(true, Ordering::Relaxed);
+                    Poll::Ready(Some(Err(std::io::Error::other(e))))
+                }
+                Poll::Ready(None) => {
+                    self.finished.store(true, Ordering::Relaxed);
+                    // Release a final line without a trailing newline, then end
+                    let rest = self.rewriter.as_mut().map(SseRewriter::finish);
+                    match rest {
+                        Some(rest) if !rest.is_empty() => Poll::Ready(Some(Ok(rest))),
+                        _ => Poll::Ready(None),
+                    }
+                }
+                // No data yet: end the stream once the upstream has been idle too long
+                Poll::Pending => {
+                    if !self.waiting {
+                        self.waiting = true;
+                        let idle_until = tokio::time::Instant::now() + self.idle_timeout;
+                        self.idle.as_mut().reset(idle_until);
+                    }
+                    if std::future::Future::poll(self.idle.as_mut(), cx).is_pending() {
+                        return Poll::Pending;
+                    }
+                    self.stalled.store(true, Ordering::Relaxed);
+                    Poll::Ready(Some(Err(std::io::Error::other(UPSTREAM_STALLED_MESSAGE))))
+                }
+            };
+        }
+    }
+}
+
+/// Whether SSE data may contain an error event (an `"error"` key).
+fn may_hold_error(data: &[u8]) -> bool {
+    data.windows(7).any(|w| w == b"\"error\"")
+}
+
+/// Rewrites the JSON of SSE `data:` lines: removes configured keys, replaces the
+/// provider's model name with the requested one and tags error events with the
+/// provider's request id. Chunks may split lines anywhere, so bytes after the last
+/// newline are held back until it arrives. With only a request id to add, chunks
+/// that cannot hold an error event are relayed as they come.
+struct SseRewriter {
+    /// Keys removed at any depth (SSE_STRIP_FIELDS).
+    fields: Vec<String>,
+    /// Set as each event's `model` (REWRITE_RESPONSE_MODEL).
+    model: Option<String>,
+    /// Added as `error.provider_request_id` to mid-stream error events.
+    provider_request_id: Option<String>,
+    partial: Vec<u8>,
+}
+
+impl SseRewriter {
+    /// None when there is nothing to rewrite, so the stream is relayed verbatim.
+    fn new(
+        fields: Vec<String>,
+        model: Option<String>,
+        provider_request_id: Option<String>,
+    ) -> Option<Self> {
+        (!fields.is_empty() || model.is_some() || provider_request_id.is_some()).then(|| Self {
+            fields,
+            model,
+            provider_request_id,
+            partial: Vec::new(),
+        })
+    }
+
+    /// Rewrite every complete line received so far.
+    fn feed(&mut self, chunk: bytes::Bytes) -> bytes::Bytes {
+        let error_only = self.fields.is_empty() && self.model.is_none();
+        if error_only && self.partial.is_empty() && !may_hold_error(&chunk) {
+            return chunk;
+        }
+        self.partial.extend_from_slice(&chunk);
+        let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            return bytes::Bytes::new();
+        };
+        let rest = self.partial.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        self.rewrite_lines(&complete)
+    }
+
+    /// Rewrite whatever is left once the upstream stream has ended.
+    fn finish(&mut self) -> bytes::Bytes {
+        let rest = std::mem::take(&mut self.partial);
+        self.rewrite_lines(&rest)
+    }
+
+    fn rewrite_lines(&self, data: &[u8]) -> bytes::Bytes {
+        let mut out = Vec::with_capacity(data.len());
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            match self.rewrite_line(line) {
+                Some(rewritten) => out.extend_from_slice(&rewritten),
+                None => out.extend_from_slice(line),
+            }
+        }
+        bytes::Bytes::from(out)
+    }
+
+    /// The rewritten line, or None when it is kept verbatim (not a JSON event,
+    /// or nothing to change in it).
+    fn rewrite_line(&self, line: &[u8]) -> Option<Vec<u8>> {
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        let payload = content.strip_prefix(b"data:")?;
+        // Without fields to strip or a model to set only error events change; skip
+        // parsing the rest
+        if self.fields.is_empty() && self.model.is_none() && !may_hold_error(payload) {
+            return None;
+        }
+        let mut event: serde_json::Value = serde_json::from_slice(payload).ok()?;
+        let mut changed = strip_json_fields(&mut event, &self.fields);
+        if let Some(model) = &self.model {
+            changed |= replace_response_model(&mut event, model);
+        }
+        if let (Some(id), Some(error)) = (
+            &self.provider_request_id,
+            event.get_mut("error").and_then(|e| e.as_object_mut()),
+        ) {
+            error.insert("provider_request_id".into(), id.clone().into());
+            changed = true;
+        }
+        if !changed {
+            return None;
+        }
+        let mut rewritten = b"data: ".to_vec();
+        rewritten.extend_from_slice(&serde_json::to_vec(&event).ok()?);
+        rewritten.extend_from_slice(&line[content.len()..]);
+        Some(rewritten)
+    }
+}
+
+/// JSON body of a gateway-generated `upstream_error`, tagged with the provider's
+/// request id when known.
+fn upstream_error_body(message: &str, code: &str, provider_request_id: Option<&str>) -> bytes::Bytes {
+    let mut error = serde_json::json!({
+        "message": message,
+        "type": ErrorType::Upstream.as_str(),
+        "code": code,
+    });
+    if let Some(id) = provider_request_id {
+        error["provider_request_id"] = id.into();
+    }
+    serde_json::to_vec(&serde_json::json!({ "error": error }))
+        .unwrap_or_default()
+        .into()
+}
+
+/// Milliseconds from an `x-gateway-timeout-ms` value; None unless from 1 to `max`.
+fn parse_timeout_ms(value: &HeaderValue, max: u64) -> Option<u64> {
+    value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|&ms| ms > 0 && ms <= max)
+}
+
+/// 504 for a request whose `x-gateway-timeout-ms` deadline passed, carrying the sends
+/// made until then.
+fn deadline_exceeded(sent: Option<&UpstreamAttempts>) -> Response {
+    let mut response = json_error(
+        StatusCode::GATEWAY_TIMEOUT,
+        ErrorType::Upstream,
+        format!("Upstream did not respond within the {TIMEOUT_HEADER} deadline"),
+        Some("deadline_exceeded"),
+    );
+    if let Some(sent) = sent {
+        response.extensions_mut().insert(sent.clone());
+    }
+    response
+}
+
+/// Append the provider's request id to a logged error message, when known.
+fn with_provider_request_id(message: String, provider_request_id: Option<&str>) -> String {
+    match provider_request_id {
+        Some(id) => format!("{message} (provider request id: {id})"),
+        None => message,
+    }
+}
+
+/// Remove `fields` from every object in `value`. Returns whether anything was removed.
+fn strip_json_fields(value: &mut serde_json::Value, fields: &[String]) -> bool {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut removed = false;
+            for field in fields {
+                removed |= map.remove(field).is_some();
             }
-            Poll::Ready(Some(Err(e))) => {
-                Poll::Ready(Some(Err(std::io::Error::new(std::io::ErrorKind::Other, e))))
+            for v in map.values_mut() {
+                removed |= strip_json_fields(v, fields);
             }
-            Poll::Ready(None) => {
-                // Stream ended — drop the sender so the receiver knows
-                Poll::Ready(None)
+            removed
+        }
+        serde_json::Value::Array(items) => {
+            let mut removed = false;
+            for v in items {
+                removed |= strip_json_fields(v, fields);
             }
-            Poll::Pending => Poll::Pending,
+            removed
+        }
+        _ => false,
+    }
+}
+
+/// The client's copy of a response body with `fields` removed and `model` set; None when
+/// neither changes anything, so the upstream bytes are forwarded as they are.
+fn rewrite_response_json(
+    body: &serde_json::Value,
+    fields: &[String],
+    model: Option<&str>,
+) -> Option<serde_json::Value> {
+    if fields.is_empty() && model.is_none() {
+        return None;
+    }
+    let mut body = body.clone();
+    let stripped = strip_json_fields(&mut body, fields);
+    let renamed = model.is_some_and(|m| replace_response_model(&mut body, m));
+    (stripped || renamed).then_some(body)
+}
+
+/// Set a response's (or stream chunk's) top-level `model` to `model` when it names
+/// something else. Returns whether it changed.
+fn replace_response_model(value: &mut serde_json::Value, model: &str) -> bool {
+    match value.get_mut("model") {
+        Some(current) if current.as_str() != Some(model) => {
+            *current = model.into();
+            true
         }
+        _ => false,
     }
 }
 
-// ── SSE Usage Parser ──────────────────────────────────────────────────
+/// Merge streamed `chat.completion.chunk` objects into a single `chat.completion`.
+/// Content is concatenated per choice, and tool_call fragments are merged by their
+/// `index` (id/type/name taken from the first fragment that has them, arguments appended).
+fn assemble_stream_chunks(chunks: &[serde_json::Value]) -> serde_json::Value {
+    use serde_json::{json, Map, Value};
 
-/// Parse concatenated SSE bytes to extract `usage` from any `data:` event.
-/// Scans all chunks and keeps the last `usage` object found (providers may place
-/// it on the final content chunk, a separate chunk, or both).
-/// Returns (prompt_tokens, completion_tokens, total_tokens, optional full response body).
-fn parse_sse_usage_and_body(
-    buffer: &[u8],
-) -> (Option<i32>, Option<i32>, Option<i32>, Option<serde_json::Value>) {
-    let text = String::from_utf8_lossy(buffer);
+    let mut assembled = Map::new();
+    assembled.insert("object".into(), json!("chat.completion"));
+    // choice index -> (message, finish_reason)
+    let mut choices: std::collections::BTreeMap<u64, (Map<String, Value>, Value)> =
+        std::collections::BTreeMap::new();
 
-    let mut all_chunks: Vec<serde_json::Value> = Vec::new();
-    let mut usage_prompt: Option<i32> = None;
-    let mut usage_completion: Option<i32> = None;
-    let mut usage_total: Option<i32> = None;
+    for chunk in chunks {
+        for field in ["id", "created", "model", "system_fingerprint"] {
+            if !assembled.contains_key(field) {
+                if let Some(v) = chunk.get(field).filter(|v| !v.is_null()) {
+                    assembled.insert(field.into(), v.clone());
+                }
+            }
+        }
+        if let Some(usage) = chunk.get("usage").filter(|v| !v.is_null()) {
+            assembled.insert("usage".into(), usage.clone());
+        }
 
-    for line in text.lines() {
-        let line = line.trim();
-        if let Some(data) = line.strip_prefix("data:") {
-            let data = data.trim();
-            if data == "[DONE]" {
-                continue;
+        let Some(chunk_choices) = chunk.get("choices").and_then(|c| c.as_array()) else {
+            continue;
+        };
+        for choice in chunk_choices {
+            let index = choice.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+            let (message, finish_reason) = choices
+                .entry(index)
+                .or_insert_with(|| (Map::new(), Value::Null));
+
+            if let Some(reason) = choice.get("finish_reason").filter(|v| !v.is_null()) {
+                *finish_reason = reason.clone();
             }
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                // Check for usage in this chunk (keep latest found)
-                if let Some(usage) = json.get("usage") {
-                    if let Some(pt) = usage.get("prompt_tokens").and_then(|v| v.as_i64()) {
-                        usage_prompt = Some(pt as i32);
+
+            let Some(delta) = choice.get("delta").and_then(|d| d.as_object()) else {
+                continue;
+            };
+            for (key, value) in delta {
+                match (key.as_str(), value) {
+                    (_, Value::Null) => {}
+                    ("tool_calls", Value::Array(fragments)) => {
+                        merge_tool_call_fragments(message, fragments);
+                    }
+                    ("role", v) => {
+                        message.insert("role".into(), v.clone());
                     }
-                    if let Some(ct) = usage.get("completion_tokens").and_then(|v| v.as_i64()) {
-                        usage_completion = Some(ct as i32);
+                    (k, Value::String(text)) => {
+                        match message.get_mut(k) {
+                            Some(Value::String(existing)) => existing.push_str(text),
+                            _ => {
+                                message.insert(k.to_string(), Value::String(text.clone()));
+                            }
+                        }
                     }
-                    if let Some(tt) = usage.get("total_tokens").and_then(|v| v.as_i64()) {
-                        usage_total = Some(tt as i32);
+                    (k, v) => {
+                        message.insert(k.to_string(), v.clone());
                     }
                 }
-                all_chunks.push(json);
             }
         }
     }
 
-    // Build a response body from the chunks for storage
-    let response_body = if all_chunks.is_empty() {
-        None
-    } else {
-        Some(serde_json::Value::Array(all_chunks))
+    let choices: Vec<Value> = choices
+        .into_iter()
+        .map(|(index, (mut message, finish_reason))| {
+            message.entry("role").or_insert_with(|| json!("assistant"));
+            json!({
+                "index": index,
+                "message": message,
+                "finish_reason": finish_reason,
+            })
+        })
+        .collect();
+    assembled.insert("choices".into(), Value::Array(choices));
+
+    Value::Object(assembled)
+}
+
+/// Merge streamed tool_call fragments into `message.tool_calls`, keyed by fragment `index`.
+fn merge_tool_call_fragments(
+    message: &mut serde_json::Map<String, serde_json::Value>,
+    fragments: &[serde_json::Value],
+) {
+    use serde_json::{json, Value};
+
+    let tool_calls = message
+        .entry("tool_calls")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    let Value::Array(tool_calls) = tool_calls else {
+        return;
     };
 
-    (usage_prompt, usage_completion, usage_total, response_body)
+    for fragment in fragments {
+        let index = fragment.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
+        while tool_calls.len() <= index {
+            tool_calls.push(json!({ "function": { "arguments": "" } }));
+        }
+        let call = &mut tool_calls[index];
+
+        for field in ["id", "type"] {
+            if let Some(v) = fragment.get(field).filter(|v| !v.is_null()) {
+                if call.get(field).is_none() {
+                    call[field] = v.clone();
+                }
+            }
+        }
+
+        if let Some(function) = fragment.get("function") {
+            if let Some(name) = function.get("name").and_then(|n| n.as_str()) {
+                if call["function"].get("name").is_none() {
+                    call["function"]["name"] = json!(name);
+                }
+            }
+            if let Some(args) = function.get("arguments").and_then(|a| a.as_str()) {
+                if let Some(Value::String(existing)) = call["function"].get_mut("arguments") {
+                    existing.push_str(args);
+                }
+            }
+        }
+    }
+}
+
+/// Raw bytes of a teed SSE stream, kept for usage parsing and logging up to
+/// `MAX_SHADOW_BUFFER_BYTES`. Past the cap the bytes are dropped and events are
+/// parsed as they arrive instead, keeping only a `StreamSummary`.
+struct ShadowBuffer {
+    bytes: Vec<u8>,
+    max_bytes: Option<usize>,
+    overflow: Option<StreamSummary>,
+}
+
+/// What the log needs from a stream too large to keep.
+#[derive(Default)]
+struct StreamSummary {
+    /// Bytes after the last newline, completed by the next chunk.
+    partial: Vec<u8>,
+    total_bytes: usize,
+    usage: Usage,
+    /// First streamed `error` object.
+    error: Option<serde_json::Value>,
+    /// Last `finish_reason` of the first choice.
+    finish_reason: Option<String>,
+    /// Characters of completion text, for token estimates.
+    completion_chars: usize,
+}
+
+impl ShadowBuffer {
+    fn new(max_bytes: Option<usize>) -> Self {
+        Self {
+            bytes: Vec::new(),
+            max_bytes,
+            overflow: None,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        if let Some(summary) = self.overflow.as_mut() {
+            summary.feed(chunk);
+            return;
+        }
+        self.bytes.extend_from_slice(chunk);
+        if self.max_bytes.is_some_and(|max| self.bytes.len() > max) {
+            let mut summary = StreamSummary::default();
+            summary.feed(&std::mem::take(&mut self.bytes));
+            self.overflow = Some(summary);
+        }
+    }
+
+    /// Push chunks from `rx` until the sender is dropped. Gives up once `max_wait`
+    /// has passed and returns true.
+    async fn fill(&mut self, mut rx: mpsc::UnboundedReceiver<ByteChunk>, max_wait: Duration) -> bool {
+        let give_up_at = tokio::time::Instant::now() + max_wait;
+        loop {
+            match tokio::time::timeout_at(give_up_at, rx.recv()).await {
+                Ok(Some(chunk)) => self.push(&chunk),
+                Ok(None) => return false,
+                Err(_) => return true,
+            }
+        }
+    }
+}
+
+impl StreamSummary {
+    fn feed(&mut self, chunk: &[u8]) {
+        self.total_bytes += chunk.len();
+        self.partial.extend_from_slice(chunk);
+        let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            return;
+        };
+        let rest = self.partial.split_off(end + 1);
+        let lines = std::mem::replace(&mut self.partial, rest);
+        for line in String::from_utf8_lossy(&lines).lines() {
+            self.feed_line(line);
+        }
+    }
+
+    /// Parse a final line without a trailing newline.
+    fn finish(&mut self) {
+        let rest = std::mem::take(&mut self.partial);
+        self.feed_line(&String::from_utf8_lossy(&rest));
+    }
+
+    fn feed_line(&mut self, line: &str) {
+        let Some(data) = line.trim().strip_prefix("data:") else {
+            return;
+        };
+        let Ok(chunk) = serde_json::from_str::<serde_json::Value>(data.trim()) else {
+            return;
+        };
+        self.usage.merge(Usage::from_response_json(&chunk));
+        if self.error.is_none() {
+            self.error = chunk.get("error").cloned();
+        }
+        let chunk = [chunk];
+        if let Some(reason) = stream_finish_reason(&chunk) {
+            self.finish_reason = Some(reason);
+        }
+        self.completion_chars += completion_text_chars(&assemble_stream_chunks(&chunk));
+    }
 }
 
 // ── Helpers ───────────────────────────────────────────────────────────
 
-/// Copy useful upstream headers (rate-limit, request-id, etc.) to the gateway response.
-fn copy_upstream_headers(from: &reqwest::header::HeaderMap, to: &mut HeaderMap) {
-    let headers_to_copy = [
-        "x-ratelimit-limit-requests",
-        "x-ratelimit-limit-tokens",
-        "x-ratelimit-remaining-requests",
-        "x-ratelimit-remaining-tokens",
-        "x-ratelimit-reset-requests",
-        "x-ratelimit-reset-tokens",
-        "x-request-id",
-        "openai-processing-ms",
-        "openai-organization",
-    ];
+const MISSING_USAGE_MESSAGE: &str = "Upstream provider did not report token usage";
 
-    for name in headers_to_copy {
-        if let Some(val) = from.get(name) {
-            if let Ok(v) = HeaderValue::from_bytes(val.as_bytes()) {
-                to.insert(
-                    header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
-                    v,
-                );
+/// `error_message` of streams the client closed before the upstream finished.
+const CLIENT_DISCONNECTED_MESSAGE: &str = "client disconnected";
+
+/// `error_message` of streams ended by `STREAM_IDLE_TIMEOUT_SECS`.
+const UPSTREAM_STALLED_MESSAGE: &str = "Upstream stream stalled: no data within STREAM_IDLE_TIMEOUT_SECS";
+
+/// Error logged (and sent to the client) when a stream outlives MAX_STREAM_DURATION_SECS.
+const STREAM_EXPIRED_MESSAGE: &str = "Stream exceeded MAX_STREAM_DURATION_SECS";
+
+/// The request body for one upstream attempt on `route`: provider-side model name,
+/// reasoning controls in the provider's dialect, buffered-upstream `stream: false`,
+/// and `stream_options` injected or stripped.
+fn upstream_body_for(
+    body: &serde_json::Value,
+    route: &ModelRoute,
+    is_stream: bool,
+) -> serde_json::Value {
+    let mut body = body.clone();
+
+    // Rewrite model name if the provider uses a different name
+    body["model"] = serde_json::Value::String(route.provider_model_name.clone());
+
+    map_reasoning_fields(&mut body, &route.provider_kind);
+
+    // Providers flagged force_buffered_upstream get a non-streaming request;
+    // the full response is re-emitted to the client as SSE
+    let buffer_upstream = is_stream && route.force_buffered_upstream;
+    if buffer_upstream {
+        body["stream"] = serde_json::Value::Bool(false);
+    }
+
+    // For streaming requests, inject stream_options to request usage data
+    // Many OpenAI-compatible providers only include usage when this is set.
+    // Providers that reject the field (supports_stream_usage = false) never see it.
+    if buffer_upstream || (is_stream && !route.supports_stream_usage) {
+        if let Some(obj) = body.as_object_mut() {
+            obj.remove("stream_options");
+        }
+    } else if injects_stream_options(&body, route, is_stream) {
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+    }
+
+    if route.prompt_caching && PROMPT_CACHE_CONTROL_KINDS.contains(&route.provider_kind.as_str()) {
+        mark_system_prompt_cacheable(&mut body);
+    }
+
+    body
+}
+
+/// Translate the client's reasoning controls to the provider's fields. Clients send
+/// `reasoning_effort` ("minimal" | "low" | "medium" | "high", or "none") and/or
+/// `reasoning: { effort, max_tokens }`:
+/// - openai: `reasoning_effort` (a thinking budget has no equivalent and is dropped)
+/// - openrouter: `reasoning: { effort }` or `reasoning: { max_tokens }`
+/// - dashscope: `enable_thinking` (off for "none" / "minimal") and `thinking_budget`
+/// - ark: `thinking: { type: "enabled" | "disabled" }`
+///
+/// Provider-native fields the client set itself are kept; `openai_compatible`
+/// requests are forwarded unchanged.
+fn map_reasoning_fields(body: &mut serde_json::Value, provider_kind: &str) {
+    if provider_kind == "openai_compatible" {
+        return;
+    }
+    let Some(obj) = body.as_object_mut() else {
+        return;
+    };
+    if !obj.contains_key("reasoning_effort") && !obj.contains_key("reasoning") {
+        return;
+    }
+    let reasoning_effort = obj.remove("reasoning_effort");
+    let reasoning = obj.remove("reasoning");
+    let effort = reasoning_effort
+        .as_ref()
+        .and_then(|e| e.as_str())
+        .or_else(|| reasoning.as_ref()?.get("effort")?.as_str())
+        .map(str::to_string);
+    let max_tokens = reasoning
+        .as_ref()
+        .and_then(|r| r.get("max_tokens"))
+        .and_then(|t| t.as_u64());
+    let thinking_off = matches!(effort.as_deref(), Some("none" | "minimal"));
+
+    match provider_kind {
+        "openai" => {
+            if let Some(effort) = effort {
+                obj.insert("reasoning_effort".into(), effort.into());
+            }
+        }
+        "openrouter" => {
+            let reasoning = match (reasoning, max_tokens, effort) {
+                (Some(reasoning @ serde_json::Value::Object(_)), _, _) => reasoning,
+                (_, Some(max_tokens), _) => serde_json::json!({ "max_tokens": max_tokens }),
+                (_, None, Some(effort)) => serde_json::json!({ "effort": effort }),
+                _ => return,
+            };
+            obj.insert("reasoning".into(), reasoning);
+        }
+        "dashscope" => {
+            if effort.is_some() || max_tokens.is_some() {
+                obj.entry("enable_thinking").or_insert((!thinking_off).into());
+            }
+            if let Some(max_tokens) = max_tokens.filter(|_| !thinking_off) {
+                obj.entry("thinking_budget").or_insert(max_tokens.into());
+            }
+        }
+        "ark" => {
+            if effort.is_some() || max_tokens.is_some() {
+                let kind = if thinking_off { "disabled" } else { "enabled" };
+                obj.entry("thinking").or_insert(serde_json::json!({ "type": kind }));
+            }
+        }
+        _ => {
+            // Unknown kind: forward the client's fields as they were
+            if let Some(reasoning_effort) = reasoning_effort {
+                obj.insert("reasoning_effort".into(), reasoning_effort);
+            }
+            if let Some(reasoning) = reasoning {
+                obj.insert("reasoning".into(), reasoning);
             }
         }
     }
 }
 
-/// Build the proxy router (to be nested under /v1)
-pub fn router() -> Router<Arc<AppState>> {
-    Router::new().route("/chat/completions", post(chat_completions))
+/// Whether `upstream_body_for` adds `stream_options.include_usage`: streamed upstream to
+/// a provider that supports it, and not already set by the client.
+fn injects_stream_options(body: &serde_json::Value, route: &ModelRoute, is_stream: bool) -> bool {
+    is_stream
+        && !route.force_buffered_upstream
+        && route.supports_stream_usage
+        && body.get("stream_options").is_none()
+}
+
+/// Provider kinds that honor Anthropic-style `cache_control` markers on content parts
+/// (OpenRouter for Anthropic/Gemini models, DashScope explicit caching). Other providers
+/// either cache automatically (OpenAI) or would reject the field.
+const PROMPT_CACHE_CONTROL_KINDS: &[&str] = &["openrouter", "dashscope"];
+
+/// Add `cache_control: {"type": "ephemeral"}` to the last text part of the last system
+/// message, converting string content to a single text part. The provider then caches
+/// the prompt prefix up to and including that part.
+fn mark_system_prompt_cacheable(body: &mut serde_json::Value) {
+    let Some(messages) = body.get_mut("messages").and_then(|m| m.as_array_mut()) else {
+        return;
+    };
+    let Some(system) = messages
+        .iter_mut()
+        .rev()
+        .find(|m| m.get("role").and_then(|r| r.as_str()) == Some("system"))
+    else {
+        return;
+    };
+    let cache_control = serde_json::json!({ "type": "ephemeral" });
+    match system.get_mut("content") {
+        Some(serde_json::Value::String(text)) => {
+            let text = std::mem::take(text);
+            system["content"] = serde_json::json!([
+                { "type": "text", "text": text, "cache_control": cache_control }
+            ]);
+        }
+        Some(serde_json::Value::Array(parts)) => {
+            if let Some(part) = parts
+                .iter_mut()
+                .rev()
+                .find(|p| p.get("type").and_then(|t| t.as_str()) == Some("text"))
+            {
+                part["cache_control"] = cache_control;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether an error status from `route` should fail over to another candidate:
+/// the provider's `retry_on_status`, or 429 / 5xx by default.
+fn fails_over_on(route: &ModelRoute, status_code: i16) -> bool {
+    match &route.retry_on_status {
+        Some(statuses) => statuses.contains(&(status_code as i32)),
+        None => is_retriable_status(status_code),
+    }
+}
+
+/// Pick a route at random, weighting each candidate's configured weight by its
+/// provider's recent success rate: `weight × ((1 − blend) + blend × success_rate)`.
+/// The chosen route is removed from `candidates`. Returns None when there are no
+/// candidates left; zero total weight picks the first.
+fn select_route(
+    candidates: &mut Vec<ModelRoute>,
+    health: &ProviderHealth,
+    blend: f64,
+) -> Option<ModelRoute> {
+    if candidates.len() <= 1 {
+        return candidates.pop();
+    }
+
+    let scores: Vec<f64> = candidates
+        .iter()
+        .map(|r| {
+            let success_rate = health.success_rate(r.provider_id);
+            r.weight.max(0) as f64 * ((1.0 - blend) + blend * success_rate)
+        })
+        .collect();
+    let total: f64 = scores.iter().sum();
+    if total <= 0.0 {
+        return Some(candidates.swap_remove(0));
+    }
+
+    let mut pick = rand::random::<f64>() * total;
+    let idx = scores
+        .iter()
+        .position(|score| {
+            pick -= score;
+            pick < 0.0
+        })
+        .unwrap_or(scores.len() - 1);
+    Some(candidates.swap_remove(idx))
+}
+
+/// The route a session sticks to, by weighted rendezvous hashing of the session id
+/// with each provider: every session maps to the same provider while the candidate
+/// set is unchanged, and losing a provider only moves that provider's sessions.
+/// Routes with zero weight, or whose provider's success rate is below
+/// `min_success_rate`, are skipped. The chosen route is removed from `candidates`.
+fn select_sticky_route(
+    candidates: &mut Vec<ModelRoute>,
+    session_id: &str,
+    health: &ProviderHealth,
+    min_success_rate: f64,
+) -> Option<ModelRoute> {
+    use sha2::{Digest, Sha256};
+
+    let idx = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| r.weight > 0 && health.success_rate(r.provider_id) >= min_success_rate)
+        .map(|(i, r)| {
+            let mut hasher = Sha256::new();
+            hasher.update(session_id.as_bytes());
+            hasher.update(r.provider_id.as_bytes());
+            let digest = hasher.finalize();
+            let bits = u64::from_be_bytes(digest[..8].try_into().unwrap()) >> 11;
+            // Uniform in (0, 1); a higher weight raises the score proportionally
+            let unit = (bits as f64 + 0.5) / (1u64 << 53) as f64;
+            (i, r.weight as f64 / -unit.ln())
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?
+        .0;
+    Some(candidates.swap_remove(idx))
+}
+
+/// Whether a failed request with this status is worth retrying: rate limits and
+/// server/upstream failures, as opposed to client errors.
+fn is_retriable_status(status_code: i16) -> bool {
+    status_code == 429 || status_code >= 500
+}
+
+/// Client response for a model's fallback completion, as SSE for streaming requests.
+fn fallback_to_response(fallback: &serde_json::Value, is_stream: bool) -> Response {
+    let (content_type, body) = if is_stream {
+        ("text/event-stream", completion_to_sse(fallback))
+    } else {
+        ("application/json", serde_json::to_vec(fallback).unwrap_or_default())
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(FALLBACK_HEADER, "true")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// Re-encode a non-streaming chat completion as an SSE body: one chunk per
+/// choice carrying the whole message, a finish chunk, a usage chunk and `[DONE]`.
+fn completion_to_sse(completion: &serde_json::Value) -> Vec<u8> {
+    let id = completion.get("id").cloned().unwrap_or(serde_json::Value::Null);
+    let created = completion.get("created").cloned().unwrap_or(serde_json::Value::Null);
+    let model = completion.get("model").cloned().unwrap_or(serde_json::Value::Null);
+    let chunk = |choices: serde_json::Value| {
+        serde_json::json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": model,
+            "choices": choices,
+        })
+    };
+
+    let choices = completion
+        .get("choices")
+        .and_then(|c| c.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    let mut events = Vec::new();
+    for (i, choice) in choices.iter().enumerate() {
+        let index = choice.get("index").cloned().unwrap_or_else(|| i.into());
+        let message = choice.get("message").cloned().unwrap_or_default();
+        let mut delta = serde_json::json!({
+            "role": message.get("role").cloned().unwrap_or_else(|| "assistant".into()),
+        });
+        if let Some(content) = message.get("content").filter(|c| !c.is_null()) {
+            delta["content"] = content.clone();
+        }
+        if let Some(calls) = message.get("tool_calls").and_then(|t| t.as_array()) {
+            let calls: Vec<serde_json::Value> = calls
+                .iter()
+                .enumerate()
+                .map(|(n, call)| {
+                    let mut call = call.clone();
+                    call["index"] = n.into();
+                    call
+                })
+                .collect();
+            delta["tool_calls"] = calls.into();
+        }
+        events.push(chunk(serde_json::json!([{
+            "index": index,
+            "delta": delta,
+            "finish_reason": null,
+        }])));
+        events.push(chunk(serde_json::json!([{
+            "index": index,
+            "delta": {},
+            "finish_reason": choice.get("finish_reason").cloned().unwrap_or_else(|| "stop".into()),
+        }])));
+    }
+    if let Some(usage) = completion.get("usage") {
+        let mut usage_chunk = chunk(serde_json::json!([]));
+        usage_chunk["usage"] = usage.clone();
+        events.push(usage_chunk);
+    }
+
+    let mut out = Vec::new();
+    for event in events {
+        out.extend_from_slice(b"data: ");
+        out.extend_from_slice(event.to_string().as_bytes());
+        out.extend_from_slice(b"\n\n");
+    }
+    out.extend_from_slice(b"data: [DONE]\n\n");
+    out
+}
+
+/// Total characters of text content across the request's `messages`.
+/// Content may be a plain string or a multimodal array; only text parts are counted.
+fn prompt_text_chars(body: &serde_json::Value) -> usize {
+    body.get("messages")
+        .and_then(|v| v.as_array())
+        .map(|messages| {
+            messages
+                .iter()
+                .filter_map(|m| m.get("content"))
+                .map(|content| match content {
+                    serde_json::Value::String(s) => s.chars().count(),
+                    serde_json::Value::Array(parts) => parts
+                        .iter()
+                        .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                        .map(|t| t.chars().count())
+                        .sum(),
+                    _ => 0,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Total characters of generated content and tool-call arguments in a chat completion.
+fn completion_text_chars(completion: &serde_json::Value) -> usize {
+    let Some(choices) = completion.get("choices").and_then(|c| c.as_array()) else {
+        return 0;
+    };
+    choices
+        .iter()
+        .filter_map(|c| c.get("message"))
+        .map(|message| {
+            let content = message
+                .get("content")
+                .and_then(|c| c.as_str())
+                .map(|c| c.chars().count())
+                .unwrap_or(0);
+            let tool_args: usize = message
+                .get("tool_calls")
+                .and_then(|t| t.as_array())
+                .map(|calls| {
+                    calls
+                        .iter()
+                        .filter_map(|c| c["function"]["arguments"].as_str())
+                        .map(|a| a.chars().count())
+                        .sum()
+                })
+                .unwrap_or(0);
+            content + tool_args
+        })
+        .sum()
+}
+
+/// `finish_reason` of the first choice of a completion (longer values are cut to
+/// fit the log column).
+fn first_finish_reason(completion: &serde_json::Value) -> Option<String> {
+    completion
+        .pointer("/choices/0/finish_reason")
+        .and_then(|r| r.as_str())
+        .map(|r| r.chars().take(64).collect())
+}
+
+/// Last `finish_reason` streamed for the first choice.
+fn stream_finish_reason(chunks: &[serde_json::Value]) -> Option<String> {
+    chunks
+        .iter()
+        .filter_map(|chunk| chunk.get("choices")?.as_array())
+        .flatten()
+        .filter(|choice| choice.get("index").and_then(|i| i.as_u64()).unwrap_or(0) == 0)
+        .filter_map(|choice| choice.get("finish_reason")?.as_str())
+        .next_back()
+        .map(|r| r.chars().take(64).collect())
+}
+
+/// A completion with no choices, or only choices without content, refusal or tool
+/// calls, whose usage reports no completion tokens.
+fn is_empty_completion(completion: &serde_json::Value, completion_tokens: Option<i32>) -> bool {
+    if completion_tokens.unwrap_or(0) > 0 {
+        return false;
+    }
+    let has_text = |message: &serde_json::Value, field: &str| {
+        message
+            .get(field)
+            .and_then(|v| v.as_str())
+            .is_some_and(|s| !s.is_empty())
+    };
+    let choices = completion
+        .get("choices")
+        .and_then(|c| c.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    !choices.iter().filter_map(|c| c.get("message")).any(|message| {
+        has_text(message, "content")
+            || has_text(message, "refusal")
+            || message
+                .get("tool_calls")
+                .and_then(|t| t.as_array())
+                .is_some_and(|calls| !calls.is_empty())
+    })
+}
+
+/// Log / error message for an empty completion.
+fn empty_completion_message(finish_reason: Option<&str>) -> String {
+    format!(
+        "Upstream returned an empty completion (finish_reason: {})",
+        finish_reason.unwrap_or("none")
+    )
+}
+
+/// `tokenizer` logged for estimates of models without a known encoding.
+const ESTIMATE_TOKENIZER: &str = "chars/4";
+
+/// Encodings of OpenAI model families, matched by name prefix as in tiktoken.
+const DEFAULT_TOKENIZER_ENCODINGS: &[(&str, &str)] = &[
+    ("gpt-5", "o200k_base"),
+    ("gpt-4.5", "o200k_base"),
+    ("gpt-4.1", "o200k_base"),
+    ("gpt-4o", "o200k_base"),
+    ("chatgpt-4o", "o200k_base"),
+    ("o1", "o200k_base"),
+    ("o3", "o200k_base"),
+    ("o4", "o200k_base"),
+    ("gpt-4", "cl100k_base"),
+    ("gpt-3.5", "cl100k_base"),
+    ("gpt-35", "cl100k_base"),
+];
+
+/// Encoding logged as the `tokenizer` of estimates for `model` (the provider-side
+/// name, without an `org/` vendor prefix): the longest `TOKENIZER_ENCODINGS` prefix
+/// match, else the longest built-in one, else `chars/4`.
+fn resolve_tokenizer<'a>(model: &str, configured: &'a [(String, String)]) -> &'a str {
+    let model = model.rsplit('/').next().unwrap_or(model);
+    let configured = configured.iter().map(|(p, e)| (p.as_str(), e.as_str()));
+    longest_prefix_match(model, configured)
+        .or_else(|| longest_prefix_match(model, DEFAULT_TOKENIZER_ENCODINGS.iter().copied()))
+        .unwrap_or(ESTIMATE_TOKENIZER)
+}
+
+/// Value of the `(prefix, value)` pair with the longest prefix of `name`.
+fn longest_prefix_match<'a>(
+    name: &str,
+    pairs: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Option<&'a str> {
+    pairs
+        .filter(|(prefix, _)| name.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, value)| value)
+}
+
+/// Rough token estimate (~4 characters per token) used when a provider omits usage.
+fn estimate_tokens(chars: usize) -> i32 {
+    chars.div_ceil(4) as i32
+}
+
+/// Prompt and completion tokens of a stream cut short, when the provider did not get to
+/// report both: reported counts are kept, the rest estimated from the request's prompt
+/// and the completion text received so far.
+fn partial_usage(
+    prompt_tokens: Option<i32>,
+    completion_tokens: Option<i32>,
+    estimated_prompt_tokens: Option<i32>,
+    completion_chars: impl FnOnce() -> usize,
+) -> Option<(i32, i32)> {
+    if prompt_tokens.is_some() && completion_tokens.is_some() {
+        return None;
+    }
+    let pt = prompt_tokens.unwrap_or(estimated_prompt_tokens.unwrap_or(0));
+    let ct = completion_tokens.unwrap_or_else(|| estimate_tokens(completion_chars()));
+    Some((pt, ct))
+}
+
+/// Warn about and count a request slower than `SLOW_REQUEST_MS`.
+fn report_slow_request(
+    threshold_ms: Option<u64>,
+    slow_requests: &AtomicU64,
+    model: &str,
+    provider_kind: &str,
+    latency_ms: i32,
+    is_stream: bool,
+) {
+    let Some(threshold_ms) = threshold_ms else {
+        return;
+    };
+    if (latency_ms as u64) <= threshold_ms {
+        return;
+    }
+    slow_requests.fetch_add(1, Ordering::Relaxed);
+    tracing::warn!(
+        "Slow {} request: model {} via {} took {}ms (SLOW_REQUEST_MS={})",
+        if is_stream { "streaming" } else { "non-streaming" },
+        model,
+        provider_kind,
+        latency_ms,
+        threshold_ms
+    );
+}
+
+/// Tokens held against a key's budget, or its budget for `model`, while a request is
+/// in flight (`BUDGET_ENFORCEMENT=reserve`). Dropping it unsettled refunds the
+/// reservation, so requests that fail before usage is known are not charged.
+struct BudgetReservation {
+    key_id: uuid::Uuid,
+    model: Option<String>,
+    tokens: i64,
+    db: sqlx::PgPool,
+    settled: bool,
+}
+
+impl BudgetReservation {
+    /// Consume the reservation; returns what is still owed for `actual` weighted
+    /// tokens (negative when the estimate was too high).
+    fn settle(mut self, actual: i64) -> i64 {
+        self.settled = true;
+        actual - self.tokens
+    }
+}
+
+impl Drop for BudgetReservation {
+    fn drop(&mut self) {
+        if self.settled || self.tokens == 0 {
+            return;
+        }
+        let (key_id, model, tokens) = (self.key_id, self.model.take(), self.tokens);
+        let db = self.db.clone();
+        tokio::spawn(async move {
+            let released = match model {
+                Some(model) => {
+                    key_service::increment_model_tokens_used(key_id, &model, -tokens, &db).await
+                }
+                None => key_service::increment_tokens_used(key_id, -tokens, &db).await,
+            };
+            if let Err(e) = released {
+                tracing::error!("Failed to release budget reservation: {}", e);
+            }
+        });
+    }
+}
+
+/// `max_completion_tokens` (or the older `max_tokens`) requested by the client.
+fn requested_completion_tokens(body: &serde_json::Value) -> Option<i64> {
+    body.get("max_completion_tokens")
+        .or_else(|| body.get("max_tokens"))
+        .and_then(|v| v.as_i64())
+}
+
+/// Weighted tokens to reserve for a request: estimated prompt tokens plus the requested
+/// completion cap. At least 1, so the reservation always checks the budget.
+fn reservation_estimate(body: &serde_json::Value, route: &ModelRoute) -> i64 {
+    let prompt = estimate_tokens(prompt_text_chars(body));
+    let completion = requested_completion_tokens(body)
+        .unwrap_or(0)
+        .clamp(0, i32::MAX as i64) as i32;
+    route.weighted_tokens(prompt, completion, 0, 0).max(1)
+}
+
+/// Worst-case USD cost of a request: the estimated prompt tokens (~4 chars per token)
+/// at the input price plus the requested `max_completion_tokens` / `max_tokens` at the
+/// output price. Without a token cap only the prompt is counted. None when the model
+/// has no prices.
+fn projected_cost_usd(body: &serde_json::Value, route: &ModelRoute) -> Option<f64> {
+    if route.input_price_per_mtok.is_none() && route.output_price_per_mtok.is_none() {
+        return None;
+    }
+    let prompt_tokens = estimate_tokens(prompt_text_chars(body)) as f64;
+    let max_completion_tokens = requested_completion_tokens(body).unwrap_or(0) as f64;
+    Some(
+        (prompt_tokens * route.input_price_per_mtok.unwrap_or(0.0)
+            + max_completion_tokens * route.output_price_per_mtok.unwrap_or(0.0))
+            / 1_000_000.0,
+    )
+}
+
+/// Enforce `MAX_MESSAGES` / `MAX_PROMPT_CHARS` on the request's `messages` array.
+/// On violation returns an error code and message.
+fn check_prompt_limits(
+    body: &serde_json::Value,
+    max_messages: Option<usize>,
+    max_prompt_chars: Option<usize>,
+) -> Result<(), (&'static str, String)> {
+    if max_messages.is_none() && max_prompt_chars.is_none() {
+        return Ok(());
+    }
+
+    let messages = match body.get("messages").and_then(|v| v.as_array()) {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+
+    if let Some(max) = max_messages {
+        if messages.len() > max {
+            return Err((
+                "too_many_messages",
+                format!("Too many messages: {} (maximum is {max})", messages.len()),
+            ));
+        }
+    }
+
+    if let Some(max) = max_prompt_chars {
+        let total = prompt_text_chars(body);
+        if total > max {
+            return Err((
+                "prompt_too_long",
+                format!("Prompt too long: {total} characters (maximum is {max})"),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// 500 for a request body that could not be serialized for the upstream.
+fn serialization_error(e: serde_json::Error) -> Response {
+    json_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorType::Api,
+        format!("JSON serialization error: {e}"),
+        None,
+    )
+}
+
+/// Build the upstream request for one route: its body, URL, auth, and provider-specific
+/// and global headers, with an optional timeout.
+fn build_upstream_request(
+    state: &AppState,
+    headers: &HeaderMap,
+    body_json: &serde_json::Value,
+    route: &ModelRoute,
+    is_stream: bool,
+    timeout: Option<Duration>,
+) -> Result<reqwest::RequestBuilder, serde_json::Error> {
+    let upstream_body = serde_json::to_vec(&upstream_body_for(body_json, route, is_stream))?;
+
+    // Build upstream URL
+    let url = route.chat_url();
+
+    // Build the upstream request with provider-specific auth
+    let mut upstream_req = state
+        .http_client
+        .post(&url)
+        .header(header::AUTHORIZATION, format!("Bearer {}", route.api_key))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(upstream_body);
+
+    // Provider-specific headers, copied from the client unless UPSTREAM_STRIP_HEADERS
+    // lists them or they carry a credential
+    let client_header = |name: &str| {
+        headers
+            .get(name)
+            .filter(|_| !is_credential_header(name, &state.config))
+            .filter(|_| !state.config.upstream_strip_headers.iter().any(|h| h == name))
+            .cloned()
+    };
+    let mut extra_headers = HeaderMap::new();
+    match route.provider_kind.as_str() {
+        "openrouter" => {
+            let referer = attribution_header(
+                client_header("http-referer"),
+                state.config.openrouter_default_referer.as_deref(),
+            );
+            if let Some(referer) = referer {
+                extra_headers.insert("http-referer", referer);
+            }
+            let title = attribution_header(
+                client_header("x-title"),
+                state.config.openrouter_default_title.as_deref(),
+            );
+            if let Some(title) = title {
+                extra_headers.insert("x-title", title);
+            }
+        }
+        // Generic compatible APIs get no vendor-specific headers
+        "openai_compatible" => {}
+        _ => {
+            if let Some(org) = client_header("openai-organization") {
+                extra_headers.insert("openai-organization", org);
+            }
+        }
+    }
+    add_global_headers(&mut extra_headers, &state.config.global_upstream_headers);
+    upstream_req = upstream_req.headers(extra_headers);
+
+    if let Some(timeout) = timeout {
+        upstream_req = upstream_req.timeout(timeout);
+    }
+
+    Ok(upstream_req)
+}
+
+/// OpenRouter attribution header: the client's value wins, the gateway-wide default
+/// (`OPENROUTER_DEFAULT_REFERER` / `OPENROUTER_DEFAULT_TITLE`) fills the gap.
+fn attribution_header(client: Option<HeaderValue>, default: Option<&str>) -> Option<HeaderValue> {
+    client.or_else(|| default.and_then(|v| HeaderValue::from_str(v).ok()))
+}
+
+/// Send an upstream request, counting it against its provider until the response
+/// body is done. Resolves with the route it was sent to.
+fn send_attempt(
+    state: &AppState,
+    route: ModelRoute,
+    request: reqwest::RequestBuilder,
+) -> impl std::future::Future<
+    Output = (reqwest::Result<reqwest::Response>, ModelRoute, InflightGuard),
+> {
+    let inflight = state.provider_inflight.acquire(route.provider_id);
+    async move { (request.send().await, route, inflight) }
+}
+
+/// GLOBAL_UPSTREAM_HEADERS fill in whatever the provider kind did not set.
+fn add_global_headers(headers: &mut HeaderMap, global: &[(String, String)]) {
+    for (name, value) in global {
+        if let (Ok(name), Ok(value)) = (
+            header::HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.entry(name).or_insert(value);
+        }
+    }
+}
+
+/// Whether a Content-Type names JSON: `application/json` or an `application/*+json`
+/// type, parameters such as `charset` ignored.
+fn is_json_content_type(value: &HeaderValue) -> bool {
+    let Ok(value) = value.to_str() else {
+        return false;
+    };
+    let mime = value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+/// `VALIDATE_REQUEST_FIELDS`: check common sampling fields against OpenAI's documented
+/// limits, so clients get a precise error instead of an opaque upstream 400.
+/// Absent and `null` fields are not checked.
+fn check_request_fields(body: &serde_json::Value) -> Result<(), String> {
+    let field = |name: &str| body.get(name).filter(|v| !v.is_null());
+    let number_in = |name: &str, min: f64, max: f64| match field(name) {
+        None => Ok(()),
+        Some(v) => match v.as_f64() {
+            Some(x) if (min..=max).contains(&x) => Ok(()),
+            _ => Err(format!("\"{name}\" must be a number between {min} and {max}")),
+        },
+    };
+    let integer_in = |name: &str, min: i64, max: i64| match field(name) {
+        None => Ok(()),
+        Some(v) => match v.as_i64() {
+            Some(x) if (min..=max).contains(&x) => Ok(()),
+            _ => Err(format!("\"{name}\" must be an integer between {min} and {max}")),
+        },
+    };
+
+    match field("stop") {
+        None | Some(serde_json::Value::String(_)) => {}
+        Some(serde_json::Value::Array(stops)) => {
+            if stops.len() > 4 {
+                return Err(format!(
+                    "\"stop\" has {} sequences (maximum is 4)",
+                    stops.len()
+                ));
+            }
+            if !stops.iter().all(|s| s.is_string()) {
+                return Err("\"stop\" sequences must be strings".to_string());
+            }
+        }
+        Some(_) => return Err("\"stop\" must be a string or an array of strings".to_string()),
+    }
+
+    let logprobs = match field("logprobs") {
+        None => false,
+        Some(v) => v
+            .as_bool()
+            .ok_or_else(|| "\"logprobs\" must be a boolean".to_string())?,
+    };
+    integer_in("top_logprobs", 0, 20)?;
+    if field("top_logprobs").is_some() && !logprobs {
+        return Err("\"top_logprobs\" requires \"logprobs\": true".to_string());
+    }
+
+    integer_in("n", 1, 128)?;
+    integer_in("max_tokens", 1, i64::from(i32::MAX))?;
+    integer_in("max_completion_tokens", 1, i64::from(i32::MAX))?;
+    number_in("temperature", 0.0, 2.0)?;
+    number_in("top_p", 0.0, 1.0)?;
+    number_in("presence_penalty", -2.0, 2.0)?;
+    number_in("frequency_penalty", -2.0, 2.0)?;
+
+    if let Some(bias) = field("logit_bias") {
+        let bias = bias
+            .as_object()
+            .ok_or_else(|| "\"logit_bias\" must be an object".to_string())?;
+        for (token, value) in bias {
+            if !value.as_f64().is_some_and(|v| (-100.0..=100.0).contains(&v)) {
+                return Err(format!(
+                    "\"logit_bias\" value for token {token} must be between -100 and 100"
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy useful upstream headers (rate-limit, request-id, etc.) to the gateway response.
+fn copy_upstream_headers(from: &reqwest::header::HeaderMap, to: &mut HeaderMap) {
+    let headers_to_copy = [
+        "x-ratelimit-limit-requests",
+        "x-ratelimit-limit-tokens",
+        "x-ratelimit-remaining-requests",
+        "x-ratelimit-remaining-tokens",
+        "x-ratelimit-reset-requests",
+        "x-ratelimit-reset-tokens",
+        "x-request-id",
+        "openai-processing-ms",
+        "openai-organization",
+    ];
+
+    for name in headers_to_copy {
+        if let Some(val) = from.get(name) {
+            if let Ok(v) = HeaderValue::from_bytes(val.as_bytes()) {
+                to.insert(
+                    header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                    v,
+                );
+            }
+        }
+    }
+}
+
+/// Build the proxy router (to be nested under /v1)
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new().route("/chat/completions", post(chat_completions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::response::IntoResponse;
+    use sqlx::PgPool;
+    use std::sync::Mutex;
+
+    use crate::metrics::ProviderInflight;
+
+    /// Serve `handler` as a provider's `/v1/chat/completions`; returns the base URL.
+    async fn mock_upstream<H, T>(handler: H) -> String
+    where
+        H: axum::handler::Handler<T, ()>,
+        T: 'static,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let app = Router::new().route("/v1/chat/completions", post(handler));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        base_url
+    }
+
+    /// Register model `gpt-4o` on an `openai` provider at `base_url`; returns the
+    /// provider id.
+    async fn insert_route(db: &PgPool, base_url: &str) -> uuid::Uuid {
+        let provider_id = uuid::Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO providers (id, name, kind, base_url, api_key)
+             VALUES ($1, 'mock', 'openai', $2, 'sk-provider')",
+        )
+        .bind(provider_id)
+        .bind(base_url)
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO models (id, name, provider_id, provider_model_name)
+             VALUES ($1, 'gpt-4o', $2, 'gpt-4o')",
+        )
+        .bind(uuid::Uuid::new_v4())
+        .bind(provider_id)
+        .execute(db)
+        .await
+        .unwrap();
+        provider_id
+    }
+
+    /// An unrestricted key in the default organization.
+    fn key_identity() -> KeyIdentity {
+        KeyIdentity {
+            key_id: uuid::Uuid::new_v4(),
+            key_hash: "hash".into(),
+            org_id: uuid::Uuid::nil(),
+            group_id: None,
+            token_budget: None,
+            tokens_used: 0,
+            allowed_provider_kinds: None,
+            tpm_limit: None,
+            provider_override_id: None,
+            allow_byo_provider_key: false,
+            has_model_budgets: false,
+        }
+    }
+
+    /// A client stream over `inner`, with the receiving end of its shadow channel.
+    fn shadow_stream(
+        inner: impl Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send + 'static,
+        rewriter: Option<SseRewriter>,
+        idle_timeout: Duration,
+    ) -> (ShadowStream, mpsc::UnboundedReceiver<ByteChunk>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let stream = ShadowStream {
+            inner: Box::pin(inner),
+            tx,
+            rewriter,
+            finished: Arc::new(AtomicBool::new(false)),
+            idle_timeout,
+            idle: Box::pin(tokio::time::sleep(idle_timeout)),
+            waiting: false,
+            stalled: Arc::new(AtomicBool::new(false)),
+            deadline: Box::pin(tokio::time::sleep(Duration::from_secs(3600))),
+            expired: Arc::new(AtomicBool::new(false)),
+            _inflight: ProviderInflight::default().acquire(uuid::Uuid::nil()),
+        };
+        (stream, rx)
+    }
+
+    /// An upstream that never ends, sending a chunk `every` after each poll.
+    fn endless_upstream(every: Duration) -> impl Stream<Item = Result<bytes::Bytes, reqwest::Error>> {
+        futures::stream::unfold((), move |()| async move {
+            tokio::time::sleep(every).await;
+            Some((Ok(bytes::Bytes::from_static(b"data: {}\n\n")), ()))
+        })
+    }
+
+    /// One chunk, then an upstream that never sends anything again.
+    fn stalled_stream(idle_timeout: Duration) -> ShadowStream {
+        let first: Result<bytes::Bytes, reqwest::Error> =
+            Ok(bytes::Bytes::from_static(b"data: {}\n\n"));
+        let inner = futures::stream::iter([first]).chain(futures::stream::pending());
+        shadow_stream(inner, None, idle_timeout).0
+    }
+
+    #[test]
+    fn streamed_tool_calls_are_assembled_into_one_completion() {
+        let chunks: Vec<serde_json::Value> = [
+            r#"{"id":"chatcmpl-1","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"role":"assistant","content":null,"tool_calls":[{"index":0,"id":"call_a","type":"function","function":{"name":"get_weather","arguments":""}}]}}]}"#,
+            r#"{"id":"chatcmpl-1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"city\":"}}]}}]}"#,
+            r#"{"id":"chatcmpl-1","choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"id":"call_b","type":"function","function":{"name":"get_time","arguments":"{}"}}]}}]}"#,
+            r#"{"id":"chatcmpl-1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Paris\"}"}}]}}]}"#,
+            r#"{"id":"chatcmpl-1","choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}"#,
+            r#"{"id":"chatcmpl-1","choices":[],"usage":{"prompt_tokens":12,"completion_tokens":9,"total_tokens":21}}"#,
+        ]
+        .iter()
+        .map(|c| serde_json::from_str(c).unwrap())
+        .collect();
+
+        let assembled = assemble_stream_chunks(&chunks);
+        assert_eq!(assembled["object"], "chat.completion");
+        assert_eq!(assembled["id"], "chatcmpl-1");
+        assert_eq!(assembled["model"], "gpt-4o");
+        assert_eq!(assembled["usage"]["total_tokens"], 21);
+
+        let choice = &assembled["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(choice["message"]["role"], "assistant");
+        let calls = choice["message"]["tool_calls"].as_array().unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0]["id"], "call_a");
+        assert_eq!(calls[0]["function"]["name"], "get_weather");
+        assert_eq!(calls[0]["function"]["arguments"], r#"{"city":"Paris"}"#);
+        assert_eq!(calls[1]["id"], "call_b");
+        assert_eq!(calls[1]["function"]["arguments"], "{}");
+    }
+
+    #[test]
+    fn streamed_content_is_concatenated_per_choice() {
+        let chunks: Vec<serde_json::Value> = [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":"Hel"}},{"index":1,"delta":{"content":"Bon"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"lo"},"finish_reason":"stop"},{"index":1,"delta":{"content":"jour"},"finish_reason":"stop"}]}"#,
+        ]
+        .iter()
+        .map(|c| serde_json::from_str(c).unwrap())
+        .collect();
+
+        let assembled = assemble_stream_chunks(&chunks);
+        assert_eq!(assembled["choices"][0]["message"]["content"], "Hello");
+        assert_eq!(assembled["choices"][1]["message"]["content"], "Bonjour");
+        assert_eq!(assembled["choices"][1]["message"]["role"], "assistant");
+    }
+
+    #[test]
+    fn synthesized_stream_carries_usage_and_ends_with_done() {
+        let completion = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "qwen-max",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hi there" },
+                "finish_reason": "stop",
+            }],
+            "usage": { "prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7 },
+        });
+
+        let sse = completion_to_sse(&completion);
+        assert!(sse.ends_with(b"data: [DONE]\n\n"));
+
+        let (usage, chunks) = Usage::from_sse_buffer(&sse);
+        assert_eq!(usage.prompt, Some(5));
+        assert_eq!(usage.completion, Some(2));
+        assert_eq!(usage.total, Some(7));
+
+        let chunks = chunks.unwrap();
+        let chunks = chunks.as_array().unwrap();
+        assert!(chunks.iter().all(|c| c["object"] == "chat.completion.chunk"));
+        let assembled = assemble_stream_chunks(chunks);
+        assert_eq!(assembled["choices"][0]["message"]["content"], "Hi there");
+        assert_eq!(assembled["choices"][0]["finish_reason"], "stop");
+    }
+
+    #[test]
+    fn attribution_defaults_apply_only_when_the_client_omits_them() {
+        let client = Some(HeaderValue::from_static("https://client.example"));
+        let default = Some("https://gateway.example");
+        assert_eq!(attribution_header(client.clone(), default), client);
+        assert_eq!(
+            attribution_header(None, default),
+            Some(HeaderValue::from_static("https://gateway.example")),
+        );
+        assert_eq!(attribution_header(None, None), None);
+        // A default that is not a valid header value is skipped
+        assert_eq!(attribution_header(None, Some("bad\nvalue")), None);
+    }
+
+    fn route(provider_kind: &str) -> ModelRoute {
+        serde_json::from_value(serde_json::json!({
+            "provider_id": uuid::Uuid::nil(),
+            "provider_model_name": "upstream-model",
+            "base_url": "https://api.example.com/v1",
+            "api_key": "sk-provider",
+            "provider_kind": provider_kind,
+            "input_token_coefficient": 1.0,
+            "output_token_coefficient": 1.0,
+        }))
+        .unwrap()
+    }
+
+    fn chat_body(system: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": system },
+                { "role": "user", "content": "Hi" },
+            ],
+        })
+    }
+
+    #[test]
+    fn cache_control_marks_the_system_prompt_when_enabled() {
+        let mut route = route("openrouter");
+        route.prompt_caching = true;
+
+        let body = upstream_body_for(&chat_body("You are terse.".into()), &route, false);
+        assert_eq!(
+            body["messages"][0]["content"],
+            serde_json::json!([{
+                "type": "text",
+                "text": "You are terse.",
+                "cache_control": { "type": "ephemeral" },
+            }])
+        );
+        assert_eq!(body["messages"][1]["content"], "Hi");
+
+        let parts = serde_json::json!([
+            { "type": "text", "text": "Rules" },
+            { "type": "text", "text": "Long context" },
+        ]);
+        let body = upstream_body_for(&chat_body(parts), &route, false);
+        let parts = &body["messages"][0]["content"];
+        assert!(parts[0].get("cache_control").is_none());
+        assert_eq!(parts[1]["cache_control"]["type"], "ephemeral");
+    }
+
+    #[test]
+    fn cache_control_is_not_added_when_disabled_or_unsupported() {
+        let body = chat_body("You are terse.".into());
+        let sent = upstream_body_for(&body, &route("openrouter"), false);
+        assert_eq!(sent["messages"], body["messages"]);
+
+        let mut openai = route("openai");
+        openai.prompt_caching = true;
+        assert_eq!(upstream_body_for(&body, &openai, false)["messages"], body["messages"]);
+    }
+
+    #[test]
+    fn reasoning_effort_is_translated_per_provider_kind() {
+        let mut body = chat_body("You are terse.".into());
+        body["reasoning_effort"] = "high".into();
+        let sent = |kind: &str| upstream_body_for(&body, &route(kind), false);
+
+        assert_eq!(sent("openai")["reasoning_effort"], "high");
+        let openrouter = sent("openrouter");
+        assert_eq!(openrouter["reasoning"], serde_json::json!({ "effort": "high" }));
+        assert!(openrouter.get("reasoning_effort").is_none());
+        let dashscope = sent("dashscope");
+        assert_eq!(dashscope["enable_thinking"], true);
+        assert!(dashscope.get("reasoning_effort").is_none());
+        assert_eq!(sent("ark")["thinking"], serde_json::json!({ "type": "enabled" }));
+        assert_eq!(sent("openai_compatible")["reasoning_effort"], "high");
+    }
+
+    #[test]
+    fn reasoning_budget_and_off_switch_map_to_thinking_fields() {
+        let mut body = chat_body("You are terse.".into());
+        body["reasoning"] = serde_json::json!({ "max_tokens": 2048 });
+        let dashscope = upstream_body_for(&body, &route("dashscope"), false);
+        assert_eq!(dashscope["enable_thinking"], true);
+        assert_eq!(dashscope["thinking_budget"], 2048);
+        let openrouter = upstream_body_for(&body, &route("openrouter"), false);
+        assert_eq!(openrouter["reasoning"], body["reasoning"]);
+        // A thinking budget has no OpenAI equivalent
+        let openai = upstream_body_for(&body, &route("openai"), false);
+        assert!(openai.get("reasoning").is_none() && openai.get("reasoning_effort").is_none());
+
+        let mut off = chat_body("You are terse.".into());
+        off["reasoning_effort"] = "none".into();
+        let dashscope = upstream_body_for(&off, &route("dashscope"), false);
+        assert_eq!(dashscope["enable_thinking"], false);
+        let ark = upstream_body_for(&off, &route("ark"), false);
+        assert_eq!(ark["thinking"], serde_json::json!({ "type": "disabled" }));
+
+        // Provider-native fields the client set are kept
+        off["enable_thinking"] = true.into();
+        assert_eq!(upstream_body_for(&off, &route("dashscope"), false)["enable_thinking"], true);
+    }
+
+    #[tokio::test]
+    async fn stripped_sse_fields_stay_in_the_shadow_copy() {
+        let events: [&'static [u8]; 3] = [
+            b"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"}}],\"x_provider\":",
+            b"{\"region\":\"cn\"}}\n\ndata: {\"choices\":[],\"usage\":{\"prompt_tokens\":3,",
+            b"\"completion_tokens\":1,\"total_tokens\":4},\"x_provider\":{\"region\":\"cn\"}}\n\n",
+        ];
+        let inner = futures::stream::iter(events.map(|c| Ok(bytes::Bytes::from_static(c))));
+        let rewriter = SseRewriter::new(vec!["x_provider".into()], None, None);
+        let (stream, mut rx) = shadow_stream(inner, rewriter, Duration::from_secs(5));
+
+        let forwarded: Vec<u8> = stream
+            .map(|chunk| chunk.unwrap().to_vec())
+            .concat()
+            .await;
+        let forwarded = String::from_utf8(forwarded).unwrap();
+        assert!(!forwarded.contains("x_provider"));
+        assert!(forwarded.contains("\"content\":\"Hi\""));
+
+        let mut shadow = Vec::new();
+        while let Ok(chunk) = rx.try_recv() {
+            shadow.extend_from_slice(&chunk);
+        }
+        assert_eq!(shadow, events.concat());
+        let (usage, _) = Usage::from_sse_buffer(&shadow);
+        assert_eq!(usage.total, Some(4));
+    }
+
+    #[test]
+    fn shadow_buffer_summarizes_streams_past_the_cap() {
+        let events = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\" world\"},",
+            "\"finish_reason\":\"stop\"}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":2,",
+            "\"total_tokens\":5}}\n\ndata: [DONE]",
+        )
+        .as_bytes();
+
+        let mut kept = ShadowBuffer::new(Some(events.len()));
+        events.chunks(7).for_each(|chunk| kept.push(chunk));
+        assert_eq!(kept.bytes, events);
+        assert!(kept.overflow.is_none());
+
+        // Chunks split events mid-line; the summary parses them whole
+        let mut capped = ShadowBuffer::new(Some(64));
+        events.chunks(7).for_each(|chunk| capped.push(chunk));
+        assert!(capped.bytes.is_empty());
+        let mut summary = capped.overflow.unwrap();
+        summary.finish();
+        assert_eq!(summary.total_bytes, events.len());
+        assert_eq!(summary.usage.total, Some(5));
+        assert_eq!(summary.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(summary.completion_chars, "Hello world".len());
+        assert!(summary.error.is_none());
+    }
+
+    #[test]
+    fn shadow_buffer_summary_keeps_the_first_stream_error() {
+        let mut capped = ShadowBuffer::new(Some(0));
+        capped.push(b"data: {\"error\":{\"message\":\"overloaded\"}}\n\n");
+        capped.push(b"data: {\"error\":{\"message\":\"later\"}}\n\n");
+        let summary = capped.overflow.unwrap();
+        assert_eq!(summary.error.unwrap()["message"], "overloaded");
+    }
+
+    #[test]
+    fn request_id_rewriter_only_buffers_error_events() {
+        let mut rewriter = SseRewriter::new(Vec::new(), None, Some("req-up".into())).unwrap();
+
+        // No error event: relayed at once, partial line included
+        let chunk = bytes::Bytes::from_static(b"data: {\"choices\":[]}\n\ndata: {\"cho");
+        assert_eq!(rewriter.feed(chunk.clone()), chunk);
+        let chunk = bytes::Bytes::from_static(b"ices\":[]}\n\n");
+        assert_eq!(rewriter.feed(chunk.clone()), chunk);
+
+        let error = bytes::Bytes::from_static(b"data: {\"error\":{\"message\":\"boom\"");
+        assert!(rewriter.feed(error).is_empty());
+        let tagged = rewriter.feed(bytes::Bytes::from_static(b"}}\n\n"));
+        let event: serde_json::Value =
+            serde_json::from_slice(tagged.strip_prefix(b"data: ").unwrap()).unwrap();
+        assert_eq!(event["error"]["provider_request_id"], "req-up");
+        assert_eq!(event["error"]["message"], "boom");
+    }
+
+    #[test]
+    fn model_rewriter_sets_the_requested_model_on_every_event() {
+        let mut rewriter = SseRewriter::new(Vec::new(), Some("gpt-4o".into()), None).unwrap();
+        let rewritten = rewriter.feed(bytes::Bytes::from_static(
+            b"data: {\"model\":\"gpt-4o-2024-08-06\",\"choices\":[]}\n\ndata: {\"mo",
+        ));
+        let rewritten = [rewritten, rewriter.feed(bytes::Bytes::from_static(b"del\":\"x\"}\n\n"))];
+        let events: Vec<serde_json::Value> = rewritten
+            .concat()
+            .split(|&b| b == b'\n')
+            .filter_map(|line| line.strip_prefix(b"data: "))
+            .map(|data| serde_json::from_slice(data).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| event["model"] == "gpt-4o"));
+
+        // `[DONE]` and events already naming the model pass through untouched
+        let done = bytes::Bytes::from_static(b"data: {\"model\":\"gpt-4o\"}\n\ndata: [DONE]\n\n");
+        assert_eq!(rewriter.feed(done.clone()), done);
+    }
+
+    #[sqlx::test]
+    async fn buffered_response_names_the_requested_model_when_enabled(db: PgPool) {
+        let base_url =
+            mock_upstream(|| async { axum::Json(completion("gpt-4o-2024-08-06")) }).await;
+        insert_route(&db, &base_url).await;
+        let body = bytes::Bytes::from(serde_json::to_vec(&chat_body("Hi".into())).unwrap());
+
+        for (enabled, model) in [("true", "gpt-4o"), ("false", "gpt-4o-2024-08-06")] {
+            let vars = [("REWRITE_RESPONSE_MODEL", enabled)];
+            let state = AppState::for_tests(db.clone(), &vars).await;
+            let headers = HeaderMap::new();
+            let result =
+                proxy_chat_completion(state, key_identity(), headers, body.clone(), "req-1".into())
+                    .await;
+            let body = axum::body::to_bytes(result.unwrap().into_body(), 64 * 1024).await.unwrap();
+            let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(response["model"], model);
+            assert_eq!(response["choices"][0]["message"]["content"], "Hi");
+        }
+    }
+
+    #[sqlx::test]
+    async fn post_processed_responses_are_buffered_past_the_passthrough_size(db: PgPool) {
+        let base_url = mock_upstream(|| async { axum::Json(completion("gpt-4o")) }).await;
+        insert_route(&db, &base_url).await;
+        let body = bytes::Bytes::from(serde_json::to_vec(&chat_body("Hi".into())).unwrap());
+
+        for (strip_fields, fingerprint) in [("system_fingerprint", false), ("", true)] {
+            let vars = [
+                ("PASSTHROUGH_RESPONSE_BYTES", "16"),
+                ("RESPONSE_STRIP_FIELDS", strip_fields),
+            ];
+            let state = AppState::for_tests(db.clone(), &vars).await;
+            let headers = HeaderMap::new();
+            let result =
+                proxy_chat_completion(state, key_identity(), headers, body.clone(), "req-1".into())
+                    .await;
+            let body = axum::body::to_bytes(result.unwrap().into_body(), 64 * 1024).await.unwrap();
+            let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(response.get("system_fingerprint").is_some(), fingerprint);
+            assert_eq!(response["choices"][0]["message"]["content"], "Hi");
+        }
+    }
+
+    /// `check_request_fields` on a chat body with `fields` added.
+    fn check_fields(fields: serde_json::Value) -> Result<(), String> {
+        let mut body = chat_body("You are terse.".into());
+        body.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        check_request_fields(&body)
+    }
+
+    #[test]
+    fn request_fields_within_limits_pass() {
+        assert!(check_fields(serde_json::json!({})).is_ok());
+        assert!(check_fields(serde_json::json!({
+            "stop": ["\n", "END", "###", "Q:"],
+            "logprobs": true,
+            "top_logprobs": 20,
+            "n": 2,
+            "max_tokens": 1024,
+            "max_completion_tokens": 2048,
+            "temperature": 2,
+            "top_p": 0.9,
+            "presence_penalty": -2.0,
+            "frequency_penalty": 1.5,
+            "logit_bias": { "50256": -100, "1234": 5.5 },
+        }))
+        .is_ok());
+        // null is treated as absent
+        assert!(check_fields(serde_json::json!({ "stop": null, "top_logprobs": null })).is_ok());
+    }
+
+    #[test]
+    fn stop_is_a_string_or_up_to_four_strings() {
+        assert!(check_fields(serde_json::json!({ "stop": "END" })).is_ok());
+        let err = check_fields(serde_json::json!({ "stop": ["a", "b", "c", "d", "e"] }));
+        assert_eq!(err.unwrap_err(), "\"stop\" has 5 sequences (maximum is 4)");
+        let err = check_fields(serde_json::json!({ "stop": ["a", 1] }));
+        assert_eq!(err.unwrap_err(), "\"stop\" sequences must be strings");
+        assert!(check_fields(serde_json::json!({ "stop": 1 })).is_err());
+    }
+
+    #[test]
+    fn top_logprobs_needs_logprobs_and_a_range() {
+        let err = check_fields(serde_json::json!({ "logprobs": "yes" }));
+        assert_eq!(err.unwrap_err(), "\"logprobs\" must be a boolean");
+        let err = check_fields(serde_json::json!({ "top_logprobs": 5 }));
+        assert_eq!(err.unwrap_err(), "\"top_logprobs\" requires \"logprobs\": true");
+        let err = check_fields(serde_json::json!({ "logprobs": false, "top_logprobs": 5 }));
+        assert!(err.is_err());
+        let err = check_fields(serde_json::json!({ "logprobs": true, "top_logprobs": 21 }));
+        assert_eq!(err.unwrap_err(), "\"top_logprobs\" must be an integer between 0 and 20");
+    }
+
+    #[test]
+    fn integer_fields_are_range_checked() {
+        for (name, bad) in [
+            ("n", serde_json::json!(0)),
+            ("n", serde_json::json!(129)),
+            ("n", serde_json::json!(1.5)),
+            ("max_tokens", serde_json::json!(0)),
+            ("max_tokens", serde_json::json!("100")),
+            ("max_completion_tokens", serde_json::json!(-1)),
+            ("max_completion_tokens", serde_json::json!(i64::from(i32::MAX) + 1)),
+        ] {
+            let err = check_fields(serde_json::json!({ name: bad })).unwrap_err();
+            assert!(err.starts_with(&format!("\"{name}\" must be an integer")), "{err}");
+        }
+    }
+
+    #[test]
+    fn sampling_numbers_are_range_checked() {
+        for (name, bad) in [
+            ("temperature", serde_json::json!(2.1)),
+            ("temperature", serde_json::json!(-0.1)),
+            ("top_p", serde_json::json!(1.01)),
+            ("top_p", serde_json::json!("0.5")),
+            ("presence_penalty", serde_json::json!(-2.5)),
+            ("frequency_penalty", serde_json::json!(3)),
+        ] {
+            let err = check_fields(serde_json::json!({ name: bad })).unwrap_err();
+            assert!(err.starts_with(&format!("\"{name}\" must be a number")), "{err}");
+        }
+    }
+
+    #[test]
+    fn logit_bias_values_are_between_minus_and_plus_100() {
+        let err = check_fields(serde_json::json!({ "logit_bias": [1, 2] }));
+        assert_eq!(err.unwrap_err(), "\"logit_bias\" must be an object");
+        let err = check_fields(serde_json::json!({ "logit_bias": { "42": 101 } }));
+        assert_eq!(
+            err.unwrap_err(),
+            "\"logit_bias\" value for token 42 must be between -100 and 100"
+        );
+        assert!(check_fields(serde_json::json!({ "logit_bias": { "42": "1" } })).is_err());
+    }
+
+    #[test]
+    fn global_upstream_headers_are_sent_unless_the_provider_set_them() {
+        let mut headers = HeaderMap::new();
+        headers.insert("openai-organization", HeaderValue::from_static("org-client"));
+        let global = vec![
+            ("openai-beta".to_string(), "assistants=v2".to_string()),
+            ("openai-organization".to_string(), "org-global".to_string()),
+        ];
+        add_global_headers(&mut headers, &global);
+
+        let request = reqwest::Client::new()
+            .post("https://api.example.com/v1/chat/completions")
+            .headers(headers)
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["openai-beta"], "assistants=v2");
+        assert_eq!(request.headers()["openai-organization"], "org-client");
+    }
+
+    #[test]
+    fn stream_options_retry_drops_only_the_injected_field() {
+        let body = chat_body("You are terse.".into());
+        let mut route = route("openai");
+        assert!(injects_stream_options(&body, &route, true));
+        assert_eq!(
+            upstream_body_for(&body, &route, true)["stream_options"]["include_usage"],
+            true
+        );
+
+        // What the retry after a 400 does; a second 400 is then not retried again
+        route.supports_stream_usage = false;
+        assert!(!injects_stream_options(&body, &route, true));
+        assert!(upstream_body_for(&body, &route, true).get("stream_options").is_none());
+    }
+
+    #[test]
+    fn client_stream_options_are_never_retried_away() {
+        let mut body = chat_body("You are terse.".into());
+        body["stream_options"] = serde_json::json!({ "include_usage": false });
+        let route = route("openai");
+        assert!(!injects_stream_options(&body, &route, true));
+        assert_eq!(upstream_body_for(&body, &route, true)["stream_options"], body["stream_options"]);
+        // Nothing is injected into non-streaming requests either
+        assert!(!injects_stream_options(&chat_body("Hi".into()), &route, false));
+    }
+
+    /// A provider that rejects `stream_options` with a 400 and otherwise streams one
+    /// chunk; every request body it received is kept in order.
+    async fn stream_options_rejecting_upstream() -> (String, Arc<Mutex<Vec<serde_json::Value>>>)
+    {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let seen = received.clone();
+        let base_url = mock_upstream(move |axum::Json(body): axum::Json<serde_json::Value>| {
+            let seen = seen.clone();
+            async move {
+                let rejects = body.get("stream_options").is_some();
+                seen.lock().unwrap().push(body);
+                if rejects {
+                    let error = serde_json::json!({ "error": { "message": "Unknown field" } });
+                    return (StatusCode::BAD_REQUEST, axum::Json(error)).into_response();
+                }
+                let chunk = serde_json::json!({
+                    "choices": [{ "index": 0, "delta": { "content": "Hi" } }],
+                });
+                let events = format!("data: {chunk}\n\ndata: [DONE]\n\n");
+                ([(header::CONTENT_TYPE, "text/event-stream")], events).into_response()
+            }
+        })
+        .await;
+        (base_url, received)
+    }
+
+    #[sqlx::test]
+    async fn stream_rejecting_injected_stream_options_is_retried_once_without_them(db: PgPool) {
+        let (base_url, received) = stream_options_rejecting_upstream().await;
+        insert_route(&db, &base_url).await;
+        let state = AppState::for_tests(db, &[]).await;
+        let mut body = chat_body("You are terse.".into());
+        body["stream"] = true.into();
+        let body = serde_json::to_vec(&body).unwrap();
+
+        let headers = HeaderMap::new();
+        let result =
+            proxy_chat_completion(state, key_identity(), headers, body.into(), "req-1".into()).await;
+        let response = result.unwrap_or_else(|response| response);
+        assert_eq!(response.status(), StatusCode::OK);
+        axum::body::to_bytes(response.into_body(), 64 * 1024).await.unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0]["stream_options"]["include_usage"], true);
+        assert!(received[1].get("stream_options").is_none());
+    }
+
+    #[sqlx::test]
+    async fn stream_rejecting_client_stream_options_is_not_retried(db: PgPool) {
+        let (base_url, received) = stream_options_rejecting_upstream().await;
+        insert_route(&db, &base_url).await;
+        let state = AppState::for_tests(db, &[]).await;
+        let mut body = chat_body("You are terse.".into());
+        body["stream"] = true.into();
+        body["stream_options"] = serde_json::json!({ "include_usage": false });
+        let sent = serde_json::to_vec(&body).unwrap();
+
+        let headers = HeaderMap::new();
+        let result =
+            proxy_chat_completion(state, key_identity(), headers, sent.into(), "req-1".into()).await;
+        let response = result.unwrap_or_else(|response| response);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["stream_options"], body["stream_options"]);
+    }
+
+    #[sqlx::test]
+    async fn non_json_content_type_is_rejected_before_reaching_upstream(db: PgPool) {
+        let calls = Arc::new(AtomicU64::new(0));
+        let counted = calls.clone();
+        let base_url = mock_upstream(move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            async { axum::Json(completion("gpt-4o")) }
+        })
+        .await;
+        insert_route(&db, &base_url).await;
+        let state = AppState::for_tests(db, &[]).await;
+        let body = bytes::Bytes::from(serde_json::to_vec(&chat_body("Hi".into())).unwrap());
+        let send = |content_type: Option<&'static str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(content_type) = content_type {
+                headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+            }
+            let request = proxy_chat_completion(
+                state.clone(),
+                key_identity(),
+                headers,
+                body.clone(),
+                "req-1".into(),
+            );
+            async { request.await.unwrap_or_else(|response| response).status() }
+        };
+
+        for rejected in ["text/plain", "application/x-www-form-urlencoded", "multipart/form-data"] {
+            assert_eq!(send(Some(rejected)).await, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let accepted = ["application/json; charset=utf-8", "application/vnd.api+json"];
+        for content_type in accepted.map(Some).into_iter().chain([None]) {
+            assert_eq!(send(content_type).await, StatusCode::OK);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[sqlx::test]
+    async fn client_provider_key_is_sent_upstream_only_for_allowed_keys(db: PgPool) {
+        let authorizations = Arc::new(Mutex::new(Vec::new()));
+        let seen = authorizations.clone();
+        let base_url = mock_upstream(move |headers: HeaderMap| {
+            seen.lock().unwrap().push(headers[header::AUTHORIZATION].clone());
+            async { axum::Json(completion("gpt-4o")) }
+        })
+        .await;
+        insert_route(&db, &base_url).await;
+        let state = AppState::for_tests(db.clone(), &[("LOG_REQUEST_HEADERS", "true")]).await;
+        let mut events = state.log_events.subscribe();
+        let body = bytes::Bytes::from(serde_json::to_vec(&chat_body("Hi".into())).unwrap());
+        let mut headers = HeaderMap::new();
+        headers.insert(PROVIDER_API_KEY_HEADER, HeaderValue::from_static("sk-client"));
+
+        let denied = chat_completions(
+            State(state.clone()),
+            Extension(key_identity()),
+            headers.clone(),
+            body.clone(),
+        )
+        .await;
+        assert_eq!(denied.status(), StatusCode::FORBIDDEN);
+        assert!(authorizations.lock().unwrap().is_empty());
+        events.recv().await.unwrap();
+
+        let trusted = KeyIdentity { allow_byo_provider_key: true, ..key_identity() };
+        let response = chat_completions(State(state), Extension(trusted), headers, body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(*authorizations.lock().unwrap(), ["Bearer sk-client"]);
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string();
+        events.recv().await.unwrap();
+
+        let (byo_provider_key, request_headers): (bool, serde_json::Value) = sqlx::query_as(
+            "SELECT byo_provider_key, request_headers FROM request_logs WHERE request_id = $1",
+        )
+        .bind(&request_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert!(byo_provider_key);
+        assert!(!request_headers.to_string().contains("sk-client"));
+    }
+
+    /// A provider whose first request succeeds after `first_delay` and later ones answer
+    /// at once with `later_status`, serving `gpt-4o` (hedged after 50ms) on two routes;
+    /// returns its call counter.
+    async fn hedged_routes(
+        db: &PgPool,
+        first_delay: Duration,
+        later_status: StatusCode,
+    ) -> Arc<AtomicU64> {
+        let calls = Arc::new(AtomicU64::new(0));
+        let counted = calls.clone();
+        let base_url = mock_upstream(move || {
+            let first = counted.fetch_add(1, Ordering::SeqCst) == 0;
+            async move {
+                if !first {
+                    return (later_status, axum::Json(completion("gpt-4o")));
+                }
+                tokio::time::sleep(first_delay).await;
+                (StatusCode::OK, axum::Json(completion("gpt-4o")))
+            }
+        })
+        .await;
+        insert_route(db, &base_url).await;
+        let provider_id = uuid::Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO providers (id, name, kind, base_url, api_key)
+             VALUES ($1, 'mock-2', 'openai', $2, 'sk-provider')",
+        )
+        .bind(provider_id)
+        .bind(&base_url)
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO model_alternate_routes (id, model_id, provider_id)
+             SELECT $1, id, $2 FROM models WHERE name = 'gpt-4o'",
+        )
+        .bind(uuid::Uuid::new_v4())
+        .bind(provider_id)
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query("UPDATE models SET hedge_after_ms = 50")
+            .execute(db)
+            .await
+            .unwrap();
+        calls
+    }
+
+    #[sqlx::test]
+    async fn slow_route_is_hedged_and_the_first_response_wins(db: PgPool) {
+        let calls = hedged_routes(&db, Duration::from_secs(10), StatusCode::OK).await;
+        let state = AppState::for_tests(db, &[]).await;
+        let body = serde_json::to_vec(&chat_body("Hi".into())).unwrap();
+
+        let started = Instant::now();
+        let result = proxy_chat_completion(
+            state,
+            key_identity(),
+            HeaderMap::new(),
+            body.into(),
+            "req-1".into(),
+        )
+        .await;
+        assert_eq!(result.unwrap().status(), StatusCode::OK);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[sqlx::test]
+    async fn failing_hedge_does_not_cancel_a_slow_healthy_route(db: PgPool) {
+        let first_delay = Duration::from_millis(300);
+        let calls = hedged_routes(&db, first_delay, StatusCode::INTERNAL_SERVER_ERROR).await;
+        let state = AppState::for_tests(db, &[]).await;
+        let body = serde_json::to_vec(&chat_body("Hi".into())).unwrap();
+
+        let result = proxy_chat_completion(
+            state,
+            key_identity(),
+            HeaderMap::new(),
+            body.into(),
+            "req-1".into(),
+        )
+        .await;
+        let response = result.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let body = axum::body::to_bytes(response.into_body(), 64 * 1024).await.unwrap();
+        let completion: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(completion["choices"][0]["message"]["content"], "Hi");
+    }
+
+    #[sqlx::test]
+    async fn hedging_is_off_with_max_hedges_zero_or_for_streams(db: PgPool) {
+        let calls = hedged_routes(&db, Duration::from_secs(10), StatusCode::OK).await;
+        let state = AppState::for_tests(db.clone(), &[("MAX_HEDGES", "0")]).await;
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMEOUT_HEADER, HeaderValue::from_static("300"));
+        let body = serde_json::to_vec(&chat_body("Hi".into())).unwrap();
+        let request_id = "req-1".to_string();
+        let result =
+            proxy_chat_completion(state, key_identity(), headers.clone(), body.into(), request_id)
+                .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        calls.store(0, Ordering::SeqCst);
+        let state = AppState::for_tests(db, &[]).await;
+        let mut stream = chat_body("Hi".into());
+        stream["stream"] = true.into();
+        let body = serde_json::to_vec(&stream).unwrap();
+        let result =
+            proxy_chat_completion(state, key_identity(), headers, body.into(), "req-2".into())
+                .await;
+        assert_eq!(result.unwrap_err().status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    /// A key with a `gpt-4o` budget of 100 tokens, `used` of them spent.
+    async fn model_budget_key(db: &PgPool, used: i64) -> KeyIdentity {
+        let key_identity = KeyIdentity { has_model_budgets: true, ..key_identity() };
+        sqlx::query(
+            "INSERT INTO user_keys (id, name, key_hash, key_prefix) VALUES ($1, 'test', $2, 'sk')",
+        )
+        .bind(key_identity.key_id)
+        .bind(key_identity.key_id.to_string())
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO key_model_budgets (key_id, model, token_budget, tokens_used)
+             VALUES ($1, 'gpt-4o', 100, $2)",
+        )
+        .bind(key_identity.key_id)
+        .bind(used)
+        .execute(db)
+        .await
+        .unwrap();
+        key_identity
+    }
+
+    /// Send `body` for `key_identity`; returns the status and error code, if any.
+    async fn send_for(
+        state: &Arc<AppState>,
+        key_identity: KeyIdentity,
+        body: &serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let body = bytes::Bytes::from(serde_json::to_vec(body).unwrap());
+        let response = proxy_chat_completion(
+            state.clone(),
+            key_identity,
+            HeaderMap::new(),
+            body,
+            "req-1".into(),
+        )
+        .await
+        .unwrap_or_else(|response| response);
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), 64 * 1024).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        (status, body["error"]["code"].clone())
+    }
+
+    #[sqlx::test]
+    async fn model_budget_counts_buffered_usage(db: PgPool) {
+        let base_url = mock_upstream(|| async { axum::Json(completion("gpt-4o")) }).await;
+        insert_route(&db, &base_url).await;
+        let state = AppState::for_tests(db, &[("USAGE_FLUSH_INTERVAL_MS", "60000")]).await;
+        let key_identity = model_budget_key(&state.db, 60).await;
+        let body = chat_body("Hi".into());
+
+        let (status, _) = send_for(&state, key_identity.clone(), &body).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let key_id = key_identity.key_id;
+        state.usage.charge_model(key_id, "gpt-4o", 40, &state.db).await.unwrap();
+        let (status, code) = send_for(&state, key_identity, &body).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(code, "model_budget_exhausted");
+    }
+
+    #[sqlx::test]
+    async fn model_budget_lookup_failure_follows_auth_fail_mode(db: PgPool) {
+        let base_url = mock_upstream(|| async { axum::Json(completion("gpt-4o")) }).await;
+        insert_route(&db, &base_url).await;
+        let key_identity = model_budget_key(&db, 0).await;
+        sqlx::query("DROP TABLE key_model_budgets").execute(&db).await.unwrap();
+        let body = chat_body("Hi".into());
+
+        let closed = AppState::for_tests(db.clone(), &[]).await;
+        let (status, _) = send_for(&closed, key_identity.clone(), &body).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let open = AppState::for_tests(db, &[("AUTH_FAIL_MODE", "open")]).await;
+        let (status, _) = send_for(&open, key_identity, &body).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn reserve_mode_holds_the_model_budget(db: PgPool) {
+        let base_url = mock_upstream(|| async { axum::Json(completion("gpt-4o")) }).await;
+        insert_route(&db, &base_url).await;
+        let state = AppState::for_tests(db, &[("BUDGET_ENFORCEMENT", "reserve")]).await;
+        let key_identity = model_budget_key(&state.db, 0).await;
+        let mut body = chat_body("Hi".into());
+
+        body["max_tokens"] = 200.into();
+        let (status, code) = send_for(&state, key_identity.clone(), &body).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(code, "model_budget_exhausted");
+
+        body["max_tokens"] = 10.into();
+        let (status, _) = send_for(&state, key_identity, &body).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn rate_limit_errors_name_the_limit_and_its_reset(db: PgPool) {
+        let base_url = mock_upstream(|| async { axum::Json(completion("gpt-4o")) }).await;
+        insert_route(&db, &base_url).await;
+        let state = AppState::for_tests(db, &[]).await;
+        let body = bytes::Bytes::from(serde_json::to_vec(&chat_body("Hi".into())).unwrap());
+        let rejected = |key_identity: KeyIdentity| {
+            let request = proxy_chat_completion(
+                state.clone(),
+                key_identity,
+                HeaderMap::new(),
+                body.clone(),
+                "req-1".into(),
+            );
+            async {
+                let response = request.await.unwrap_err();
+                assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+                let retry_after = response.headers().get(header::RETRY_AFTER).cloned();
+                let body = axum::body::to_bytes(response.into_body(), 64 * 1024).await.unwrap();
+                let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                (error["error"].clone(), retry_after)
+            }
+        };
+
+        // Budgets have no automatic reset
+        let spent = KeyIdentity { token_budget: Some(100), tokens_used: 100, ..key_identity() };
+        let (error, retry_after) = rejected(spent).await;
+        assert_eq!(error["type"], "rate_limit_error");
+        assert_eq!(error["code"], "budget_exhausted");
+        assert_eq!(error["limit_type"], "budget");
+        assert!(error["reset_at"].is_null());
+        assert!(retry_after.is_none());
+
+        // The TPM counter resets at the next minute
+        let limited = KeyIdentity { tpm_limit: Some(0), ..key_identity() };
+        let (error, retry_after) = rejected(limited).await;
+        assert_eq!(error["code"], "tpm_exceeded");
+        assert_eq!(error["limit_type"], "tpm");
+        let reset_at: chrono::DateTime<chrono::Utc> =
+            serde_json::from_value(error["reset_at"].clone()).unwrap();
+        let wait = (reset_at - chrono::Utc::now()).num_seconds();
+        assert!((0..=60).contains(&wait) && reset_at.timestamp() % 60 == 0);
+        let retry_after: i64 = retry_after.unwrap().to_str().unwrap().parse().unwrap();
+        assert!((1..=60).contains(&retry_after));
+    }
+
+    #[sqlx::test]
+    async fn slow_upstream_gets_a_504_at_the_client_deadline(db: PgPool) {
+        let base_url = mock_upstream(|| async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            axum::Json(completion("gpt-4o"))
+        })
+        .await;
+        insert_route(&db, &base_url).await;
+        let state = AppState::for_tests(db, &[]).await;
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMEOUT_HEADER, HeaderValue::from_static("200"));
+        let body = serde_json::to_vec(&chat_body("You are terse.".into())).unwrap();
+
+        let started = Instant::now();
+        let result =
+            proxy_chat_completion(state, key_identity(), headers, body.into(), "req-1".into()).await;
+        let response = result.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = axum::body::to_bytes(response.into_body(), 64 * 1024).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["error"]["code"], "deadline_exceeded");
+    }
+
+    #[sqlx::test]
+    async fn deadline_rejection_is_logged_with_its_upstream_attempt(db: PgPool) {
+        let base_url = mock_upstream(|| async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            axum::Json(completion("gpt-4o"))
+        })
+        .await;
+        let provider_id = insert_route(&db, &base_url).await;
+        let state = AppState::for_tests(db.clone(), &[]).await;
+        let mut events = state.log_events.subscribe();
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMEOUT_HEADER, HeaderValue::from_static("200"));
+        let body = serde_json::to_vec(&chat_body("You are terse.".into())).unwrap();
+
+        let response =
+            chat_completions(State(state), Extension(key_identity()), headers, body.into()).await;
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string();
+        events.recv().await.unwrap();
+
+        let logged: (i32, Option<uuid::Uuid>, String, bool) = sqlx::query_as(
+            "SELECT attempts, provider_id, error_source, is_error
+             FROM request_logs WHERE request_id = $1",
+        )
+        .bind(&request_id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        let (attempts, logged_provider, error_source, is_error) = logged;
+        assert_eq!(attempts, 1);
+        assert_eq!(logged_provider, Some(provider_id));
+        assert_eq!(error_source, "upstream");
+        assert!(is_error);
+    }
+
+    #[test]
+    fn timeout_header_must_be_within_the_server_cap() {
+        let parse = |v: &'static str| parse_timeout_ms(&HeaderValue::from_static(v), 30_000);
+        assert_eq!(parse("2500"), Some(2500));
+        assert_eq!(parse(" 30000 "), Some(30_000));
+        assert_eq!(parse("30001"), None);
+        assert_eq!(parse("0"), None);
+        assert_eq!(parse("-5"), None);
+        assert_eq!(parse("1.5s"), None);
+    }
+
+    fn completion(model: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "model": model,
+            "system_fingerprint": "fp_44709d6fcb",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hi", "x_provider": "cn-1" },
+                "finish_reason": "stop",
+            }],
+            "usage": { "prompt_tokens": 5, "completion_tokens": 1, "total_tokens": 6 },
+        })
+    }
+
+    #[test]
+    fn response_strip_fields_are_removed_at_any_depth() {
+        let upstream = completion("gpt-4o");
+        let fields = vec!["system_fingerprint".to_string(), "x_provider".to_string()];
+
+        let client = rewrite_response_json(&upstream, &fields, None).unwrap();
+        assert!(client.get("system_fingerprint").is_none());
+        assert!(client["choices"][0]["message"].get("x_provider").is_none());
+        assert_eq!(client["choices"][0]["message"]["content"], "Hi");
+        // Usage is parsed (and the body logged) from the upstream response, untouched
+        assert_eq!(upstream["system_fingerprint"], "fp_44709d6fcb");
+        assert_eq!(Usage::from_response_json(&upstream).total, Some(6));
+    }
+
+    #[test]
+    fn responses_without_strip_fields_are_forwarded_as_is() {
+        let upstream = completion("gpt-4o");
+        assert_eq!(rewrite_response_json(&upstream, &[], None), None);
+        assert_eq!(rewrite_response_json(&upstream, &["logprobs".to_string()], None), None);
+    }
+
+    #[tokio::test]
+    async fn dropping_a_stream_early_leaves_it_unfinished() {
+        let events: [&'static [u8]; 2] = [
+            b"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Once upon a \"}}]}\n\n",
+            b"data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"time\"}}]}\n\n",
+        ];
+        let inner = futures::stream::iter(events.map(|c| Ok(bytes::Bytes::from_static(c))));
+        let (mut stream, mut rx) = shadow_stream(inner, None, Duration::from_secs(5));
+        let finished = stream.finished.clone();
+
+        // The client reads the first event and goes away
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+        assert!(!finished.load(Ordering::Relaxed));
+
+        let mut shadow = Vec::new();
+        while let Ok(chunk) = rx.try_recv() {
+            shadow.extend_from_slice(&chunk);
+        }
+        let (usage, chunks) = Usage::from_sse_buffer(&shadow);
+        assert_eq!(usage, Usage::default());
+        let received = completion_text_chars(&assemble_stream_chunks(
+            chunks.unwrap().as_array().unwrap(),
+        ));
+        // Prompt estimated from the request, completion from the 12 characters received
+        assert_eq!(partial_usage(None, None, Some(30), || received), Some((30, 3)));
+    }
+
+    #[test]
+    fn partial_usage_keeps_reported_counts() {
+        assert_eq!(partial_usage(Some(120), None, Some(100), || 40), Some((120, 10)));
+        assert_eq!(partial_usage(Some(120), Some(7), Some(100), || 40), None);
+        assert_eq!(partial_usage(None, None, None, || 0), Some((0, 0)));
+    }
+
+    #[tokio::test]
+    async fn never_ending_stream_is_cut_off_after_idle_timeout() {
+        let mut stream = stalled_stream(Duration::from_millis(50));
+        let stalled = stream.stalled.clone();
+
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(&first[..], b"data: {}\n\n");
+
+        let err = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("stream did not end")
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.to_string(), UPSTREAM_STALLED_MESSAGE);
+        assert!(stalled.load(Ordering::Relaxed));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn client_that_stops_reading_is_not_an_upstream_stall() {
+        let inner = endless_upstream(Duration::from_millis(20));
+        let (mut stream, _rx) = shadow_stream(inner, None, Duration::from_millis(50));
+
+        assert!(stream.next().await.unwrap().is_ok());
+        // Longer than the idle timeout without reading; the upstream is still sending
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(stream.next().await.unwrap().is_ok());
+        assert!(!stream.stalled.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn never_ending_stream_is_cut_off_at_max_duration() {
+        let inner = endless_upstream(Duration::from_millis(10));
+        let (mut stream, rx) = shadow_stream(inner, None, Duration::from_millis(50));
+        stream.deadline = Box::pin(tokio::time::sleep(Duration::from_millis(200)));
+        let expired = stream.expired.clone();
+        let shadow = tokio::spawn(async move {
+            let mut buffer = ShadowBuffer::new(None);
+            let timed_out = buffer.fill(rx, Duration::from_secs(5)).await;
+            (timed_out, buffer.bytes)
+        });
+
+        let client = async {
+            while let Some(chunk) = stream.next().await {
+                if let Err(e) = chunk {
+                    return e;
+                }
+            }
+            panic!("stream ended without an error");
+        };
+        let err = tokio::time::timeout(Duration::from_secs(5), client)
+            .await
+            .expect("stream was never cut off");
+        assert_eq!(err.to_string(), STREAM_EXPIRED_MESSAGE);
+        assert!(expired.load(Ordering::Relaxed));
+        assert!(!stream.stalled.load(Ordering::Relaxed));
+        assert!(stream.next().await.is_none());
+
+        drop(stream);
+        let (timed_out, bytes) = shadow.await.unwrap();
+        assert!(!timed_out);
+        assert!(bytes.starts_with(b"data: {}\n\n"));
+    }
+
+    #[tokio::test]
+    async fn shadow_buffer_stops_waiting_on_a_silent_sender() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tx.send(b"data: {}\n\n".to_vec()).unwrap();
+        let mut buffer = ShadowBuffer::new(None);
+
+        // `tx` stays alive, as when the stream is never polled again
+        let fill = buffer.fill(rx, Duration::from_millis(50));
+        let timed_out = tokio::time::timeout(Duration::from_secs(5), fill)
+            .await
+            .expect("shadow task never gave up");
+        assert!(timed_out);
+        assert_eq!(buffer.bytes, b"data: {}\n\n");
+        drop(tx);
+    }
+
+    #[test]
+    fn tokenizer_follows_openai_model_families() {
+        assert_eq!(resolve_tokenizer("gpt-4o-mini", &[]), "o200k_base");
+        assert_eq!(resolve_tokenizer("openai/gpt-4.1", &[]), "o200k_base");
+        assert_eq!(resolve_tokenizer("gpt-4-turbo", &[]), "cl100k_base");
+        assert_eq!(resolve_tokenizer("qwen-max", &[]), ESTIMATE_TOKENIZER);
+    }
+
+    #[test]
+    fn configured_tokenizer_encodings_win() {
+        let configured = vec![
+            ("qwen".to_string(), "qwen2".to_string()),
+            ("gpt-4".to_string(), "custom".to_string()),
+        ];
+        assert_eq!(resolve_tokenizer("qwen-max", &configured), "qwen2");
+        assert_eq!(resolve_tokenizer("gpt-4o", &configured), "custom");
+        assert_eq!(resolve_tokenizer("o3-mini", &configured), "o200k_base");
+    }
+
+    #[tokio::test]
+    async fn shadow_buffer_finishes_when_sender_drops() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tx.send(b"a".to_vec()).unwrap();
+        drop(tx);
+        let mut buffer = ShadowBuffer::new(None);
+        assert!(!buffer.fill(rx, Duration::from_secs(5)).await);
+        assert_eq!(buffer.bytes, b"a");
+    }
 }
@@ -0,0 +1,328 @@
+use chrono::Utc;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::config::S3BodyStoreConfig;
+use crate::error::AppError;
+
+/// Per-request timeout for object storage calls.
+const OBJECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Request and response body of one log entry.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LogBodies {
+    pub request_body: Option<serde_json::Value>,
+    pub response_body: Option<serde_json::Value>,
+}
+
+impl LogBodies {
+    pub fn is_empty(&self) -> bool {
+        self.request_body.is_none() && self.response_body.is_none()
+    }
+}
+
+/// Where the bodies of request logs are kept, selected by `LOG_BODY_STORE`.
+pub trait BodyStore: Send + Sync {
+    /// Persist the bodies of log `log_id`. Returns the key to record in
+    /// `request_logs.body_key`, or None when the bodies belong in the row itself.
+    fn put<'a>(
+        &'a self,
+        log_id: Uuid,
+        bodies: &'a LogBodies,
+    ) -> BoxFuture<'a, Result<Option<String>, AppError>>;
+
+    /// Bodies stored under `key` (empty if the object no longer exists).
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<LogBodies, AppError>>;
+
+    /// Remove the bodies stored under `key`; a missing object is not an error.
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), AppError>>;
+}
+
+/// Bodies stay in the `request_body` / `response_body` columns.
+pub struct PostgresBodyStore;
+
+impl BodyStore for PostgresBodyStore {
+    fn put<'a>(
+        &'a self,
+        _log_id: Uuid,
+        _bodies: &'a LogBodies,
+    ) -> BoxFuture<'a, Result<Option<String>, AppError>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<LogBodies, AppError>> {
+        Box::pin(async move {
+            Err(AppError::Internal(format!(
+                "Log bodies {key} are in object storage but LOG_BODY_STORE is postgres"
+            )))
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            Err(AppError::Internal(format!(
+                "Log bodies {key} are in object storage but LOG_BODY_STORE is postgres"
+            )))
+        })
+    }
+}
+
+/// Bodies are written as one JSON object per log (`{prefix}{log_id}.json`) to an
+/// S3-compatible bucket, addressed path-style and signed with AWS Signature V4.
+pub struct S3BodyStore {
+    config: S3BodyStoreConfig,
+    http_client: reqwest::Client,
+}
+
+impl S3BodyStore {
+    pub fn new(config: S3BodyStoreConfig, http_client: reqwest::Client) -> Self {
+        Self {
+            config,
+            http_client,
+        }
+    }
+
+    /// Send a signed request for object `key`.
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, AppError> {
+        let url = format!(
+            "{}/{}/{}",
+            self.config.endpoint.trim_end_matches('/'),
+            uri_encode(&self.config.bucket, true),
+            uri_encode(key, false)
+        );
+        let url = reqwest::Url::parse(&url)
+            .map_err(|e| AppError::Internal(format!("Invalid object URL {url}: {e}")))?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}",
+            url.path()
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.config.region);
+        let signing_key = signing_key(
+            &self.config.secret_access_key,
+            &date,
+            &self.config.region,
+            "s3",
+        );
+        let signature = sigv4_signature(&signing_key, &amz_date, &scope, &canonical_request);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.config.access_key_id
+        );
+
+        let response = self
+            .http_client
+            .request(method, url)
+            .timeout(OBJECT_TIMEOUT)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(reqwest::header::AUTHORIZATION, authorization)
+            .body(body)
+            .send()
+            .await?;
+        Ok(response)
+    }
+}
+
+impl BodyStore for S3BodyStore {
+    fn put<'a>(
+        &'a self,
+        log_id: Uuid,
+        bodies: &'a LogBodies,
+    ) -> BoxFuture<'a, Result<Option<String>, AppError>> {
+        Box::pin(async move {
+            let key = format!("{}{log_id}.json", self.config.prefix);
+            let body = serde_json::to_vec(bodies)
+                .map_err(|e| AppError::Internal(format!("Failed to serialize log bodies: {e}")))?;
+            let response = self.send(reqwest::Method::PUT, &key, body).await?;
+            if !response.status().is_success() {
+                return Err(AppError::Internal(format!(
+                    "Object storage PUT {key} returned {}",
+                    response.status()
+                )));
+            }
+            Ok(Some(key))
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<LogBodies, AppError>> {
+        Box::pin(async move {
+            let response = self.send(reqwest::Method::GET, key, Vec::new()).await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(LogBodies::default());
+            }
+            if !response.status().is_success() {
+                return Err(AppError::Internal(format!(
+                    "Object storage GET {key} returned {}",
+                    response.status()
+                )));
+            }
+            let bytes = response.bytes().await?;
+            serde_json::from_slice(&bytes)
+                .map_err(|e| AppError::Internal(format!("Invalid log bodies object {key}: {e}")))
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), AppError>> {
+        Box::pin(async move {
+            let response = self.send(reqwest::Method::DELETE, key, Vec::new()).await?;
+            let status = response.status();
+            if !status.is_success() && status != reqwest::StatusCode::NOT_FOUND {
+                return Err(AppError::Internal(format!(
+                    "Object storage DELETE {key} returned {status}"
+                )));
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Signature V4 key for one day (`%Y%m%d`), region and service.
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> [u8; 32] {
+    [region, service, "aws4_request"].iter().fold(
+        hmac_sha256(
+            format!("AWS4{secret_access_key}").as_bytes(),
+            date.as_bytes(),
+        ),
+        |key, part| hmac_sha256(&key, part.as_bytes()),
+    )
+}
+
+/// Hex Signature V4 of `canonical_request`, made at `amz_date` (`%Y%m%dT%H%M%SZ`)
+/// within credential `scope`.
+fn sigv4_signature(
+    signing_key: &[u8],
+    amz_date: &str,
+    scope: &str,
+    canonical_request: &str,
+) -> String {
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    hex::encode(hmac_sha256(signing_key, string_to_sign.as_bytes()))
+}
+
+/// HMAC-SHA256 as used by Signature V4.
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+/// Percent-encode everything but unreserved characters (and `/` unless `encode_slash`).
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(b as char)
+            }
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_sha256_matches_rfc_4231() {
+        // Test cases 1, 2 and 6 (key longer than the block size)
+        let cases: [(&[u8], &[u8], &str); 3] = [
+            (
+                &[0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+        ];
+        for (key, data, expected) in cases {
+            assert_eq!(hex::encode(hmac_sha256(key, data)), expected);
+        }
+    }
+
+    #[test]
+    fn signing_key_matches_aws_example() {
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn signature_matches_aws_test_suite_get_vanilla() {
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20150830",
+            "us-east-1",
+            "service",
+        );
+        let canonical_request =
+            "GET\n/\n\nhost:example.amazonaws.com\nx-amz-date:20150830T123600Z\n\n\
+            host;x-amz-date\ne3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let signature = sigv4_signature(
+            &key,
+            "20150830T123600Z",
+            "20150830/us-east-1/service/aws4_request",
+            canonical_request,
+        );
+        assert_eq!(
+            signature,
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn uri_encode_keeps_only_unreserved_characters() {
+        assert_eq!(uri_encode("logs/a b+c~_.-", false), "logs/a%20b%2Bc~_.-");
+        assert_eq!(uri_encode("logs/2024", true), "logs%2F2024");
+        assert_eq!(uri_encode("é", false), "%C3%A9");
+    }
+}
@@ -0,0 +1,135 @@
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::redis_keys;
+
+/// Per-attempt timeout for webhook deliveries.
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Budget usage notifications at `BUDGET_ALERT_THRESHOLDS`, each sent once per
+/// key (or key group) until its usage is reset.
+pub struct BudgetAlerts {
+    /// Percentages of the budget, ascending. Empty = disabled.
+    thresholds: Vec<u32>,
+    webhook_url: Option<String>,
+    http_client: reqwest::Client,
+}
+
+/// Redis scope of a budget: the group's for grouped keys, otherwise the key's.
+fn budget_scope(key_id: Uuid, group_id: Option<Uuid>) -> String {
+    match group_id {
+        Some(group_id) => format!("group:{group_id}"),
+        None => format!("key:{key_id}"),
+    }
+}
+
+impl BudgetAlerts {
+    pub fn new(
+        thresholds: Vec<u32>,
+        webhook_url: Option<String>,
+        http_client: reqwest::Client,
+    ) -> Self {
+        Self {
+            thresholds,
+            webhook_url,
+            http_client,
+        }
+    }
+
+    /// Notify every threshold `tokens_used` has reached that was not notified yet.
+    /// Notifications are logged and, with `BUDGET_ALERT_WEBHOOK_URL`, POSTed as JSON;
+    /// a failed delivery is logged and not retried.
+    pub async fn check(
+        &self,
+        key_id: Uuid,
+        group_id: Option<Uuid>,
+        token_budget: Option<i64>,
+        tokens_used: i64,
+        redis: &mut ConnectionManager,
+    ) -> Result<(), AppError> {
+        let budget = match token_budget {
+            Some(budget) if budget > 0 && !self.thresholds.is_empty() => budget,
+            _ => return Ok(()),
+        };
+        let scope = budget_scope(key_id, group_id);
+
+        for &percent in &self.thresholds {
+            if tokens_used.saturating_mul(100) < budget.saturating_mul(percent as i64) {
+                break;
+            }
+            // First request to claim the marker sends the notification
+            let claimed: bool = redis::cmd("SET")
+                .arg(redis_keys::budget_alert(&scope, percent))
+                .arg(tokens_used)
+                .arg("NX")
+                .query_async::<Option<String>>(redis)
+                .await?
+                .is_some();
+            if !claimed {
+                continue;
+            }
+
+            tracing::warn!(
+                "Budget of {} reached {}%: {}/{} tokens used",
+                scope,
+                percent,
+                tokens_used,
+                budget
+            );
+            if let Some(url) = &self.webhook_url {
+                let payload = serde_json::json!({
+                    "event": "budget_threshold",
+                    "key_id": key_id,
+                    "group_id": group_id,
+                    "threshold_percent": percent,
+                    "tokens_used": tokens_used,
+                    "token_budget": budget,
+                });
+                let result = self
+                    .http_client
+                    .post(url)
+                    .timeout(WEBHOOK_TIMEOUT)
+                    .json(&payload)
+                    .send()
+                    .await
+                    .and_then(|resp| resp.error_for_status());
+                if let Err(e) = result {
+                    tracing::warn!("Budget alert webhook for {} failed: {}", scope, e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Forget which thresholds were notified for a key's own budget (on usage reset).
+pub async fn clear_key_alerts(key_id: Uuid, redis: &mut ConnectionManager) -> Result<(), AppError> {
+    clear_alerts(&budget_scope(key_id, None), redis).await
+}
+
+/// Forget which thresholds were notified for a key group's budget (on usage reset).
+pub async fn clear_group_alerts(
+    group_id: Uuid,
+    redis: &mut ConnectionManager,
+) -> Result<(), AppError> {
+    clear_alerts(&budget_scope(Uuid::nil(), Some(group_id)), redis).await
+}
+
+async fn clear_alerts(scope: &str, redis: &mut ConnectionManager) -> Result<(), AppError> {
+    let keys: Vec<String> = {
+        let mut iter: redis::AsyncIter<String> = redis
+            .scan_match(redis_keys::budget_alerts_pattern(scope))
+            .await?;
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        keys
+    };
+    if !keys.is_empty() {
+        let _: () = redis.del(keys).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::model::Model;
+use crate::models::provider::{ModelNameCase, Provider, ProviderKind};
+use crate::models::user_key::UserKey;
+use crate::services::{model_service, provider_service};
+
+/// Bundle format version; bumped on incompatible changes.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// An organization's providers, models, key groups and keys, referencing each other
+/// by name so the bundle can be imported into another instance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigBundle {
+    pub version: u32,
+    #[serde(default)]
+    pub exported_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub providers: Vec<ProviderExport>,
+    #[serde(default)]
+    pub models: Vec<ModelExport>,
+    #[serde(default)]
+    pub key_groups: Vec<KeyGroupExport>,
+    #[serde(default)]
+    pub keys: Vec<KeyExport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProviderExport {
+    pub name: String,
+    pub kind: String,
+    pub base_url: String,
+    /// Masked on export. On import, a masked or missing key keeps the existing
+    /// provider's key; new providers need the real key filled in.
+    pub api_key: Option<String>,
+    pub is_active: bool,
+    pub force_buffered_upstream: bool,
+    pub supports_stream_usage: bool,
+    pub retry_on_status: Option<Vec<i32>>,
+    /// "none" | "lower" | "upper"
+    #[serde(default = "default_model_case")]
+    pub normalize_model_case: String,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub chat_path: Option<String>,
+    #[serde(default)]
+    pub passthrough: bool,
+    #[serde(default)]
+    pub passthrough_prefix: Option<String>,
+}
+
+fn default_model_case() -> String {
+    ModelNameCase::None.as_str().to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelExport {
+    pub name: String,
+    /// Name of the primary provider.
+    pub provider: String,
+    pub provider_model_name: Option<String>,
+    pub is_active: bool,
+    pub input_token_coefficient: f64,
+    pub output_token_coefficient: f64,
+    pub reasoning_token_coefficient: Option<f64>,
+    pub cached_token_coefficient: Option<f64>,
+    pub input_price_per_mtok: Option<f64>,
+    pub output_price_per_mtok: Option<f64>,
+    pub weight: i32,
+    pub fallback_response: Option<serde_json::Value>,
+    pub default_max_tokens: Option<i32>,
+    #[serde(default)]
+    pub prompt_caching: bool,
+    #[serde(default)]
+    pub hedge_after_ms: Option<i32>,
+    /// Replaces the model's alternate routes on import.
+    #[serde(default)]
+    pub alternates: Vec<AlternateExport>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AlternateExport {
+    /// Provider name.
+    pub provider: String,
+    pub provider_model_name: Option<String>,
+    pub weight: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct KeyGroupExport {
+    pub name: String,
+    pub token_budget: Option<i64>,
+}
+
+/// A user key by hash; the plaintext is never stored, so imported keys keep
+/// working with the secrets clients already hold.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyExport {
+    pub name: String,
+    pub key_hash: String,
+    pub key_prefix: String,
+    pub is_active: bool,
+    pub token_budget: Option<i64>,
+    pub allowed_provider_kinds: Option<Vec<String>>,
+    pub tag: Option<String>,
+    pub tpm_limit: Option<i64>,
+    pub metadata: Option<serde_json::Value>,
+    /// Key group name.
+    pub group: Option<String>,
+    /// Name of the provider the key is pinned to.
+    #[serde(default)]
+    pub provider_override: Option<String>,
+    /// May send its own provider key per request.
+    #[serde(default)]
+    pub allow_byo_provider_key: bool,
+}
+
+/// Number of records written by an import.
+#[derive(Debug, Serialize)]
+pub struct ImportSummary {
+    pub providers: usize,
+    pub models: usize,
+    pub key_groups: usize,
+    pub keys: usize,
+}
+
+/// Export an organization's configuration. Provider API keys are masked.
+pub async fn export_bundle(org_id: Uuid, db: &PgPool) -> Result<ConfigBundle, AppError> {
+    let providers = sqlx::query_as::<_, Provider>(
+        "SELECT * FROM providers WHERE org_id = $1 ORDER BY name",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    let models = sqlx::query_as::<_, ModelWithProviderName>(
+        r#"
+        SELECT m.*, p.name AS provider_name
+        FROM models m
+        JOIN providers p ON m.provider_id = p.id
+        WHERE m.org_id = $1
+        ORDER BY m.name
+        "#,
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    let alternates = sqlx::query_as::<_, AlternateRow>(
+        r#"
+        SELECT m.name AS model_name, p.name AS provider, a.provider_model_name, a.weight
+        FROM model_alternate_routes a
+        JOIN models m ON a.model_id = m.id
+        JOIN providers p ON a.provider_id = p.id
+        WHERE m.org_id = $1
+        ORDER BY a.created_at
+        "#,
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+    let mut alternates_by_model: HashMap<String, Vec<AlternateExport>> = HashMap::new();
+    for row in alternates {
+        alternates_by_model
+            .entry(row.model_name)
+            .or_default()
+            .push(row.alternate);
+    }
+
+    let key_groups = sqlx::query_as::<_, KeyGroupExport>(
+        "SELECT name, token_budget FROM key_groups WHERE org_id = $1 ORDER BY name",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    let keys = sqlx::query_as::<_, KeyWithGroupName>(
+        r#"
+        SELECT k.*, g.name AS group_name, p.name AS provider_override_name
+        FROM user_keys k
+        LEFT JOIN key_groups g ON g.id = k.group_id
+        LEFT JOIN providers p ON p.id = k.provider_override_id
+        WHERE k.org_id = $1
+        ORDER BY k.created_at
+        "#,
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(ConfigBundle {
+        version: BUNDLE_VERSION,
+        exported_at: Some(Utc::now()),
+        providers: providers
+            .into_iter()
+            .map(|p| ProviderExport {
+                api_key: Some(model_service::mask_api_key(&p.api_key)),
+                name: p.name,
+                kind: p.kind,
+                base_url: p.base_url,
+                is_active: p.is_active,
+                force_buffered_upstream: p.force_buffered_upstream,
+                supports_stream_usage: p.supports_stream_usage,
+                retry_on_status: p.retry_on_status,
+                normalize_model_case: p.normalize_model_case,
+                region: p.region,
+                chat_path: p.chat_path,
+                passthrough: p.passthrough,
+                passthrough_prefix: p.passthrough_prefix,
+            })
+            .collect(),
+        models: models
+            .into_iter()
+            .map(|r| {
+                let m = r.model;
+                ModelExport {
+                    alternates: alternates_by_model.remove(&m.name).unwrap_or_default(),
+                    name: m.name,
+                    provider: r.provider_name,
+                    provider_model_name: m.provider_model_name,
+                    is_active: m.is_active,
+                    input_token_coefficient: m.input_token_coefficient,
+                    output_token_coefficient: m.output_token_coefficient,
+                    reasoning_token_coefficient: m.reasoning_token_coefficient,
+                    cached_token_coefficient: m.cached_token_coefficient,
+                    input_price_per_mtok: m.input_price_per_mtok,
+                    output_price_per_mtok: m.output_price_per_mtok,
+                    weight: m.weight,
+                    fallback_response: m.fallback_response,
+                    default_max_tokens: m.default_max_tokens,
+                    prompt_caching: m.prompt_caching,
+                    hedge_after_ms: m.hedge_after_ms,
+                }
+            })
+            .collect(),
+        key_groups,
+        keys: keys
+            .into_iter()
+            .map(|r| {
+                let k = r.key;
+                KeyExport {
+                    name: k.name,
+                    key_hash: k.key_hash,
+                    key_prefix: k.key_prefix,
+                    is_active: k.is_active,
+                    token_budget: k.token_budget,
+                    allowed_provider_kinds: k.allowed_provider_kinds,
+                    tag: k.tag,
+                    tpm_limit: k.tpm_limit,
+                    metadata: k.metadata,
+                    group: r.group_name,
+                    provider_override: r.provider_override_name,
+                    allow_byo_provider_key: k.allow_byo_provider_key,
+                }
+            })
+            .collect(),
+    })
+}
+
+/// Upsert a bundle into an organization in one transaction: providers, key groups
+/// and models by name, keys by hash (only with `include_keys`). Each imported model's
+/// alternate routes are replaced. Usage counters are never touched. References to
+/// providers and key groups must resolve to the bundle or to existing rows.
+/// Field values must already be validated; callers re-warm the caches afterwards.
+pub async fn import_bundle(
+    org_id: Uuid,
+    bundle: &ConfigBundle,
+    include_keys: bool,
+    allow_insecure_urls: bool,
+    db: &PgPool,
+) -> Result<ImportSummary, AppError> {
+    if bundle.version != BUNDLE_VERSION {
+        return Err(AppError::BadRequest(format!(
+            "Unsupported bundle version {} (expected {BUNDLE_VERSION})",
+            bundle.version
+        )));
+    }
+
+    let mut tx = db.begin().await?;
+
+    for p in &bundle.providers {
+        import_provider(org_id, p, allow_insecure_urls, &mut tx).await?;
+    }
+    let provider_ids = name_ids("providers", org_id, &mut tx).await?;
+    let provider_id = |name: &str, model: &str| {
+        provider_ids.get(name).copied().ok_or_else(|| {
+            AppError::BadRequest(format!("Model '{model}' references unknown provider '{name}'"))
+        })
+    };
+
+    for m in &bundle.models {
+        let primary_id = provider_id(&m.provider, &m.name)?;
+        let model_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO models (id, org_id, name, provider_id, provider_model_name, is_active,
+                                input_token_coefficient, output_token_coefficient,
+                                reasoning_token_coefficient, input_price_per_mtok,
+                                output_price_per_mtok, weight, fallback_response,
+                                default_max_tokens, cached_token_coefficient, prompt_caching,
+                                hedge_after_ms)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
+            ON CONFLICT (org_id, name) DO UPDATE
+            SET provider_id = EXCLUDED.provider_id,
+                provider_model_name = EXCLUDED.provider_model_name,
+                is_active = EXCLUDED.is_active,
+                input_token_coefficient = EXCLUDED.input_token_coefficient,
+                output_token_coefficient = EXCLUDED.output_token_coefficient,
+                reasoning_token_coefficient = EXCLUDED.reasoning_token_coefficient,
+                input_price_per_mtok = EXCLUDED.input_price_per_mtok,
+                output_price_per_mtok = EXCLUDED.output_price_per_mtok,
+                weight = EXCLUDED.weight,
+                fallback_response = EXCLUDED.fallback_response,
+                default_max_tokens = EXCLUDED.default_max_tokens,
+                cached_token_coefficient = EXCLUDED.cached_token_coefficient,
+                prompt_caching = EXCLUDED.prompt_caching,
+                hedge_after_ms = EXCLUDED.hedge_after_ms,
+                updated_at = NOW()
+            RETURNING id
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(org_id)
+        .bind(&m.name)
+        .bind(primary_id)
+        .bind(&m.provider_model_name)
+        .bind(m.is_active)
+        .bind(m.input_token_coefficient)
+        .bind(m.output_token_coefficient)
+        .bind(m.reasoning_token_coefficient)
+        .bind(m.input_price_per_mtok)
+        .bind(m.output_price_per_mtok)
+        .bind(m.weight)
+        .bind(&m.fallback_response)
+        .bind(m.default_max_tokens)
+        .bind(m.cached_token_coefficient)
+        .bind(m.prompt_caching)
+        .bind(m.hedge_after_ms)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM model_alternate_routes WHERE model_id = $1")
+            .bind(model_id)
+            .execute(&mut *tx)
+            .await?;
+        for a in &m.alternates {
+            let alternate_id = provider_id(&a.provider, &m.name)?;
+            if alternate_id == primary_id {
+                return Err(AppError::BadRequest(format!(
+                    "Model '{}': alternate provider '{}' is already its primary provider",
+                    m.name, a.provider
+                )));
+            }
+            sqlx::query(
+                r#"
+                INSERT INTO model_alternate_routes (id, model_id, provider_id,
+                                                    provider_model_name, weight)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (model_id, provider_id) DO UPDATE
+                SET provider_model_name = EXCLUDED.provider_model_name, weight = EXCLUDED.weight
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(model_id)
+            .bind(alternate_id)
+            .bind(&a.provider_model_name)
+            .bind(a.weight)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    for g in &bundle.key_groups {
+        sqlx::query(
+            r#"
+            INSERT INTO key_groups (id, org_id, name, token_budget)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (org_id, name) DO UPDATE
+            SET token_budget = EXCLUDED.token_budget, updated_at = NOW()
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(org_id)
+        .bind(&g.name)
+        .bind(g.token_budget)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let keys = if include_keys { bundle.keys.as_slice() } else { &[] };
+    if !keys.is_empty() {
+        let group_ids = name_ids("key_groups", org_id, &mut tx).await?;
+        for k in keys {
+            let group_id = k
+                .group
+                .as_deref()
+                .map(|name| {
+                    group_ids.get(name).copied().ok_or_else(|| {
+                        AppError::BadRequest(format!(
+                            "Key '{}' references unknown key group '{name}'",
+                            k.key_prefix
+                        ))
+                    })
+                })
+                .transpose()?;
+            let provider_override_id = k
+                .provider_override
+                .as_deref()
+                .map(|name| {
+                    provider_ids.get(name).copied().ok_or_else(|| {
+                        AppError::BadRequest(format!(
+                            "Key '{}' references unknown provider '{name}'",
+                            k.key_prefix
+                        ))
+                    })
+                })
+                .transpose()?;
+
+            // Hashes are unique across organizations; never take over another one's key
+            let result = sqlx::query(
+                r#"
+                INSERT INTO user_keys (id, org_id, name, key_hash, key_prefix, is_active,
+                                       token_budget, tokens_used, allowed_provider_kinds, tag,
+                                       tpm_limit, metadata, group_id, provider_override_id,
+                                       allow_byo_provider_key)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, 0, $8, $9, $10, $11, $12, $13, $14)
+                ON CONFLICT (key_hash) DO UPDATE
+                SET name = EXCLUDED.name, is_active = EXCLUDED.is_active,
+                    token_budget = EXCLUDED.token_budget,
+                    allowed_provider_kinds = EXCLUDED.allowed_provider_kinds,
+                    tag = EXCLUDED.tag, tpm_limit = EXCLUDED.tpm_limit,
+                    metadata = EXCLUDED.metadata, group_id = EXCLUDED.group_id,
+                    provider_override_id = EXCLUDED.provider_override_id,
+                    allow_byo_provider_key = EXCLUDED.allow_byo_provider_key,
+                    updated_at = NOW()
+                WHERE user_keys.org_id = EXCLUDED.org_id
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(org_id)
+            .bind(&k.name)
+            .bind(&k.key_hash)
+            .bind(&k.key_prefix)
+            .bind(k.is_active)
+            .bind(k.token_budget)
+            .bind(&k.allowed_provider_kinds)
+            .bind(&k.tag)
+            .bind(k.tpm_limit)
+            .bind(&k.metadata)
+            .bind(group_id)
+            .bind(provider_override_id)
+            .bind(k.allow_byo_provider_key)
+            .execute(&mut *tx)
+            .await?;
+            if result.rows_affected() == 0 {
+                return Err(AppError::Conflict(format!(
+                    "Key '{}' already exists in another organization",
+                    k.key_prefix
+                )));
+            }
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(ImportSummary {
+        providers: bundle.providers.len(),
+        models: bundle.models.len(),
+        key_groups: bundle.key_groups.len(),
+        keys: keys.len(),
+    })
+}
+
+/// Upsert one provider by name. A usable API key replaces the stored one; a masked
+/// or missing key keeps it, which only works for providers that already exist.
+async fn import_provider(
+    org_id: Uuid,
+    p: &ProviderExport,
+    allow_insecure_urls: bool,
+    tx: &mut Transaction<'_, Postgres>,
+) -> Result<(), AppError> {
+    let kind = ProviderKind::from_str(&p.kind).ok_or_else(|| {
+        AppError::BadRequest(format!("Provider '{}': unknown kind {}", p.name, p.kind))
+    })?;
+    let model_case = ModelNameCase::from_str(&p.normalize_model_case).ok_or_else(|| {
+        AppError::BadRequest(format!(
+            "Provider '{}': unknown normalize_model_case {}",
+            p.name, p.normalize_model_case
+        ))
+    })?;
+    provider_service::check_base_url(&p.name, &p.base_url, allow_insecure_urls)?;
+
+    let api_key = p.api_key.as_deref().filter(|k| !model_service::is_masked_api_key(k));
+    let Some(api_key) = api_key else {
+        let result = sqlx::query(
+            r#"
+            UPDATE providers
+            SET kind = $1, base_url = $2, is_active = $3, force_buffered_upstream = $4,
+                supports_stream_usage = $5, retry_on_status = $6, normalize_model_case = $7,
+                region = $8, chat_path = $9, passthrough = $10, passthrough_prefix = $11,
+                updated_at = NOW()
+            WHERE org_id = $12 AND name = $13
+            "#,
+        )
+        .bind(kind.as_str())
+        .bind(&p.base_url)
+        .bind(p.is_active)
+        .bind(p.force_buffered_upstream)
+        .bind(p.supports_stream_usage)
+        .bind(&p.retry_on_status)
+        .bind(model_case.as_str())
+        .bind(&p.region)
+        .bind(&p.chat_path)
+        .bind(p.passthrough)
+        .bind(&p.passthrough_prefix)
+        .bind(org_id)
+        .bind(&p.name)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| provider_service::passthrough_conflict(e, p.passthrough_prefix.as_deref()))?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::BadRequest(format!(
+                "Provider '{}' does not exist yet; replace its masked api_key with the real one",
+                p.name
+            )));
+        }
+        return Ok(());
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO providers (id, org_id, name, kind, base_url, api_key, is_active,
+                               force_buffered_upstream, supports_stream_usage, retry_on_status,
+                               normalize_model_case, region, chat_path, passthrough,
+                               passthrough_prefix)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+        ON CONFLICT (org_id, name) DO UPDATE
+        SET kind = EXCLUDED.kind, base_url = EXCLUDED.base_url, api_key = EXCLUDED.api_key,
+            is_active = EXCLUDED.is_active,
+            force_buffered_upstream = EXCLUDED.force_buffered_upstream,
+            supports_stream_usage = EXCLUDED.supports_stream_usage,
+            retry_on_status = EXCLUDED.retry_on_status,
+            normalize_model_case = EXCLUDED.normalize_model_case,
+            region = EXCLUDED.region,
+            chat_path = EXCLUDED.chat_path,
+            passthrough = EXCLUDED.passthrough,
+            passthrough_prefix = EXCLUDED.passthrough_prefix,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(org_id)
+    .bind(&p.name)
+    .bind(kind.as_str())
+    .bind(&p.base_url)
+    .bind(api_key)
+    .bind(p.is_active)
+    .bind(p.force_buffered_upstream)
+    .bind(p.supports_stream_usage)
+    .bind(&p.retry_on_status)
+    .bind(model_case.as_str())
+    .bind(&p.region)
+    .bind(&p.chat_path)
+    .bind(p.passthrough)
+    .bind(&p.passthrough_prefix)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| provider_service::passthrough_conflict(e, p.passthrough_prefix.as_deref()))?;
+    Ok(())
+}
+
+/// Ids of an organization's providers or key groups keyed by name.
+async fn name_ids(
+    table: &str,
+    org_id: Uuid,
+    tx: &mut Transaction<'_, Postgres>,
+) -> Result<HashMap<String, Uuid>, AppError> {
+    let rows = sqlx::query_as::<_, (String, Uuid)>(&format!(
+        "SELECT name, id FROM {table} WHERE org_id = $1"
+    ))
+    .bind(org_id)
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
+
+// ── Internal query types ──────────────────────────────────────────────
+
+#[derive(Debug, sqlx::FromRow)]
+struct ModelWithProviderName {
+    #[sqlx(flatten)]
+    model: Model,
+    provider_name: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct AlternateRow {
+    model_name: String,
+    #[sqlx(flatten)]
+    alternate: AlternateExport,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct KeyWithGroupName {
+    #[sqlx(flatten)]
+    key: UserKey,
+    group_name: Option<String>,
+    provider_override_name: Option<String>,
+}
@@ -0,0 +1,119 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::models::key_group::KeyGroupInfo;
+
+/// Group columns plus the number of active member keys.
+const GROUP_SELECT: &str = r#"
+    SELECT g.id, g.name, g.token_budget, g.tokens_used,
+           (SELECT COUNT(*) FROM user_keys k WHERE k.group_id = g.id AND k.is_active = TRUE)
+               AS key_count,
+           g.created_at, g.updated_at
+    FROM key_groups g
+"#;
+
+/// Partial update for a key group. `None` keeps the current value.
+pub struct KeyGroupUpdate {
+    pub name: Option<String>,
+    /// `Some(None)` removes the budget.
+    pub token_budget: Option<Option<i64>>,
+    /// Reset the shared usage counter to 0.
+    pub reset_usage: bool,
+}
+
+/// Map a unique violation on the group name to a conflict.
+fn duplicate_name_error(e: sqlx::Error, name: &str) -> AppError {
+    match &e {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            AppError::Conflict(format!("A key group named \"{name}\" already exists"))
+        }
+        _ => e.into(),
+    }
+}
+
+/// Create an empty key group in an organization.
+pub async fn create_group(
+    org_id: Uuid,
+    name: &str,
+    token_budget: Option<i64>,
+    db: &PgPool,
+) -> Result<KeyGroupInfo, AppError> {
+    let id = Uuid::new_v4();
+    sqlx::query("INSERT INTO key_groups (id, name, token_budget, org_id) VALUES ($1, $2, $3, $4)")
+        .bind(id)
+        .bind(name)
+        .bind(token_budget)
+        .bind(org_id)
+        .execute(db)
+        .await
+        .map_err(|e| duplicate_name_error(e, name))?;
+
+    get_group(id, org_id, db).await
+}
+
+/// List an organization's key groups with their shared usage.
+pub async fn list_groups(org_id: Uuid, db: &PgPool) -> Result<Vec<KeyGroupInfo>, AppError> {
+    let groups = sqlx::query_as::<_, KeyGroupInfo>(&format!(
+        "{GROUP_SELECT} WHERE g.org_id = $1 ORDER BY g.name"
+    ))
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+    Ok(groups)
+}
+
+/// A single key group with its shared usage; `NotFound` outside the organization.
+pub async fn get_group(id: Uuid, org_id: Uuid, db: &PgPool) -> Result<KeyGroupInfo, AppError> {
+    sqlx::query_as::<_, KeyGroupInfo>(&format!("{GROUP_SELECT} WHERE g.id = $1 AND g.org_id = $2"))
+        .bind(id)
+        .bind(org_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+/// Rename a group, change its budget, or reset its usage.
+pub async fn update_group(
+    id: Uuid,
+    org_id: Uuid,
+    update: KeyGroupUpdate,
+    db: &PgPool,
+) -> Result<KeyGroupInfo, AppError> {
+    let existing = get_group(id, org_id, db).await?;
+    let name = update.name.unwrap_or(existing.name);
+    let token_budget = update.token_budget.unwrap_or(existing.token_budget);
+
+    sqlx::query(
+        r#"
+        UPDATE key_groups
+        SET name = $1, token_budget = $2,
+            tokens_used = CASE WHEN $3 THEN 0 ELSE tokens_used END,
+            updated_at = NOW()
+        WHERE id = $4
+        "#,
+    )
+    .bind(&name)
+    .bind(token_budget)
+    .bind(update.reset_usage)
+    .bind(id)
+    .execute(db)
+    .await
+    .map_err(|e| duplicate_name_error(e, &name))?;
+
+    get_group(id, org_id, db).await
+}
+
+/// Delete a group. Member keys fall back to their own budgets.
+pub async fn delete_group(id: Uuid, org_id: Uuid, db: &PgPool) -> Result<(), AppError> {
+    let result = sqlx::query("DELETE FROM key_groups WHERE id = $1 AND org_id = $2")
+        .bind(id)
+        .bind(org_id)
+        .execute(db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+    Ok(())
+}
@@ -1,20 +1,40 @@
+use std::collections::HashSet;
+
 use chrono::Utc;
 use redis::aio::ConnectionManager;
 use redis::AsyncCommands;
+use serde::Serialize;
 use sha2::{Digest, Sha256};
 use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::error::AppError;
-use crate::models::user_key::{UserKey, UserKeyCreated, UserKeyInfo};
-
-const REDIS_ACTIVE_KEYS_SET: &str = "gateway:active_key_hashes";
+use crate::models::user_key::{KeyModelBudget, UserKey, UserKeyCreated, UserKeyInfo};
+use crate::redis_keys;
 
 /// Generate a new key in the format `sk-{uuid v4}`
 pub fn generate_key() -> String {
     format!("sk-{}", Uuid::new_v4())
 }
 
+/// Why `key` falls short of the key policy (`MIN_KEY_LENGTH`, `KEY_REQUIRED_PREFIX`,
+/// ASCII letters, digits, `-`, `_` and `.` only), or None if it meets it.
+pub fn key_policy_violation(key: &str, min_length: usize, required_prefix: &str) -> Option<String> {
+    if key.len() < min_length {
+        return Some(format!("must be at least {min_length} characters (got {})", key.len()));
+    }
+    if !key.starts_with(required_prefix) {
+        return Some(format!("must start with '{required_prefix}'"));
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Some("may only contain ASCII letters, digits, '-', '_' and '.'".into());
+    }
+    None
+}
+
 /// SHA-256 hash of a plaintext key
 pub fn hash_key(plain: &str) -> String {
     let mut hasher = Sha256::new();
@@ -31,11 +51,38 @@ fn key_prefix(plain: &str) -> String {
     }
 }
 
+/// Parameters for creating a user key.
+pub struct NewKey {
+    pub org_id: Uuid,
+    pub name: String,
+    pub token_budget: Option<i64>,
+    /// Provider kinds this key may use. None = all.
+    pub allowed_provider_kinds: Option<Vec<String>>,
+    pub tag: Option<String>,
+    pub tpm_limit: Option<i64>,
+    pub metadata: Option<serde_json::Value>,
+    /// Key group whose shared budget applies instead of `token_budget`.
+    pub group_id: Option<Uuid>,
+    /// Provider the key is pinned to. None = normal routing.
+    pub provider_override_id: Option<Uuid>,
+    /// May send its own provider key per request (`x-provider-api-key`).
+    pub allow_byo_provider_key: bool,
+}
+
+/// Map a foreign key violation on `group_id` to a client error.
+fn unknown_group_error(e: sqlx::Error) -> AppError {
+    match &e {
+        sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation() => {
+            AppError::BadRequest("Unknown key group".into())
+        }
+        _ => e.into(),
+    }
+}
+
 /// Create a new user key, persist to PG + cache in Redis.
 /// Returns the full key info plus the plaintext key (shown only once).
 pub async fn create_key(
-    name: &str,
-    token_budget: Option<i64>,
+    new: NewKey,
     db: &PgPool,
     redis: &mut ConnectionManager,
 ) -> Result<UserKeyCreated, AppError> {
@@ -47,37 +94,81 @@ pub async fn create_key(
 
     sqlx::query(
         r#"
-        INSERT INTO user_keys (id, name, key_hash, key_prefix, is_active, token_budget, tokens_used, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, TRUE, $5, 0, $6, $6)
+        INSERT INTO user_keys (id, name, key_hash, key_prefix, is_active, token_budget, tokens_used,
+                               allowed_provider_kinds, tag, tpm_limit, metadata, group_id,
+                               provider_override_id, org_id, created_at, updated_at,
+                               allow_byo_provider_key)
+        VALUES ($1, $2, $3, $4, TRUE, $5, 0, $6, $7, $8, $9, $10, $11, $12, $13, $13, $14)
         "#,
     )
     .bind(id)
-    .bind(name)
+    .bind(&new.name)
     .bind(&hash)
     .bind(&prefix)
-    .bind(token_budget)
+    .bind(new.token_budget)
+    .bind(&new.allowed_provider_kinds)
+    .bind(&new.tag)
+    .bind(new.tpm_limit)
+    .bind(&new.metadata)
+    .bind(new.group_id)
+    .bind(new.provider_override_id)
+    .bind(new.org_id)
     .bind(now)
+    .bind(new.allow_byo_provider_key)
     .execute(db)
-    .await?;
+    .await
+    .map_err(unknown_group_error)?;
 
     // Add hash to Redis active set
-    let _: () = redis.sadd(REDIS_ACTIVE_KEYS_SET, &hash).await?;
+    let _: () = redis.sadd(redis_keys::active_key_hashes(), &hash).await?;
 
     Ok(UserKeyCreated {
         id,
-        name: name.to_string(),
+        name: new.name,
         key: plain,
         key_prefix: prefix,
         created_at: now,
     })
 }
 
-/// Result of a successful key validation.
+/// Result of a successful key validation. For grouped keys the budget and
+/// usage are the group's.
+#[derive(Debug, sqlx::FromRow)]
 pub struct KeyValidation {
     pub key_id: Uuid,
     pub key_hash: String,
+    pub org_id: Uuid,
+    pub group_id: Option<Uuid>,
     pub token_budget: Option<i64>,
     pub tokens_used: i64,
+    pub allowed_provider_kinds: Option<Vec<String>>,
+    pub tpm_limit: Option<i64>,
+    pub provider_override_id: Option<Uuid>,
+    pub allow_byo_provider_key: bool,
+    /// Whether the key has any per-model budget.
+    pub has_model_budgets: bool,
+}
+
+/// Load the validation details for an active key by hash.
+async fn fetch_active_key(hash: &str, db: &PgPool) -> Result<Option<KeyValidation>, AppError> {
+    let row = sqlx::query_as::<_, KeyValidation>(
+        r#"
+        SELECT k.id AS key_id, k.key_hash, k.org_id, k.group_id,
+               CASE WHEN g.id IS NULL THEN k.token_budget ELSE g.token_budget END AS token_budget,
+               CASE WHEN g.id IS NULL THEN k.tokens_used ELSE g.tokens_used END AS tokens_used,
+               k.allowed_provider_kinds, k.tpm_limit, k.provider_override_id,
+               k.allow_byo_provider_key,
+               EXISTS (SELECT 1 FROM key_model_budgets b WHERE b.key_id = k.id)
+                   AS has_model_budgets
+        FROM user_keys k
+        LEFT JOIN key_groups g ON g.id = k.group_id
+        WHERE k.key_hash = $1 AND k.is_active = TRUE
+        "#,
+    )
+    .bind(hash)
+    .fetch_optional(db)
+    .await?;
+    Ok(row)
 }
 
 /// Validate a plaintext key against Redis (fast path) or PG (slow path + backfill).
@@ -90,53 +181,71 @@ pub async fn validate_key(
     let hash = hash_key(plain);
 
     // Fast path: check Redis SET
-    let exists: bool = redis.sismember(REDIS_ACTIVE_KEYS_SET, &hash).await?;
+    let exists: bool = redis.sismember(redis_keys::active_key_hashes(), &hash).await?;
     if exists {
         // Look up key details from PG
-        let row = sqlx::query_as::<_, (Uuid, Option<i64>, i64)>(
-            "SELECT id, token_budget, tokens_used FROM user_keys WHERE key_hash = $1 AND is_active = TRUE",
-        )
-        .bind(&hash)
-        .fetch_optional(db)
-        .await?;
-
-        return Ok(row.map(|(id, budget, used)| KeyValidation {
-            key_id: id,
-            key_hash: hash,
-            token_budget: budget,
-            tokens_used: used,
-        }));
+        return fetch_active_key(&hash, db).await;
     }
 
     // Slow path: check PG
-    let row = sqlx::query_as::<_, (Uuid, Option<i64>, i64)>(
-        "SELECT id, token_budget, tokens_used FROM user_keys WHERE key_hash = $1 AND is_active = TRUE",
-    )
-    .bind(&hash)
-    .fetch_optional(db)
-    .await?;
+    let row = fetch_active_key(&hash, db).await?;
 
-    if let Some((id, budget, used)) = row {
+    if let Some(validation) = row {
         // Backfill Redis
-        let _: () = redis.sadd(REDIS_ACTIVE_KEYS_SET, &hash).await?;
-        return Ok(Some(KeyValidation {
-            key_id: id,
-            key_hash: hash,
-            token_budget: budget,
-            tokens_used: used,
-        }));
+        let _: () = redis.sadd(redis_keys::active_key_hashes(), &hash).await?;
+        return Ok(Some(validation));
     }
 
     Ok(None)
 }
 
-/// List all keys (without exposing hashes or plaintext).
-pub async fn list_keys(db: &PgPool) -> Result<Vec<UserKeyInfo>, AppError> {
-    let keys = sqlx::query_as::<_, UserKey>("SELECT * FROM user_keys ORDER BY created_at DESC")
-        .fetch_all(db)
-        .await?;
+/// Row for the key listing query: key columns plus aggregated spend.
+#[derive(Debug, sqlx::FromRow)]
+struct UserKeyWithSpend {
+    #[sqlx(flatten)]
+    key: UserKey,
+    spend_usd: f64,
+}
+
+/// List an organization's keys (without exposing hashes or plaintext), with USD spend
+/// summed from request logs. Models without a price contribute zero. `tag` limits the
+/// listing to keys carrying that tag.
+pub async fn list_keys(
+    org_id: Uuid,
+    tag: Option<&str>,
+    db: &PgPool,
+) -> Result<Vec<UserKeyInfo>, AppError> {
+    let rows = sqlx::query_as::<_, UserKeyWithSpend>(
+        r#"
+        SELECT k.*, COALESCE(s.spend_usd, 0)::FLOAT8 AS spend_usd
+        FROM user_keys k
+        LEFT JOIN (
+            SELECT r.user_key_id,
+                   SUM(
+                       COALESCE(r.prompt_tokens, 0) * COALESCE(m.input_price_per_mtok, 0)
+                       + COALESCE(r.completion_tokens, 0) * COALESCE(m.output_price_per_mtok, 0)
+                   ) / 1000000.0 AS spend_usd
+            FROM request_logs r
+            LEFT JOIN models m ON m.name = r.model_requested AND m.org_id = r.org_id
+            WHERE r.org_id = $1
+            GROUP BY r.user_key_id
+        ) s ON s.user_key_id = k.id
+        WHERE k.org_id = $1 AND ($2::TEXT IS NULL OR k.tag = $2)
+        ORDER BY k.created_at DESC
+        "#,
+    )
+    .bind(org_id)
+    .bind(tag)
+    .fetch_all(db)
+    .await?;
 
-    Ok(keys.into_iter().map(UserKeyInfo::from).collect())
+    Ok(rows
+        .into_iter()
+        .map(|r| UserKeyInfo {
+            spend_usd: Some(r.spend_usd),
+            ..UserKeyInfo::from(r.key)
+        })
+        .collect())
 }
 
 /// Rotate a key: invalidate the old key and generate a new one for the same record.
@@ -156,7 +265,7 @@ pub async fn rotate_key(
     .ok_or(AppError::NotFound)?;
 
     // Remove old hash from Redis
-    let _: () = redis.srem(REDIS_ACTIVE_KEYS_SET, &existing.key_hash).await?;
+    let _: () = redis.srem(redis_keys::active_key_hashes(), &existing.key_hash).await?;
 
     // Generate new key
     let new_plain = generate_key();
@@ -175,7 +284,7 @@ pub async fn rotate_key(
     .await?;
 
     // Add new hash to Redis
-    let _: () = redis.sadd(REDIS_ACTIVE_KEYS_SET, &new_hash).await?;
+    let _: () = redis.sadd(redis_keys::active_key_hashes(), &new_hash).await?;
 
     Ok(UserKeyCreated {
         id,
@@ -205,11 +314,45 @@ pub async fn delete_key(
         .execute(db)
         .await?;
 
-    let _: () = redis.srem(REDIS_ACTIVE_KEYS_SET, &existing.key_hash).await?;
+    let _: () = redis.srem(redis_keys::active_key_hashes(), &existing.key_hash).await?;
 
     Ok(())
 }
 
+/// Members of the Redis active-key set, for cache debugging.
+#[derive(Debug, Serialize)]
+pub struct CachedKeyHashes {
+    /// Size of the set in Redis.
+    pub total: u64,
+    /// True when `hashes` stops short of `total`.
+    pub truncated: bool,
+    pub hashes: Vec<String>,
+}
+
+/// Up to `limit` hashes from the Redis active-key set (hashes only; plaintext is never stored).
+pub async fn cached_key_hashes(
+    limit: usize,
+    redis: &mut ConnectionManager,
+) -> Result<CachedKeyHashes, AppError> {
+    let total: u64 = redis.scard(redis_keys::active_key_hashes()).await?;
+    let mut hashes = Vec::new();
+    {
+        let mut iter: redis::AsyncIter<String> = redis.sscan(redis_keys::active_key_hashes()).await?;
+        while hashes.len() < limit {
+            match iter.next_item().await {
+                Some(hash) => hashes.push(hash),
+                None => break,
+            }
+        }
+    }
+    hashes.sort();
+    Ok(CachedKeyHashes {
+        total,
+        truncated: (hashes.len() as u64) < total,
+        hashes,
+    })
+}
+
 /// Warm up Redis with all active key hashes from PG (call on startup).
 pub async fn warm_up_redis(
     db: &PgPool,
@@ -224,12 +367,12 @@ pub async fn warm_up_redis(
     if !hashes.is_empty() {
         // Clear stale data and re-populate
         let _: () = redis::cmd("DEL")
-            .arg(REDIS_ACTIVE_KEYS_SET)
+            .arg(redis_keys::active_key_hashes())
             .query_async(redis)
             .await?;
 
         for hash in &hashes {
-            let _: () = redis.sadd(REDIS_ACTIVE_KEYS_SET, hash).await?;
+            let _: () = redis.sadd(redis_keys::active_key_hashes(), hash).await?;
         }
 
         tracing::info!("Warmed up Redis with {} active key hashes", hashes.len());
@@ -240,42 +383,338 @@ pub async fn warm_up_redis(
     Ok(())
 }
 
-/// Update token budget and optionally reset usage for a key.
-pub async fn update_key_budget(
-    id: Uuid,
-    token_budget: Option<i64>,
+/// Bring the Redis active-key set in line with the active keys in PG, for keys
+/// added or removed out-of-band. Returns how many hashes were added and removed.
+/// A key created during the run may be removed again; its next request restores
+/// it through the PG fallback of `validate_key`.
+pub async fn reconcile_active_keys(
+    db: &PgPool,
+    redis: &mut ConnectionManager,
+) -> Result<(usize, usize), AppError> {
+    let active: HashSet<String> = sqlx::query_scalar::<_, String>(
+        "SELECT key_hash FROM user_keys WHERE is_active = TRUE",
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .collect();
+    let cached: HashSet<String> = redis.smembers(redis_keys::active_key_hashes()).await?;
+
+    let missing: Vec<&String> = active.difference(&cached).collect();
+    let stale: Vec<&String> = cached.difference(&active).collect();
+    if !missing.is_empty() {
+        let _: () = redis.sadd(redis_keys::active_key_hashes(), &missing).await?;
+    }
+    if !stale.is_empty() {
+        let _: () = redis.srem(redis_keys::active_key_hashes(), &stale).await?;
+    }
+    Ok((missing.len(), stale.len()))
+}
+
+/// Partial update for a key. `None` keeps the current value; for nullable columns
+/// `Some(None)` clears it.
+#[derive(Default)]
+pub struct KeyUpdate {
+    pub token_budget: Option<Option<i64>>,
+    /// Zero the key's tokens_used.
+    pub reset_usage: bool,
+    pub allowed_provider_kinds: Option<Option<Vec<String>>>,
+    pub tag: Option<Option<String>>,
+    pub tpm_limit: Option<Option<i64>>,
+    pub metadata: Option<Option<serde_json::Value>>,
+    pub group_id: Option<Option<Uuid>>,
+    pub provider_override_id: Option<Option<Uuid>>,
+    pub allow_byo_provider_key: Option<bool>,
+}
+
+/// Apply a partial update to a key in one statement, so it changes all of the given
+/// fields or none of them.
+pub async fn update_key(id: Uuid, update: KeyUpdate, db: &PgPool) -> Result<UserKeyInfo, AppError> {
+    let mut tx = db.begin().await?;
+    let existing =
+        sqlx::query_as::<_, UserKey>("SELECT * FROM user_keys WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+    let key = sqlx::query_as::<_, UserKey>(
+        r#"
+        UPDATE user_keys
+        SET token_budget = $1,
+            tokens_used = CASE WHEN $2 THEN 0 ELSE tokens_used END,
+            allowed_provider_kinds = $3, tag = $4, tpm_limit = $5, metadata = $6,
+            group_id = $7, provider_override_id = $8, allow_byo_provider_key = $9,
+            updated_at = NOW()
+        WHERE id = $10
+        RETURNING *
+        "#,
+    )
+    .bind(update.token_budget.unwrap_or(existing.token_budget))
+    .bind(update.reset_usage)
+    .bind(update.allowed_provider_kinds.unwrap_or(existing.allowed_provider_kinds))
+    .bind(update.tag.unwrap_or(existing.tag))
+    .bind(update.tpm_limit.unwrap_or(existing.tpm_limit))
+    .bind(update.metadata.unwrap_or(existing.metadata))
+    .bind(update.group_id.unwrap_or(existing.group_id))
+    .bind(update.provider_override_id.unwrap_or(existing.provider_override_id))
+    .bind(update.allow_byo_provider_key.unwrap_or(existing.allow_byo_provider_key))
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(unknown_group_error)?;
+    tx.commit().await?;
+
+    Ok(UserKeyInfo::from(key))
+}
+
+/// Tag of a key, or `NotFound` if the key does not exist in the organization.
+pub async fn get_key_tag(id: Uuid, org_id: Uuid, db: &PgPool) -> Result<Option<String>, AppError> {
+    sqlx::query_scalar::<_, Option<String>>(
+        "SELECT tag FROM user_keys WHERE id = $1 AND org_id = $2",
+    )
+    .bind(id)
+    .bind(org_id)
+    .fetch_optional(db)
+    .await?
+    .ok_or(AppError::NotFound)
+}
+
+/// Per-model budgets of a key, by model name.
+pub async fn list_key_model_budgets(
+    key_id: Uuid,
+    db: &PgPool,
+) -> Result<Vec<KeyModelBudget>, AppError> {
+    let budgets = sqlx::query_as::<_, KeyModelBudget>(
+        "SELECT * FROM key_model_budgets WHERE key_id = $1 ORDER BY model",
+    )
+    .bind(key_id)
+    .fetch_all(db)
+    .await?;
+    Ok(budgets)
+}
+
+/// A key's budget for one model, if it has one.
+pub async fn get_key_model_budget(
+    key_id: Uuid,
+    model: &str,
+    db: &PgPool,
+) -> Result<Option<KeyModelBudget>, AppError> {
+    let budget = sqlx::query_as::<_, KeyModelBudget>(
+        "SELECT * FROM key_model_budgets WHERE key_id = $1 AND model = $2",
+    )
+    .bind(key_id)
+    .bind(model)
+    .fetch_optional(db)
+    .await?;
+    Ok(budget)
+}
+
+/// Create or replace a key's budget for a model, optionally resetting its usage.
+pub async fn set_key_model_budget(
+    key_id: Uuid,
+    model: &str,
+    token_budget: i64,
     reset_usage: bool,
     db: &PgPool,
-) -> Result<UserKeyInfo, AppError> {
-    let key = if reset_usage {
-        sqlx::query_as::<_, UserKey>(
-            "UPDATE user_keys SET token_budget = $1, tokens_used = 0, updated_at = NOW() WHERE id = $2 RETURNING *",
-        )
-        .bind(token_budget)
-        .bind(id)
-        .fetch_optional(db)
-        .await?
-    } else {
-        sqlx::query_as::<_, UserKey>(
-            "UPDATE user_keys SET token_budget = $1, updated_at = NOW() WHERE id = $2 RETURNING *",
-        )
-        .bind(token_budget)
-        .bind(id)
-        .fetch_optional(db)
-        .await?
-    };
+) -> Result<KeyModelBudget, AppError> {
+    let budget = sqlx::query_as::<_, KeyModelBudget>(
+        r#"
+        INSERT INTO key_model_budgets (key_id, model, token_budget)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (key_id, model) DO UPDATE SET
+            token_budget = EXCLUDED.token_budget,
+            tokens_used = CASE WHEN $4 THEN 0 ELSE key_model_budgets.tokens_used END,
+            updated_at = NOW()
+        RETURNING *
+        "#,
+    )
+    .bind(key_id)
+    .bind(model)
+    .bind(token_budget)
+    .bind(reset_usage)
+    .fetch_one(db)
+    .await?;
+    Ok(budget)
+}
+
+/// Remove a key's budget for a model.
+pub async fn delete_key_model_budget(
+    key_id: Uuid,
+    model: &str,
+    db: &PgPool,
+) -> Result<(), AppError> {
+    let result = sqlx::query("DELETE FROM key_model_budgets WHERE key_id = $1 AND model = $2")
+        .bind(key_id)
+        .bind(model)
+        .execute(db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+    Ok(())
+}
+
+/// Add weighted tokens to a key's usage of a model; no-op without a budget for it.
+pub async fn increment_model_tokens_used(
+    key_id: Uuid,
+    model: &str,
+    tokens: i64,
+    db: &PgPool,
+) -> Result<(), AppError> {
+    sqlx::query(
+        "UPDATE key_model_budgets SET tokens_used = tokens_used + $1, updated_at = NOW() \
+         WHERE key_id = $2 AND model = $3",
+    )
+    .bind(tokens)
+    .bind(key_id)
+    .bind(model)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Add several keys' model charges at once (`UsageBuffer::flush`); charges for models
+/// without a budget are dropped.
+pub async fn increment_model_tokens_used_batch(
+    charges: &[(Uuid, String, i64)],
+    db: &PgPool,
+) -> Result<(), AppError> {
+    let mut ids = Vec::with_capacity(charges.len());
+    let mut models = Vec::with_capacity(charges.len());
+    let mut tokens = Vec::with_capacity(charges.len());
+    for (id, model, amount) in charges {
+        ids.push(*id);
+        models.push(model.as_str());
+        tokens.push(*amount);
+    }
+    sqlx::query(
+        r#"
+        UPDATE key_model_budgets b SET tokens_used = b.tokens_used + c.tokens, updated_at = NOW()
+        FROM UNNEST($1::uuid[], $2::text[], $3::bigint[]) AS c(key_id, model, tokens)
+        WHERE b.key_id = c.key_id AND b.model = c.model
+        "#,
+    )
+    .bind(&ids)
+    .bind(&models)
+    .bind(&tokens)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Atomically add `tokens` to a key's usage of a model only if it stays within the
+/// model's budget, counting `pending` unflushed usage as stored. Returns false, changing
+/// nothing, when the reservation does not fit or the key has no budget for the model.
+pub async fn reserve_model_tokens(
+    key_id: Uuid,
+    model: &str,
+    tokens: i64,
+    pending: i64,
+    db: &PgPool,
+) -> Result<bool, AppError> {
+    let result = sqlx::query(
+        "UPDATE key_model_budgets SET tokens_used = tokens_used + $1, updated_at = NOW() \
+         WHERE key_id = $2 AND model = $3 AND tokens_used + $4 + $1 <= token_budget",
+    )
+    .bind(tokens)
+    .bind(key_id)
+    .bind(model)
+    .bind(pending)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Redis counter for a key's tokens in the minute starting at `minute` (Unix minutes).
+fn tpm_counter_key(id: Uuid, minute: i64) -> String {
+    redis_keys::tpm_counter(id, minute)
+}
 
-    key.map(UserKeyInfo::from).ok_or(AppError::NotFound)
+/// Tokens consumed by a key in the current minute.
+pub async fn current_minute_tokens(
+    id: Uuid,
+    redis: &mut ConnectionManager,
+) -> Result<i64, AppError> {
+    let minute = Utc::now().timestamp() / 60;
+    let used: Option<i64> = redis.get(tpm_counter_key(id, minute)).await?;
+    Ok(used.unwrap_or(0))
 }
 
-/// Atomically increment tokens_used for a key.
+/// Add tokens to a key's current-minute counter; counters expire after two minutes.
+pub async fn add_minute_tokens(
+    id: Uuid,
+    tokens: i64,
+    redis: &mut ConnectionManager,
+) -> Result<(), AppError> {
+    let key = tpm_counter_key(id, Utc::now().timestamp() / 60);
+    let _: () = redis::pipe()
+        .incr(&key, tokens)
+        .ignore()
+        .expire(&key, 120)
+        .ignore()
+        .query_async(redis)
+        .await?;
+    Ok(())
+}
+
+/// Atomically add `tokens` to a key's usage (and its group's) only if the result stays
+/// within the applicable budget: the group's for grouped keys, otherwise the key's.
+/// `pending` is usage charged but not yet flushed (`UsageBuffer::pending`), counted as
+/// if it were stored. Returns false, changing nothing, when the reservation does not fit.
+pub async fn reserve_tokens(
+    id: Uuid,
+    tokens: i64,
+    pending: i64,
+    db: &PgPool,
+) -> Result<bool, AppError> {
+    // Each UPDATE re-checks its WHERE clause against the latest row version, so
+    // concurrent reservations cannot both squeeze into the same remaining budget
+    let reserved = sqlx::query_scalar::<_, bool>(
+        r#"
+        WITH g AS (
+            UPDATE key_groups SET tokens_used = tokens_used + $1, updated_at = NOW()
+            WHERE id = (SELECT group_id FROM user_keys WHERE id = $2)
+              AND (token_budget IS NULL OR tokens_used + $3 + $1 <= token_budget)
+            RETURNING id
+        ), k AS (
+            UPDATE user_keys SET tokens_used = tokens_used + $1, updated_at = NOW()
+            WHERE id = $2
+              AND CASE WHEN group_id IS NULL
+                       THEN token_budget IS NULL OR tokens_used + $3 + $1 <= token_budget
+                       ELSE EXISTS (SELECT 1 FROM g)
+                  END
+            RETURNING id
+        )
+        SELECT EXISTS (SELECT 1 FROM k)
+        "#,
+    )
+    .bind(tokens)
+    .bind(id)
+    .bind(pending)
+    .fetch_one(db)
+    .await?;
+    Ok(reserved)
+}
+
+/// Atomically increment tokens_used for a key and, in the same statement, for
+/// its group so concurrent requests from member keys cannot lose updates.
 pub async fn increment_tokens_used(
     id: Uuid,
     tokens: i64,
     db: &PgPool,
 ) -> Result<(), AppError> {
     sqlx::query(
-        "UPDATE user_keys SET tokens_used = tokens_used + $1, updated_at = NOW() WHERE id = $2",
+        r#"
+        WITH k AS (
+            UPDATE user_keys SET tokens_used = tokens_used + $1, updated_at = NOW()
+            WHERE id = $2
+            RETURNING group_id
+        )
+        UPDATE key_groups SET tokens_used = tokens_used + $1, updated_at = NOW()
+        WHERE id = (SELECT group_id FROM k)
+        "#,
     )
     .bind(tokens)
     .bind(id)
@@ -283,3 +722,110 @@ pub async fn increment_tokens_used(
     .await?;
     Ok(())
 }
+
+/// Apply many `(key id, tokens)` increments in one statement, adding each key's
+/// amount to its group as well.
+pub async fn increment_tokens_used_batch(
+    charges: &[(Uuid, i64)],
+    db: &PgPool,
+) -> Result<(), AppError> {
+    let (ids, tokens): (Vec<Uuid>, Vec<i64>) = charges.iter().copied().unzip();
+    sqlx::query(
+        r#"
+        WITH c AS (
+            SELECT * FROM UNNEST($1::uuid[], $2::bigint[]) AS c(id, tokens)
+        ), k AS (
+            UPDATE user_keys u SET tokens_used = u.tokens_used + c.tokens, updated_at = NOW()
+            FROM c
+            WHERE u.id = c.id
+            RETURNING u.group_id, c.tokens
+        )
+        UPDATE key_groups g SET tokens_used = g.tokens_used + s.tokens, updated_at = NOW()
+        FROM (
+            SELECT group_id, SUM(tokens)::BIGINT AS tokens FROM k
+            WHERE group_id IS NOT NULL
+            GROUP BY group_id
+        ) s
+        WHERE g.id = s.group_id
+        "#,
+    )
+    .bind(&ids)
+    .bind(&tokens)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn insert_key(budget: Option<i64>, group_id: Option<Uuid>, db: &PgPool) -> Uuid {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO user_keys (id, name, key_hash, key_prefix, token_budget, group_id)
+             VALUES ($1, 'test', $2, 'sk-test', $3, $4)",
+        )
+        .bind(id)
+        .bind(hash_key(&id.to_string()))
+        .bind(budget)
+        .bind(group_id)
+        .execute(db)
+        .await
+        .unwrap();
+        id
+    }
+
+    /// Reserve `tokens` from `n` concurrent tasks; returns how many succeeded.
+    async fn reserve_concurrently(key: Uuid, tokens: i64, n: usize, db: &PgPool) -> usize {
+        let tasks: Vec<_> = (0..n)
+            .map(|_| {
+                let db = db.clone();
+                tokio::spawn(async move { reserve_tokens(key, tokens, 0, &db).await.unwrap() })
+            })
+            .collect();
+        let mut reserved = 0;
+        for task in tasks {
+            reserved += usize::from(task.await.unwrap());
+        }
+        reserved
+    }
+
+    #[sqlx::test]
+    async fn concurrent_reservations_never_exceed_the_key_budget(db: PgPool) {
+        let key = insert_key(Some(1_000), None, &db).await;
+
+        assert_eq!(reserve_concurrently(key, 100, 50, &db).await, 10);
+        let used: i64 = sqlx::query_scalar("SELECT tokens_used FROM user_keys WHERE id = $1")
+            .bind(key)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(used, 1_000);
+    }
+
+    #[sqlx::test]
+    async fn concurrent_reservations_never_exceed_the_group_budget(db: PgPool) {
+        let group: Uuid = sqlx::query_scalar(
+            "INSERT INTO key_groups (id, name, token_budget) VALUES ($1, 'team', 500) RETURNING id",
+        )
+        .bind(Uuid::new_v4())
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        let first = insert_key(None, Some(group), &db).await;
+        let second = insert_key(None, Some(group), &db).await;
+
+        let (a, b) = tokio::join!(
+            reserve_concurrently(first, 100, 20, &db),
+            reserve_concurrently(second, 100, 20, &db),
+        );
+        assert_eq!(a + b, 5);
+        let used: i64 = sqlx::query_scalar("SELECT tokens_used FROM key_groups WHERE id = $1")
+            .bind(group)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(used, 500);
+    }
+}
@@ -1,13 +1,69 @@
 use chrono::Utc;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::config::{is_credential_header, Config};
 use crate::error::AppError;
+use crate::services::body_store_service::{BodyStore, LogBodies};
 use crate::models::request_log::{LogListResponse, RequestLogInfo};
+use crate::redis_keys;
+
+/// Incoming headers as a JSON object for `request_logs.request_headers`, without
+/// credentials and `LOG_REDACT_HEADERS`. Repeated headers are joined with ", ";
+/// values that are not valid UTF-8 are dropped.
+pub fn redacted_headers(headers: &axum::http::HeaderMap, config: &Config) -> serde_json::Value {
+    let mut map = serde_json::Map::new();
+    for (name, value) in headers {
+        let name = name.as_str();
+        if is_credential_header(name, config) || config.log_redact_headers.iter().any(|r| r == name) {
+            continue;
+        }
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        match map.get_mut(name) {
+            Some(serde_json::Value::String(existing)) => {
+                existing.push_str(", ");
+                existing.push_str(value);
+            }
+            _ => {
+                map.insert(name.to_string(), value.into());
+            }
+        }
+    }
+    serde_json::Value::Object(map)
+}
+
+/// Which side produced a logged error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorSource {
+    /// Rejected by the gateway itself (invalid request, unknown model, budget, ...).
+    Gateway,
+    /// The provider failed, was unreachable or returned an unusable response.
+    Upstream,
+}
+
+impl ErrorSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorSource::Gateway => "gateway",
+            ErrorSource::Upstream => "upstream",
+        }
+    }
+}
 
 /// Parameters for inserting a new log entry (built by the proxy).
 pub struct NewRequestLog {
+    /// Organization of the user key.
+    pub org_id: Uuid,
+    /// Gateway-generated id, also returned as `x-gateway-request-id`.
     pub request_id: Option<String>,
+    /// The provider's `x-request-id`, when it sent one.
+    pub upstream_request_id: Option<String>,
     pub user_key_id: Option<Uuid>,
     pub user_key_hash: String,
     pub model_requested: String,
@@ -16,31 +72,190 @@ pub struct NewRequestLog {
     pub provider_kind: Option<String>,
     pub status_code: i16,
     pub is_error: bool,
+    /// Error worth retrying (429 / 5xx); false for client errors and successes.
+    pub retriable: bool,
     pub prompt_tokens: Option<i32>,
     pub completion_tokens: Option<i32>,
     pub total_tokens: Option<i32>,
+    /// Reasoning tokens, already included in `completion_tokens`.
+    pub reasoning_tokens: Option<i32>,
+    /// Prompt tokens served from the provider's cache, included in `prompt_tokens`.
+    pub cached_prompt_tokens: Option<i32>,
     pub latency_ms: i32,
     pub is_stream: bool,
     pub request_body: Option<serde_json::Value>,
     pub response_body: Option<serde_json::Value>,
     pub error_message: Option<String>,
+    /// Side that produced `error_message`; None for successes.
+    pub error_source: Option<ErrorSource>,
+    /// `finish_reason` of the first choice, when the response had one.
+    pub finish_reason: Option<String>,
+    /// Token counts were estimated by the gateway rather than reported by the provider.
+    pub tokens_estimated: bool,
+    /// Encoding estimated token counts stand in for; None when reported.
+    pub tokenizer: Option<String>,
+    /// Routes the request was sent to, failovers and hedges included; 0 = never sent.
+    pub attempts: i32,
+    /// Redacted incoming headers (`LOG_REQUEST_HEADERS`); None when not logged.
+    pub request_headers: Option<serde_json::Value>,
+    /// Sent with the client's own provider key (`x-provider-api-key`); the key itself
+    /// is never stored.
+    pub byo_provider_key: bool,
+    /// False when the client opted out of body storage (`x-gateway-no-store`).
+    pub stored: bool,
+    /// Answered with the model's fallback response after every route failed.
+    pub is_fallback: bool,
 }
 
-/// Insert a request log entry into the database.
-pub async fn insert_log(db: &PgPool, log: NewRequestLog) -> Result<(), AppError> {
+/// Buffered events per live log subscriber before it starts lagging.
+pub const LOG_EVENT_CAPACITY: usize = 1024;
+
+/// Summary of a newly logged request, broadcast to live log subscribers.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEvent {
+    /// Used to route events to the right organization's subscribers; not sent.
+    #[serde(skip)]
+    pub org_id: Uuid,
+    pub request_id: Option<String>,
+    pub upstream_request_id: Option<String>,
+    pub user_key_id: Option<Uuid>,
+    pub model_requested: String,
+    pub model_sent: String,
+    pub provider_id: Option<Uuid>,
+    pub provider_kind: Option<String>,
+    pub status_code: i16,
+    pub is_error: bool,
+    pub retriable: bool,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    pub total_tokens: Option<i32>,
+    pub reasoning_tokens: Option<i32>,
+    pub cached_prompt_tokens: Option<i32>,
+    pub latency_ms: i32,
+    pub is_stream: bool,
+    pub error_message: Option<String>,
+    pub error_source: Option<ErrorSource>,
+    pub finish_reason: Option<String>,
+    pub tokens_estimated: bool,
+    pub tokenizer: Option<String>,
+    pub attempts: i32,
+    pub byo_provider_key: bool,
+    pub is_fallback: bool,
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+impl From<&NewRequestLog> for LogEvent {
+    fn from(log: &NewRequestLog) -> Self {
+        Self {
+            org_id: log.org_id,
+            request_id: log.request_id.clone(),
+            upstream_request_id: log.upstream_request_id.clone(),
+            user_key_id: log.user_key_id,
+            model_requested: log.model_requested.clone(),
+            model_sent: log.model_sent.clone(),
+            provider_id: log.provider_id,
+            provider_kind: log.provider_kind.clone(),
+            status_code: log.status_code,
+            is_error: log.is_error,
+            retriable: log.retriable,
+            prompt_tokens: log.prompt_tokens,
+            completion_tokens: log.completion_tokens,
+            total_tokens: log.total_tokens,
+            reasoning_tokens: log.reasoning_tokens,
+            cached_prompt_tokens: log.cached_prompt_tokens,
+            latency_ms: log.latency_ms,
+            is_stream: log.is_stream,
+            error_message: log.error_message.clone(),
+            error_source: log.error_source,
+            finish_reason: log.finish_reason.clone(),
+            tokens_estimated: log.tokens_estimated,
+            tokenizer: log.tokenizer.clone(),
+            attempts: log.attempts,
+            byo_provider_key: log.byo_provider_key,
+            is_fallback: log.is_fallback,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Replace a body whose serialized size exceeds `max_bytes` with a
+/// `{ "_truncated": true, "original_bytes": N }` marker. None = no limit.
+pub fn truncate_body(
+    body: Option<serde_json::Value>,
+    max_bytes: Option<usize>,
+) -> Option<serde_json::Value> {
+    let (body, max_bytes) = match (body, max_bytes) {
+        (Some(body), Some(max_bytes)) => (body, max_bytes),
+        (body, _) => return body,
+    };
+    let original_bytes = serde_json::to_vec(&body).map(|v| v.len()).unwrap_or(0);
+    if original_bytes <= max_bytes {
+        return Some(body);
+    }
+    Some(serde_json::json!({ "_truncated": true, "original_bytes": original_bytes }))
+}
+
+/// Keep the first and last `max_chunks / 2` chunks of a streamed response, with a
+/// `{ "_omitted_chunks": N }` marker in between. `max_chunks` = 0 keeps everything.
+pub fn cap_stream_chunks(
+    mut chunks: Vec<serde_json::Value>,
+    max_chunks: usize,
+) -> Vec<serde_json::Value> {
+    if max_chunks == 0 || chunks.len() <= max_chunks {
+        return chunks;
+    }
+    let head = max_chunks.div_ceil(2);
+    let tail = max_chunks - head;
+    let omitted = chunks.len() - max_chunks;
+    let last: Vec<serde_json::Value> = chunks.split_off(chunks.len() - tail);
+    chunks.truncate(head);
+    chunks.push(serde_json::json!({ "_omitted_chunks": omitted }));
+    chunks.extend(last);
+    chunks
+}
+
+/// Insert a request log entry into the database. Bodies go to `body_store`; if it
+/// fails they are kept in the row instead.
+pub async fn insert_log(
+    db: &PgPool,
+    body_store: &dyn BodyStore,
+    log: NewRequestLog,
+) -> Result<(), AppError> {
     let id = Uuid::new_v4();
     let now = Utc::now();
 
+    let mut bodies = LogBodies {
+        request_body: log.request_body,
+        response_body: log.response_body,
+    };
+    let mut body_key = None;
+    if !bodies.is_empty() {
+        match body_store.put(id, &bodies).await {
+            Ok(Some(key)) => {
+                body_key = Some(key);
+                bodies = LogBodies::default();
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!("Failed to store bodies of log {}, keeping them inline: {}", id, e)
+            }
+        }
+    }
+
     sqlx::query(
         r#"
         INSERT INTO request_logs (
             id, request_id, user_key_id, user_key_hash,
             model_requested, model_sent, provider_id, provider_kind,
             status_code, is_error, prompt_tokens, completion_tokens, total_tokens,
-            latency_ms, is_stream, request_body, response_body, error_message, created_at
+            latency_ms, is_stream, request_body, response_body, error_message, created_at,
+            stored, retriable, reasoning_tokens, cached_prompt_tokens, is_fallback, org_id,
+            upstream_request_id, finish_reason, body_key, tokens_estimated, error_source,
+            byo_provider_key, tokenizer, attempts, request_headers
         ) VALUES (
-            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13,
-            $14, $15, $16, $17, $18, $19
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15,
+            $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30,
+            $31, $32, $33, $34
         )
         "#,
     )
@@ -59,10 +274,25 @@ pub async fn insert_log(db: &PgPool, log: NewRequestLog) -> Result<(), AppError>
     .bind(log.total_tokens)
     .bind(log.latency_ms)
     .bind(log.is_stream)
-    .bind(&log.request_body)
-    .bind(&log.response_body)
+    .bind(&bodies.request_body)
+    .bind(&bodies.response_body)
     .bind(&log.error_message)
     .bind(now)
+    .bind(log.stored)
+    .bind(log.retriable)
+    .bind(log.reasoning_tokens)
+    .bind(log.cached_prompt_tokens)
+    .bind(log.is_fallback)
+    .bind(log.org_id)
+    .bind(&log.upstream_request_id)
+    .bind(&log.finish_reason)
+    .bind(&body_key)
+    .bind(log.tokens_estimated)
+    .bind(log.error_source.map(|s| s.as_str()))
+    .bind(log.byo_provider_key)
+    .bind(&log.tokenizer)
+    .bind(log.attempts)
+    .bind(&log.request_headers)
     .execute(db)
     .await?;
 
@@ -71,10 +301,12 @@ pub async fn insert_log(db: &PgPool, log: NewRequestLog) -> Result<(), AppError>
 
 /// Query parameters for listing logs.
 pub struct ListLogsParams {
+    pub org_id: Uuid,
     pub page: i64,
     pub per_page: i64,
     pub key_id: Option<Uuid>,
     pub model: Option<String>,
+    pub retriable: Option<bool>,
 }
 
 /// Row struct for the joined log + model coefficients query.
@@ -84,6 +316,7 @@ struct RequestLogRow {
     // request_logs columns
     id: uuid::Uuid,
     request_id: Option<String>,
+    upstream_request_id: Option<String>,
     user_key_id: Option<uuid::Uuid>,
     user_key_hash: String,
     model_requested: String,
@@ -101,7 +334,21 @@ struct RequestLogRow {
     response_body: Option<serde_json::Value>,
     error_message: Option<String>,
     created_at: chrono::DateTime<chrono::Utc>,
-    // computed
+    stored: bool,
+    retriable: bool,
+    reasoning_tokens: Option<i32>,
+    cached_prompt_tokens: Option<i32>,
+    is_fallback: bool,
+    finish_reason: Option<String>,
+    body_key: Option<String>,
+    tokens_estimated: bool,
+    error_source: Option<String>,
+    byo_provider_key: bool,
+    tokenizer: Option<String>,
+    attempts: i32,
+    request_headers: Option<serde_json::Value>,
+    // joined / computed
+    key_metadata: Option<serde_json::Value>,
     weighted_total_tokens: Option<i64>,
 }
 
@@ -110,6 +357,7 @@ impl From<RequestLogRow> for RequestLogInfo {
         Self {
             id: r.id,
             request_id: r.request_id,
+            upstream_request_id: r.upstream_request_id,
             user_key_id: r.user_key_id,
             model_requested: r.model_requested,
             model_sent: r.model_sent,
@@ -120,67 +368,112 @@ impl From<RequestLogRow> for RequestLogInfo {
             prompt_tokens: r.prompt_tokens,
             completion_tokens: r.completion_tokens,
             total_tokens: r.total_tokens,
+            reasoning_tokens: r.reasoning_tokens,
+            cached_prompt_tokens: r.cached_prompt_tokens,
             weighted_total_tokens: r.weighted_total_tokens,
             latency_ms: r.latency_ms,
             is_stream: r.is_stream,
             request_body: r.request_body,
             response_body: r.response_body,
+            body_key: r.body_key,
             error_message: r.error_message,
+            finish_reason: r.finish_reason,
+            tokens_estimated: r.tokens_estimated,
+            error_source: r.error_source,
+            byo_provider_key: r.byo_provider_key,
+            tokenizer: r.tokenizer,
+            attempts: r.attempts,
+            request_headers: r.request_headers,
             created_at: r.created_at,
+            stored: r.stored,
+            retriable: r.retriable,
+            is_fallback: r.is_fallback,
+            key_metadata: r.key_metadata,
         }
     }
 }
 
+/// Token usage of a log row `r` weighted by the coefficients of its model `m`
+/// (NULL when the row reports no usage).
+const WEIGHTED_TOKENS_SQL: &str = r#"CASE WHEN r.prompt_tokens IS NOT NULL OR r.completion_tokens IS NOT NULL
+                       THEN ROUND(
+                           (COALESCE(r.prompt_tokens, 0) - COALESCE(r.cached_prompt_tokens, 0))
+                             * COALESCE(m.input_token_coefficient, 1.0)
+                           + COALESCE(r.cached_prompt_tokens, 0)
+                             * COALESCE(m.cached_token_coefficient, m.input_token_coefficient, 1.0)
+                           + (COALESCE(r.completion_tokens, 0) - COALESCE(r.reasoning_tokens, 0))
+                             * COALESCE(m.output_token_coefficient, 1.0)
+                           + COALESCE(r.reasoning_tokens, 0)
+                             * COALESCE(m.reasoning_token_coefficient, m.output_token_coefficient, 1.0)
+                       )::BIGINT
+                       ELSE NULL
+                  END"#;
+
+/// Columns and joins of `RequestLogRow` queries; callers append the WHERE clause.
+fn log_select_sql() -> String {
+    format!(
+        r#"SELECT r.id, r.request_id, r.upstream_request_id, r.user_key_id, r.user_key_hash,
+                  r.model_requested, r.model_sent, r.provider_id, r.provider_kind,
+                  r.status_code, r.is_error, r.prompt_tokens, r.completion_tokens, r.total_tokens,
+                  r.latency_ms, r.is_stream, r.request_body, r.response_body, r.error_message,
+                  r.created_at, r.stored, r.retriable, r.reasoning_tokens, r.cached_prompt_tokens,
+                  r.is_fallback, r.finish_reason, r.body_key, r.tokens_estimated,
+                  r.error_source, r.byo_provider_key, r.tokenizer, r.attempts,
+                  r.request_headers,                  k.metadata AS key_metadata,
+                  {WEIGHTED_TOKENS_SQL} AS weighted_total_tokens
+           FROM request_logs r
+           LEFT JOIN models m ON m.name = r.model_requested AND m.org_id = r.org_id
+           LEFT JOIN user_keys k ON k.id = r.user_key_id"#
+    )
+}
+
 /// List logs with offset-based pagination and optional filters.
 pub async fn list_logs(db: &PgPool, params: ListLogsParams) -> Result<LogListResponse, AppError> {
     let offset = (params.page - 1).max(0) * params.per_page;
 
-    // Build dynamic WHERE clauses
-    let mut conditions: Vec<String> = vec![];
-    if params.key_id.is_some() {
-        conditions.push("r.user_key_id = $3".to_string());
-    }
-    if params.model.is_some() {
-        let idx = if params.key_id.is_some() { 4 } else { 3 };
-        conditions.push(format!("r.model_requested = ${idx}"));
-    }
-
-    let where_clause = if conditions.is_empty() {
-        String::new()
-    } else {
+    // Build dynamic WHERE clauses; filter placeholders start at `first`
+    let where_clause = |first: usize| {
+        let mut idx = first;
+        let mut conditions: Vec<String> = vec![];
+        let mut push = |column: &str| {
+            conditions.push(format!("r.{column} = ${idx}"));
+            idx += 1;
+        };
+        push("org_id");
+        if params.key_id.is_some() {
+            push("user_key_id");
+        }
+        if params.model.is_some() {
+            push("model_requested");
+        }
+        if params.retriable.is_some() {
+            push("retriable");
+        }
         format!("WHERE {}", conditions.join(" AND "))
     };
 
-    let count_query = format!("SELECT COUNT(*) FROM request_logs r {where_clause}");
+    let count_query = format!("SELECT COUNT(*) FROM request_logs r {}", where_clause(1));
     let data_query = format!(
-        r#"SELECT r.id, r.request_id, r.user_key_id, r.user_key_hash,
-                  r.model_requested, r.model_sent, r.provider_id, r.provider_kind,
-                  r.status_code, r.is_error, r.prompt_tokens, r.completion_tokens, r.total_tokens,
-                  r.latency_ms, r.is_stream, r.request_body, r.response_body, r.error_message,
-                  r.created_at,
-                  CASE WHEN r.prompt_tokens IS NOT NULL OR r.completion_tokens IS NOT NULL
-                       THEN ROUND(
-                           COALESCE(r.prompt_tokens, 0) * COALESCE(m.input_token_coefficient, 1.0)
-                           + COALESCE(r.completion_tokens, 0) * COALESCE(m.output_token_coefficient, 1.0)
-                       )::BIGINT
-                       ELSE NULL
-                  END AS weighted_total_tokens
-           FROM request_logs r
-           LEFT JOIN models m ON m.name = r.model_requested
-           {where_clause}
+        r#"{}
+           {}
            ORDER BY r.created_at DESC
-           LIMIT $1 OFFSET $2"#
+           LIMIT $1 OFFSET $2"#,
+        log_select_sql(),
+        where_clause(3)
     );
 
     // Execute count query
     let total: i64 = {
-        let mut q = sqlx::query_scalar::<_, i64>(&count_query);
+        let mut q = sqlx::query_scalar::<_, i64>(&count_query).bind(params.org_id);
         if let Some(ref kid) = params.key_id {
             q = q.bind(kid);
         }
         if let Some(ref m) = params.model {
             q = q.bind(m);
         }
+        if let Some(retriable) = params.retriable {
+            q = q.bind(retriable);
+        }
         q.fetch_one(db).await?
     };
 
@@ -188,13 +481,17 @@ pub async fn list_logs(db: &PgPool, params: ListLogsParams) -> Result<LogListRes
     let rows: Vec<RequestLogRow> = {
         let mut q = sqlx::query_as::<_, RequestLogRow>(&data_query)
             .bind(params.per_page)
-            .bind(offset);
+            .bind(offset)
+            .bind(params.org_id);
         if let Some(ref kid) = params.key_id {
             q = q.bind(kid);
         }
         if let Some(ref m) = params.model {
             q = q.bind(m);
         }
+        if let Some(retriable) = params.retriable {
+            q = q.bind(retriable);
+        }
         q.fetch_all(db).await?
     };
 
@@ -206,30 +503,115 @@ pub async fn list_logs(db: &PgPool, params: ListLogsParams) -> Result<LogListRes
     })
 }
 
-/// Delete request logs older than `retention_days` days.
-/// Returns the number of rows deleted.
-pub async fn cleanup_old_logs(db: &PgPool, retention_days: u32) -> Result<u64, AppError> {
-    let result = sqlx::query(
-        "DELETE FROM request_logs WHERE created_at < NOW() - make_interval(days => $1)",
+/// A log of the organization with its bodies, loaded from `body_store` when they
+/// are kept there. A failed load is logged and leaves the bodies null.
+pub async fn get_log(
+    id: Uuid,
+    org_id: Uuid,
+    body_store: &dyn BodyStore,
+    db: &PgPool,
+) -> Result<RequestLogInfo, AppError> {
+    let query = format!("{} WHERE r.id = $1 AND r.org_id = $2", log_select_sql());
+    let row = sqlx::query_as::<_, RequestLogRow>(&query)
+        .bind(id)
+        .bind(org_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let mut log = RequestLogInfo::from(row);
+    if let Some(key) = &log.body_key {
+        match body_store.get(key).await {
+            Ok(bodies) => {
+                log.request_body = bodies.request_body;
+                log.response_body = bodies.response_body;
+            }
+            Err(e) => tracing::warn!("Failed to load bodies of log {}: {}", id, e),
+        }
+    }
+    Ok(log)
+}
+
+/// Delete externally stored bodies whose rows are gone or compacted; failures are
+/// logged and leave the object behind.
+async fn delete_stored_bodies(body_store: &dyn BodyStore, keys: Vec<Option<String>>) {
+    for key in keys.into_iter().flatten() {
+        if let Err(e) = body_store.delete(&key).await {
+            tracing::warn!("Failed to delete stored log bodies {}: {}", key, e);
+        }
+    }
+}
+
+/// Delete request logs older than `retention_days` days, along with their
+/// externally stored bodies. Returns the number of rows deleted.
+pub async fn cleanup_old_logs(
+    db: &PgPool,
+    body_store: &dyn BodyStore,
+    retention_days: u32,
+) -> Result<u64, AppError> {
+    let keys: Vec<Option<String>> = sqlx::query_scalar(
+        r#"
+        DELETE FROM request_logs WHERE created_at < NOW() - make_interval(days => $1)
+        RETURNING body_key
+        "#,
     )
     .bind(retention_days as i32)
-    .execute(db)
+    .fetch_all(db)
     .await?;
 
-    Ok(result.rows_affected())
+    let deleted = keys.len() as u64;
+    delete_stored_bodies(body_store, keys).await;
+    Ok(deleted)
 }
 
-// ── Dashboard Stats ───────────────────────────────────────────────────
+/// Clear request/response bodies (and logged request headers) of logs older than
+/// `body_retention_days` days, keeping the rest of each row. Returns the number of
+/// rows compacted.
+pub async fn compact_bodies(
+    db: &PgPool,
+    body_store: &dyn BodyStore,
+    body_retention_days: u32,
+) -> Result<u64, AppError> {
+    let keys: Vec<Option<String>> = sqlx::query_scalar(
+        r#"
+        WITH old AS (
+            SELECT id, body_key FROM request_logs
+            WHERE created_at < NOW() - make_interval(days => $1)
+              AND (request_body IS NOT NULL OR response_body IS NOT NULL OR body_key IS NOT NULL
+                   OR request_headers IS NOT NULL)
+            FOR UPDATE
+        )
+        UPDATE request_logs r SET request_body = NULL, response_body = NULL, body_key = NULL,
+                                  request_headers = NULL
+        FROM old
+        WHERE r.id = old.id
+        RETURNING old.body_key
+        "#,
+    )
+    .bind(body_retention_days as i32)
+    .fetch_all(db)
+    .await?;
+
+    let compacted = keys.len() as u64;
+    delete_stored_bodies(body_store, keys).await;
+    Ok(compacted)
+}
 
-use serde::Serialize;
+// ── Dashboard Stats ───────────────────────────────────────────────────
 
 /// Summary numbers for the dashboard.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DashboardStats {
+    /// When served from the dashboard cache, the time the numbers were computed;
+    /// None for live results.
+    #[serde(default)]
+    pub stale_as_of: Option<chrono::DateTime<Utc>>,
     pub total_requests: i64,
     pub total_requests_24h: i64,
     pub total_errors_24h: i64,
     pub total_tokens_24h: i64,
+    /// Prompt tokens served from the provider's prompt cache (last 24h).
+    pub cached_prompt_tokens_24h: i64,
     pub avg_latency_24h: f64,
     /// Requests per hour (last 24h). Each entry: { hour: "HH:00", requests, errors }.
     pub requests_per_hour: Vec<HourlyBucket>,
@@ -237,9 +619,11 @@ pub struct DashboardStats {
     pub model_usage: Vec<ModelUsage>,
     /// Per-provider request count (last 7 days).
     pub provider_usage: Vec<ProviderUsage>,
+    /// Most frequent error reasons (last 24h), normalized and grouped.
+    pub top_errors: Vec<ErrorReason>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct HourlyBucket {
     pub hour: String,
     pub requests: i64,
@@ -248,26 +632,38 @@ pub struct HourlyBucket {
     pub avg_latency: f64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ModelUsage {
     pub model: String,
     pub requests: i64,
     pub tokens: i64,
+    pub cached_prompt_tokens: i64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ProviderUsage {
     pub provider: String,
     pub requests: i64,
     pub errors: i64,
 }
 
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ErrorReason {
+    /// Error message with digits collapsed to `N`, truncated; `HTTP <status>` when absent.
+    pub reason: String,
+    pub count: i64,
+}
+
+/// Number of distinct error reasons returned in `top_errors`.
+const TOP_ERRORS_LIMIT: i64 = 10;
+
 #[derive(Debug, sqlx::FromRow)]
 struct SummaryRow {
     total_requests: Option<i64>,
     total_requests_24h: Option<i64>,
     total_errors_24h: Option<i64>,
     total_tokens_24h: Option<i64>,
+    cached_prompt_tokens_24h: Option<i64>,
     avg_latency_24h: Option<f64>,
 }
 
@@ -285,6 +681,7 @@ struct ModelRow {
     model: String,
     requests: i64,
     tokens: i64,
+    cached_prompt_tokens: i64,
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -294,7 +691,8 @@ struct ProviderRow {
     errors: i64,
 }
 
-pub async fn get_dashboard_stats(db: &PgPool) -> Result<DashboardStats, AppError> {
+/// Dashboard statistics over one organization's request logs.
+pub async fn get_dashboard_stats(org_id: Uuid, db: &PgPool) -> Result<DashboardStats, AppError> {
     // 1) Summary
     let summary = sqlx::query_as::<_, SummaryRow>(
         r#"
@@ -303,10 +701,13 @@ pub async fn get_dashboard_stats(db: &PgPool) -> Result<DashboardStats, AppError
             COUNT(*) FILTER (WHERE created_at >= NOW() - INTERVAL '24 hours')::BIGINT AS total_requests_24h,
             COUNT(*) FILTER (WHERE created_at >= NOW() - INTERVAL '24 hours' AND is_error)::BIGINT AS total_errors_24h,
             COALESCE(SUM(total_tokens) FILTER (WHERE created_at >= NOW() - INTERVAL '24 hours'), 0)::BIGINT AS total_tokens_24h,
+            COALESCE(SUM(cached_prompt_tokens) FILTER (WHERE created_at >= NOW() - INTERVAL '24 hours'), 0)::BIGINT AS cached_prompt_tokens_24h,
             COALESCE(AVG(latency_ms) FILTER (WHERE created_at >= NOW() - INTERVAL '24 hours'), 0)::FLOAT8 AS avg_latency_24h
         FROM request_logs
+        WHERE org_id = $1
         "#,
     )
+    .bind(org_id)
     .fetch_one(db)
     .await?;
 
@@ -320,11 +721,12 @@ pub async fn get_dashboard_stats(db: &PgPool) -> Result<DashboardStats, AppError
             COALESCE(SUM(total_tokens), 0)::BIGINT AS tokens,
             COALESCE(AVG(latency_ms), 0)::FLOAT8 AS avg_latency
         FROM request_logs
-        WHERE created_at >= NOW() - INTERVAL '24 hours'
+        WHERE org_id = $1 AND created_at >= NOW() - INTERVAL '24 hours'
         GROUP BY date_trunc('hour', created_at)
         ORDER BY hour
         "#,
     )
+    .bind(org_id)
     .fetch_all(db)
     .await?;
 
@@ -345,14 +747,16 @@ pub async fn get_dashboard_stats(db: &PgPool) -> Result<DashboardStats, AppError
         SELECT
             model_requested AS model,
             COUNT(*) AS requests,
-            COALESCE(SUM(total_tokens), 0)::BIGINT AS tokens
+            COALESCE(SUM(total_tokens), 0)::BIGINT AS tokens,
+            COALESCE(SUM(cached_prompt_tokens), 0)::BIGINT AS cached_prompt_tokens
         FROM request_logs
-        WHERE created_at >= NOW() - INTERVAL '7 days'
+        WHERE org_id = $1 AND created_at >= NOW() - INTERVAL '7 days'
         GROUP BY model_requested
         ORDER BY requests DESC
         LIMIT 20
         "#,
     )
+    .bind(org_id)
     .fetch_all(db)
     .await?;
 
@@ -362,6 +766,7 @@ pub async fn get_dashboard_stats(db: &PgPool) -> Result<DashboardStats, AppError
             model: r.model,
             requests: r.requests,
             tokens: r.tokens,
+            cached_prompt_tokens: r.cached_prompt_tokens,
         })
         .collect();
 
@@ -373,11 +778,12 @@ pub async fn get_dashboard_stats(db: &PgPool) -> Result<DashboardStats, AppError
             COUNT(*) AS requests,
             COUNT(*) FILTER (WHERE is_error) AS errors
         FROM request_logs
-        WHERE created_at >= NOW() - INTERVAL '7 days'
+        WHERE org_id = $1 AND created_at >= NOW() - INTERVAL '7 days'
         GROUP BY provider_kind
         ORDER BY requests DESC
         "#,
     )
+    .bind(org_id)
     .fetch_all(db)
     .await?;
 
@@ -390,14 +796,491 @@ pub async fn get_dashboard_stats(db: &PgPool) -> Result<DashboardStats, AppError
         })
         .collect();
 
+    // 5) Top error reasons (last 24h)
+    let top_errors = sqlx::query_as::<_, ErrorReason>(
+        r#"
+        SELECT
+            LEFT(
+                COALESCE(regexp_replace(error_message, '[0-9]+', 'N', 'g'), 'HTTP ' || status_code),
+                120
+            ) AS reason,
+            COUNT(*) AS count
+        FROM request_logs
+        WHERE org_id = $1 AND created_at >= NOW() - INTERVAL '24 hours' AND is_error
+        GROUP BY reason
+        ORDER BY count DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(org_id)
+    .bind(TOP_ERRORS_LIMIT)
+    .fetch_all(db)
+    .await?;
+
     Ok(DashboardStats {
+        stale_as_of: None,
         total_requests: summary.total_requests.unwrap_or(0),
         total_requests_24h: summary.total_requests_24h.unwrap_or(0),
         total_errors_24h: summary.total_errors_24h.unwrap_or(0),
         total_tokens_24h: summary.total_tokens_24h.unwrap_or(0),
+        cached_prompt_tokens_24h: summary.cached_prompt_tokens_24h.unwrap_or(0),
         avg_latency_24h: (summary.avg_latency_24h.unwrap_or(0.0) * 10.0).round() / 10.0,
         requests_per_hour,
         model_usage,
         provider_usage,
+        top_errors,
     })
 }
+
+/// Compute an organization's dashboard stats and cache them for `refresh_secs`
+/// (twice over, so one missed refresh does not empty the cache). Returns the stats.
+pub async fn refresh_dashboard_stats(
+    org_id: Uuid,
+    refresh_secs: u64,
+    db: &PgPool,
+    redis: &mut ConnectionManager,
+) -> Result<DashboardStats, AppError> {
+    let mut stats = get_dashboard_stats(org_id, db).await?;
+    stats.stale_as_of = Some(Utc::now());
+    let json = serde_json::to_string(&stats)
+        .map_err(|e| AppError::Internal(format!("Failed to serialize dashboard stats: {e}")))?;
+    let _: () = redis
+        .set_ex(
+            redis_keys::dashboard_stats(org_id),
+            json,
+            refresh_secs.saturating_mul(2).max(1),
+        )
+        .await?;
+    Ok(stats)
+}
+
+/// Dashboard stats from the cache filled by `refresh_dashboard_stats`, or computed
+/// live when it has no entry (or cannot be read).
+pub async fn get_cached_dashboard_stats(
+    org_id: Uuid,
+    db: &PgPool,
+    redis: &mut ConnectionManager,
+) -> Result<DashboardStats, AppError> {
+    let cached: Result<Option<String>, _> =
+        redis.get(redis_keys::dashboard_stats(org_id)).await;
+    match cached {
+        Ok(Some(json)) => match serde_json::from_str(&json) {
+            Ok(stats) => return Ok(stats),
+            Err(e) => tracing::warn!("Ignoring unreadable cached dashboard stats: {}", e),
+        },
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Dashboard stats cache lookup failed: {}", e),
+    }
+    get_dashboard_stats(org_id, db).await
+}
+
+// ── Model Usage Series ────────────────────────────────────────────────
+
+/// Bucket width of a usage series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsInterval {
+    Hour,
+    Day,
+}
+
+impl StatsInterval {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "hour" => Some(Self::Hour),
+            "day" => Some(Self::Day),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+
+    pub fn width(&self) -> chrono::TimeDelta {
+        match self {
+            Self::Hour => chrono::TimeDelta::hours(1),
+            Self::Day => chrono::TimeDelta::days(1),
+        }
+    }
+
+    /// Start of the bucket containing `t` (UTC).
+    fn truncate(&self, t: chrono::DateTime<Utc>) -> chrono::DateTime<Utc> {
+        use chrono::DurationRound;
+        t.duration_trunc(self.width()).unwrap_or(t)
+    }
+}
+
+/// Query parameters for the per-model usage series.
+pub struct ModelUsageSeriesParams {
+    pub org_id: Uuid,
+    /// Inclusive start; rounded down to a bucket boundary.
+    pub from: chrono::DateTime<Utc>,
+    /// Exclusive end.
+    pub to: chrono::DateTime<Utc>,
+    pub interval: StatsInterval,
+    /// Number of models returned, by total tokens over the range.
+    pub limit: i64,
+}
+
+/// Per-model usage over time, zero-filled so every model has one entry per bucket.
+#[derive(Debug, Serialize)]
+pub struct ModelUsageSeries {
+    pub from: chrono::DateTime<Utc>,
+    pub to: chrono::DateTime<Utc>,
+    pub interval: StatsInterval,
+    /// Bucket start times, shared by every model's `buckets`.
+    pub buckets: Vec<chrono::DateTime<Utc>>,
+    /// Top models by total tokens over the range, largest first.
+    pub models: Vec<ModelUsageTrend>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelUsageTrend {
+    pub model: String,
+    pub requests: i64,
+    pub tokens: i64,
+    pub weighted_tokens: i64,
+    pub buckets: Vec<UsageBucket>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct UsageBucket {
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub tokens: i64,
+    /// Tokens weighted by the model's current coefficients.
+    pub weighted_tokens: i64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ModelBucketRow {
+    model: String,
+    bucket: chrono::DateTime<Utc>,
+    requests: i64,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+    tokens: i64,
+    weighted_tokens: i64,
+}
+
+/// Bucket start times from `from` up to (excluding) `to`.
+fn series_buckets(
+    from: chrono::DateTime<Utc>,
+    to: chrono::DateTime<Utc>,
+    interval: StatsInterval,
+) -> Vec<chrono::DateTime<Utc>> {
+    let mut buckets = Vec::new();
+    let mut bucket = interval.truncate(from);
+    while bucket < to {
+        buckets.push(bucket);
+        bucket += interval.width();
+    }
+    buckets
+}
+
+/// Request counts and token sums per model and time bucket, for the top models
+/// by total tokens in the range.
+pub async fn get_model_usage_series(
+    db: &PgPool,
+    params: ModelUsageSeriesParams,
+) -> Result<ModelUsageSeries, AppError> {
+    let from = params.interval.truncate(params.from);
+    let buckets = series_buckets(from, params.to, params.interval);
+
+    let query = format!(
+        r#"
+        WITH top_models AS (
+            SELECT model_requested
+            FROM request_logs
+            WHERE org_id = $1 AND created_at >= $2 AND created_at < $3
+            GROUP BY model_requested
+            ORDER BY COALESCE(SUM(total_tokens), 0) DESC, COUNT(*) DESC, model_requested
+            LIMIT $4
+        )
+        SELECT
+            r.model_requested AS model,
+            date_trunc($5, r.created_at AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' AS bucket,
+            COUNT(*) AS requests,
+            COALESCE(SUM(r.prompt_tokens), 0)::BIGINT AS prompt_tokens,
+            COALESCE(SUM(r.completion_tokens), 0)::BIGINT AS completion_tokens,
+            COALESCE(SUM(r.total_tokens), 0)::BIGINT AS tokens,
+            COALESCE(SUM({WEIGHTED_TOKENS_SQL}), 0)::BIGINT AS weighted_tokens
+        FROM request_logs r
+        JOIN top_models t ON t.model_requested = r.model_requested
+        LEFT JOIN models m ON m.name = r.model_requested AND m.org_id = r.org_id
+        WHERE r.org_id = $1 AND r.created_at >= $2 AND r.created_at < $3
+        GROUP BY r.model_requested, bucket
+        "#
+    );
+    let rows = sqlx::query_as::<_, ModelBucketRow>(&query)
+        .bind(params.org_id)
+        .bind(from)
+        .bind(params.to)
+        .bind(params.limit)
+        .bind(params.interval.as_str())
+        .fetch_all(db)
+        .await?;
+
+    let mut models: Vec<ModelUsageTrend> = Vec::new();
+    for row in rows {
+        let Ok(index) = buckets.binary_search(&row.bucket) else {
+            continue;
+        };
+        let trend = match models.iter().position(|m| m.model == row.model) {
+            Some(i) => &mut models[i],
+            None => {
+                models.push(ModelUsageTrend {
+                    model: row.model.clone(),
+                    requests: 0,
+                    tokens: 0,
+                    weighted_tokens: 0,
+                    buckets: vec![UsageBucket::default(); buckets.len()],
+                });
+                models.last_mut().expect("just pushed")
+            }
+        };
+        trend.requests += row.requests;
+        trend.tokens += row.tokens;
+        trend.weighted_tokens += row.weighted_tokens;
+        trend.buckets[index] = UsageBucket {
+            requests: row.requests,
+            prompt_tokens: row.prompt_tokens,
+            completion_tokens: row.completion_tokens,
+            tokens: row.tokens,
+            weighted_tokens: row.weighted_tokens,
+        };
+    }
+    models.sort_by(|a, b| {
+        b.tokens
+            .cmp(&a.tokens)
+            .then(b.requests.cmp(&a.requests))
+            .then_with(|| a.model.cmp(&b.model))
+    });
+
+    Ok(ModelUsageSeries {
+        from,
+        to: params.to,
+        interval: params.interval,
+        buckets,
+        models,
+    })
+}
+
+// ── Token Drift ───────────────────────────────────────────────────────
+
+/// Difference between keys' `tokens_used` counters and their logged usage.
+#[derive(Debug, Serialize)]
+pub struct TokenDrift {
+    /// Keys whose counter differs from their logged usage.
+    pub keys_with_drift: i64,
+    /// Sum of `drift` over all keys.
+    pub total_drift: i64,
+    /// Keys with nonzero drift, largest absolute drift first.
+    pub keys: Vec<KeyDrift>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct KeyDrift {
+    pub key_id: Uuid,
+    pub name: String,
+    pub tokens_used: i64,
+    /// Weighted usage of the key's logged requests, at current model coefficients.
+    pub logged_tokens: i64,
+    /// `tokens_used - logged_tokens`.
+    pub drift: i64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct KeyDriftRow {
+    #[sqlx(flatten)]
+    key: KeyDrift,
+    keys_with_drift: i64,
+    total_drift: i64,
+}
+
+/// Compare each key's `tokens_used` with the weighted sum of its request logs,
+/// listing up to `limit` keys with nonzero drift.
+pub async fn get_token_drift(
+    org_id: Uuid,
+    limit: i64,
+    db: &PgPool,
+) -> Result<TokenDrift, AppError> {
+    let query = format!(
+        r#"
+        WITH logged AS (
+            SELECT r.user_key_id, COALESCE(SUM({WEIGHTED_TOKENS_SQL}), 0)::BIGINT AS logged_tokens
+            FROM request_logs r
+            LEFT JOIN models m ON m.name = r.model_requested AND m.org_id = r.org_id
+            WHERE r.org_id = $1 AND r.user_key_id IS NOT NULL
+            GROUP BY r.user_key_id
+        ),
+        drift AS (
+            SELECT k.id AS key_id, k.name, k.tokens_used,
+                   COALESCE(l.logged_tokens, 0) AS logged_tokens,
+                   k.tokens_used - COALESCE(l.logged_tokens, 0) AS drift
+            FROM user_keys k
+            LEFT JOIN logged l ON l.user_key_id = k.id
+            WHERE k.org_id = $1
+        )
+        SELECT key_id, name, tokens_used, logged_tokens, drift,
+               COUNT(*) OVER () AS keys_with_drift,
+               SUM(drift) OVER ()::BIGINT AS total_drift
+        FROM drift
+        WHERE drift <> 0
+        ORDER BY ABS(drift) DESC, key_id
+        LIMIT $2
+        "#
+    );
+    let rows = sqlx::query_as::<_, KeyDriftRow>(&query)
+        .bind(org_id)
+        .bind(limit)
+        .fetch_all(db)
+        .await?;
+
+    let (keys_with_drift, total_drift) = rows
+        .first()
+        .map(|r| (r.keys_with_drift, r.total_drift))
+        .unwrap_or((0, 0));
+    Ok(TokenDrift {
+        keys_with_drift,
+        total_drift,
+        keys: rows.into_iter().map(|r| r.key).collect(),
+    })
+}
+
+/// Overwrite `tokens_used` of an organization's keys (only those tagged `tag`, if
+/// given) with the weighted usage of their request logs at current coefficients. Each
+/// key's group is adjusted by the same difference. Returns the number of keys changed.
+pub async fn recompute_key_usage(
+    org_id: Uuid,
+    tag: Option<&str>,
+    db: &PgPool,
+) -> Result<u64, AppError> {
+    let query = format!(
+        r#"
+        WITH logged AS (
+            SELECT r.user_key_id, COALESCE(SUM({WEIGHTED_TOKENS_SQL}), 0)::BIGINT AS logged_tokens
+            FROM request_logs r
+            LEFT JOIN models m ON m.name = r.model_requested AND m.org_id = r.org_id
+            WHERE r.org_id = $1 AND r.user_key_id IS NOT NULL
+            GROUP BY r.user_key_id
+        ),
+        target AS (
+            SELECT k.id, k.group_id,
+                   COALESCE(l.logged_tokens, 0) AS logged_tokens,
+                   COALESCE(l.logged_tokens, 0) - k.tokens_used AS delta
+            FROM user_keys k
+            LEFT JOIN logged l ON l.user_key_id = k.id
+            WHERE k.org_id = $1 AND ($2::TEXT IS NULL OR k.tag = $2)
+              AND k.tokens_used <> COALESCE(l.logged_tokens, 0)
+            FOR UPDATE OF k
+        ),
+        g AS (
+            UPDATE key_groups g SET tokens_used = g.tokens_used + s.delta, updated_at = NOW()
+            FROM (
+                SELECT group_id, SUM(delta)::BIGINT AS delta FROM target
+                WHERE group_id IS NOT NULL
+                GROUP BY group_id
+            ) s
+            WHERE g.id = s.group_id
+        )
+        UPDATE user_keys k SET tokens_used = t.logged_tokens, updated_at = NOW()
+        FROM target t
+        WHERE k.id = t.id
+        "#
+    );
+    let result = sqlx::query(&query).bind(org_id).bind(tag).execute(db).await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::body_store_service::PostgresBodyStore;
+
+    fn new_log(request_id: &str) -> NewRequestLog {
+        NewRequestLog {
+            org_id: Uuid::nil(),
+            request_id: Some(request_id.to_string()),
+            upstream_request_id: None,
+            user_key_id: None,
+            user_key_hash: "hash".into(),
+            model_requested: "gpt-4o".into(),
+            model_sent: "gpt-4o".into(),
+            provider_id: None,
+            provider_kind: Some("openai".into()),
+            status_code: 200,
+            is_error: false,
+            retriable: false,
+            prompt_tokens: Some(10),
+            completion_tokens: Some(5),
+            total_tokens: Some(15),
+            reasoning_tokens: None,
+            cached_prompt_tokens: None,
+            latency_ms: 100,
+            is_stream: false,
+            request_body: None,
+            response_body: None,
+            error_message: None,
+            error_source: None,
+            finish_reason: Some("stop".into()),
+            tokens_estimated: false,
+            tokenizer: None,
+            attempts: 1,
+            request_headers: None,
+            byo_provider_key: false,
+            stored: true,
+            is_fallback: false,
+        }
+    }
+
+    async fn logged<T>(column: &str, request_id: &str, db: &PgPool) -> T
+    where
+        T: for<'r> sqlx::Decode<'r, sqlx::Postgres> + sqlx::Type<sqlx::Postgres> + Send + Unpin,
+    {
+        sqlx::query_scalar(&format!("SELECT {column} FROM request_logs WHERE request_id = $1"))
+            .bind(request_id)
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn estimated_request_records_its_encoding(db: PgPool) {
+        let log = NewRequestLog {
+            tokens_estimated: true,
+            tokenizer: Some("o200k_base".into()),
+            ..new_log("req-estimated")
+        };
+        insert_log(&db, &PostgresBodyStore, log).await.unwrap();
+        insert_log(&db, &PostgresBodyStore, new_log("req-reported")).await.unwrap();
+
+        let tokenizer: Option<String> = logged("tokenizer", "req-estimated", &db).await;
+        assert_eq!(tokenizer.as_deref(), Some("o200k_base"));
+        assert!(logged::<bool>("tokens_estimated", "req-estimated", &db).await);
+        let tokenizer: Option<String> = logged("tokenizer", "req-reported", &db).await;
+        assert_eq!(tokenizer, None);
+    }
+
+    #[test]
+    fn redacted_headers_drop_credentials_and_user_key_headers() {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert("authorization", "Bearer sk-secret".parse().unwrap());
+        headers.insert("x-gateway-key", "sk-custom".parse().unwrap());
+        headers.insert("x-internal", "hidden".parse().unwrap());
+        headers.append("x-title", "a".parse().unwrap());
+        headers.append("x-title", "b".parse().unwrap());
+
+        let config = Config::for_tests(&[
+            ("USER_KEY_HEADERS", "authorization,x-gateway-key"),
+            ("LOG_REDACT_HEADERS", "x-internal"),
+        ]);
+        let logged = redacted_headers(&headers, &config);
+        assert_eq!(logged, serde_json::json!({ "x-title": "a, b" }));
+    }
+}
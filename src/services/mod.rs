@@ -1,4 +1,11 @@
+pub mod body_store_service;
+pub mod budget_alert_service;
+pub mod export_service;
+pub mod key_group_service;
 pub mod key_service;
 pub mod log_service;
 pub mod model_service;
+pub mod org_service;
 pub mod provider_service;
+pub mod selftest_service;
+pub mod usage_service;
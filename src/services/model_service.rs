@@ -1,29 +1,162 @@
 use chrono::Utc;
 use redis::aio::ConnectionManager;
 use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::error::AppError;
-use crate::models::model::{Model, ModelInfo, ModelRoute};
-use crate::models::provider::Provider;
+use crate::models::model::{AlternateRouteInfo, Model, ModelInfo, ModelRoute};
+use crate::models::provider::{ModelNameCase, Provider};
+use crate::redis_keys;
+use crate::services::provider_service;
 
-const REDIS_MODEL_ROUTES_HASH: &str = "gateway:model_routes";
+/// Columns needed to build a `ModelRoute`; append a WHERE clause.
+const ROUTE_SELECT: &str = r#"
+    SELECT m.org_id, m.name AS model_name, m.provider_model_name, m.provider_id,
+           m.input_token_coefficient, m.output_token_coefficient, m.reasoning_token_coefficient,
+           m.cached_token_coefficient, m.input_price_per_mtok, m.output_price_per_mtok,
+           p.base_url, p.api_key, p.kind AS provider_kind, p.force_buffered_upstream,
+           p.supports_stream_usage, p.retry_on_status, m.weight, m.fallback_response,
+           m.default_max_tokens, m.prompt_caching, m.hedge_after_ms, p.normalize_model_case,
+           p.region AS provider_region, p.chat_path
+    FROM models m
+    JOIN providers p ON m.provider_id = p.id
+"#;
+
+/// Same columns as `ROUTE_SELECT` for a model's alternate routes (active providers only);
+/// append further `AND` conditions.
+const ALTERNATE_SELECT: &str = r#"
+    SELECT m.org_id, m.name AS model_name,
+           COALESCE(a.provider_model_name, m.provider_model_name) AS provider_model_name,
+           a.provider_id, m.input_token_coefficient, m.output_token_coefficient,
+           m.reasoning_token_coefficient, m.cached_token_coefficient,
+           m.input_price_per_mtok, m.output_price_per_mtok,
+           p.base_url, p.api_key, p.kind AS provider_kind, p.force_buffered_upstream,
+           p.supports_stream_usage, p.retry_on_status, a.weight, m.fallback_response,
+           m.default_max_tokens, m.prompt_caching, m.hedge_after_ms, p.normalize_model_case,
+           p.region AS provider_region, p.chat_path
+    FROM model_alternate_routes a
+    JOIN models m ON a.model_id = m.id
+    JOIN providers p ON a.provider_id = p.id
+    WHERE p.is_active = TRUE
+"#;
+
+/// Redis key holding the cached route for an organization's model.
+fn route_cache_key(org_id: Uuid, model_name: &str) -> String {
+    redis_keys::route(org_id, model_name)
+}
+
+/// Write a route to its per-model key. `ttl_secs` = 0 stores without expiry.
+async fn store_route(
+    org_id: Uuid,
+    model_name: &str,
+    route: &ModelRoute,
+    ttl_secs: u64,
+    redis: &mut ConnectionManager,
+) -> Result<(), AppError> {
+    store_json(route_cache_key(org_id, model_name), route, ttl_secs, redis).await
+}
+
+/// Write `value` as JSON to `key`. `ttl_secs` = 0 stores without expiry.
+async fn store_json<T: Serialize>(
+    key: String,
+    value: &T,
+    ttl_secs: u64,
+    redis: &mut ConnectionManager,
+) -> Result<(), AppError> {
+    let json_str = serde_json::to_string(value)
+        .map_err(|e| AppError::Internal(format!("JSON serialization error: {e}")))?;
+
+    if ttl_secs > 0 {
+        let _: () = redis.set_ex(key, json_str, ttl_secs).await?;
+    } else {
+        let _: () = redis.set(key, json_str).await?;
+    }
+    Ok(())
+}
+
+/// Parameters for creating a model mapping.
+pub struct NewModel {
+    pub org_id: Uuid,
+    pub name: String,
+    pub provider_id: Uuid,
+    pub provider_model_name: Option<String>,
+    pub input_token_coefficient: f64,
+    pub output_token_coefficient: f64,
+    /// None = reasoning tokens cost the same as other completion tokens.
+    pub reasoning_token_coefficient: Option<f64>,
+    /// None = cached prompt tokens cost the same as other prompt tokens.
+    pub cached_token_coefficient: Option<f64>,
+    pub input_price_per_mtok: Option<f64>,
+    pub output_price_per_mtok: Option<f64>,
+    pub weight: i32,
+    pub fallback_response: Option<serde_json::Value>,
+    /// None = `DEFAULT_MAX_TOKENS` applies.
+    pub default_max_tokens: Option<i32>,
+    pub prompt_caching: bool,
+    /// None = no hedging.
+    pub hedge_after_ms: Option<i32>,
+}
+
+/// Partial update for a model. `None` keeps the current value; for nullable
+/// columns `Some(None)` clears it.
+pub struct ModelUpdate {
+    pub name: Option<String>,
+    pub provider_id: Option<Uuid>,
+    pub provider_model_name: Option<Option<String>>,
+    pub is_active: Option<bool>,
+    pub input_token_coefficient: Option<f64>,
+    pub output_token_coefficient: Option<f64>,
+    pub reasoning_token_coefficient: Option<Option<f64>>,
+    pub cached_token_coefficient: Option<Option<f64>>,
+    pub input_price_per_mtok: Option<Option<f64>>,
+    pub output_price_per_mtok: Option<Option<f64>>,
+    pub weight: Option<i32>,
+    pub fallback_response: Option<Option<serde_json::Value>>,
+    pub default_max_tokens: Option<Option<i32>>,
+    pub prompt_caching: Option<bool>,
+    pub hedge_after_ms: Option<Option<i32>>,
+}
+
+/// Parameters for adding an alternate provider route to a model.
+pub struct NewAlternateRoute {
+    pub provider_id: Uuid,
+    pub provider_model_name: Option<String>,
+    pub weight: i32,
+}
+
+/// A model of the organization, or `NotFound` if it does not exist there.
+async fn get_model(id: Uuid, org_id: Uuid, db: &PgPool) -> Result<Model, AppError> {
+    sqlx::query_as::<_, Model>("SELECT * FROM models WHERE id = $1 AND org_id = $2")
+        .bind(id)
+        .bind(org_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+/// Map a unique violation on `models.name` to a 409 naming the model.
+fn duplicate_name_error(e: sqlx::Error, name: &str) -> AppError {
+    match &e {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => AppError::Conflict(
+            format!("A model named \"{name}\" already exists; choose another name or update it"),
+        ),
+        _ => e.into(),
+    }
+}
 
 /// Create a new model mapping.
 pub async fn create_model(
-    name: &str,
-    provider_id: Uuid,
-    provider_model_name: Option<&str>,
-    input_token_coefficient: f64,
-    output_token_coefficient: f64,
+    new: NewModel,
+    route_ttl_secs: u64,
     db: &PgPool,
     redis: &mut ConnectionManager,
 ) -> Result<ModelInfo, AppError> {
-    // Verify provider exists
-    let provider = sqlx::query_as::<_, Provider>("SELECT * FROM providers WHERE id = $1")
-        .bind(provider_id)
-        .fetch_optional(db)
+    let provider_id = new.provider_id;
+
+    // Verify provider exists in the model's organization
+    let provider = provider_service::get_provider(provider_id, new.org_id, db)
         .await?
         .ok_or_else(|| AppError::BadRequest(format!("Provider {provider_id} not found")))?;
 
@@ -33,80 +166,219 @@ pub async fn create_model(
     sqlx::query(
         r#"
         INSERT INTO models (id, name, provider_id, provider_model_name, is_active,
-                            input_token_coefficient, output_token_coefficient, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, TRUE, $5, $6, $7, $7)
+                            input_token_coefficient, output_token_coefficient,
+                            input_price_per_mtok, output_price_per_mtok, weight,
+                            reasoning_token_coefficient, fallback_response, org_id,
+                            default_max_tokens, cached_token_coefficient, prompt_caching,
+                            created_at, updated_at, hedge_after_ms)
+        VALUES ($1, $2, $3, $4, TRUE, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $16,
+                $17)
         "#,
     )
     .bind(id)
-    .bind(name)
+    .bind(&new.name)
     .bind(provider_id)
-    .bind(provider_model_name)
-    .bind(input_token_coefficient)
-    .bind(output_token_coefficient)
+    .bind(&new.provider_model_name)
+    .bind(new.input_token_coefficient)
+    .bind(new.output_token_coefficient)
+    .bind(new.input_price_per_mtok)
+    .bind(new.output_price_per_mtok)
+    .bind(new.weight)
+    .bind(new.reasoning_token_coefficient)
+    .bind(&new.fallback_response)
+    .bind(new.org_id)
+    .bind(new.default_max_tokens)
+    .bind(new.cached_token_coefficient)
+    .bind(new.prompt_caching)
     .bind(now)
+    .bind(new.hedge_after_ms)
     .execute(db)
-    .await?;
+    .await
+    .map_err(|e| duplicate_name_error(e, &new.name))?;
 
     // Update Redis cache
-    cache_model_route(name, provider_model_name, input_token_coefficient, output_token_coefficient, &provider, redis).await?;
+    refresh_model_route(new.org_id, &new.name, route_ttl_secs, db, redis).await?;
 
     Ok(ModelInfo {
         id,
-        name: name.to_string(),
+        name: new.name,
         provider_id,
         provider_name: Some(provider.name),
-        provider_model_name: provider_model_name.map(|s| s.to_string()),
+        provider_model_name: new.provider_model_name,
         is_active: true,
-        input_token_coefficient,
-        output_token_coefficient,
+        input_token_coefficient: new.input_token_coefficient,
+        output_token_coefficient: new.output_token_coefficient,
+        reasoning_token_coefficient: new.reasoning_token_coefficient,
+        cached_token_coefficient: new.cached_token_coefficient,
+        input_price_per_mtok: new.input_price_per_mtok,
+        output_price_per_mtok: new.output_price_per_mtok,
+        weight: new.weight,
+        fallback_response: new.fallback_response,
+        default_max_tokens: new.default_max_tokens,
+        prompt_caching: new.prompt_caching,
+        hedge_after_ms: new.hedge_after_ms,
         created_at: now,
         updated_at: now,
     })
 }
 
-/// List all models with their provider names.
-pub async fn list_models(db: &PgPool) -> Result<Vec<ModelInfo>, AppError> {
+/// How discovered provider model ids become gateway model names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveryNaming {
+    /// Use the provider's id unchanged (e.g. `openai/gpt-4o`).
+    AsIs,
+    /// Drop everything up to the last `/` (e.g. `openai/gpt-4o` → `gpt-4o`).
+    StripNamespace,
+}
+
+impl DiscoveryNaming {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "as_is" => Some(DiscoveryNaming::AsIs),
+            "strip_namespace" => Some(DiscoveryNaming::StripNamespace),
+            _ => None,
+        }
+    }
+}
+
+/// Parameters for discovering a provider's models.
+pub struct ModelDiscovery {
+    pub org_id: Uuid,
+    pub provider_id: Uuid,
+    /// Prepended to every discovered model name.
+    pub prefix: String,
+    pub naming: DiscoveryNaming,
+}
+
+/// Per-request timeout for a provider's model list.
+const DISCOVERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Fetch the provider's `GET {base_url}/models` list and create a model for every id
+/// whose gateway name (`prefix` + the id per `naming`) is not taken in the
+/// organization yet, with default coefficients and weight. Returns the new models.
+pub async fn discover_models(
+    discovery: ModelDiscovery,
+    route_ttl_secs: u64,
+    http_client: &reqwest::Client,
+    db: &PgPool,
+    redis: &mut ConnectionManager,
+) -> Result<Vec<ModelInfo>, AppError> {
+    let ModelDiscovery {
+        org_id,
+        provider_id,
+        prefix,
+        naming,
+    } = discovery;
+    let provider = provider_service::get_provider(provider_id, org_id, db)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let url = format!("{}/models", provider.base_url.trim_end_matches('/'));
+    let resp = http_client
+        .get(&url)
+        .header(reqwest::header::AUTHORIZATION, format!("Bearer {}", provider.api_key))
+        .timeout(DISCOVERY_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| {
+            AppError::BadRequest(format!("Provider '{}': GET {url} failed: {e}", provider.name))
+        })?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(AppError::BadRequest(format!(
+            "Provider '{}' does not expose a model list: GET {url} returned {status}",
+            provider.name
+        )));
+    }
+    let list: serde_json::Value = resp.json().await.map_err(|e| {
+        AppError::BadRequest(format!("Provider '{}': invalid model list: {e}", provider.name))
+    })?;
+    let ids: Vec<String> = list
+        .get("data")
+        .and_then(|d| d.as_array())
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Provider '{}' returned no OpenAI-style model list (`data` array) from GET {url}",
+                provider.name
+            ))
+        })?
+        .iter()
+        .filter_map(|m| m.get("id").and_then(|id| id.as_str()))
+        .map(str::to_string)
+        .collect();
+
+    let mut added = Vec::new();
+    for id in ids {
+        let base_name = match naming {
+            DiscoveryNaming::AsIs => id.as_str(),
+            DiscoveryNaming::StripNamespace => id.rsplit('/').next().unwrap_or(&id),
+        };
+        if base_name.is_empty() {
+            continue;
+        }
+        let name = format!("{prefix}{base_name}");
+        let provider_model_name = (name != id).then(|| id.clone());
+        let now = Utc::now();
+
+        let model = sqlx::query_as::<_, Model>(
+            r#"
+            INSERT INTO models (id, name, provider_id, provider_model_name, is_active,
+                                input_token_coefficient, output_token_coefficient, weight,
+                                org_id, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, TRUE, 1.0, 1.0, 1, $5, $6, $6)
+            ON CONFLICT (org_id, name) DO NOTHING
+            RETURNING *
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(&name)
+        .bind(provider_id)
+        .bind(&provider_model_name)
+        .bind(org_id)
+        .bind(now)
+        .fetch_optional(db)
+        .await?;
+
+        if let Some(model) = model {
+            added.push(ModelInfo::from(ModelWithProvider {
+                model,
+                provider_name: provider.name.clone(),
+            }));
+        }
+    }
+
+    if !added.is_empty() {
+        warm_up_model_routes(route_ttl_secs, db, redis).await?;
+    }
+    Ok(added)
+}
+
+/// List an organization's models with their provider names.
+pub async fn list_models(org_id: Uuid, db: &PgPool) -> Result<Vec<ModelInfo>, AppError> {
     let rows = sqlx::query_as::<_, ModelWithProvider>(
         r#"
-        SELECT m.id, m.name, m.provider_id, m.provider_model_name, m.is_active,
-               m.input_token_coefficient, m.output_token_coefficient,
-               m.created_at, m.updated_at, p.name AS provider_name
+        SELECT m.*, p.name AS provider_name
         FROM models m
         JOIN providers p ON m.provider_id = p.id
+        WHERE m.org_id = $1
         ORDER BY m.created_at DESC
         "#,
     )
+    .bind(org_id)
     .fetch_all(db)
     .await?;
 
-    Ok(rows
-        .into_iter()
-        .map(|r| ModelInfo {
-            id: r.id,
-            name: r.name,
-            provider_id: r.provider_id,
-            provider_name: Some(r.provider_name),
-            provider_model_name: r.provider_model_name,
-            is_active: r.is_active,
-            input_token_coefficient: r.input_token_coefficient,
-            output_token_coefficient: r.output_token_coefficient,
-            created_at: r.created_at,
-            updated_at: r.updated_at,
-        })
-        .collect())
+    Ok(rows.into_iter().map(ModelInfo::from).collect())
 }
 
 /// Delete a model and remove from Redis cache.
 pub async fn delete_model(
     id: Uuid,
+    org_id: Uuid,
     db: &PgPool,
     redis: &mut ConnectionManager,
 ) -> Result<(), AppError> {
-    let model = sqlx::query_as::<_, Model>("SELECT * FROM models WHERE id = $1")
-        .bind(id)
-        .fetch_optional(db)
-        .await?
-        .ok_or(AppError::NotFound)?;
+    let model = get_model(id, org_id, db).await?;
 
     sqlx::query("DELETE FROM models WHERE id = $1")
         .bind(id)
@@ -114,7 +386,7 @@ pub async fn delete_model(
         .await?;
 
     // Remove from Redis
-    let _: () = redis.hdel(REDIS_MODEL_ROUTES_HASH, &model.name).await?;
+    let _: () = redis.del(route_cache_key(org_id, &model.name)).await?;
 
     Ok(())
 }
@@ -122,36 +394,51 @@ pub async fn delete_model(
 /// Update an existing model and rebuild Redis cache.
 pub async fn update_model(
     id: Uuid,
-    name: Option<&str>,
-    provider_id: Option<Uuid>,
-    provider_model_name: Option<Option<&str>>,
-    is_active: Option<bool>,
-    input_token_coefficient: Option<f64>,
-    output_token_coefficient: Option<f64>,
+    org_id: Uuid,
+    update: ModelUpdate,
+    route_ttl_secs: u64,
     db: &PgPool,
     redis: &mut ConnectionManager,
 ) -> Result<ModelInfo, AppError> {
-    let existing = sqlx::query_as::<_, Model>("SELECT * FROM models WHERE id = $1")
-        .bind(id)
-        .fetch_optional(db)
-        .await?
-        .ok_or(AppError::NotFound)?;
+    let existing = get_model(id, org_id, db).await?;
 
-    let new_name = name.map(|s| s.to_string()).unwrap_or(existing.name.clone());
-    let new_provider_id = provider_id.unwrap_or(existing.provider_id);
-    let new_provider_model_name = match provider_model_name {
-        Some(opt) => opt.map(|s| s.to_string()),
-        None => existing.provider_model_name.clone(),
-    };
-    let new_is_active = is_active.unwrap_or(existing.is_active);
-    let new_input_coeff = input_token_coefficient.unwrap_or(existing.input_token_coefficient);
-    let new_output_coeff = output_token_coefficient.unwrap_or(existing.output_token_coefficient);
+    let new_name = update.name.unwrap_or(existing.name.clone());
+    let new_provider_id = update.provider_id.unwrap_or(existing.provider_id);
+    let new_provider_model_name = update
+        .provider_model_name
+        .unwrap_or(existing.provider_model_name.clone());
+    let new_is_active = update.is_active.unwrap_or(existing.is_active);
+    let new_input_coeff = update
+        .input_token_coefficient
+        .unwrap_or(existing.input_token_coefficient);
+    let new_output_coeff = update
+        .output_token_coefficient
+        .unwrap_or(existing.output_token_coefficient);
+    let new_reasoning_coeff = update
+        .reasoning_token_coefficient
+        .unwrap_or(existing.reasoning_token_coefficient);
+    let new_cached_coeff = update
+        .cached_token_coefficient
+        .unwrap_or(existing.cached_token_coefficient);
+    let new_input_price = update
+        .input_price_per_mtok
+        .unwrap_or(existing.input_price_per_mtok);
+    let new_output_price = update
+        .output_price_per_mtok
+        .unwrap_or(existing.output_price_per_mtok);
+    let new_weight = update.weight.unwrap_or(existing.weight);
+    let new_fallback_response = update
+        .fallback_response
+        .unwrap_or(existing.fallback_response.clone());
+    let new_default_max_tokens = update
+        .default_max_tokens
+        .unwrap_or(existing.default_max_tokens);
+    let new_prompt_caching = update.prompt_caching.unwrap_or(existing.prompt_caching);
+    let new_hedge_after_ms = update.hedge_after_ms.unwrap_or(existing.hedge_after_ms);
 
-    // If provider changed, verify it exists
+    // If provider changed, verify it exists in the organization
     if new_provider_id != existing.provider_id {
-        sqlx::query_as::<_, Provider>("SELECT * FROM providers WHERE id = $1")
-            .bind(new_provider_id)
-            .fetch_optional(db)
+        provider_service::get_provider(new_provider_id, org_id, db)
             .await?
             .ok_or_else(|| AppError::BadRequest(format!("Provider {new_provider_id} not found")))?;
     }
@@ -160,8 +447,12 @@ pub async fn update_model(
         r#"
         UPDATE models
         SET name = $1, provider_id = $2, provider_model_name = $3, is_active = $4,
-            input_token_coefficient = $5, output_token_coefficient = $6, updated_at = NOW()
-        WHERE id = $7
+            input_token_coefficient = $5, output_token_coefficient = $6,
+            input_price_per_mtok = $7, output_price_per_mtok = $8, weight = $9,
+            reasoning_token_coefficient = $10, fallback_response = $11,
+            default_max_tokens = $12, cached_token_coefficient = $13, prompt_caching = $14,
+            hedge_after_ms = $15, updated_at = NOW()
+        WHERE id = $16
         "#,
     )
     .bind(&new_name)
@@ -170,24 +461,32 @@ pub async fn update_model(
     .bind(new_is_active)
     .bind(new_input_coeff)
     .bind(new_output_coeff)
+    .bind(new_input_price)
+    .bind(new_output_price)
+    .bind(new_weight)
+    .bind(new_reasoning_coeff)
+    .bind(&new_fallback_response)
+    .bind(new_default_max_tokens)
+    .bind(new_cached_coeff)
+    .bind(new_prompt_caching)
+    .bind(new_hedge_after_ms)
     .bind(id)
     .execute(db)
-    .await?;
+    .await
+    .map_err(|e| duplicate_name_error(e, &new_name))?;
 
-    // Remove old name from Redis if name changed
-    if new_name != existing.name {
-        let _: () = redis.hdel(REDIS_MODEL_ROUTES_HASH, &existing.name).await?;
+    // Remove old name from Redis if name changed or the model was deactivated
+    if new_name != existing.name || !new_is_active {
+        let _: () = redis.del(route_cache_key(org_id, &existing.name)).await?;
     }
 
     // Rebuild the full cache to keep everything consistent
-    warm_up_model_routes(db, redis).await?;
+    warm_up_model_routes(route_ttl_secs, db, redis).await?;
 
     // Fetch updated row with provider name
     let row = sqlx::query_as::<_, ModelWithProvider>(
         r#"
-        SELECT m.id, m.name, m.provider_id, m.provider_model_name, m.is_active,
-               m.input_token_coefficient, m.output_token_coefficient,
-               m.created_at, m.updated_at, p.name AS provider_name
+        SELECT m.*, p.name AS provider_name
         FROM models m
         JOIN providers p ON m.provider_id = p.id
         WHERE m.id = $1
@@ -197,178 +496,783 @@ pub async fn update_model(
     .fetch_one(db)
     .await?;
 
-    Ok(ModelInfo {
-        id: row.id,
-        name: row.name,
-        provider_id: row.provider_id,
-        provider_name: Some(row.provider_name),
-        provider_model_name: row.provider_model_name,
-        is_active: row.is_active,
-        input_token_coefficient: row.input_token_coefficient,
-        output_token_coefficient: row.output_token_coefficient,
-        created_at: row.created_at,
-        updated_at: row.updated_at,
-    })
+    Ok(ModelInfo::from(row))
 }
 
-/// Resolve a user-facing model name to its routing information.
-/// Fast path: Redis hash lookup. Slow path: PG query + backfill Redis.
+/// Resolve a user-facing model name within an organization to its routing information.
+/// Fast path: Redis key lookup. Slow path: PG query + backfill Redis with `route_ttl_secs`.
 pub async fn resolve_model_route(
+    org_id: Uuid,
     model_name: &str,
+    route_ttl_secs: u64,
     redis: &mut ConnectionManager,
     db: &PgPool,
 ) -> Result<Option<ModelRoute>, AppError> {
     // Fast path: check Redis
-    let cached: Option<String> = redis.hget(REDIS_MODEL_ROUTES_HASH, model_name).await?;
+    let (cached, inactive_providers): (Option<String>, Vec<String>) = redis::pipe()
+        .get(route_cache_key(org_id, model_name))
+        .smembers(redis_keys::inactive_providers())
+        .query_async(redis)
+        .await?;
     if let Some(json_str) = cached {
         if let Ok(route) = serde_json::from_str::<ModelRoute>(&json_str) {
-            return Ok(Some(route));
+            // A stale entry may still point at a provider deactivated since it was cached
+            if !uses_inactive_provider(&route, &inactive_providers) {
+                return Ok(Some(route));
+            }
+            tracing::debug!("Cached route for {} uses an inactive provider", model_name);
         }
     }
 
     // Slow path: query PG
-    let row = sqlx::query_as::<_, ModelWithProviderFull>(
-        r#"
-        SELECT m.name AS model_name, m.provider_model_name, m.provider_id,
-               m.input_token_coefficient, m.output_token_coefficient,
-               p.base_url, p.api_key, p.kind AS provider_kind
-        FROM models m
-        JOIN providers p ON m.provider_id = p.id
-        WHERE m.name = $1 AND m.is_active = TRUE AND p.is_active = TRUE
-        "#,
-    )
-    .bind(model_name)
-    .fetch_optional(db)
-    .await?;
-
-    match row {
-        Some(r) => {
-            let route = ModelRoute {
-                provider_id: r.provider_id,
-                provider_model_name: r
-                    .provider_model_name
-                    .unwrap_or_else(|| r.model_name.clone()),
-                base_url: r.base_url,
-                api_key: r.api_key,
-                provider_kind: r.provider_kind,
-                input_token_coefficient: r.input_token_coefficient,
-                output_token_coefficient: r.output_token_coefficient,
-            };
+    let route = load_routes(Some((org_id, model_name)), db).await?.pop();
 
+    match route {
+        Some((_, _, route)) => {
             // Backfill Redis
-            if let Ok(json_str) = serde_json::to_string(&route) {
-                let _: Result<(), _> = redis
-                    .hset(REDIS_MODEL_ROUTES_HASH, model_name, &json_str)
-                    .await;
+            if let Err(e) = store_route(org_id, model_name, &route, route_ttl_secs, redis).await {
+                tracing::warn!("Failed to backfill route cache for {}: {}", model_name, e);
             }
 
             Ok(Some(route))
         }
-        None => Ok(None),
+        // Not registered: a passthrough provider may still serve it
+        None => resolve_passthrough_route(org_id, model_name, route_ttl_secs, redis, db).await,
+    }
+}
+
+/// A passthrough provider as cached under `redis_keys::passthrough_providers`: its
+/// route, with the provider-side model name filled in per request.
+#[derive(Debug, Serialize, Deserialize)]
+struct PassthroughTarget {
+    prefix: Option<String>,
+    normalize_model_case: String,
+    route: ModelRoute,
+}
+
+/// Route an unregistered model name to one of the organization's passthrough
+/// providers. The provider list is cached in Redis for `route_ttl_secs`, an empty
+/// one included, so unknown names do not query PG on every request.
+async fn resolve_passthrough_route(
+    org_id: Uuid,
+    model_name: &str,
+    route_ttl_secs: u64,
+    redis: &mut ConnectionManager,
+    db: &PgPool,
+) -> Result<Option<ModelRoute>, AppError> {
+    let key = redis_keys::passthrough_providers(org_id);
+    let cached: Option<String> = redis.get(&key).await?;
+    let targets = match cached.and_then(|json| serde_json::from_str(&json).ok()) {
+        Some(targets) => targets,
+        None => {
+            let targets = load_passthrough_targets(org_id, db).await?;
+            if let Err(e) = store_json(key, &targets, route_ttl_secs, redis).await {
+                tracing::warn!("Failed to cache passthrough providers of {}: {}", org_id, e);
+            }
+            targets
+        }
+    };
+    Ok(match_passthrough(targets, model_name))
+}
+
+/// The passthrough target whose `prefix` the model name starts with (longest prefix
+/// wins, the unprefixed provider catches the rest). The name, minus the prefix, is
+/// sent as-is with default coefficients and no prices.
+fn match_passthrough(targets: Vec<PassthroughTarget>, model_name: &str) -> Option<ModelRoute> {
+    let (_, name, target) = targets
+        .into_iter()
+        .filter_map(|t| {
+            let name = model_name.strip_prefix(t.prefix.as_deref().unwrap_or(""))?;
+            let prefix_len = t.prefix.as_ref().map_or(0, String::len);
+            (!name.is_empty()).then(|| (prefix_len, name.to_string(), t))
+        })
+        .max_by_key(|(prefix_len, _, _)| *prefix_len)?;
+
+    Some(ModelRoute {
+        provider_model_name: ModelNameCase::from_str(&target.normalize_model_case)
+            .unwrap_or(ModelNameCase::None)
+            .apply(&name),
+        ..target.route
+    })
+}
+
+/// The organization's active passthrough providers as cacheable targets.
+async fn load_passthrough_targets(
+    org_id: Uuid,
+    db: &PgPool,
+) -> Result<Vec<PassthroughTarget>, AppError> {
+    let providers = sqlx::query_as::<_, Provider>(
+        "SELECT * FROM providers WHERE org_id = $1 AND passthrough = TRUE AND is_active = TRUE",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+    Ok(providers.into_iter().map(passthrough_target).collect())
+}
+
+fn passthrough_target(p: Provider) -> PassthroughTarget {
+    let route = ModelRoute {
+        provider_id: p.id,
+        provider_model_name: String::new(),
+        base_url: p.base_url,
+        api_key: p.api_key,
+        provider_kind: p.kind,
+        input_token_coefficient: 1.0,
+        output_token_coefficient: 1.0,
+        reasoning_token_coefficient: None,
+        cached_token_coefficient: None,
+        input_price_per_mtok: None,
+        output_price_per_mtok: None,
+        force_buffered_upstream: p.force_buffered_upstream,
+        supports_stream_usage: p.supports_stream_usage,
+        weight: 1,
+        retry_on_status: p.retry_on_status,
+        fallback_response: None,
+        default_max_tokens: None,
+        prompt_caching: false,
+        hedge_after_ms: None,
+        provider_region: p.region,
+        chat_path: p.chat_path,
+        alternates: Vec::new(),
+    };
+    PassthroughTarget {
+        prefix: p.passthrough_prefix,
+        normalize_model_case: p.normalize_model_case,
+        route,
     }
 }
 
-/// Warm up Redis with all active model routes (call on startup).
+/// Drop an organization's cached passthrough providers, e.g. after one was added.
+pub async fn invalidate_passthrough_providers(
+    org_id: Uuid,
+    redis: &mut ConnectionManager,
+) -> Result<(), AppError> {
+    let _: () = redis.del(redis_keys::passthrough_providers(org_id)).await?;
+    Ok(())
+}
+
+/// Whether the route or any of its alternates goes through one of `inactive_providers`.
+fn uses_inactive_provider(route: &ModelRoute, inactive_providers: &[String]) -> bool {
+    std::iter::once(route)
+        .chain(&route.alternates)
+        .any(|r| inactive_providers.contains(&r.provider_id.to_string()))
+}
+
+/// Stop serving cached routes through a provider right away, ahead of the cache
+/// rebuild (which also refreshes the set from PG).
+pub async fn mark_provider_inactive(
+    provider_id: Uuid,
+    redis: &mut ConnectionManager,
+) -> Result<(), AppError> {
+    let _: () = redis
+        .sadd(redis_keys::inactive_providers(), provider_id.to_string())
+        .await?;
+    Ok(())
+}
+
+/// Drop the cached route for a single model so the next request re-resolves from PG.
+/// Returns whether an entry was actually removed.
+pub async fn invalidate_model_route(
+    org_id: Uuid,
+    model_name: &str,
+    redis: &mut ConnectionManager,
+) -> Result<bool, AppError> {
+    let removed: i64 = redis.del(route_cache_key(org_id, model_name)).await?;
+    Ok(removed > 0)
+}
+
+/// One cached route entry as stored in Redis, with provider API keys masked.
+#[derive(Debug, Serialize)]
+pub struct CachedRoute {
+    pub model: String,
+    /// Seconds until expiry; -1 = no expiry.
+    pub ttl_secs: i64,
+    /// None when the cached value no longer parses as a route.
+    pub route: Option<ModelRoute>,
+}
+
+/// Cached model routes, for cache debugging.
+#[derive(Debug, Serialize)]
+pub struct CachedRoutes {
+    /// True when more route keys exist than were returned.
+    pub truncated: bool,
+    pub routes: Vec<CachedRoute>,
+}
+
+/// Hide a provider API key, keeping the last 4 characters for identification.
+pub fn mask_api_key(api_key: &str) -> String {
+    let len = api_key.chars().count();
+    if len > 8 {
+        let tail: String = api_key.chars().skip(len - 4).collect();
+        format!("****{tail}")
+    } else {
+        "****".to_string()
+    }
+}
+
+/// Whether `api_key` is the output of `mask_api_key` rather than a real key.
+pub fn is_masked_api_key(api_key: &str) -> bool {
+    api_key.starts_with("****")
+}
+
+/// Up to `limit` of an organization's cached routes read straight from Redis,
+/// with API keys masked.
+pub async fn cached_routes(
+    org_id: Uuid,
+    limit: usize,
+    redis: &mut ConnectionManager,
+) -> Result<CachedRoutes, AppError> {
+    let org_prefix = route_cache_key(org_id, "");
+    let mut keys: Vec<String> = Vec::new();
+    let mut truncated = false;
+    {
+        let mut iter: redis::AsyncIter<String> =
+            redis.scan_match(format!("{org_prefix}*")).await?;
+        while let Some(key) = iter.next_item().await {
+            if keys.len() == limit {
+                truncated = true;
+                break;
+            }
+            keys.push(key);
+        }
+    }
+    keys.sort();
+
+    let mut routes = Vec::with_capacity(keys.len());
+    for key in keys {
+        let value: Option<String> = redis.get(&key).await?;
+        let ttl_secs: i64 = redis.ttl(&key).await?;
+        let route = value
+            .and_then(|v| serde_json::from_str::<ModelRoute>(&v).ok())
+            .map(|mut route| {
+                route.api_key = mask_api_key(&route.api_key);
+                for alternate in &mut route.alternates {
+                    alternate.api_key = mask_api_key(&alternate.api_key);
+                }
+                route
+            });
+        routes.push(CachedRoute {
+            model: key[org_prefix.len()..].to_string(),
+            ttl_secs,
+            route,
+        });
+    }
+
+    Ok(CachedRoutes { truncated, routes })
+}
+
+/// Warm up Redis with all active model routes (call on startup and on each refresh).
+/// Entries for models that are no longer active are removed.
 pub async fn warm_up_model_routes(
+    route_ttl_secs: u64,
+    db: &PgPool,
+    redis: &mut ConnectionManager,
+) -> Result<(), AppError> {
+    let routes = load_routes(None, db).await?;
+
+    // Replace the inactive provider set first so it never lags behind PG
+    let inactive: Vec<Uuid> =
+        sqlx::query_scalar("SELECT id FROM providers WHERE is_active = FALSE")
+            .fetch_all(db)
+            .await?;
+    let mut pipe = redis::pipe();
+    pipe.atomic().del(redis_keys::inactive_providers()).ignore();
+    if !inactive.is_empty() {
+        let ids: Vec<String> = inactive.iter().map(Uuid::to_string).collect();
+        pipe.sadd(redis_keys::inactive_providers(), ids).ignore();
+    }
+    let _: () = pipe.query_async(redis).await?;
+
+    // Passthrough provider lists are rebuilt on demand
+    let passthrough_keys =
+        scan_keys(redis_keys::all_passthrough_providers_pattern(), redis).await?;
+    if !passthrough_keys.is_empty() {
+        let _: () = redis.del(passthrough_keys).await?;
+    }
+
+    // Collect currently cached route keys so stale ones can be dropped
+    let existing_keys = scan_keys(redis_keys::all_routes_pattern(), redis).await?;
+
+    // Clear stale cache before storing, so deactivated models drop out even if a
+    // store below fails
+    let fresh_keys: std::collections::HashSet<String> = routes
+        .iter()
+        .map(|(org_id, model_name, _)| route_cache_key(*org_id, model_name))
+        .collect();
+    let stale: Vec<&String> = existing_keys
+        .iter()
+        .filter(|k| !fresh_keys.contains(*k))
+        .collect();
+    if !stale.is_empty() {
+        let _: () = redis.del(stale).await?;
+    }
+
+    for (org_id, model_name, route) in &routes {
+        store_route(*org_id, model_name, route, route_ttl_secs, redis).await?;
+    }
+    let _: () = redis.del(redis_keys::legacy_model_routes()).await?;
+
+    tracing::info!("Warmed up Redis with {} model routes", routes.len());
+    Ok(())
+}
+
+/// Every Redis key matching `pattern`.
+async fn scan_keys(
+    pattern: String,
+    redis: &mut ConnectionManager,
+) -> Result<Vec<String>, AppError> {
+    let mut iter: redis::AsyncIter<String> = redis.scan_match(pattern).await?;
+    let mut keys = Vec::new();
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// Load resolvable routes keyed by organization and model name, with alternates attached.
+/// `model` = None loads every active model of every organization.
+async fn load_routes(
+    model: Option<(Uuid, &str)>,
+    db: &PgPool,
+) -> Result<Vec<(Uuid, String, ModelRoute)>, AppError> {
+    let (org_id, model_name) = model.unzip();
+
+    let primaries = sqlx::query_as::<_, ModelWithProviderFull>(&format!(
+        "{ROUTE_SELECT} WHERE m.is_active = TRUE AND p.is_active = TRUE
+         AND ($1::UUID IS NULL OR (m.org_id = $1 AND m.name = $2))"
+    ))
+    .bind(org_id)
+    .bind(model_name)
+    .fetch_all(db)
+    .await?;
+
+    let alternates = sqlx::query_as::<_, ModelWithProviderFull>(&format!(
+        "{ALTERNATE_SELECT} AND m.is_active = TRUE
+         AND ($1::UUID IS NULL OR (m.org_id = $1 AND m.name = $2))
+         ORDER BY a.created_at"
+    ))
+    .bind(org_id)
+    .bind(model_name)
+    .fetch_all(db)
+    .await?;
+
+    let mut by_model: std::collections::HashMap<(Uuid, String), Vec<ModelRoute>> =
+        std::collections::HashMap::new();
+    for a in &alternates {
+        by_model
+            .entry((a.org_id, a.model_name.clone()))
+            .or_default()
+            .push(a.to_route());
+    }
+
+    Ok(primaries
+        .iter()
+        .map(|r| {
+            let mut route = r.to_route();
+            route.alternates = by_model
+                .remove(&(r.org_id, r.model_name.clone()))
+                .unwrap_or_default();
+            (r.org_id, r.model_name.clone(), route)
+        })
+        .collect())
+}
+
+/// Re-cache (or drop, if no longer resolvable) the route for one model.
+async fn refresh_model_route(
+    org_id: Uuid,
+    model_name: &str,
+    route_ttl_secs: u64,
     db: &PgPool,
     redis: &mut ConnectionManager,
 ) -> Result<(), AppError> {
-    let rows = sqlx::query_as::<_, ModelWithProviderFull>(
+    match load_routes(Some((org_id, model_name)), db).await?.pop() {
+        Some((_, _, route)) => store_route(org_id, model_name, &route, route_ttl_secs, redis).await,
+        None => {
+            let _: () = redis.del(route_cache_key(org_id, model_name)).await?;
+            Ok(())
+        }
+    }
+}
+
+// ── Alternate routes ──────────────────────────────────────────────────
+
+/// List the alternate provider routes of a model.
+pub async fn list_alternate_routes(
+    model_id: Uuid,
+    org_id: Uuid,
+    db: &PgPool,
+) -> Result<Vec<AlternateRouteInfo>, AppError> {
+    get_model(model_id, org_id, db).await?;
+
+    let routes = sqlx::query_as::<_, AlternateRouteInfo>(
         r#"
-        SELECT m.name AS model_name, m.provider_model_name, m.provider_id,
-               m.input_token_coefficient, m.output_token_coefficient,
-               p.base_url, p.api_key, p.kind AS provider_kind
-        FROM models m
-        JOIN providers p ON m.provider_id = p.id
-        WHERE m.is_active = TRUE AND p.is_active = TRUE
+        SELECT a.id, a.model_id, a.provider_id, p.name AS provider_name,
+               a.provider_model_name, a.weight, a.created_at
+        FROM model_alternate_routes a
+        JOIN providers p ON a.provider_id = p.id
+        WHERE a.model_id = $1
+        ORDER BY a.created_at
         "#,
     )
+    .bind(model_id)
     .fetch_all(db)
     .await?;
 
-    // Clear stale cache
-    let _: () = redis::cmd("DEL")
-        .arg(REDIS_MODEL_ROUTES_HASH)
-        .query_async(redis)
-        .await?;
+    Ok(routes)
+}
 
-    for r in &rows {
-        let route = ModelRoute {
-            provider_id: r.provider_id,
-            provider_model_name: r
-                .provider_model_name
-                .clone()
-                .unwrap_or_else(|| r.model_name.clone()),
-            base_url: r.base_url.clone(),
-            api_key: r.api_key.clone(),
-            provider_kind: r.provider_kind.clone(),
-            input_token_coefficient: r.input_token_coefficient,
-            output_token_coefficient: r.output_token_coefficient,
-        };
+/// Add an alternate provider route to a model and refresh its cached route.
+pub async fn add_alternate_route(
+    model_id: Uuid,
+    org_id: Uuid,
+    new: NewAlternateRoute,
+    route_ttl_secs: u64,
+    db: &PgPool,
+    redis: &mut ConnectionManager,
+) -> Result<AlternateRouteInfo, AppError> {
+    let model = get_model(model_id, org_id, db).await?;
 
-        if let Ok(json_str) = serde_json::to_string(&route) {
-            let _: Result<(), _> = redis
-                .hset(REDIS_MODEL_ROUTES_HASH, &r.model_name, &json_str)
-                .await;
-        }
+    let provider_id = new.provider_id;
+    let provider = provider_service::get_provider(provider_id, org_id, db)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(format!("Provider {provider_id} not found")))?;
+
+    if provider_id == model.provider_id {
+        return Err(AppError::BadRequest(
+            "Provider is already the model's primary provider".into(),
+        ));
     }
 
-    tracing::info!("Warmed up Redis with {} model routes", rows.len());
-    Ok(())
+    let id = Uuid::new_v4();
+    let now = Utc::now();
+
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO model_alternate_routes (id, model_id, provider_id, provider_model_name,
+                                            weight, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (model_id, provider_id) DO NOTHING
+        "#,
+    )
+    .bind(id)
+    .bind(model_id)
+    .bind(provider_id)
+    .bind(&new.provider_model_name)
+    .bind(new.weight)
+    .bind(now)
+    .execute(db)
+    .await?;
+
+    if inserted.rows_affected() == 0 {
+        return Err(AppError::BadRequest(format!(
+            "Model already has an alternate route to provider {provider_id}"
+        )));
+    }
+
+    refresh_model_route(org_id, &model.name, route_ttl_secs, db, redis).await?;
+
+    Ok(AlternateRouteInfo {
+        id,
+        model_id,
+        provider_id,
+        provider_name: provider.name,
+        provider_model_name: new.provider_model_name,
+        weight: new.weight,
+        created_at: now,
+    })
+}
+
+/// Remove an alternate route from a model and refresh its cached route.
+pub async fn delete_alternate_route(
+    model_id: Uuid,
+    route_id: Uuid,
+    org_id: Uuid,
+    route_ttl_secs: u64,
+    db: &PgPool,
+    redis: &mut ConnectionManager,
+) -> Result<(), AppError> {
+    let model_name = sqlx::query_scalar::<_, String>(
+        r#"
+        DELETE FROM model_alternate_routes a
+        USING models m
+        WHERE a.id = $1 AND a.model_id = $2 AND m.id = a.model_id AND m.org_id = $3
+        RETURNING m.name
+        "#,
+    )
+    .bind(route_id)
+    .bind(model_id)
+    .bind(org_id)
+    .fetch_optional(db)
+    .await?
+    .ok_or(AppError::NotFound)?;
+
+    refresh_model_route(org_id, &model_name, route_ttl_secs, db, redis).await
 }
 
 // ── Internal query types ──────────────────────────────────────────────
 
 #[derive(Debug, sqlx::FromRow)]
 struct ModelWithProvider {
-    id: Uuid,
-    name: String,
-    provider_id: Uuid,
-    provider_model_name: Option<String>,
-    is_active: bool,
-    input_token_coefficient: f64,
-    output_token_coefficient: f64,
-    created_at: chrono::DateTime<chrono::Utc>,
-    updated_at: chrono::DateTime<chrono::Utc>,
+    #[sqlx(flatten)]
+    model: Model,
     provider_name: String,
 }
 
+impl From<ModelWithProvider> for ModelInfo {
+    fn from(r: ModelWithProvider) -> Self {
+        let m = r.model;
+        Self {
+            id: m.id,
+            name: m.name,
+            provider_id: m.provider_id,
+            provider_name: Some(r.provider_name),
+            provider_model_name: m.provider_model_name,
+            is_active: m.is_active,
+            input_token_coefficient: m.input_token_coefficient,
+            output_token_coefficient: m.output_token_coefficient,
+            reasoning_token_coefficient: m.reasoning_token_coefficient,
+            cached_token_coefficient: m.cached_token_coefficient,
+            input_price_per_mtok: m.input_price_per_mtok,
+            output_price_per_mtok: m.output_price_per_mtok,
+            weight: m.weight,
+            fallback_response: m.fallback_response,
+            default_max_tokens: m.default_max_tokens,
+            prompt_caching: m.prompt_caching,
+            hedge_after_ms: m.hedge_after_ms,
+            created_at: m.created_at,
+            updated_at: m.updated_at,
+        }
+    }
+}
+
 #[derive(Debug, sqlx::FromRow)]
 struct ModelWithProviderFull {
+    org_id: Uuid,
     model_name: String,
     provider_model_name: Option<String>,
     provider_id: Uuid,
     input_token_coefficient: f64,
     output_token_coefficient: f64,
+    reasoning_token_coefficient: Option<f64>,
+    cached_token_coefficient: Option<f64>,
+    input_price_per_mtok: Option<f64>,
+    output_price_per_mtok: Option<f64>,
     base_url: String,
     api_key: String,
     provider_kind: String,
+    force_buffered_upstream: bool,
+    supports_stream_usage: bool,
+    retry_on_status: Option<Vec<i32>>,
+    weight: i32,
+    fallback_response: Option<serde_json::Value>,
+    default_max_tokens: Option<i32>,
+    prompt_caching: bool,
+    hedge_after_ms: Option<i32>,
+    normalize_model_case: String,
+    provider_region: Option<String>,
+    chat_path: Option<String>,
 }
 
-/// Cache a single model route into Redis.
-async fn cache_model_route(
-    model_name: &str,
-    provider_model_name: Option<&str>,
-    input_token_coefficient: f64,
-    output_token_coefficient: f64,
-    provider: &Provider,
-    redis: &mut ConnectionManager,
-) -> Result<(), AppError> {
-    let route = ModelRoute {
-        provider_id: provider.id,
-        provider_model_name: provider_model_name
-            .unwrap_or(model_name)
-            .to_string(),
-        base_url: provider.base_url.clone(),
-        api_key: provider.api_key.clone(),
-        provider_kind: provider.kind.clone(),
-        input_token_coefficient,
-        output_token_coefficient,
-    };
+impl ModelWithProviderFull {
+    fn to_route(&self) -> ModelRoute {
+        ModelRoute {
+            provider_id: self.provider_id,
+            provider_model_name: ModelNameCase::from_str(&self.normalize_model_case)
+                .unwrap_or(ModelNameCase::None)
+                .apply(self.provider_model_name.as_deref().unwrap_or(&self.model_name)),
+            base_url: self.base_url.clone(),
+            api_key: self.api_key.clone(),
+            provider_kind: self.provider_kind.clone(),
+            input_token_coefficient: self.input_token_coefficient,
+            output_token_coefficient: self.output_token_coefficient,
+            reasoning_token_coefficient: self.reasoning_token_coefficient,
+            cached_token_coefficient: self.cached_token_coefficient,
+            input_price_per_mtok: self.input_price_per_mtok,
+            output_price_per_mtok: self.output_price_per_mtok,
+            force_buffered_upstream: self.force_buffered_upstream,
+            supports_stream_usage: self.supports_stream_usage,
+            weight: self.weight,
+            retry_on_status: self.retry_on_status.clone(),
+            fallback_response: self.fallback_response.clone(),
+            default_max_tokens: self.default_max_tokens,
+            prompt_caching: self.prompt_caching,
+            hedge_after_ms: self.hedge_after_ms,
+            provider_region: self.provider_region.clone(),
+            chat_path: self.chat_path.clone(),
+            alternates: Vec::new(),
+        }
+    }
+}
 
-    let json_str = serde_json::to_string(&route)
-        .map_err(|e| AppError::Internal(format!("JSON serialization error: {e}")))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let _: () = redis.hset(REDIS_MODEL_ROUTES_HASH, model_name, &json_str).await?;
-    Ok(())
+    #[sqlx::test]
+    async fn duplicate_model_name_is_a_409(db: PgPool) {
+        let provider_id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO providers (id, name, kind, base_url, api_key)
+             VALUES ($1, 'openai', 'openai', 'https://api.openai.com/v1', 'sk-test')",
+        )
+        .bind(provider_id)
+        .execute(&db)
+        .await
+        .unwrap();
+        let insert = || {
+            sqlx::query(
+                "INSERT INTO models (id, name, provider_id, provider_model_name)
+                 VALUES ($1, 'gpt-4o', $2, 'gpt-4o')",
+            )
+            .bind(Uuid::new_v4())
+            .bind(provider_id)
+            .execute(&db)
+        };
+        insert().await.unwrap();
+
+        let err = duplicate_name_error(insert().await.unwrap_err(), "gpt-4o");
+        assert!(matches!(&err, AppError::Conflict(msg) if msg.contains("\"gpt-4o\" already exists")));
+        let response = axum::response::IntoResponse::into_response(err);
+        assert_eq!(response.status(), axum::http::StatusCode::CONFLICT);
+    }
+    #[sqlx::test]
+    async fn model_case_normalization_only_changes_the_name_sent(db: PgPool) {
+        let provider_id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO providers (id, name, kind, base_url, api_key, normalize_model_case)
+             VALUES ($1, 'dashscope', 'dashscope', 'https://dashscope.example.com/v1', 'sk-test',
+                     'lower')",
+        )
+        .bind(provider_id)
+        .execute(&db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO models (id, name, provider_id, provider_model_name)
+             VALUES ($1, 'Qwen-Max', $2, 'Qwen-MAX')",
+        )
+        .bind(Uuid::new_v4())
+        .bind(provider_id)
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let (_, model_name, route) = load_routes(Some((Uuid::nil(), "Qwen-Max")), &db)
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(model_name, "Qwen-Max");
+        assert_eq!(route.provider_model_name, "qwen-max");
+    }
+
+    fn cached_route(provider_id: Uuid, alternate_ids: &[Uuid]) -> ModelRoute {
+        let route = |id: &Uuid| {
+            serde_json::json!({
+                "provider_id": id,
+                "provider_model_name": "qwen-max",
+                "base_url": "https://api.example.com/v1",
+                "api_key": "sk-test",
+                "provider_kind": "openai",
+                "input_token_coefficient": 1.0,
+                "output_token_coefficient": 1.0,
+            })
+        };
+        let mut json = route(&provider_id);
+        json["alternates"] = alternate_ids.iter().map(route).collect();
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn cached_route_through_an_inactive_provider_is_a_miss() {
+        let (primary, alternate) = (Uuid::new_v4(), Uuid::new_v4());
+        let route = cached_route(primary, &[alternate]);
+
+        assert!(!uses_inactive_provider(&route, &[]));
+        assert!(!uses_inactive_provider(&route, &[Uuid::new_v4().to_string()]));
+        assert!(uses_inactive_provider(&route, &[primary.to_string()]));
+        // Deactivating a fallback provider also invalidates the cached entry
+        assert!(uses_inactive_provider(&route, &[alternate.to_string()]));
+    }
+
+    fn provider(name: &str, prefix: Option<&str>, case: ModelNameCase) -> Provider {
+        Provider {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            kind: "openai_compatible".into(),
+            base_url: format!("https://{name}.example.com/v1"),
+            api_key: "sk-test".into(),
+            is_active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            force_buffered_upstream: false,
+            supports_stream_usage: true,
+            retry_on_status: None,
+            normalize_model_case: case.as_str().into(),
+            region: None,
+            chat_path: None,
+            passthrough: true,
+            passthrough_prefix: prefix.map(Into::into),
+        }
+    }
+
+    /// Targets as read back from the Redis cache.
+    fn cached_targets(providers: Vec<Provider>) -> Vec<PassthroughTarget> {
+        let targets: Vec<_> = providers.into_iter().map(passthrough_target).collect();
+        serde_json::from_str(&serde_json::to_string(&targets).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn longest_passthrough_prefix_wins_and_is_stripped() {
+        let providers = vec![
+            provider("any", None, ModelNameCase::None),
+            provider("or", Some("or/"), ModelNameCase::None),
+            provider("or-anthropic", Some("or/anthropic/"), ModelNameCase::Upper),
+        ];
+        let base_url = |route: &ModelRoute| route.base_url.clone();
+
+        let route =
+            match_passthrough(cached_targets(providers.clone()), "or/anthropic/claude").unwrap();
+        assert_eq!(route.provider_model_name, "CLAUDE");
+        assert_eq!(base_url(&route), providers[2].base_url);
+
+        let route = match_passthrough(cached_targets(providers.clone()), "or/qwen").unwrap();
+        assert_eq!(route.provider_model_name, "qwen");
+        assert_eq!(base_url(&route), providers[1].base_url);
+
+        let route = match_passthrough(cached_targets(providers.clone()), "mistral-large").unwrap();
+        assert_eq!(route.provider_model_name, "mistral-large");
+        assert_eq!(base_url(&route), providers[0].base_url);
+    }
+
+    #[test]
+    fn no_passthrough_match_without_a_name_after_the_prefix() {
+        let providers = vec![provider("or", Some("or/"), ModelNameCase::None)];
+        assert!(match_passthrough(cached_targets(providers.clone()), "or/").is_none());
+        assert!(match_passthrough(cached_targets(providers), "gpt-4o").is_none());
+        assert!(match_passthrough(cached_targets(Vec::new()), "gpt-4o").is_none());
+    }
+
+    #[sqlx::test]
+    async fn only_active_passthrough_providers_are_targets(db: PgPool) {
+        let insert = |name: &'static str, passthrough: bool, active: bool| {
+            sqlx::query(
+                "INSERT INTO providers (id, name, kind, base_url, api_key, is_active,
+                                        passthrough, passthrough_prefix)
+                 VALUES ($1, $2, 'openai', 'https://api.example.com/v1', 'sk-test', $3, $4, $2)",
+            )
+            .bind(Uuid::new_v4())
+            .bind(name)
+            .bind(active)
+            .bind(passthrough)
+            .execute(&db)
+        };
+        insert("registered-only/", false, true).await.unwrap();
+        insert("inactive/", true, false).await.unwrap();
+        insert("passthrough/", true, true).await.unwrap();
+
+        let targets = load_passthrough_targets(Uuid::nil(), &db).await.unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].prefix.as_deref(), Some("passthrough/"));
+    }
 }
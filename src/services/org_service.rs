@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// Organization owning all pre-existing data; `ADMIN_KEY` and scoped admin keys act in it.
+pub const DEFAULT_ORG_ID: Uuid = Uuid::nil();
+
+/// Make sure an organization exists for every name, creating missing ones.
+/// Returns organization ids keyed by name (call on startup).
+pub async fn ensure_orgs(names: &[&str], db: &PgPool) -> Result<HashMap<String, Uuid>, AppError> {
+    let mut ids = HashMap::new();
+    for &name in names {
+        let id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO organizations (id, name) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+            RETURNING id
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(name)
+        .fetch_one(db)
+        .await?;
+        ids.insert(name.to_string(), id);
+    }
+    Ok(ids)
+}
@@ -1,39 +1,226 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use sqlx::PgPool;
 use uuid::Uuid;
 
 use crate::error::AppError;
-use crate::models::provider::{Provider, ProviderInfo, ProviderKind};
+use crate::models::provider::{ModelNameCase, Provider, ProviderInfo, ProviderKind};
 
-/// Create a new provider.
+/// Parameters for creating a provider.
+pub struct NewProvider {
+    pub org_id: Uuid,
+    pub name: String,
+    pub kind: String,
+    /// Defaults based on kind when None.
+    pub base_url: Option<String>,
+    pub api_key: String,
+    pub force_buffered_upstream: bool,
+    pub supports_stream_usage: bool,
+    /// None = default failover set (429 and 5xx).
+    pub retry_on_status: Option<Vec<i32>>,
+    pub normalize_model_case: ModelNameCase,
+    /// Region tag (lowercase). None = no region.
+    pub region: Option<String>,
+    /// Chat completions path. None = `/chat/completions`.
+    pub chat_path: Option<String>,
+    /// Forward unregistered model names to this provider.
+    pub passthrough: bool,
+    /// Model name prefix selecting this passthrough provider. None = catch-all.
+    pub passthrough_prefix: Option<String>,
+}
+
+/// Partial update for a provider. `None` keeps the current value.
+pub struct ProviderUpdate {
+    pub name: Option<String>,
+    pub kind: Option<String>,
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    pub is_active: Option<bool>,
+    pub force_buffered_upstream: Option<bool>,
+    pub supports_stream_usage: Option<bool>,
+    pub retry_on_status: Option<Option<Vec<i32>>>,
+    pub normalize_model_case: Option<ModelNameCase>,
+    pub region: Option<Option<String>>,
+    pub chat_path: Option<Option<String>>,
+    pub passthrough: Option<bool>,
+    pub passthrough_prefix: Option<Option<String>>,
+}
+
+/// Reject base URLs that would send the provider API key in cleartext.
+/// `http://` is accepted only when `allow_insecure` is set.
+pub fn check_base_url(provider: &str, base_url: &str, allow_insecure: bool) -> Result<(), AppError> {
+    let scheme = base_url
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+    match scheme.as_deref() {
+        Some("https") => Ok(()),
+        Some("http") if allow_insecure => Ok(()),
+        Some("http") => Err(AppError::BadRequest(format!(
+            "Provider '{provider}': base_url {base_url} is not HTTPS; \
+             set ALLOW_INSECURE_PROVIDER_URLS=true to allow http:// (development only)"
+        ))),
+        _ => Err(AppError::BadRequest(format!(
+            "Provider '{provider}': base_url {base_url} must start with https://"
+        ))),
+    }
+}
+
+/// A provider of the organization, or None if it does not exist there.
+pub async fn get_provider(
+    id: Uuid,
+    org_id: Uuid,
+    db: &PgPool,
+) -> Result<Option<Provider>, AppError> {
+    let provider =
+        sqlx::query_as::<_, Provider>("SELECT * FROM providers WHERE id = $1 AND org_id = $2")
+            .bind(id)
+            .bind(org_id)
+            .fetch_optional(db)
+            .await?;
+    Ok(provider)
+}
+
+/// A provider with how many models route through it and its recent traffic.
+#[derive(Debug, Serialize)]
+pub struct ProviderDetail {
+    #[serde(flatten)]
+    pub provider: ProviderInfo,
+    /// Models using it as their primary provider
+    pub model_count: i64,
+    /// Models using it as an alternate route
+    pub alternate_route_count: i64,
+    pub requests_24h: i64,
+    pub errors_24h: i64,
+    pub avg_latency_24h: f64,
+    pub last_request_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ProviderStatsRow {
+    model_count: i64,
+    alternate_route_count: i64,
+    requests_24h: i64,
+    errors_24h: i64,
+    avg_latency_24h: f64,
+    last_request_at: Option<DateTime<Utc>>,
+}
+
+/// A provider of the organization with its model counts and last-24h request stats.
+pub async fn get_provider_detail(
+    id: Uuid,
+    org_id: Uuid,
+    db: &PgPool,
+) -> Result<ProviderDetail, AppError> {
+    let provider = get_provider(id, org_id, db).await?.ok_or(AppError::NotFound)?;
+
+    let stats = sqlx::query_as::<_, ProviderStatsRow>(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM models WHERE provider_id = $1)::BIGINT AS model_count,
+            (SELECT COUNT(*) FROM model_alternate_routes WHERE provider_id = $1)::BIGINT
+                AS alternate_route_count,
+            COUNT(*) FILTER (WHERE r.created_at >= NOW() - INTERVAL '24 hours')::BIGINT
+                AS requests_24h,
+            COUNT(*) FILTER (WHERE r.created_at >= NOW() - INTERVAL '24 hours' AND r.is_error)::BIGINT
+                AS errors_24h,
+            COALESCE(AVG(r.latency_ms) FILTER (WHERE r.created_at >= NOW() - INTERVAL '24 hours'), 0)::FLOAT8
+                AS avg_latency_24h,
+            MAX(r.created_at) AS last_request_at
+        FROM request_logs r
+        WHERE r.provider_id = $1 AND r.org_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(org_id)
+    .fetch_one(db)
+    .await?;
+
+    Ok(ProviderDetail {
+        provider: ProviderInfo::from(provider),
+        model_count: stats.model_count,
+        alternate_route_count: stats.alternate_route_count,
+        requests_24h: stats.requests_24h,
+        errors_24h: stats.errors_24h,
+        avg_latency_24h: stats.avg_latency_24h,
+        last_request_at: stats.last_request_at,
+    })
+}
+
+/// Map a unique violation of the passthrough index to a 409 naming the prefix.
+pub fn passthrough_conflict(e: sqlx::Error, prefix: Option<&str>) -> AppError {
+    match &e {
+        sqlx::Error::Database(db_err)
+            if db_err.constraint() == Some("providers_passthrough_prefix_key") =>
+        {
+            AppError::Conflict(match prefix {
+                Some(prefix) => {
+                    format!("Another passthrough provider already uses the prefix \"{prefix}\"")
+                }
+                None => "Another passthrough provider already has no prefix; \
+                         set a passthrough_prefix to tell them apart"
+                    .to_string(),
+            })
+        }
+        _ => e.into(),
+    }
+}
+
+/// Timeout of the credential check run before creating a provider.
+const VALIDATION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Create a new provider. With `validate_with`, its credentials are checked against
+/// the provider first (`VALIDATE_PROVIDER_ON_CREATE`).
 pub async fn create_provider(
-    name: &str,
-    kind: &str,
-    base_url: Option<&str>,
-    api_key: &str,
+    new: NewProvider,
+    allow_insecure_urls: bool,
+    validate_with: Option<&reqwest::Client>,
     db: &PgPool,
 ) -> Result<ProviderInfo, AppError> {
+    let kind = &new.kind;
     let pk = ProviderKind::from_str(kind)
-        .ok_or_else(|| AppError::BadRequest(format!("Unknown provider kind: {kind}. Supported: openai, openrouter, dashscope, ark")))?;
+        .ok_or_else(|| AppError::BadRequest(format!("Unknown provider kind: {kind}. Supported: openai, openrouter, dashscope, ark, openai_compatible")))?;
 
-    let resolved_base_url = base_url.unwrap_or_else(|| pk.default_base_url());
+    let resolved_base_url = new
+        .base_url
+        .as_deref()
+        .or_else(|| pk.default_base_url())
+        .ok_or_else(|| {
+            AppError::BadRequest(format!("base_url is required for kind {}", pk.as_str()))
+        })?;
+    check_base_url(&new.name, resolved_base_url, allow_insecure_urls)?;
+    if let Some(http_client) = validate_with {
+        check_credentials(http_client, &new.name, resolved_base_url, &new.api_key).await?;
+    }
     let id = Uuid::new_v4();
     let now = Utc::now();
 
     sqlx::query(
         r#"
-        INSERT INTO providers (id, name, kind, base_url, api_key, is_active, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5, TRUE, $6, $6)
+        INSERT INTO providers (id, name, kind, base_url, api_key, is_active,
+                               force_buffered_upstream, supports_stream_usage, retry_on_status,
+                               normalize_model_case, region, chat_path, passthrough,
+                               passthrough_prefix, org_id, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, TRUE, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $15)
         "#,
     )
     .bind(id)
-    .bind(name)
+    .bind(&new.name)
     .bind(pk.as_str())
     .bind(resolved_base_url)
-    .bind(api_key)
+    .bind(&new.api_key)
+    .bind(new.force_buffered_upstream)
+    .bind(new.supports_stream_usage)
+    .bind(&new.retry_on_status)
+    .bind(new.normalize_model_case.as_str())
+    .bind(&new.region)
+    .bind(&new.chat_path)
+    .bind(new.passthrough)
+    .bind(&new.passthrough_prefix)
+    .bind(new.org_id)
     .bind(now)
     .execute(db)
-    .await?;
+    .await
+    .map_err(|e| passthrough_conflict(e, new.passthrough_prefix.as_deref()))?;
 
     let provider = sqlx::query_as::<_, Provider>("SELECT * FROM providers WHERE id = $1")
         .bind(id)
@@ -43,50 +230,72 @@ pub async fn create_provider(
     Ok(ProviderInfo::from(provider))
 }
 
-/// List all providers.
-pub async fn list_providers(db: &PgPool) -> Result<Vec<ProviderInfo>, AppError> {
-    let providers = sqlx::query_as::<_, Provider>("SELECT * FROM providers ORDER BY created_at DESC")
-        .fetch_all(db)
-        .await?;
+/// List an organization's providers.
+pub async fn list_providers(org_id: Uuid, db: &PgPool) -> Result<Vec<ProviderInfo>, AppError> {
+    let providers = sqlx::query_as::<_, Provider>(
+        "SELECT * FROM providers WHERE org_id = $1 ORDER BY created_at DESC",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
 
     Ok(providers.into_iter().map(ProviderInfo::from).collect())
 }
 
-/// Update a provider.
+/// Update a provider. A new base URL must pass the HTTPS check; an existing
+/// one is left alone so unrelated edits keep working.
 pub async fn update_provider(
     id: Uuid,
-    name: Option<&str>,
-    kind: Option<&str>,
-    base_url: Option<&str>,
-    api_key: Option<&str>,
-    is_active: Option<bool>,
+    org_id: Uuid,
+    update: ProviderUpdate,
+    allow_insecure_urls: bool,
     db: &PgPool,
 ) -> Result<ProviderInfo, AppError> {
-    let existing = sqlx::query_as::<_, Provider>("SELECT * FROM providers WHERE id = $1")
-        .bind(id)
-        .fetch_optional(db)
-        .await?
-        .ok_or(AppError::NotFound)?;
+    let existing = get_provider(id, org_id, db).await?.ok_or(AppError::NotFound)?;
 
-    let new_kind = match kind {
+    let new_kind = match update.kind {
         Some(k) => {
-            ProviderKind::from_str(k)
-                .ok_or_else(|| AppError::BadRequest(format!("Unknown provider kind: {k}")))?;
-            k.to_lowercase()
+            ProviderKind::from_str(&k)
+                .ok_or_else(|| AppError::BadRequest(format!("Unknown provider kind: {k}")))?
+                .as_str()
+                .to_string()
         }
         None => existing.kind,
     };
 
-    let new_name = name.map(|s| s.to_string()).unwrap_or(existing.name);
-    let new_base_url = base_url.map(|s| s.to_string()).unwrap_or(existing.base_url);
-    let new_api_key = api_key.map(|s| s.to_string()).unwrap_or(existing.api_key);
-    let new_is_active = is_active.unwrap_or(existing.is_active);
+    let new_name = update.name.unwrap_or(existing.name);
+    if let Some(base_url) = &update.base_url {
+        check_base_url(&new_name, base_url, allow_insecure_urls)?;
+    }
+    let new_base_url = update.base_url.unwrap_or(existing.base_url);
+    let new_api_key = update.api_key.unwrap_or(existing.api_key);
+    let new_is_active = update.is_active.unwrap_or(existing.is_active);
+    let new_force_buffered = update
+        .force_buffered_upstream
+        .unwrap_or(existing.force_buffered_upstream);
+    let new_supports_stream_usage = update
+        .supports_stream_usage
+        .unwrap_or(existing.supports_stream_usage);
+    let new_retry_on_status = update.retry_on_status.unwrap_or(existing.retry_on_status);
+    let new_normalize_model_case = update
+        .normalize_model_case
+        .map(|case| case.as_str().to_string())
+        .unwrap_or(existing.normalize_model_case);
+    let new_region = update.region.unwrap_or(existing.region);
+    let new_chat_path = update.chat_path.unwrap_or(existing.chat_path);
+    let new_passthrough = update.passthrough.unwrap_or(existing.passthrough);
+    let new_passthrough_prefix = update
+        .passthrough_prefix
+        .unwrap_or(existing.passthrough_prefix);
 
     sqlx::query(
         r#"
         UPDATE providers
-        SET name = $1, kind = $2, base_url = $3, api_key = $4, is_active = $5, updated_at = NOW()
-        WHERE id = $6
+        SET name = $1, kind = $2, base_url = $3, api_key = $4, is_active = $5,
+            force_buffered_upstream = $6, supports_stream_usage = $7, retry_on_status = $8,
+            normalize_model_case = $9, region = $10, chat_path = $11, passthrough = $12,
+            passthrough_prefix = $13, updated_at = NOW()
+        WHERE id = $14
         "#,
     )
     .bind(&new_name)
@@ -94,9 +303,18 @@ pub async fn update_provider(
     .bind(&new_base_url)
     .bind(&new_api_key)
     .bind(new_is_active)
+    .bind(new_force_buffered)
+    .bind(new_supports_stream_usage)
+    .bind(&new_retry_on_status)
+    .bind(&new_normalize_model_case)
+    .bind(&new_region)
+    .bind(&new_chat_path)
+    .bind(new_passthrough)
+    .bind(&new_passthrough_prefix)
     .bind(id)
     .execute(db)
-    .await?;
+    .await
+    .map_err(|e| passthrough_conflict(e, new_passthrough_prefix.as_deref()))?;
 
     let updated = sqlx::query_as::<_, Provider>("SELECT * FROM providers WHERE id = $1")
         .bind(id)
@@ -106,10 +324,90 @@ pub async fn update_provider(
     Ok(ProviderInfo::from(updated))
 }
 
-/// Delete a provider (hard delete — will fail if models reference it).
-pub async fn delete_provider(id: Uuid, db: &PgPool) -> Result<(), AppError> {
-    let result = sqlx::query("DELETE FROM providers WHERE id = $1")
+/// Send an authenticated `GET {base_url}/models` and reject the provider when it
+/// cannot be reached or answers 401/403, quoting the provider's response. Other
+/// statuses pass, since not every provider lists its models.
+async fn check_credentials(
+    http_client: &reqwest::Client,
+    name: &str,
+    base_url: &str,
+    api_key: &str,
+) -> Result<(), AppError> {
+    let url = format!("{}/models", base_url.trim_end_matches('/'));
+    let resp = http_client
+        .get(&url)
+        .header(reqwest::header::AUTHORIZATION, format!("Bearer {api_key}"))
+        .timeout(VALIDATION_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Provider '{name}': GET {url} failed: {e}")))?;
+    let status = resp.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        let body = resp.text().await.unwrap_or_default();
+        let body: String = body.chars().take(500).collect();
+        return Err(AppError::BadRequest(format!(
+            "Provider '{name}' rejected the API key: GET {url} returned {status}: {body}"
+        )));
+    }
+    Ok(())
+}
+
+/// Log a warning for every stored provider whose base URL is not HTTPS.
+/// Called at startup when insecure URLs are not allowed; such rows keep
+/// working until their base URL is edited.
+pub async fn warn_insecure_providers(db: &PgPool) -> Result<(), AppError> {
+    let rows: Vec<(Uuid, String, String)> = sqlx::query_as(
+        "SELECT id, name, base_url FROM providers WHERE base_url NOT ILIKE 'https://%'",
+    )
+    .fetch_all(db)
+    .await?;
+
+    for (id, name, base_url) in rows {
+        tracing::warn!(
+            "Provider '{}' ({}) uses non-HTTPS base_url {}; its API key is sent in cleartext. \
+             Update it to https:// or set ALLOW_INSECURE_PROVIDER_URLS=true",
+            name,
+            id,
+            base_url
+        );
+    }
+    Ok(())
+}
+
+/// Send a `HEAD {base_url}/models` to every distinct active provider base URL so
+/// the shared client keeps a pooled connection to each. No credentials are sent;
+/// any HTTP status counts as reached. Returns how many base URLs were reached.
+pub async fn warm_provider_connections(
+    http_client: &reqwest::Client,
+    db: &PgPool,
+) -> Result<usize, AppError> {
+    let base_urls: Vec<String> =
+        sqlx::query_scalar("SELECT DISTINCT base_url FROM providers WHERE is_active = TRUE")
+            .fetch_all(db)
+            .await?;
+
+    let results = futures::future::join_all(base_urls.iter().map(|base_url| async move {
+        let url = format!("{}/models", base_url.trim_end_matches('/'));
+        let result = http_client
+            .head(&url)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await;
+        if let Err(e) = &result {
+            tracing::debug!("Provider keep-alive to {} failed: {}", base_url, e);
+        }
+        result.is_ok()
+    }))
+    .await;
+
+    Ok(results.into_iter().filter(|&reached| reached).count())
+}
+
+/// Delete a provider (hard delete — will fail if models or pinned keys reference it).
+pub async fn delete_provider(id: Uuid, org_id: Uuid, db: &PgPool) -> Result<(), AppError> {
+    let result = sqlx::query("DELETE FROM providers WHERE id = $1 AND org_id = $2")
         .bind(id)
+        .bind(org_id)
         .execute(db)
         .await?;
 
@@ -0,0 +1,53 @@
+use redis::aio::ConnectionManager;
+use reqwest::header;
+use sqlx::PgPool;
+
+use crate::error::AppError;
+use crate::services::{model_service, org_service};
+
+/// Send a minimal chat completion through `model_name` (a model of the default
+/// organization) to confirm it routes end-to-end.
+/// Nothing is written to `request_logs` and no key budget is charged.
+pub async fn run_selftest(
+    model_name: &str,
+    route_ttl_secs: u64,
+    http_client: &reqwest::Client,
+    db: &PgPool,
+    redis: &mut ConnectionManager,
+) -> Result<(), AppError> {
+    let route = model_service::resolve_model_route(
+        org_service::DEFAULT_ORG_ID,
+        model_name,
+        route_ttl_secs,
+        redis,
+        db,
+    )
+    .await?
+    .ok_or_else(|| {
+        AppError::BadRequest(format!("Model \"{model_name}\" is not configured in the gateway"))
+    })?;
+
+    let body = serde_json::json!({
+        "model": route.provider_model_name,
+        "messages": [{ "role": "user", "content": "ping" }],
+        "max_tokens": 1,
+    });
+
+    let resp = http_client
+        .post(route.chat_url())
+        .header(header::AUTHORIZATION, format!("Bearer {}", route.api_key))
+        .json(&body)
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let text = resp.text().await.unwrap_or_default();
+        return Err(AppError::Internal(format!(
+            "provider {} returned {}: {}",
+            route.provider_kind, status, text
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::services::key_service;
+
+/// Weighted token charges not yet written to Postgres (`USAGE_FLUSH_INTERVAL_MS`).
+/// When disabled every charge is written immediately. Budget checks add the pending
+/// amounts to the stored usage, so buffering does not loosen budgets.
+#[derive(Default)]
+pub struct UsageBuffer {
+    enabled: bool,
+    pending: Mutex<PendingUsage>,
+    /// Serializes flushes so a snapshot is never written twice.
+    flush_lock: tokio::sync::Mutex<()>,
+    /// Shared by every charge, exclusive while usage is recomputed, so no charge
+    /// lands between the final flush and the rewrite of the totals.
+    recompute_lock: tokio::sync::RwLock<()>,
+}
+
+#[derive(Default, Clone)]
+struct PendingUsage {
+    keys: HashMap<Uuid, i64>,
+    /// Same charges summed per key group, for group budget checks.
+    groups: HashMap<Uuid, i64>,
+    /// Charges to per-model budgets, by key and model.
+    models: HashMap<(Uuid, String), i64>,
+}
+
+impl UsageBuffer {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Self::default()
+        }
+    }
+
+    /// Charge `tokens` to a key (and its group): buffered when enabled, else written now.
+    pub async fn charge(
+        &self,
+        key_id: Uuid,
+        group_id: Option<Uuid>,
+        tokens: i64,
+        db: &PgPool,
+    ) -> Result<(), AppError> {
+        let _charging = self.recompute_lock.read().await;
+        if !self.enabled {
+            return key_service::increment_tokens_used(key_id, tokens, db).await;
+        }
+        let mut pending = self.pending.lock().unwrap();
+        *pending.keys.entry(key_id).or_default() += tokens;
+        if let Some(group_id) = group_id {
+            *pending.groups.entry(group_id).or_default() += tokens;
+        }
+        Ok(())
+    }
+
+    /// Charge `tokens` to a key's budget for `model`, buffered like `charge`.
+    pub async fn charge_model(
+        &self,
+        key_id: Uuid,
+        model: &str,
+        tokens: i64,
+        db: &PgPool,
+    ) -> Result<(), AppError> {
+        let _charging = self.recompute_lock.read().await;
+        if !self.enabled {
+            return key_service::increment_model_tokens_used(key_id, model, tokens, db).await;
+        }
+        let mut pending = self.pending.lock().unwrap();
+        *pending.models.entry((key_id, model.to_string())).or_default() += tokens;
+        Ok(())
+    }
+
+    /// Unflushed usage of a key's budget for `model`.
+    pub fn pending_model(&self, key_id: Uuid, model: &str) -> i64 {
+        let pending = self.pending.lock().unwrap();
+        pending.models.get(&(key_id, model.to_string())).copied().unwrap_or(0)
+    }
+
+    /// Unflushed usage counted against the applicable budget: the group's for
+    /// grouped keys, otherwise the key's.
+    pub fn pending(&self, key_id: Uuid, group_id: Option<Uuid>) -> i64 {
+        let pending = self.pending.lock().unwrap();
+        match group_id {
+            Some(group_id) => pending.groups.get(&group_id).copied().unwrap_or(0),
+            None => pending.keys.get(&key_id).copied().unwrap_or(0),
+        }
+    }
+
+    /// Write all pending charges, key and model charges in one statement each. The written
+    /// amounts stay pending until their write succeeds, so budget checks briefly overcount
+    /// rather than undercount, and a failed flush is retried with the next one. Returns the
+    /// number of keys written.
+    pub async fn flush(&self, db: &PgPool) -> Result<usize, AppError> {
+        let _flushing = self.flush_lock.lock().await;
+        let snapshot = self.pending.lock().unwrap().clone();
+        let charges: Vec<(Uuid, i64)> = snapshot
+            .keys
+            .iter()
+            .filter(|(_, &tokens)| tokens != 0)
+            .map(|(&id, &tokens)| (id, tokens))
+            .collect();
+        if !charges.is_empty() {
+            key_service::increment_tokens_used_batch(&charges, db).await?;
+        }
+        let model_charges: Vec<(Uuid, String, i64)> = snapshot
+            .models
+            .iter()
+            .filter(|(_, &tokens)| tokens != 0)
+            .map(|((id, model), &tokens)| (*id, model.clone(), tokens))
+            .collect();
+        if !model_charges.is_empty() {
+            // The key charges are already written; only the model charges stay pending
+            if let Err(e) = key_service::increment_model_tokens_used_batch(&model_charges, db).await
+            {
+                let mut pending = self.pending.lock().unwrap();
+                subtract(&mut pending.keys, &snapshot.keys);
+                subtract(&mut pending.groups, &snapshot.groups);
+                return Err(e);
+            }
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        subtract(&mut pending.keys, &snapshot.keys);
+        subtract(&mut pending.groups, &snapshot.groups);
+        subtract(&mut pending.models, &snapshot.models);
+        Ok(charges.len())
+    }
+
+    /// Flush, then run `recompute` with charges held back until it finishes, so the
+    /// recomputed totals neither miss nor double-count a charge.
+    pub async fn flush_and_recompute<F, Fut, T>(
+        &self,
+        db: &PgPool,
+        recompute: F,
+    ) -> Result<T, AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        let _recomputing = self.recompute_lock.write().await;
+        self.flush(db).await?;
+        recompute().await
+    }
+}
+
+/// Remove flushed amounts, dropping entries that reach zero.
+fn subtract<K: Eq + std::hash::Hash>(pending: &mut HashMap<K, i64>, flushed: &HashMap<K, i64>) {
+    for (id, tokens) in flushed {
+        if let Some(left) = pending.get_mut(id) {
+            *left -= tokens;
+            if *left == 0 {
+                pending.remove(id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    async fn insert_key(budget: Option<i64>, db: &PgPool) -> Uuid {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO user_keys (id, name, key_hash, key_prefix, token_budget)
+             VALUES ($1, 'test', $2, 'sk-test', $3)",
+        )
+        .bind(id)
+        .bind(id.to_string())
+        .bind(budget)
+        .execute(db)
+        .await
+        .unwrap();
+        id
+    }
+
+    async fn tokens_used(id: Uuid, db: &PgPool) -> i64 {
+        sqlx::query_scalar("SELECT tokens_used FROM user_keys WHERE id = $1")
+            .bind(id)
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn charges_during_flushes_are_written_exactly_once(db: PgPool) {
+        let key = insert_key(None, &db).await;
+        let usage = Arc::new(UsageBuffer::new(true));
+
+        let mut tasks = Vec::new();
+        for i in 0..50 {
+            let (usage, db) = (usage.clone(), db.clone());
+            tasks.push(tokio::spawn(async move {
+                usage.charge(key, None, 3, &db).await.unwrap();
+                if i % 5 == 0 {
+                    usage.flush(&db).await.unwrap();
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        usage.flush(&db).await.unwrap();
+
+        assert_eq!(tokens_used(key, &db).await, 150);
+        assert_eq!(usage.pending(key, None), 0);
+    }
+
+    #[sqlx::test]
+    async fn model_charges_are_flushed_with_key_charges(db: PgPool) {
+        let key = insert_key(None, &db).await;
+        sqlx::query(
+            "INSERT INTO key_model_budgets (key_id, model, token_budget)
+             VALUES ($1, 'gpt-4o', 100)",
+        )
+        .bind(key)
+        .execute(&db)
+        .await
+        .unwrap();
+        let usage = UsageBuffer::new(true);
+        usage.charge(key, None, 7, &db).await.unwrap();
+        usage.charge_model(key, "gpt-4o", 7, &db).await.unwrap();
+        assert_eq!(usage.pending_model(key, "gpt-4o"), 7);
+
+        usage.flush(&db).await.unwrap();
+        let model_used: i64 =
+            sqlx::query_scalar("SELECT tokens_used FROM key_model_budgets WHERE key_id = $1")
+                .bind(key)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!((tokens_used(key, &db).await, model_used), (7, 7));
+        assert_eq!(usage.pending_model(key, "gpt-4o"), 0);
+    }
+
+    #[sqlx::test]
+    async fn failed_flush_keeps_charges_pending(db: PgPool) {
+        let key = insert_key(None, &db).await;
+        let usage = UsageBuffer::new(true);
+        usage.charge(key, None, 42, &db).await.unwrap();
+
+        db.close().await;
+        assert!(usage.flush(&db).await.is_err());
+        assert_eq!(usage.pending(key, None), 42);
+    }
+
+    #[sqlx::test]
+    async fn charges_wait_for_a_running_recompute(db: PgPool) {
+        let key = insert_key(None, &db).await;
+        let usage = Arc::new(UsageBuffer::new(true));
+        usage.charge(key, None, 5, &db).await.unwrap();
+
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let recompute = {
+            let (usage, db) = (usage.clone(), db.clone());
+            tokio::spawn(async move {
+                usage
+                    .flush_and_recompute(&db, || async {
+                        started_tx.send(()).unwrap();
+                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                        Ok(usage.pending(key, None))
+                    })
+                    .await
+            })
+        };
+        started_rx.await.unwrap();
+        usage.charge(key, None, 7, &db).await.unwrap();
+
+        // The first charge was flushed before the recompute and the second waited for it
+        assert_eq!(recompute.await.unwrap().unwrap(), 0);
+        assert_eq!(tokens_used(key, &db).await, 5);
+        assert_eq!(usage.pending(key, None), 7);
+    }
+
+    #[sqlx::test]
+    async fn reservations_count_pending_charges(db: PgPool) {
+        let key = insert_key(Some(100), &db).await;
+        let usage = UsageBuffer::new(true);
+        usage.charge(key, None, 90, &db).await.unwrap();
+
+        let pending = usage.pending(key, None);
+        assert!(!key_service::reserve_tokens(key, 20, pending, &db)
+            .await
+            .unwrap());
+        assert!(key_service::reserve_tokens(key, 10, pending, &db)
+            .await
+            .unwrap());
+        assert_eq!(tokens_used(key, &db).await, 10);
+    }
+}
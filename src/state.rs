@@ -1,7 +1,16 @@
 use redis::aio::ConnectionManager;
 use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use uuid::Uuid;
 
 use crate::config::Config;
+use crate::metrics::{ProviderHealth, ProviderInflight, ProviderQuotas, StreamSlots};
+use crate::services::body_store_service::BodyStore;
+use crate::services::budget_alert_service::BudgetAlerts;
+use crate::services::log_service::LogEvent;
+use crate::services::usage_service::UsageBuffer;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -9,4 +18,103 @@ pub struct AppState {
     pub redis: ConnectionManager,
     pub config: Config,
     pub http_client: reqwest::Client,
+    /// In-flight upstream requests per provider.
+    pub provider_inflight: Arc<ProviderInflight>,
+    /// Rolling upstream success rate per provider, used for route selection.
+    pub provider_health: Arc<ProviderHealth>,
+    /// Latest `x-ratelimit-*` quota reported by each provider.
+    pub provider_quotas: Arc<ProviderQuotas>,
+    /// Fan-out of newly written request logs for the admin live tail.
+    pub log_events: tokio::sync::broadcast::Sender<LogEvent>,
+    /// Requests that exceeded `SLOW_REQUEST_MS` since startup.
+    pub slow_requests: Arc<AtomicU64>,
+    /// Organization ids keyed by the names used in `ORG_ADMIN_KEYS`.
+    pub org_ids: HashMap<String, Uuid>,
+    /// Token charges awaiting the next `USAGE_FLUSH_INTERVAL_MS` flush.
+    pub usage: Arc<UsageBuffer>,
+    /// Open streaming responses, capped by `MAX_STREAMS`.
+    pub stream_slots: Arc<StreamSlots>,
+    /// Notifications at `BUDGET_ALERT_THRESHOLDS`.
+    pub budget_alerts: Arc<BudgetAlerts>,
+    /// Where request/response bodies of logs are written (`LOG_BODY_STORE`).
+    pub body_store: Arc<dyn BodyStore>,
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State over `db` and a nil Redis, configured by `vars`.
+    pub async fn for_tests(db: PgPool, vars: &[(&str, &str)]) -> Arc<Self> {
+        use crate::services::body_store_service::PostgresBodyStore;
+        use crate::services::log_service::LOG_EVENT_CAPACITY;
+
+        let config = Config::for_tests(vars);
+        let http_client = reqwest::Client::new();
+        Arc::new(Self {
+            db,
+            redis: nil_redis().await,
+            provider_inflight: Arc::new(ProviderInflight::default()),
+            provider_health: Arc::new(ProviderHealth::new(std::time::Duration::from_secs(
+                config.routing_health_window_secs,
+            ))),
+            provider_quotas: Arc::new(ProviderQuotas::default()),
+            log_events: tokio::sync::broadcast::channel(LOG_EVENT_CAPACITY).0,
+            slow_requests: Arc::new(AtomicU64::new(0)),
+            org_ids: Default::default(),
+            usage: Arc::new(UsageBuffer::new(config.usage_flush_interval_ms > 0)),
+            stream_slots: Arc::new(StreamSlots::new(config.max_streams)),
+            budget_alerts: Arc::new(BudgetAlerts::new(Vec::new(), None, http_client.clone())),
+            body_store: Arc::new(PostgresBodyStore),
+            http_client,
+            config,
+        })
+    }
+}
+
+/// Length of the first complete RESP command (an array of bulk strings) in `buf`.
+#[cfg(test)]
+fn resp_command_len(buf: &[u8]) -> Option<usize> {
+    // `*<n>` or `$<n>` at `at`: n and where the next line starts
+    let header = |at: usize| -> Option<(usize, usize)> {
+        let end = at + buf.get(at..)?.windows(2).position(|w| w == b"\r\n")?;
+        let n = std::str::from_utf8(buf.get(at + 1..end)?).ok()?.parse().ok()?;
+        Some((n, end + 2))
+    };
+    let (args, mut at) = header(0)?;
+    for _ in 0..args {
+        let (len, next) = header(at)?;
+        at = next + len + 2;
+    }
+    (at <= buf.len()).then_some(at)
+}
+
+/// A Redis stand-in that answers every command with nil: caches always miss, so
+/// reads go to Postgres, and counter updates fail as when Redis is down.
+#[cfg(test)]
+async fn nil_redis() -> ConnectionManager {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("redis://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut pending = Vec::new();
+                let mut buf = [0u8; 4096];
+                loop {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => pending.extend_from_slice(&buf[..n]),
+                    }
+                    while let Some(len) = resp_command_len(&pending) {
+                        pending.drain(..len);
+                        if socket.write_all(b"$-1\r\n").await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    let client = redis::Client::open(url).unwrap();
+    ConnectionManager::new(client).await.unwrap()
 }